@@ -0,0 +1,62 @@
+#[macro_use]
+extern crate criterion;
+extern crate fontgen;
+
+use criterion::Criterion;
+use std::path::Path;
+use fontgen::{AtlasChannels, AtlasSpec, CharacterSet, Packing, ZeroWidthClasses};
+use fontgen::bmfa::Origin;
+use fontgen::freetype::Library;
+
+
+/// Copy the glyphs of a 4096 by 4096 atlas into its image, in each channel layout and origin.
+fn create_bitmap_image(c: &mut Criterion) {
+    let library = Library::init().unwrap();
+    let face = library.new_face("assets/FreeMono.ttf", 0).unwrap();
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 4096, 4096, 16, 16, 16, 256, 224,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default()).unwrap();
+
+    c.bench_function("create_bitmap_image rgba", |b| {
+        b.iter(|| fontgen::create_bitmap_image(&glyph_tab, spec))
+    });
+    let mut a8_spec = spec;
+    a8_spec.channels = AtlasChannels::A8;
+    c.bench_function("create_bitmap_image a8", |b| {
+        b.iter(|| fontgen::create_bitmap_image(&glyph_tab, a8_spec))
+    });
+    let mut bottom_left_spec = spec;
+    bottom_left_spec.origin = Origin::BottomLeft;
+    c.bench_function("create_bitmap_image bottom-left", |b| {
+        b.iter(|| fontgen::create_bitmap_image(&glyph_tab, bottom_left_spec))
+    });
+}
+
+/// Stroke the outlines of the glyphs of a typeface serially and on four worker threads.
+fn sample_outlined_glyphs(c: &mut Criterion) {
+    let library = Library::init().unwrap();
+    let face = library.new_face("assets/FreeMono.ttf", 0).unwrap();
+    let chars = CharacterSet::default();
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 16, 16, 4, 128, 112,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    spec.outline = 4;
+
+    c.bench_function("sample_typeface outline serial", |b| {
+        b.iter(|| fontgen::sample_typeface(&face, spec, &chars).unwrap())
+    });
+    c.bench_function("sample_typeface outline parallel", |b| {
+        let font_path = Path::new("assets/FreeMono.ttf");
+        b.iter(|| fontgen::sample_typeface_parallel(font_path, 0, &[], spec, &chars, 4).unwrap())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = create_bitmap_image, sample_outlined_glyphs
+}
+criterion_main!(benches);
@@ -0,0 +1,126 @@
+/*
+ * A reader for the glyph lookup tables `fontgen --export-lookup` writes, for C and C++
+ * runtimes. It looks glyphs up in place in the contents of a `.lookup.bin` file, without
+ * allocating or decoding the table first.
+ *
+ *     fontgen_glyph_lookup lookup;
+ *     fontgen_glyph_record record;
+ *     if (fontgen_glyph_lookup_parse(&lookup, data, size) &&
+ *         fontgen_glyph_lookup_find(&lookup, 0x41, &record)) {
+ *         draw_glyph(record.x, record.y, record.width, record.height);
+ *     }
+ *
+ * See the documentation of `GlyphLookupTable` for the layout of the file.
+ */
+#ifndef FONTGEN_GLYPH_LOOKUP_H
+#define FONTGEN_GLYPH_LOOKUP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define FONTGEN_GLYPH_LOOKUP_HEADER_SIZE 12
+#define FONTGEN_GLYPH_RECORD_SIZE 20
+#define FONTGEN_GLYPH_LOOKUP_EMPTY 0xFFFF
+
+/* The origin the positions of the glyph images are measured from. */
+typedef enum fontgen_origin {
+    FONTGEN_ORIGIN_TOP_LEFT = 0,
+    FONTGEN_ORIGIN_BOTTOM_LEFT = 1
+} fontgen_origin;
+
+/* A glyph lookup table read out of the contents of a `.lookup.bin` file. */
+typedef struct fontgen_glyph_lookup {
+    const uint8_t *data;
+    fontgen_origin origin;
+    size_t block_count;
+    size_t records_offset;
+} fontgen_glyph_lookup;
+
+/* The record of a character, in the layout of the BMFont binary format. */
+typedef struct fontgen_glyph_record {
+    uint32_t id;
+    uint16_t x;
+    uint16_t y;
+    uint16_t width;
+    uint16_t height;
+    int16_t x_offset;
+    int16_t y_offset;
+    int16_t x_advance;
+    uint8_t page;
+    uint8_t channel;
+} fontgen_glyph_record;
+
+static inline uint16_t fontgen_read_u16(const uint8_t *bytes) {
+    return (uint16_t)(bytes[0] | (bytes[1] << 8));
+}
+
+/* Read the header of a glyph lookup table. Returns zero if the data is not a glyph lookup
+ * table, or is truncated. The data has to outlive the lookup. */
+static inline int fontgen_glyph_lookup_parse(fontgen_glyph_lookup *lookup, const uint8_t *data, size_t size) {
+    size_t block_count, leaf_count, record_count, records_offset;
+    if (size < FONTGEN_GLYPH_LOOKUP_HEADER_SIZE ||
+        data[0] != 'F' || data[1] != 'G' || data[2] != 'L' || data[3] != 'T' ||
+        data[4] != 1 || data[5] > 1) {
+        return 0;
+    }
+    block_count = fontgen_read_u16(data + 6);
+    leaf_count = fontgen_read_u16(data + 8);
+    record_count = fontgen_read_u16(data + 10);
+    records_offset = FONTGEN_GLYPH_LOOKUP_HEADER_SIZE + 2 * block_count + 512 * leaf_count;
+    if (size < records_offset + FONTGEN_GLYPH_RECORD_SIZE * record_count) {
+        return 0;
+    }
+
+    lookup->data = data;
+    lookup->origin = (fontgen_origin)data[5];
+    lookup->block_count = block_count;
+    lookup->records_offset = records_offset;
+    return 1;
+}
+
+/* Find the offset of the record of a code point in the table. Returns zero if the atlas does
+ * not have the character, since no record starts at the beginning of the table. */
+static inline size_t fontgen_glyph_lookup_offset(const fontgen_glyph_lookup *lookup, uint32_t code_point) {
+    size_t block = code_point >> 8;
+    size_t leaves_offset = FONTGEN_GLYPH_LOOKUP_HEADER_SIZE + 2 * lookup->block_count;
+    uint16_t leaf, index;
+    if (block >= lookup->block_count) {
+        return 0;
+    }
+    leaf = fontgen_read_u16(lookup->data + FONTGEN_GLYPH_LOOKUP_HEADER_SIZE + 2 * block);
+    if (leaf == FONTGEN_GLYPH_LOOKUP_EMPTY) {
+        return 0;
+    }
+    index = fontgen_read_u16(lookup->data + leaves_offset + 512 * (size_t)leaf + 2 * (code_point & 0xFF));
+    if (index == FONTGEN_GLYPH_LOOKUP_EMPTY) {
+        return 0;
+    }
+
+    return lookup->records_offset + FONTGEN_GLYPH_RECORD_SIZE * (size_t)index;
+}
+
+/* Look up the record of a code point. Returns zero if the atlas does not have the character. */
+static inline int fontgen_glyph_lookup_find(
+    const fontgen_glyph_lookup *lookup, uint32_t code_point, fontgen_glyph_record *record) {
+
+    const uint8_t *bytes;
+    size_t offset = fontgen_glyph_lookup_offset(lookup, code_point);
+    if (offset == 0) {
+        return 0;
+    }
+
+    bytes = lookup->data + offset;
+    record->id = (uint32_t)fontgen_read_u16(bytes) | ((uint32_t)fontgen_read_u16(bytes + 2) << 16);
+    record->x = fontgen_read_u16(bytes + 4);
+    record->y = fontgen_read_u16(bytes + 6);
+    record->width = fontgen_read_u16(bytes + 8);
+    record->height = fontgen_read_u16(bytes + 10);
+    record->x_offset = (int16_t)fontgen_read_u16(bytes + 12);
+    record->y_offset = (int16_t)fontgen_read_u16(bytes + 14);
+    record->x_advance = (int16_t)fontgen_read_u16(bytes + 16);
+    record->page = bytes[18];
+    record->channel = bytes[19];
+    return 1;
+}
+
+#endif
@@ -0,0 +1,8479 @@
+//! `fontgen` converts TrueType and OpenType fonts into bitmapped font atlases in the
+//! [bmfa](https://github.com/lambdaxymox/bmfa) format. The `fontgen` shell utility is a thin
+//! wrapper around this library, so asset pipelines can generate atlases directly instead of
+//! shelling out to the binary.
+//!
+//! Generating an atlas happens in two steps. First, [`sample_typeface`] rasterizes each glyph
+//! of a [`CharacterSet`] from a typeface into a [`GlyphTable`]. Then [`create_bitmap_atlas`] packs the glyph images
+//! into a single atlas image along with the metadata for indexing into it.
+//!
+//! ```no_run
+//! use fontgen::{AtlasSpec, CharacterSet, Packing, ZeroWidthClasses};
+//! use fontgen::bmfa::Origin;
+//! use fontgen::freetype::Library;
+//!
+//! let library = Library::init().unwrap();
+//! let face = library.new_face("assets/FreeMono.ttf", 0).unwrap();
+//! // A 16x16 grid of 64 pixel slots, each holding a glyph at most 58 pixels in size.
+//! let spec = AtlasSpec::new(
+//!     Origin::TopLeft, 1024, 1024, 16, 16, 6, 64, 58,
+//!     1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+//! );
+//! let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default()).unwrap();
+//! let atlas = fontgen::create_bitmap_atlas(&glyph_tab, spec);
+//! fontgen::bmfa::write_to_file("FreeMono.bmfa", &atlas).unwrap();
+//! ```
+//!
+//! To render single glyphs with the same configuration without packing an atlas, e.g. for a tool
+//! that draws glyphs on demand, use [`rasterize_glyph`] instead, with a specification from
+//! [`AtlasSpec::for_glyph`] when there is no atlas to match.
+pub extern crate bmfa;
+pub extern crate freetype;
+extern crate image;
+extern crate png;
+#[cfg(feature = "scripting")]
+extern crate rhai;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate unicode_bidi;
+extern crate unicode_names2;
+extern crate unicode_script;
+extern crate zip;
+
+
+use bmfa::{BitmapFontAtlas, BitmapFontAtlasMetadata, GlyphMetadata};
+use freetype::Library;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use unicode_script::UnicodeScript;
+
+
+/// The classes of code points that occupy no horizontal space and should not be drawn on
+/// their own. Code points in a selected class get metadata entries without an image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ZeroWidthClasses {
+    /// Zero width joiners and non-joiners, the word joiner, and the combining grapheme joiner.
+    pub joiners: bool,
+    /// Combining marks, which attach to the preceding base character.
+    pub combining: bool,
+    /// Invisible formatting characters like the soft hyphen and bidirectional controls.
+    pub format: bool,
+}
+
+const ZERO_WIDTH_JOINERS: [(usize, usize); 3] = [
+    (0x034F, 0x034F), (0x200C, 0x200D), (0x2060, 0x2060),
+];
+
+const ZERO_WIDTH_COMBINING_MARKS: [(usize, usize); 19] = [
+    (0x0300, 0x034E), (0x0350, 0x036F), (0x0483, 0x0489), (0x0591, 0x05BD), (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2), (0x05C4, 0x05C5), (0x05C7, 0x05C7), (0x0610, 0x061A), (0x064B, 0x065F),
+    (0x0670, 0x0670), (0x06D6, 0x06DC), (0x06DF, 0x06E4), (0x06E7, 0x06E8), (0x06EA, 0x06ED),
+    (0x1AB0, 0x1AFF), (0x1DC0, 0x1DFF), (0x20D0, 0x20FF), (0xFE20, 0xFE2F),
+];
+
+const ZERO_WIDTH_FORMAT_CHARACTERS: [(usize, usize); 10] = [
+    (0x00AD, 0x00AD), (0x061C, 0x061C), (0x180E, 0x180E), (0x200B, 0x200B), (0x200E, 0x200F),
+    (0x202A, 0x202E), (0x2061, 0x2064), (0x2066, 0x206F), (0xFEFF, 0xFEFF), (0xFFF9, 0xFFFB),
+];
+
+impl ZeroWidthClasses {
+    /// Determine whether a code point belongs to one of the selected classes.
+    pub fn contains(&self, code_point: usize) -> bool {
+        let in_ranges = |ranges: &[(usize, usize)]| {
+            ranges.iter().any(|&(start, end)| start <= code_point && code_point <= end)
+        };
+
+        (self.joiners && in_ranges(&ZERO_WIDTH_JOINERS))
+            || (self.combining && in_ranges(&ZERO_WIDTH_COMBINING_MARKS))
+            || (self.format && in_ranges(&ZERO_WIDTH_FORMAT_CHARACTERS))
+    }
+}
+
+/// The code points to include in an atlas, stored as a sorted list of disjoint inclusive
+/// ranges. The space character always gets the first slot of the atlas, whether or not
+/// the set contains it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CharacterSet {
+    ranges: Vec<(usize, usize)>,
+}
+
+/// The names of the built-in character sets, and the code point ranges each one expands to.
+pub const CHARACTER_SET_PRESETS: [(&str, &[(usize, usize)]); 8] = [
+    ("ascii", &[(0x0020, 0x007E)]),
+    ("latin1", &[(0x0020, 0x007E), (0x00A0, 0x00FF)]),
+    ("latin-ext", &[(0x0020, 0x007E), (0x00A0, 0x024F)]),
+    ("cyrillic", &[(0x0400, 0x04FF)]),
+    ("greek", &[(0x0370, 0x03FF)]),
+    ("hiragana", &[(0x3040, 0x309F)]),
+    ("katakana", &[(0x30A0, 0x30FF)]),
+    ("cjk-common", &[(0x3000, 0x303F), (0x4E00, 0x9FFF), (0xFF00, 0xFFEF)]),
+];
+
+/// The characters of the shared base atlas of a locale bundle: the printable ASCII and Latin-1
+/// characters, the general punctuation block, and the euro sign.
+pub const BUNDLE_BASE_RANGES: [(usize, usize); 4] = [
+    (0x0020, 0x007E), (0x00A0, 0x00FF), (0x2010, 0x205E), (0x20AC, 0x20AC),
+];
+
+/// The locales with a supplement atlas in a locale bundle, and the code point ranges each one
+/// needs. The characters of the shared base get left out of the supplements.
+pub const LOCALE_SUPPLEMENTS: [(&str, &[(usize, usize)]); 15] = [
+    ("cs", &[(0x0100, 0x017F)]),
+    ("pl", &[(0x0100, 0x017F)]),
+    ("tr", &[(0x0100, 0x017F)]),
+    ("vi", &[
+        (0x0102, 0x0103), (0x0110, 0x0111), (0x0128, 0x0129), (0x0168, 0x0169), (0x01A0, 0x01A1),
+        (0x01AF, 0x01B0), (0x0300, 0x0303), (0x0309, 0x0309), (0x0323, 0x0323), (0x1EA0, 0x1EF9),
+        (0x20AB, 0x20AB),
+    ]),
+    ("el", &[(0x0370, 0x03FF)]),
+    ("ru", &[(0x0400, 0x04FF), (0x20BD, 0x20BD)]),
+    ("uk", &[(0x0400, 0x04FF), (0x20B4, 0x20B4)]),
+    ("he", &[(0x0590, 0x05FF), (0x20AA, 0x20AA)]),
+    ("ar", &[(0x0600, 0x06FF)]),
+    ("hi", &[(0x0900, 0x097F), (0x20B9, 0x20B9)]),
+    ("th", &[(0x0E00, 0x0E7F)]),
+    ("ko", &[(0x1100, 0x11FF), (0x3000, 0x303F), (0x3130, 0x318F), (0xAC00, 0xD7A3)]),
+    ("ja", &[(0x3000, 0x303F), (0x3040, 0x309F), (0x30A0, 0x30FF), (0x4E00, 0x9FFF), (0xFF00, 0xFFEF)]),
+    ("zh", &[(0x3000, 0x303F), (0x4E00, 0x9FFF), (0xFF00, 0xFFEF)]),
+    ("zh-hant", &[(0x3000, 0x303F), (0x3100, 0x312F), (0x4E00, 0x9FFF), (0xFF00, 0xFFEF)]),
+];
+
+impl CharacterSet {
+    /// The characters of the shared base atlas of a locale bundle.
+    pub fn bundle_base() -> CharacterSet {
+        CharacterSet::new(&BUNDLE_BASE_RANGES)
+    }
+
+    /// Look up the characters a locale needs beyond the shared base atlas of a locale bundle,
+    /// e.g. the Greek block for `el`. The locales are listed in `LOCALE_SUPPLEMENTS`.
+    pub fn locale_supplement(locale: &str, base: &CharacterSet) -> Option<CharacterSet> {
+        LOCALE_SUPPLEMENTS.iter()
+            .find(|&&(name, _)| name == locale)
+            .map(|&(_, ranges)| CharacterSet::new(ranges).difference(base))
+    }
+
+    /// Look up one of the built-in character sets by name. The presets are
+    ///
+    /// * `ascii`: the printable ASCII characters.
+    /// * `latin1`: the printable ASCII and Latin-1 characters.
+    /// * `latin-ext`: Latin-1 along with the Latin Extended-A and Latin Extended-B blocks.
+    /// * `cyrillic`, `greek`, `hiragana`, `katakana`: the Unicode block of the script.
+    /// * `cjk-common`: the CJK Unified Ideographs block, along with CJK punctuation and
+    ///   the fullwidth forms.
+    ///
+    /// The script presets leave out ASCII, so combine them with `ascii` for digits and punctuation.
+    pub fn preset(name: &str) -> Option<CharacterSet> {
+        CHARACTER_SET_PRESETS.iter()
+            .find(|&&(preset, _)| preset == name)
+            .map(|&(_, ranges)| CharacterSet::new(ranges))
+    }
+
+    /// Construct a character set from a list of inclusive code point ranges. The ranges can
+    /// be given in any order, and overlapping ranges get merged.
+    pub fn new(ranges: &[(usize, usize)]) -> CharacterSet {
+        let mut sorted: Vec<(usize, usize)> = ranges.iter()
+            .map(|&(start, end)| (start.min(end), start.max(end)))
+            .collect();
+        sorted.sort();
+
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (start, end) in sorted {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        CharacterSet {
+            ranges: merged,
+        }
+    }
+
+    /// Construct the character set of the distinct characters used in a text. Control
+    /// characters, like line breaks and tabs, are left out.
+    pub fn from_text(text: &str) -> CharacterSet {
+        let ranges: Vec<(usize, usize)> = text.chars()
+            .filter(|c| !c.is_control())
+            .map(|c| (c as usize, c as usize))
+            .collect();
+
+        CharacterSet::new(&ranges)
+    }
+
+    /// The set of code points contained in either character set.
+    pub fn union(&self, other: &CharacterSet) -> CharacterSet {
+        let ranges: Vec<(usize, usize)> = self.ranges.iter().chain(other.ranges.iter()).cloned().collect();
+
+        CharacterSet::new(&ranges)
+    }
+
+    /// The set of code points contained in this character set, but not in the other one.
+    pub fn difference(&self, other: &CharacterSet) -> CharacterSet {
+        let mut ranges = vec![];
+        for &(start, end) in self.ranges.iter() {
+            let mut next = start;
+            for &(other_start, other_end) in other.ranges.iter() {
+                if other_end < next || other_start > end {
+                    continue;
+                }
+                if other_start > next {
+                    ranges.push((next, other_start - 1));
+                }
+                next = other_end + 1;
+            }
+            if next <= end {
+                ranges.push((next, end));
+            }
+        }
+
+        CharacterSet::new(&ranges)
+    }
+
+    /// Keep only the code points for which a predicate holds, e.g. the characters a typeface covers.
+    pub fn filter<F: Fn(usize) -> bool>(&self, predicate: F) -> CharacterSet {
+        let ranges: Vec<(usize, usize)> = self.code_points().into_iter()
+            .filter(|&code_point| predicate(code_point))
+            .map(|code_point| (code_point, code_point))
+            .collect();
+
+        CharacterSet::new(&ranges)
+    }
+
+    /// The inclusive code point ranges in the set, in ascending order.
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    /// The code points in the set, in ascending order.
+    pub fn code_points(&self) -> Vec<usize> {
+        self.ranges.iter().flat_map(|&(start, end)| start..(end + 1)).collect()
+    }
+
+    /// Determine whether the set contains a code point.
+    pub fn contains(&self, code_point: usize) -> bool {
+        self.ranges.iter().any(|&(start, end)| start <= code_point && code_point <= end)
+    }
+
+    /// The number of code points in the set.
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|&(start, end)| end - start + 1).sum()
+    }
+
+    /// Determine whether the set has no code points.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The number of rows and columns of the smallest square grid that holds a slot
+    /// for the space character and for every other code point in the set.
+    pub fn grid_size(&self) -> (usize, usize) {
+        let slots = if self.contains(32) { self.len() } else { self.len() + 1 };
+        let columns = (slots as f64).sqrt().ceil() as usize;
+        let rows = (slots + columns - 1) / columns;
+
+        (rows, columns)
+    }
+}
+
+impl Default for CharacterSet {
+    /// The printable characters of the ASCII and Latin-1 ranges, `U+0021` through `U+00FF`.
+    fn default() -> CharacterSet {
+        CharacterSet::new(&[(0x21, 0xFF)])
+    }
+}
+
+/// A signed distance field stored in the atlas in place of the coverage values of each glyph.
+/// The spread is the distance in pixels that the field extends on either side of the outline.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DistanceField {
+    /// A single channel distance field computed from the rasterized glyph.
+    Sdf { spread: usize },
+    /// A multi-channel distance field computed from the glyph outline, with one distance
+    /// per color channel so that corners stay sharp.
+    Msdf { spread: usize },
+}
+
+impl DistanceField {
+    /// The distance in pixels that the field extends on either side of the outline.
+    pub fn spread(&self) -> usize {
+        match *self {
+            DistanceField::Sdf { spread } => spread,
+            DistanceField::Msdf { spread } => spread,
+        }
+    }
+
+    /// The number of bytes each pixel of the field takes up in a glyph image.
+    pub fn channels(&self) -> usize {
+        match *self {
+            DistanceField::Sdf { .. } => 1,
+            DistanceField::Msdf { .. } => 3,
+        }
+    }
+
+    /// The name of the kind of distance field, `sdf` or `msdf`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DistanceField::Sdf { .. } => "sdf",
+            DistanceField::Msdf { .. } => "msdf",
+        }
+    }
+
+    /// The same kind of distance field with its spread scaled, and rounded to at least a pixel.
+    pub fn scaled(&self, scale: f32) -> DistanceField {
+        let spread = ((self.spread() as f32 * scale).round() as usize).max(1);
+        match *self {
+            DistanceField::Sdf { .. } => DistanceField::Sdf { spread: spread },
+            DistanceField::Msdf { .. } => DistanceField::Msdf { spread: spread },
+        }
+    }
+}
+
+/// The ways to draw hard-edged glyphs, with every pixel either fully on or fully off, for
+/// pixel art games and embedded displays without antialiasing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Monochrome {
+    /// The one bit per pixel rasterizer of FreeType, with the hinting tuned for it.
+    Rasterizer,
+    /// Antialiased glyphs cut off at a coverage value, where higher values make thinner strokes.
+    Threshold(u8),
+}
+
+impl Monochrome {
+    /// The lowest coverage value that turns a pixel on.
+    pub fn threshold(&self) -> u8 {
+        match *self {
+            Monochrome::Rasterizer => 128,
+            Monochrome::Threshold(threshold) => threshold,
+        }
+    }
+}
+
+/// A glyph size in typographic points at a display resolution, the way UI toolkits give font
+/// sizes, instead of in pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PointSize {
+    /// The size of an em in points, where a point is 1/72 of an inch.
+    pub points: f32,
+    /// The resolution of the display in dots per inch.
+    pub dpi: u32,
+}
+
+impl PointSize {
+    /// The size of an em in pixels at the resolution, rounded to the nearest pixel.
+    pub fn pixels(&self) -> usize {
+        (self.points * self.dpi as f32 / 72.0).round() as usize
+    }
+}
+
+/// The ways glyph images can be arranged in the atlas image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Packing {
+    /// A grid of uniform slots the size of the slot glyph size, one glyph per slot.
+    Grid,
+    /// Glyphs packed tightly by their actual sizes with the skyline algorithm.
+    Skyline,
+    /// Glyphs packed tightly by their actual sizes with the maximal rectangles algorithm.
+    MaxRects,
+    /// Glyphs packed by their actual sizes into shelves, rows as tall as their tallest glyph,
+    /// so glyphs of very different heights fill bands of their own height.
+    Shelf,
+}
+
+impl Packing {
+    /// The name of the packing.
+    pub fn name(self) -> &'static str {
+        match self {
+            Packing::Grid => "grid",
+            Packing::Skyline => "skyline",
+            Packing::MaxRects => "maxrects",
+            Packing::Shelf => "shelf",
+        }
+    }
+}
+
+/// The channels each pixel of the atlas image holds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AtlasChannels {
+    /// Red, green, blue, and alpha, with the coverage of single channel glyphs replicated in all four.
+    Rgba,
+    /// A single alpha channel holding the coverage, a quarter the size of an RGBA image.
+    A8,
+}
+
+impl AtlasChannels {
+    /// The name of the channel layout.
+    pub fn name(self) -> &'static str {
+        match self {
+            AtlasChannels::Rgba => "rgba",
+            AtlasChannels::A8 => "a8",
+        }
+    }
+
+    /// The number of bytes each pixel takes up.
+    pub fn count(self) -> usize {
+        match self {
+            AtlasChannels::Rgba => 4,
+            AtlasChannels::A8 => 1,
+        }
+    }
+}
+
+/// The filter glyph images get resampled with when they get scaled, like the bitmap strikes of
+/// color emoji typefaces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResampleFilter {
+    /// The average of the pixels under each new pixel, which keeps the coverage of thin strokes
+    /// but blurs detail.
+    Box,
+    /// The nearest pixel, which keeps pixel art crisp.
+    Nearest,
+    /// Linear interpolation between the nearest pixels.
+    Triangle,
+    /// The Catmull-Rom cubic spline, which is sharper than linear interpolation.
+    CatmullRom,
+    /// The Lanczos windowed sinc with three lobes, the sharpest of the filters, which can ring
+    /// around hard edges.
+    Lanczos3,
+}
+
+impl ResampleFilter {
+    /// The name of the filter.
+    pub fn name(self) -> &'static str {
+        match self {
+            ResampleFilter::Box => "box",
+            ResampleFilter::Nearest => "nearest",
+            ResampleFilter::Triangle => "triangle",
+            ResampleFilter::CatmullRom => "catmull-rom",
+            ResampleFilter::Lanczos3 => "lanczos3",
+        }
+    }
+}
+
+/// A drop shadow composited underneath each glyph, for engines that cannot draw text effects in
+/// shaders.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Shadow {
+    /// How far right of the glyph the shadow falls, in pixels.
+    pub dx: i32,
+    /// How far below the glyph the shadow falls, in pixels.
+    pub dy: i32,
+    /// The radius of the blur softening the shadow, in pixels, or zero for a hard shadow.
+    pub blur: usize,
+    /// The opacity of the shadow, from zero to one.
+    pub alpha: f32,
+}
+
+impl Shadow {
+    /// How many pixels the shadow can grow a glyph by, along either axis.
+    pub fn extent(&self) -> usize {
+        self.dx.abs().max(self.dy.abs()) as usize + 2 * self.blur
+    }
+}
+
+impl fmt::Display for Shadow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.dx, self.dy, self.blur, self.alpha)
+    }
+}
+
+/// A glow around each glyph, rendered into the padding of its slot, for sci-fi style UI text
+/// without runtime shaders.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Glow {
+    /// How far the glow reaches past the glyph, in pixels.
+    pub radius: usize,
+    /// The brightness of the glow, as a multiple of the blurred coverage of the glyph.
+    pub intensity: f32,
+}
+
+impl fmt::Display for Glow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.radius, self.intensity)
+    }
+}
+
+/// One of the effects of a glyph, written to an atlas of its own with the same layout as the
+/// atlas of the composited glyphs, so runtimes can color and composite the effects themselves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EffectLayer {
+    /// The glyph itself.
+    Fill,
+    /// The glyph along with its stroked border.
+    Outline,
+    /// The drop shadow of the glyph, along with its border.
+    Shadow,
+}
+
+impl EffectLayer {
+    /// The name of the layer, as given to `--layers`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EffectLayer::Fill => "fill",
+            EffectLayer::Outline => "outline",
+            EffectLayer::Shadow => "shadow",
+        }
+    }
+}
+
+/// The atlas specification is a description of the dimensions of the atlas
+/// and the dimensions of each glyph in the atlas. This comes in as input at
+/// runtime.
+#[derive(Copy, Clone)]
+pub struct AtlasSpec {
+    /// The origin and coordinate chart for the atlas image.
+    pub origin: bmfa::Origin,
+    /// The width of the atlas in pixels.
+    pub width: usize,
+    /// The height of the atls in pixels.
+    pub height: usize,
+    /// The number of glyphs per column in the atlas.
+    pub rows: usize,
+    /// The number of glyphs per row in the atlas.
+    pub columns: usize,
+    /// The amount of padding available for outlines in the glyph, in pixels.
+    pub padding: usize,
+    /// The maximum size of a glyph slot in pixels.
+    pub slot_glyph_size: usize,
+    /// The size of a glyph inside the slot, leaving room for padding for outlines.
+    pub glyph_size: usize,
+    /// The horizontal scale factor applied to each glyph before rendering.
+    pub stretch: f32,
+    /// The size of synthetic small capitals relative to the glyph size, if lowercase letters
+    /// should be drawn as scaled down capitals.
+    pub small_caps_scale: Option<f32>,
+    /// Whether to draw a last resort glyph for each code point the typeface does not cover.
+    pub last_resort: bool,
+    /// Whether to draw the printable ASCII characters the typeface does not cover from the
+    /// built-in fallback font, ahead of last resort glyphs. This is `false` after construction.
+    pub builtin_fallback: bool,
+    /// How to draw hard-edged glyphs, if the glyphs should not be antialiased. This is `None`
+    /// after construction.
+    pub mono: Option<Monochrome>,
+    /// The horizontal shear applied to each glyph outline for a synthetic italic, as the shift to
+    /// the right per pixel above the baseline, e.g. `0.2` for a slant of about 11 degrees. The
+    /// glyph images widen with the slant, while the advances stay as they are. This is `0.0` after
+    /// construction.
+    pub oblique: f32,
+    /// How far to thicken each glyph outline, in pixels, for a heavier variant of typefaces that
+    /// ship without a bold face. The outlines get rendered smaller by the strength to still fit
+    /// the slot, and bitmap glyphs stay as they are. This is `0.0` after construction.
+    pub embolden: f32,
+    /// The width of the border stroked around each glyph outline, in pixels, e.g. for game HUD
+    /// text that needs to stand out against any background. The outlines get rendered smaller by
+    /// the stroke on every side to still fit the slot. This is `0` after construction.
+    pub outline: usize,
+    /// The drop shadow to composite underneath each glyph, if any. The outlines get rendered
+    /// smaller by the extent of the shadow to still fit the slot. This is `None` after construction.
+    pub shadow: Option<Shadow>,
+    /// The glow to render around each glyph, if any. Unlike the other effects, the glow gets
+    /// rendered into the padding around the glyph, so it takes up to twice its radius of the
+    /// padding instead of shrinking the glyphs. This is `None` after construction.
+    pub glow: Option<Glow>,
+    /// The classes of code points that get advance-only entries instead of glyph images.
+    pub zero_width: ZeroWidthClasses,
+    /// The kind of distance field to store in place of the coverage values of each glyph, if any.
+    pub distance_field: Option<DistanceField>,
+    /// How the glyph images get arranged in the atlas image.
+    pub packing: Packing,
+    /// The gamma curve applied to the coverage values of the glyphs before packing. Values above
+    /// one make antialiased edges heavier, and values below one lighter. Distance fields and
+    /// color glyphs are left as they are. This is `1.0` after construction.
+    pub gamma: f32,
+    /// Whether the glyphs get sampled by their glyph index in the typeface instead of by code
+    /// point, e.g. for inspecting a typeface or for icon fonts with glyphs no code point maps to.
+    /// The glyph tables and metadata of such atlases are keyed by glyph index, and the space gets
+    /// no special treatment. This is `false` after construction.
+    pub glyph_indices: bool,
+    /// The channels each pixel of the atlas image holds. Color glyphs need all four, so they
+    /// get drawn like the other glyphs with `A8`. This is `AtlasChannels::Rgba` after construction.
+    pub channels: AtlasChannels,
+    /// The point size and resolution to set the typeface to, if the glyph size was given in
+    /// points. The typeface then gets scaled by the exact, possibly fractional, pixel size
+    /// instead of the glyph size rounded to whole pixels. This is `None` after construction.
+    pub point_size: Option<PointSize>,
+    /// The filter glyph images get resampled with when they get scaled. This is
+    /// `ResampleFilter::Box` after construction.
+    pub resample_filter: ResampleFilter,
+}
+
+impl AtlasSpec {
+    /// Construct an atlas specification.
+    pub fn new(
+        origin: bmfa::Origin,
+        width: usize, height: usize, rows: usize, columns: usize,
+        padding: usize, slot_glyph_size: usize, glyph_size: usize,
+        stretch: f32, small_caps_scale: Option<f32>, last_resort: bool,
+        zero_width: ZeroWidthClasses, distance_field: Option<DistanceField>,
+        packing: Packing) -> AtlasSpec {
+
+        AtlasSpec {
+            origin: origin,
+            width: width,
+            height: height,
+            rows: rows,
+            columns: columns,
+            padding: padding,
+            slot_glyph_size: slot_glyph_size,
+            glyph_size: glyph_size,
+            stretch: stretch,
+            small_caps_scale: small_caps_scale,
+            last_resort: last_resort,
+            builtin_fallback: false,
+            mono: None,
+            oblique: 0.0,
+            embolden: 0.0,
+            outline: 0,
+            shadow: None,
+            glow: None,
+            zero_width: zero_width,
+            distance_field: distance_field,
+            packing: packing,
+            gamma: 1.0,
+            glyph_indices: false,
+            channels: AtlasChannels::Rgba,
+            point_size: None,
+            resample_filter: ResampleFilter::Box,
+        }
+    }
+
+    /// Construct an atlas specification for rendering single glyphs `glyph_size` pixels in size with
+    /// `rasterize_glyph`, which ignores the size, grid, padding, and packing of an atlas. The
+    /// rendering configuration, like the effects or the distance field, can be set on it afterwards.
+    pub fn for_glyph(glyph_size: usize) -> AtlasSpec {
+        AtlasSpec::new(
+            bmfa::Origin::TopLeft, 0, 0, 0, 0, 0, glyph_size, glyph_size,
+            1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+        )
+    }
+
+    /// The number of pixels the effects add to the size of each glyph. A signed distance field
+    /// grows each glyph by the spread on every side, a stroked border by its width, a drop shadow
+    /// by its extent, and emboldening by its strength.
+    pub fn effects_size(&self) -> usize {
+        let spread = self.distance_field.map_or(0, |distance_field| distance_field.spread());
+        let shadow = self.shadow.map_or(0, |shadow| shadow.extent());
+        2 * (spread + self.outline) + shadow + self.embolden.ceil() as usize
+    }
+
+    /// The pixel size to rasterize glyphs at, which is the glyph size less the size of the effects,
+    /// so the rendered glyphs still fit the slot. This is zero when the effects leave no room for
+    /// the glyph, and sampling a typeface at it fails.
+    pub fn render_size(&self) -> usize {
+        self.glyph_size.saturating_sub(self.effects_size())
+    }
+
+    /// The padding left around each glyph image once the glow has been rendered into it. Glyphs
+    /// sit half of this away from the corner of their cell.
+    pub fn free_padding(&self) -> usize {
+        self.padding.saturating_sub(2 * self.glow.map_or(0, |glow| glow.radius))
+    }
+
+    /// Scale the slots, padding, and distance field spread of the atlas specification, e.g. for
+    /// a companion atlas of smaller annotation text. A grid keeps its rows and columns. This
+    /// returns `None` when nothing of the glyphs would be left to render at the scaled size.
+    pub fn scaled(&self, scale: f32) -> Option<AtlasSpec> {
+        let scale_px = |size: usize| (size as f32 * scale).round() as usize;
+        let slot_glyph_size = scale_px(self.slot_glyph_size);
+        let padding = scale_px(self.padding);
+        let distance_field = self.distance_field.map(|distance_field| distance_field.scaled(scale));
+        let spread = distance_field.map_or(0, |distance_field| distance_field.spread());
+        let outline = scale_px(self.outline);
+        let embolden = self.embolden * scale;
+        let shadow = self.shadow.map(|shadow| Shadow {
+            dx: (shadow.dx as f32 * scale).round() as i32,
+            dy: (shadow.dy as f32 * scale).round() as i32,
+            blur: scale_px(shadow.blur),
+            alpha: shadow.alpha,
+        });
+        let shadow_extent = shadow.map_or(0, |shadow| shadow.extent());
+        if slot_glyph_size <= padding + 2 * (spread + outline) + shadow_extent + embolden.ceil() as usize {
+            return None;
+        }
+
+        let mut scaled = *self;
+        scaled.slot_glyph_size = slot_glyph_size;
+        scaled.padding = padding;
+        scaled.glyph_size = slot_glyph_size - padding;
+        scaled.distance_field = distance_field;
+        scaled.embolden = embolden;
+        scaled.outline = outline;
+        scaled.shadow = shadow;
+        scaled.glow = self.glow.map(|glow| Glow { radius: scale_px(glow.radius), intensity: glow.intensity });
+        scaled.point_size = self.point_size.map(|point_size| {
+            PointSize { points: point_size.points * scale, dpi: point_size.dpi }
+        });
+        if self.packing == Packing::Grid {
+            scaled.width = self.columns * slot_glyph_size;
+            scaled.height = self.rows * slot_glyph_size;
+        } else {
+            scaled.width = scale_px(self.width);
+            scaled.height = scale_px(self.height);
+        }
+
+        Some(scaled)
+    }
+}
+
+/// A `GlyphImage` is a bitmapped representation of a single font glyph.
+#[derive(Clone)]
+pub struct GlyphImage {
+    /// The coverage values of the glyph, one byte per pixel in row-major order.
+    pub data: Vec<u8>,
+}
+
+impl GlyphImage {
+    /// Construct a glyph image from its row-major coverage values.
+    pub fn new(data: Vec<u8>) -> GlyphImage {
+        GlyphImage {
+            data: data,
+        }
+    }
+}
+
+/// A `GlyphTable` is an intermediate date structure storing all the typeface parameters
+/// for each glyph to be used in the construction of the final bitmap atlas.
+pub struct GlyphTable {
+    /// The height of each glyph in pixels, by code point.
+    pub rows: HashMap<usize, i32>,
+    /// The width of a row in each glyph in pixels, by code point.
+    pub width: HashMap<usize, i32>,
+    /// The number of bytes per row in each glyph, by code point.
+    pub pitch: HashMap<usize, i32>,
+    /// The offset in pixels of each character from the baseline, by code point.
+    pub y_min: HashMap<usize, i64>,
+    /// The horizontal offset in pixels from the pen position to the left edge of each glyph
+    /// image, by code point.
+    pub x_bearing: HashMap<usize, i32>,
+    /// The distance in pixels the pen advances after drawing each character, by code point.
+    /// This includes the space character.
+    pub advance: HashMap<usize, i32>,
+    /// The distance in pixels from the baseline to the top of a line of text.
+    pub ascender: i32,
+    /// The distance in pixels between the baselines of consecutive lines of text.
+    pub line_height: i32,
+    /// The family name of the typeface.
+    pub family: String,
+    /// A table holding the individual bitmap images for each glyph.
+    pub buffer: HashMap<usize, GlyphImage>,
+    /// The code points whose glyphs rasterize to fully transparent bitmaps. These get
+    /// metadata entries but no space in the atlas image.
+    pub empty: Vec<usize>,
+    /// The code points in the zero width classes selected for the atlas. These are never
+    /// rasterized, and get metadata entries without an image.
+    pub zero_width: Vec<usize>,
+    /// How long each glyph took to rasterize, in sampling order.
+    pub telemetry: Vec<GlyphTelemetry>,
+    /// The number of bytes per pixel in each glyph image: one for coverage values and single
+    /// channel distance fields, three for the red, green, and blue channels of a multi-channel
+    /// distance field, and four for the premultiplied red, green, blue, and alpha channels of
+    /// the glyphs of a color typeface.
+    pub channels: usize,
+    /// The kerning pairs between the characters the typeface covers, sorted by code point.
+    pub kerning: Vec<KerningPair>,
+    /// The render mode of each glyph image, `coverage`, `sdf`, or `msdf`, by code point, when
+    /// the glyphs were sampled with per-character render modes.
+    pub render_modes: BTreeMap<usize, &'static str>,
+    /// The family name of the typeface each glyph was drawn from, by code point, for the glyphs
+    /// drawn from a fallback or substitute typeface instead of the primary one.
+    pub glyph_typefaces: BTreeMap<usize, String>,
+    /// The code points whose glyphs were drawn in color from the color glyph tables of the
+    /// typeface, like emoji, sorted by code point. Their images should not get tinted.
+    pub color_glyphs: Vec<usize>,
+}
+
+/// An adjustment to the advance of a character when it is followed by another character.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KerningPair {
+    /// The code point of the first character of the pair.
+    pub first: usize,
+    /// The code point of the second character of the pair.
+    pub second: usize,
+    /// The adjustment to the advance of the first character, in pixels.
+    pub amount: i32,
+}
+
+/// The time it took to produce the image of a single glyph, and the fallback used to
+/// produce it, if any.
+#[derive(Copy, Clone, Debug)]
+pub struct GlyphTelemetry {
+    /// The code point of the glyph.
+    pub code_point: usize,
+    /// The time it took to produce the glyph image.
+    pub elapsed: Duration,
+    /// A description of the fallback used in place of the typeface's own glyph, if any.
+    pub fallback: Option<&'static str>,
+}
+
+impl GlyphTable {
+    /// Assign the glyph slots in the atlas, in code point order. Slot zero holds the space
+    /// character, and only glyphs with a visible image take up a slot after that.
+    pub fn slots(&self) -> Vec<usize> {
+        let mut code_points: Vec<usize> = self.buffer.keys().cloned().collect();
+        code_points.sort();
+
+        let mut slots = vec![32];
+        slots.extend(code_points);
+
+        slots
+    }
+
+    /// Copy the glyph table for one page of a multi-page atlas, keeping only the images of
+    /// the given code points. The metrics of every character are kept.
+    pub fn page(&self, code_points: &[usize]) -> GlyphTable {
+        let buffer = code_points.iter()
+            .filter_map(|i| self.buffer.get(i).map(|image| (*i, image.clone())))
+            .collect();
+
+        GlyphTable {
+            rows: self.rows.clone(),
+            width: self.width.clone(),
+            pitch: self.pitch.clone(),
+            y_min: self.y_min.clone(),
+            x_bearing: self.x_bearing.clone(),
+            advance: self.advance.clone(),
+            ascender: self.ascender,
+            line_height: self.line_height,
+            family: self.family.clone(),
+            buffer: buffer,
+            empty: self.empty.clone(),
+            zero_width: self.zero_width.clone(),
+            telemetry: self.telemetry.clone(),
+            channels: self.channels,
+            kerning: self.kerning.clone(),
+            render_modes: self.render_modes.clone(),
+            glyph_typefaces: self.glyph_typefaces.clone(),
+            color_glyphs: self.color_glyphs.clone(),
+        }
+    }
+
+    /// Copy the glyph table with the coverage of the glyphs of another glyph table of the same
+    /// characters in place of its own images, e.g. the glyphs without the effects composited into
+    /// the images of this one. Each image of the other table gets placed where its bearings put it
+    /// in the image of this one, so the images keep their sizes, and an atlas of the copy has the
+    /// same layout as an atlas of this table. The copy has one channel.
+    pub fn layer(&self, source: &GlyphTable) -> GlyphTable {
+        let buffer = self.buffer.keys()
+            .map(|&code_point| (code_point, GlyphImage::new(self.layer_coverage(source, code_point))))
+            .collect();
+
+        self.with_coverage(buffer)
+    }
+
+    /// Copy the glyph table with the drop shadows of the glyphs of another glyph table in place of
+    /// its own images, placed like the images of `layer` and offset, blurred, and faded like the
+    /// shadows of `sample_typeface`. The shadows have to fit the images of this table, which holds
+    /// when it was sampled with the same shadow.
+    pub fn shadow_layer(&self, source: &GlyphTable, shadow: Shadow) -> GlyphTable {
+        let buffer = self.buffer.keys()
+            .map(|&code_point| {
+                let width = self.width[&code_point] as i64;
+                let rows = self.rows[&code_point] as i64;
+                let coverage = self.layer_coverage(source, code_point);
+                let mut offset = vec![0.0; coverage.len()];
+                for row in 0..rows {
+                    for col in 0..width {
+                        let (shadow_row, shadow_col) = (row + shadow.dy as i64, col + shadow.dx as i64);
+                        if shadow_row >= 0 && shadow_row < rows && shadow_col >= 0 && shadow_col < width {
+                            offset[(shadow_row * width + shadow_col) as usize] =
+                                coverage[(row * width + col) as usize] as f32;
+                        }
+                    }
+                }
+                let blurred = blur_coverage(&offset, width as usize, rows as usize, shadow.blur);
+                let data = blurred.iter()
+                    .map(|&value| (shadow.alpha * value).round().min(255.0) as u8)
+                    .collect();
+
+                (code_point, GlyphImage::new(data))
+            })
+            .collect();
+
+        self.with_coverage(buffer)
+    }
+
+    /// The coverage of the glyph of another glyph table, placed in an image the size of the glyph
+    /// of this one, with one value per pixel.
+    fn layer_coverage(&self, source: &GlyphTable, code_point: usize) -> Vec<u8> {
+        let width = self.width[&code_point] as i64;
+        let rows = self.rows[&code_point] as i64;
+        let mut coverage = vec![0 as u8; (width * rows) as usize];
+        let image = match source.buffer.get(&code_point) {
+            Some(val) => val,
+            None => return coverage,
+        };
+        let channels = source.channels;
+        let source_width = source.width[&code_point] as i64;
+        let source_rows = source.rows[&code_point] as i64;
+        let pitch = source.pitch[&code_point] as usize;
+        // The images line up at their left edges and their tops, which sit the rows above their
+        // bottom edges.
+        let left = (source.x_bearing[&code_point] - self.x_bearing[&code_point]) as i64;
+        let top = (self.y_min[&code_point] + rows) - (source.y_min[&code_point] + source_rows);
+        for row in 0..source_rows {
+            for col in 0..source_width {
+                let (layer_row, layer_col) = (row + top, col + left);
+                if layer_row >= 0 && layer_row < rows && layer_col >= 0 && layer_col < width {
+                    let value = image.data[row as usize * pitch + channels * col as usize + channels - 1];
+                    coverage[(layer_row * width + layer_col) as usize] = value;
+                }
+            }
+        }
+
+        coverage
+    }
+
+    /// Copy the glyph table with other images of one channel, the same sizes as its own.
+    fn with_coverage(&self, buffer: HashMap<usize, GlyphImage>) -> GlyphTable {
+        GlyphTable {
+            rows: self.rows.clone(),
+            width: self.width.clone(),
+            pitch: self.width.clone(),
+            y_min: self.y_min.clone(),
+            x_bearing: self.x_bearing.clone(),
+            advance: self.advance.clone(),
+            ascender: self.ascender,
+            line_height: self.line_height,
+            family: self.family.clone(),
+            buffer: buffer,
+            empty: self.empty.clone(),
+            zero_width: self.zero_width.clone(),
+            telemetry: self.telemetry.clone(),
+            channels: 1,
+            kerning: self.kerning.clone(),
+            render_modes: self.render_modes.clone(),
+            glyph_typefaces: self.glyph_typefaces.clone(),
+            color_glyphs: vec![],
+        }
+    }
+
+    /// Move the glyphs of another glyph table into this one, keeping the line metrics of this
+    /// one. When the tables have different numbers of channels, the images with fewer channels
+    /// get expanded to the larger number, like in the atlas image.
+    fn merge(&mut self, other: GlyphTable) {
+        if self.channels < other.channels {
+            expand_channels(self, other.channels);
+        }
+        let mut other = other;
+        if other.channels < self.channels {
+            expand_channels(&mut other, self.channels);
+        }
+        self.rows.extend(other.rows);
+        self.width.extend(other.width);
+        self.pitch.extend(other.pitch);
+        self.y_min.extend(other.y_min);
+        self.x_bearing.extend(other.x_bearing);
+        self.advance.extend(other.advance);
+        self.buffer.extend(other.buffer);
+        self.empty.extend(other.empty);
+        self.empty.sort();
+        self.zero_width.extend(other.zero_width);
+        self.zero_width.sort();
+        self.telemetry.extend(other.telemetry);
+        self.kerning.extend(other.kerning);
+        self.kerning.sort_by_key(|pair| (pair.first, pair.second));
+        self.render_modes.extend(other.render_modes);
+        self.glyph_typefaces.extend(other.glyph_typefaces);
+        self.color_glyphs.extend(other.color_glyphs);
+        self.color_glyphs.sort();
+    }
+
+    /// Halve the advances of full width CJK punctuation, following the usual rules of Japanese
+    /// typesetting: opening brackets lose the empty left half of their em square, closing
+    /// brackets, commas, and periods lose the empty right half, and middle dots lose a quarter
+    /// on either side. Only punctuation at least nine tenths of an em of `em_size` pixels wide
+    /// gets compressed. This returns the compressed code points.
+    pub fn compress_cjk_punctuation(&mut self, em_size: usize) -> Vec<usize> {
+        let mut compressed = vec![];
+        let mut code_points: Vec<usize> = self.advance.keys().cloned().collect();
+        code_points.sort();
+        for code_point in code_points {
+            let advance = self.advance[&code_point];
+            if 10 * advance < 9 * em_size as i32 {
+                continue;
+            }
+            // How far the ink moves to the left once the empty space before it is gone.
+            let bearing_shift = if CJK_OPENING_PUNCTUATION.contains(&code_point) {
+                advance / 2
+            } else if CJK_MIDDLE_PUNCTUATION.contains(&code_point) {
+                advance / 4
+            } else if CJK_CLOSING_PUNCTUATION.contains(&code_point) {
+                0
+            } else {
+                continue;
+            };
+
+            self.advance.insert(code_point, advance - advance / 2);
+            if let Some(x_bearing) = self.x_bearing.get_mut(&code_point) {
+                *x_bearing -= bearing_shift;
+            }
+            compressed.push(code_point);
+        }
+
+        compressed
+    }
+
+    /// Apply the corrections of an advance override file to the glyphs of the table, scaling
+    /// their pixels by `scale`, the multiple of the glyph size the overrides were written for.
+    /// This returns the overrides of the characters the table has, as given.
+    pub fn apply_overrides(
+        &mut self, overrides: &BTreeMap<usize, GlyphOverride>, scale: f32) -> BTreeMap<usize, GlyphOverride> {
+
+        let scaled = |pixels: i32| (pixels as f32 * scale).round() as i32;
+        let mut applied = BTreeMap::new();
+        for (&code_point, glyph_override) in overrides.iter() {
+            if !self.advance.contains_key(&code_point) {
+                continue;
+            }
+            if let Some(advance) = glyph_override.advance {
+                self.advance.insert(code_point, scaled(advance));
+            }
+            if let Some(x_bearing) = self.x_bearing.get_mut(&code_point) {
+                *x_bearing += scaled(glyph_override.x_offset);
+            }
+            if let Some(y_min) = self.y_min.get_mut(&code_point) {
+                *y_min += scaled(glyph_override.y_offset) as i64;
+            }
+            applied.insert(code_point, *glyph_override);
+        }
+
+        applied
+    }
+}
+
+/// A correction to the metrics of a character the typeface gets wrong, like a space that is too
+/// wide, in pixels at the slot glyph size of the atlas.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GlyphOverride {
+    /// The advance replacing the one of the typeface, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advance: Option<i32>,
+    /// How far to move the glyph image to the right.
+    #[serde(default)]
+    pub x_offset: i32,
+    /// How far to move the glyph image up.
+    #[serde(default)]
+    pub y_offset: i32,
+}
+
+/// The CJK opening brackets, whose ink sits in the right half of the em square.
+const CJK_OPENING_PUNCTUATION: &[usize] = &[
+    0x2018, 0x201C, 0x3008, 0x300A, 0x300C, 0x300E, 0x3010, 0x3014, 0x3016, 0x3018, 0x301A,
+    0x301D, 0xFF08, 0xFF3B, 0xFF5B, 0xFF5F,
+];
+
+/// The CJK closing brackets, commas, and periods, whose ink sits in the left half of the em square.
+const CJK_CLOSING_PUNCTUATION: &[usize] = &[
+    0x2019, 0x201D, 0x3001, 0x3002, 0x3009, 0x300B, 0x300D, 0x300F, 0x3011, 0x3015, 0x3017,
+    0x3019, 0x301B, 0x301E, 0x301F, 0xFF09, 0xFF0C, 0xFF0E, 0xFF3D, 0xFF5D, 0xFF60,
+];
+
+/// The CJK middle dots, colons, and semicolons, whose ink sits in the middle of the em square.
+const CJK_MIDDLE_PUNCTUATION: &[usize] = &[0x30FB, 0xFF1A, 0xFF1B];
+
+/// Sample a single bitmap image for a single glyph from a font. The FreeType library interns
+/// each sampled glyph image one at a time internally. Each time the library samples a new glyph,
+/// the old glyph gets overwritten, so the data must be copied out before each subsequent
+/// sampling of a new glyph.
+fn create_glyph_image(glyph: &freetype::glyph_slot::GlyphSlot) -> GlyphImage {
+    let bitmap = glyph.bitmap();
+    let rows = bitmap.rows() as usize;
+    let pitch = bitmap.pitch() as usize;
+
+    let mut glyph_data = vec![0 as u8; rows * pitch];
+    glyph_data.clone_from_slice(bitmap.buffer());
+
+    GlyphImage::new(glyph_data)
+}
+
+/// Map each coverage value through a gamma curve, raising its fraction of full coverage to the
+/// power of one over the gamma. A gamma of one leaves the values as they are, so there is no table.
+fn gamma_table(gamma: f32) -> Option<[u8; 256]> {
+    if gamma == 1.0 {
+        return None;
+    }
+    let mut table = [0 as u8; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = (255.0 * (value as f32 / 255.0).powf(1.0 / gamma)).round() as u8;
+    }
+
+    Some(table)
+}
+
+/// Create a glyph image from the one bit per pixel bitmap of a glyph slot rendered by the
+/// monochrome rasterizer, unpacking each bit into a coverage value of either 0 or 255. The
+/// image has a pitch of one byte per pixel.
+fn create_mono_glyph_image(glyph: &freetype::glyph_slot::GlyphSlot) -> GlyphImage {
+    let bitmap = glyph.bitmap();
+    let rows = bitmap.rows() as usize;
+    let width = bitmap.width() as usize;
+    let pitch = bitmap.pitch().abs() as usize;
+    let buffer = bitmap.buffer();
+
+    let mut glyph_data = Vec::with_capacity(width * rows);
+    for row in 0..rows {
+        for col in 0..width {
+            let bit = buffer[row * pitch + col / 8] & (0x80 >> (col % 8));
+            glyph_data.push(if bit != 0 { 255 } else { 0 });
+        }
+    }
+
+    GlyphImage::new(glyph_data)
+}
+
+/// Create a premultiplied RGBA glyph image from a glyph slot of a color typeface. Color glyphs
+/// come as premultiplied BGRA bitmaps, while the coverage values of the other glyphs get
+/// copied into all four channels, which makes them white.
+fn create_color_glyph_image(glyph: &freetype::glyph_slot::GlyphSlot) -> GlyphImage {
+    let bitmap = glyph.bitmap();
+    let rows = bitmap.rows() as usize;
+    let width = bitmap.width() as usize;
+    let pitch = bitmap.pitch().abs() as usize;
+    let buffer = bitmap.buffer();
+    let bgra = match bitmap.pixel_mode() {
+        Ok(freetype::bitmap::PixelMode::Bgra) => true,
+        _ => false,
+    };
+
+    let mut glyph_data = Vec::with_capacity(4 * width * rows);
+    for row in 0..rows {
+        for col in 0..width {
+            if bgra {
+                let pixel = &buffer[(row * pitch + 4 * col)..(row * pitch + 4 * col + 4)];
+                glyph_data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            } else {
+                let value = buffer[row * pitch + col];
+                glyph_data.extend_from_slice(&[value; 4]);
+            }
+        }
+    }
+
+    GlyphImage::new(glyph_data)
+}
+
+/// Create a stroker that draws borders of the given width, in pixels, with round joins and caps.
+fn create_stroker(face: &freetype::face::Face, width: usize) -> Result<freetype::Stroker, freetype::error::Error> {
+    let library = face.glyph().raw().library;
+    let mut stroker = std::ptr::null_mut();
+    let error = unsafe { freetype::ffi::FT_Stroker_New(library, &mut stroker) };
+    if error != 0 {
+        return Err(freetype::error::Error::from(error));
+    }
+    let stroker = unsafe { freetype::Stroker::from_raw(library, stroker) };
+    stroker.set(
+        (64 * width) as freetype::ffi::FT_Fixed,
+        freetype::StrokerLineCap::Round, freetype::StrokerLineJoin::Round, 0
+    );
+
+    Ok(stroker)
+}
+
+/// A glyph image along with its width, rows, and the offsets of its left and top edges from
+/// the pen position, in pixels.
+type PlacedGlyphImage = (i32, i32, i32, i32, GlyphImage);
+
+/// Draw the glyph outline in a glyph slot grown by the border of a stroker, i.e. the glyph along
+/// with its border. Glyphs without an outline, like those of bitmap strikes, have no border.
+fn create_stroked_glyph_image(
+    glyph: &freetype::glyph_slot::GlyphSlot,
+    stroker: &freetype::Stroker) -> Result<Option<PlacedGlyphImage>, freetype::error::Error> {
+
+    let outline = glyph.get_glyph()?;
+    if outline.format() != freetype::ffi::FT_GLYPH_FORMAT_OUTLINE {
+        return Ok(None);
+    }
+    let stroked = outline.stroke_border(stroker, false)?;
+    let stroked = stroked.to_bitmap(freetype::render_mode::RenderMode::Normal, None)?;
+    let bitmap = stroked.bitmap();
+    let rows = bitmap.rows() as usize;
+    let width = bitmap.width() as usize;
+    let pitch = bitmap.pitch().abs() as usize;
+    let buffer = bitmap.buffer();
+
+    let mut glyph_data = Vec::with_capacity(width * rows);
+    for row in 0..rows {
+        glyph_data.extend_from_slice(&buffer[(row * pitch)..(row * pitch + width)]);
+    }
+
+    Ok(Some((width as i32, rows as i32, stroked.left(), stroked.top(), GlyphImage::new(glyph_data))))
+}
+
+/// Lay a glyph image over the image of the same glyph grown by a stroked border, with the
+/// coverage of the glyph `fill_offset` pixels right of and below the top left corner of the
+/// border. With four channels, the glyph gets drawn white over a black border, so the color
+/// channels hold the glyph and the alpha channel the glyph along with its border. With one
+/// channel, the coverage is the glyph along with its border.
+fn create_outlined_glyph_image(
+    fill: &GlyphImage, fill_width: usize, fill_rows: usize, fill_pitch: usize, fill_offset: (i32, i32),
+    border: &GlyphImage, width: usize, rows: usize, channels: usize) -> GlyphImage {
+
+    let mut glyph_data = vec![0 as u8; channels * width * rows];
+    for row in 0..rows {
+        for col in 0..width {
+            let fill_row = row as i32 - fill_offset.1;
+            let fill_col = col as i32 - fill_offset.0;
+            let inside = fill_row >= 0 && (fill_row as usize) < fill_rows &&
+                fill_col >= 0 && (fill_col as usize) < fill_width;
+            let coverage = if inside {
+                fill.data[fill_row as usize * fill_pitch + fill_col as usize]
+            } else {
+                0
+            };
+            let alpha = coverage.max(border.data[row * width + col]);
+            let pixel = &mut glyph_data[(channels * (row * width + col))..(channels * (row * width + col + 1))];
+            match channels {
+                4 => pixel.copy_from_slice(&[coverage, coverage, coverage, alpha]),
+                _ => pixel[0] = alpha,
+            }
+        }
+    }
+
+    GlyphImage::new(glyph_data)
+}
+
+/// The size of a glyph image grown by a drop shadow, along with how many pixels it grew by to the
+/// left of and below the glyph.
+struct ShadowBounds {
+    width: usize,
+    rows: usize,
+    left: usize,
+    below: usize,
+}
+
+/// Blur the coverage values of an image with a gaussian kernel of the given radius, as a
+/// horizontal pass followed by a vertical pass.
+fn blur_coverage(data: &[f32], width: usize, rows: usize, radius: usize) -> Vec<f32> {
+    if radius == 0 {
+        return data.to_vec();
+    }
+    let sigma = radius as f32 / 2.0;
+    let kernel: Vec<f32> = (0..(2 * radius + 1))
+        .map(|i| {
+            let x = i as f32 - radius as f32;
+            (-x * x / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|weight| weight / total).collect();
+
+    let blur_pass = |source: &[f32], step: (usize, usize)| -> Vec<f32> {
+        let mut blurred = vec![0.0; source.len()];
+        for row in 0..rows {
+            for col in 0..width {
+                let mut sum = 0.0;
+                for (i, weight) in kernel.iter().enumerate() {
+                    let offset = i as i64 - radius as i64;
+                    let sample_col = col as i64 + offset * step.0 as i64;
+                    let sample_row = row as i64 + offset * step.1 as i64;
+                    let inside = sample_col >= 0 && sample_col < width as i64 &&
+                        sample_row >= 0 && sample_row < rows as i64;
+                    if inside {
+                        sum += weight * source[sample_row as usize * width + sample_col as usize];
+                    }
+                }
+                blurred[row * width + col] = sum;
+            }
+        }
+        blurred
+    };
+
+    let horizontal = blur_pass(data, (1, 0));
+    blur_pass(&horizontal, (0, 1))
+}
+
+/// Composite a drop shadow underneath a glyph image with `channels` values per pixel. The shadow
+/// is the coverage of the glyph offset, blurred, and faded by the opacity of the shadow. With four
+/// channels the shadow is black, so it only adds to the alpha channel of the premultiplied glyph.
+/// With one channel, the coverage is the glyph along with its shadow.
+fn create_shadowed_glyph_image(
+    image: &GlyphImage, width: usize, rows: usize, pitch: usize, channels: usize,
+    shadow: Shadow) -> (GlyphImage, ShadowBounds) {
+
+    let blur = shadow.blur as i64;
+    let (dx, dy) = (shadow.dx as i64, shadow.dy as i64);
+    let left = (blur - dx).max(0);
+    let top = (blur - dy).max(0);
+    let right = (dx + blur).max(0);
+    let below = (dy + blur).max(0);
+    let new_width = width + (left + right) as usize;
+    let new_rows = rows + (top + below) as usize;
+
+    // The coverage of the glyph, placed where the shadow falls in the grown image.
+    let mut coverage = vec![0.0; new_width * new_rows];
+    for row in 0..rows {
+        for col in 0..width {
+            let value = image.data[row * pitch + channels * col + channels - 1];
+            let shadow_row = (row as i64 + top + dy) as usize;
+            let shadow_col = (col as i64 + left + dx) as usize;
+            coverage[shadow_row * new_width + shadow_col] = value as f32;
+        }
+    }
+    let coverage = blur_coverage(&coverage, new_width, new_rows, shadow.blur);
+
+    let mut glyph_data = vec![0 as u8; channels * new_width * new_rows];
+    for row in 0..new_rows {
+        for col in 0..new_width {
+            let shadow_alpha = shadow.alpha * coverage[row * new_width + col];
+            let pixel_start = channels * (row * new_width + col);
+            let pixel = &mut glyph_data[pixel_start..(pixel_start + channels)];
+            let glyph_row = row as i64 - top;
+            let glyph_col = col as i64 - left;
+            let inside = glyph_row >= 0 && (glyph_row as usize) < rows &&
+                glyph_col >= 0 && (glyph_col as usize) < width;
+            if inside {
+                let glyph_start = glyph_row as usize * pitch + channels * glyph_col as usize;
+                pixel.copy_from_slice(&image.data[glyph_start..(glyph_start + channels)]);
+            }
+            let glyph_alpha = pixel[channels - 1] as f32;
+            let alpha = glyph_alpha + shadow_alpha * (255.0 - glyph_alpha) / 255.0;
+            pixel[channels - 1] = alpha.round().min(255.0) as u8;
+        }
+    }
+
+    let bounds = ShadowBounds {
+        width: new_width,
+        rows: new_rows,
+        left: left as usize,
+        below: below as usize,
+    };
+
+    (GlyphImage::new(glyph_data), bounds)
+}
+
+/// Render a glow around a glyph image with `channels` values per pixel, growing it by the radius
+/// of the glow on every side. The glow is the coverage of the glyph blurred over the radius and
+/// brightened by the intensity, drawn white underneath the glyph. Since a premultiplied white
+/// pixel has the same value in every channel, each channel gets composited the same way.
+fn create_glowing_glyph_image(
+    image: &GlyphImage, width: usize, rows: usize, pitch: usize, channels: usize, glow: Glow) -> GlyphImage {
+
+    let radius = glow.radius;
+    let new_width = width + 2 * radius;
+    let new_rows = rows + 2 * radius;
+
+    let mut coverage = vec![0.0; new_width * new_rows];
+    for row in 0..rows {
+        for col in 0..width {
+            let value = image.data[row * pitch + channels * col + channels - 1];
+            coverage[(row + radius) * new_width + col + radius] = value as f32;
+        }
+    }
+    let coverage = blur_coverage(&coverage, new_width, new_rows, radius);
+
+    let mut glyph_data = vec![0 as u8; channels * new_width * new_rows];
+    for row in 0..new_rows {
+        for col in 0..new_width {
+            let glow_value = (glow.intensity * coverage[row * new_width + col]).min(255.0);
+            let pixel_start = channels * (row * new_width + col);
+            let pixel = &mut glyph_data[pixel_start..(pixel_start + channels)];
+            let inside = row >= radius && row < rows + radius && col >= radius && col < width + radius;
+            if inside {
+                let glyph_start = (row - radius) * pitch + channels * (col - radius);
+                pixel.copy_from_slice(&image.data[glyph_start..(glyph_start + channels)]);
+            }
+            let glyph_alpha = pixel[channels - 1] as f32;
+            for value in pixel.iter_mut() {
+                let composited = *value as f32 + glow_value * (255.0 - glyph_alpha) / 255.0;
+                *value = composited.round().min(255.0) as u8;
+            }
+        }
+    }
+
+    GlyphImage::new(glyph_data)
+}
+
+/// Scale a glyph image with `channels` values per pixel to a new size with a resampling filter.
+fn resample_glyph_image(
+    image: &GlyphImage, width: usize, rows: usize, channels: usize,
+    new_width: usize, new_rows: usize, filter: ResampleFilter) -> GlyphImage {
+
+    if width == 0 || rows == 0 {
+        return GlyphImage::new(vec![0; new_width * new_rows * channels]);
+    }
+    let filter_type = match filter {
+        ResampleFilter::Box => {
+            return box_resample_glyph_image(image, width, rows, channels, new_width, new_rows);
+        }
+        ResampleFilter::Nearest => image::FilterType::Nearest,
+        ResampleFilter::Triangle => image::FilterType::Triangle,
+        ResampleFilter::CatmullRom => image::FilterType::CatmullRom,
+        ResampleFilter::Lanczos3 => image::FilterType::Lanczos3,
+    };
+    let (width, rows, new_width, new_rows) = (width as u32, rows as u32, new_width as u32, new_rows as u32);
+    let data = image.data.clone();
+    let resized = match channels {
+        1 => image::ImageBuffer::<image::Luma<u8>, _>::from_raw(width, rows, data)
+            .map(|buffer| image::imageops::resize(&buffer, new_width, new_rows, filter_type).into_raw()),
+        3 => image::ImageBuffer::<image::Rgb<u8>, _>::from_raw(width, rows, data)
+            .map(|buffer| image::imageops::resize(&buffer, new_width, new_rows, filter_type).into_raw()),
+        _ => image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(width, rows, data)
+            .map(|buffer| image::imageops::resize(&buffer, new_width, new_rows, filter_type).into_raw()),
+    };
+
+    GlyphImage::new(resized.unwrap_or_else(|| vec![0; (new_width * new_rows) as usize * channels]))
+}
+
+/// Scale a glyph image with `channels` values per pixel to a new size, averaging the pixels
+/// under each new pixel when shrinking the image.
+fn box_resample_glyph_image(
+    image: &GlyphImage, width: usize, rows: usize, channels: usize,
+    new_width: usize, new_rows: usize) -> GlyphImage {
+
+    let mut data = vec![0 as u8; new_width * new_rows * channels];
+    let x_ratio = width as f32 / new_width as f32;
+    let y_ratio = rows as f32 / new_rows as f32;
+    for y in 0..new_rows {
+        let y_start = (y as f32 * y_ratio) as usize;
+        let y_end = (((y + 1) as f32 * y_ratio).ceil() as usize).min(rows).max(y_start + 1);
+        for x in 0..new_width {
+            let x_start = (x as f32 * x_ratio) as usize;
+            let x_end = (((x + 1) as f32 * x_ratio).ceil() as usize).min(width).max(x_start + 1);
+            let count = ((y_end - y_start) * (x_end - x_start)) as u32;
+            for channel in 0..channels {
+                let mut sum = 0;
+                for source_y in y_start..y_end {
+                    for source_x in x_start..x_end {
+                        sum += image.data[(source_y * width + source_x) * channels + channel] as u32;
+                    }
+                }
+                data[(y * new_width + x) * channels + channel] = ((sum + count / 2) / count) as u8;
+            }
+        }
+    }
+
+    GlyphImage::new(data)
+}
+
+
+/// Compute the squared distance from each cell to the nearest feature cell along a single row
+/// or column, where `f` holds zero for feature cells and a huge value elsewhere. This is the
+/// lower envelope of parabolas algorithm of Felzenszwalb and Huttenlocher.
+fn distance_transform_1d(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    let mut d = vec![0.0; n];
+    let mut v = vec![0 as usize; n];
+    let mut z = vec![0.0; n + 1];
+    let mut k = 0;
+    z[0] = std::f64::NEG_INFINITY;
+    z[1] = std::f64::INFINITY;
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2.0 * (q as f64 - p as f64))
+    };
+    for q in 1..n {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = std::f64::INFINITY;
+    }
+
+    k = 0;
+    for q in 0..n {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as f64 - v[k] as f64;
+        d[q] = offset * offset + f[v[k]];
+    }
+
+    d
+}
+
+/// Compute the Euclidean distance from each pixel of a grid to the nearest pixel marked
+/// as a feature.
+fn distance_transform(features: &[bool], width: usize, rows: usize) -> Vec<f64> {
+    let mut grid: Vec<f64> = features.iter()
+        .map(|&feature| if feature { 0.0 } else { 1e20 })
+        .collect();
+    for x in 0..width {
+        let column: Vec<f64> = (0..rows).map(|y| grid[y * width + x]).collect();
+        for (y, distance) in distance_transform_1d(&column).into_iter().enumerate() {
+            grid[y * width + x] = distance;
+        }
+    }
+    for y in 0..rows {
+        let row = distance_transform_1d(&grid[(y * width)..((y + 1) * width)]);
+        grid[(y * width)..((y + 1) * width)].copy_from_slice(&row);
+    }
+
+    grid.iter().map(|distance| distance.sqrt()).collect()
+}
+
+/// Convert the coverage values of a glyph into a signed distance field. The field grows the
+/// glyph by the spread on every side. Each pixel stores the distance to the outline, mapped so
+/// that the outline sits at 128, values above it lie inside the glyph, and the spread in either
+/// direction reaches 255 or 0.
+fn create_signed_distance_field(
+    glyph_image: &GlyphImage,
+    width: usize, rows: usize, pitch: usize, spread: usize) -> GlyphImage {
+
+    let sdf_width = width + 2 * spread;
+    let sdf_rows = rows + 2 * spread;
+    let mut inside = vec![false; sdf_width * sdf_rows];
+    for y in 0..rows {
+        for x in 0..width {
+            inside[(y + spread) * sdf_width + (x + spread)] = glyph_image.data[y * pitch + x] >= 128;
+        }
+    }
+    let outside: Vec<bool> = inside.iter().map(|&pixel| !pixel).collect();
+
+    let distance_to_inside = distance_transform(&inside, sdf_width, sdf_rows);
+    let distance_to_outside = distance_transform(&outside, sdf_width, sdf_rows);
+    let data = (0..(sdf_width * sdf_rows)).map(|i| {
+        // The outline runs between pixel centers, half a pixel from the nearest pixel across it.
+        let signed_distance = if inside[i] {
+            distance_to_outside[i] - 0.5
+        } else {
+            -(distance_to_inside[i] - 0.5)
+        };
+        let value = 127.5 + 127.5 * signed_distance / spread as f64;
+
+        value.round().max(0.0).min(255.0) as u8
+    }).collect();
+
+    GlyphImage::new(data)
+}
+
+/// The color channels that an edge of a glyph outline contributes to in a multi-channel signed
+/// distance field, as a bit mask of red, green, and blue.
+const EDGE_RED: u8 = 0b001;
+const EDGE_GREEN: u8 = 0b010;
+const EDGE_BLUE: u8 = 0b100;
+const EDGE_WHITE: u8 = EDGE_RED | EDGE_GREEN | EDGE_BLUE;
+const EDGE_CYAN: u8 = EDGE_GREEN | EDGE_BLUE;
+const EDGE_MAGENTA: u8 = EDGE_RED | EDGE_BLUE;
+const EDGE_YELLOW: u8 = EDGE_RED | EDGE_GREEN;
+
+/// Two edges meet at a corner when the direction turns by more than about three degrees.
+const EDGE_CORNER_THRESHOLD: f64 = 0.05;
+
+/// The number of line segments each Bézier curve gets flattened into.
+const BEZIER2_SEGMENTS: usize = 8;
+const BEZIER3_SEGMENTS: usize = 12;
+
+/// A straight piece of a flattened glyph outline edge. Only the pieces at either end of an
+/// edge extend past their endpoints when measuring pseudo-distances.
+#[derive(Copy, Clone, Debug)]
+struct EdgeSegment {
+    start: (f64, f64),
+    end: (f64, f64),
+    color: u8,
+    first: bool,
+    last: bool,
+}
+
+fn edge_direction(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length > 0.0 { (dx / length, dy / length) } else { (0.0, 0.0) }
+}
+
+/// Flatten the contours of a glyph outline into polylines, one per edge, in pixel units.
+fn flatten_outline(outline: &freetype::outline::Outline) -> Vec<Vec<Vec<(f64, f64)>>> {
+    let to_pixels = |v: &freetype::Vector| (v.x as f64 / 64.0, v.y as f64 / 64.0);
+    let bezier = |p0: (f64, f64), controls: &[(f64, f64)], segments: usize| {
+        (1..(segments + 1)).map(|k| {
+            let t = k as f64 / segments as f64;
+            let s = 1.0 - t;
+            match *controls {
+                [c, p1] => (
+                    s * s * p0.0 + 2.0 * s * t * c.0 + t * t * p1.0,
+                    s * s * p0.1 + 2.0 * s * t * c.1 + t * t * p1.1,
+                ),
+                [c1, c2, p1] => (
+                    s * s * s * p0.0 + 3.0 * s * s * t * c1.0 + 3.0 * s * t * t * c2.0 + t * t * t * p1.0,
+                    s * s * s * p0.1 + 3.0 * s * s * t * c1.1 + 3.0 * s * t * t * c2.1 + t * t * t * p1.1,
+                ),
+                _ => p0,
+            }
+        }).collect::<Vec<(f64, f64)>>()
+    };
+
+    let mut contours = vec![];
+    for contour in outline.contours_iter() {
+        let mut current = to_pixels(contour.start());
+        let mut edges = vec![];
+        for curve in contour {
+            let mut edge = vec![current];
+            match curve {
+                freetype::outline::Curve::Line(p) => edge.push(to_pixels(&p)),
+                freetype::outline::Curve::Bezier2(c, p) => {
+                    edge.extend(bezier(current, &[to_pixels(&c), to_pixels(&p)], BEZIER2_SEGMENTS));
+                }
+                freetype::outline::Curve::Bezier3(c1, c2, p) => {
+                    edge.extend(bezier(
+                        current, &[to_pixels(&c1), to_pixels(&c2), to_pixels(&p)], BEZIER3_SEGMENTS
+                    ));
+                }
+            }
+            edge.dedup();
+            current = edge[edge.len() - 1];
+            if edge.len() > 1 {
+                edges.push(edge);
+            }
+        }
+        if !edges.is_empty() {
+            contours.push(edges);
+        }
+    }
+
+    contours
+}
+
+/// Assign each edge of a contour the color channels it contributes to, following the simple
+/// edge coloring of msdfgen. Edges that meet at a corner always share exactly one channel,
+/// which is what keeps the corner sharp when the median of the channels gets sampled.
+fn color_contour_edges(edges: &[Vec<(f64, f64)>]) -> Vec<u8> {
+    let count = edges.len();
+    let corners: Vec<usize> = (0..count).filter(|&k| {
+        let previous = &edges[(k + count - 1) % count];
+        let incoming = edge_direction(previous[previous.len() - 2], previous[previous.len() - 1]);
+        let outgoing = edge_direction(edges[k][0], edges[k][1]);
+        let dot = incoming.0 * outgoing.0 + incoming.1 * outgoing.1;
+        let cross = incoming.0 * outgoing.1 - incoming.1 * outgoing.0;
+
+        dot <= 0.0 || cross.abs() > EDGE_CORNER_THRESHOLD
+    }).collect();
+
+    let mut colors = vec![EDGE_WHITE; count];
+    match corners.len() {
+        // A smooth contour has no corners to keep sharp.
+        0 => {}
+        // A teardrop shape spreads three colors over the edges around its single corner.
+        1 if count >= 3 => {
+            let teardrop = [EDGE_MAGENTA, EDGE_WHITE, EDGE_YELLOW];
+            for k in 0..count {
+                let position = 3.0 + 2.875 * k as f64 / (count - 1) as f64 - 1.4375 + 0.5;
+                let color = (position as usize).saturating_sub(2).min(2);
+                colors[(corners[0] + k) % count] = teardrop[color];
+            }
+        }
+        1 => {}
+        // Otherwise the color changes at every corner, and the last spline takes a color
+        // that differs from both of its neighbors.
+        corner_count => {
+            let cycle = [EDGE_CYAN, EDGE_MAGENTA, EDGE_YELLOW];
+            for spline in 0..corner_count {
+                let mut color = cycle[spline % 3];
+                if spline == corner_count - 1 && spline % 3 == 0 {
+                    color = EDGE_MAGENTA;
+                }
+                let end = corners[(spline + 1) % corner_count];
+                let mut k = corners[spline];
+                loop {
+                    colors[k] = color;
+                    k = (k + 1) % count;
+                    if k == end {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    colors
+}
+
+/// Determine whether two neighboring texels of a multi-channel distance field disagree about
+/// which side of an edge they lie on in a way that interpolation between them would turn into
+/// a stray artifact. Only the texel farther from the edge gets flagged.
+fn detect_channel_clash(a: [f64; 3], b: [f64; 3], threshold: f64) -> bool {
+    let mut pairs = [(a[0], b[0]), (a[1], b[1]), (a[2], b[2])];
+    pairs.sort_by(|p, q| (q.1 - q.0).abs().partial_cmp(&(p.1 - p.0).abs()).unwrap());
+    let equalized = b[0] == b[1] && b[0] == b[2];
+
+    (pairs[1].1 - pairs[1].0).abs() >= threshold
+        && !equalized
+        && (pairs[2].0 - 0.5).abs() >= (pairs[2].1 - 0.5).abs()
+}
+
+/// Replace the texels of a multi-channel distance field that clash with a neighbor by the
+/// median of their channels, following the error correction of msdfgen.
+fn correct_channel_clashes(field: &mut [[f64; 3]], width: usize, rows: usize, spread: usize) {
+    let threshold = 1.001 / (2.0 * spread as f64);
+    let mut clashes = vec![];
+    for row in 0..rows {
+        for column in 0..width {
+            let texel = field[row * width + column];
+            let neighbors = [
+                if column > 0 { Some(field[row * width + column - 1]) } else { None },
+                if column + 1 < width { Some(field[row * width + column + 1]) } else { None },
+                if row > 0 { Some(field[(row - 1) * width + column]) } else { None },
+                if row + 1 < rows { Some(field[(row + 1) * width + column]) } else { None },
+            ];
+            if neighbors.iter().flatten().any(|&neighbor| detect_channel_clash(texel, neighbor, threshold)) {
+                clashes.push(row * width + column);
+            }
+        }
+    }
+    for index in clashes {
+        let [r, g, b] = field[index];
+        let median = r.min(g).max(r.max(g).min(b));
+        field[index] = [median; 3];
+    }
+}
+
+/// Compute a multi-channel signed distance field from the outline of a glyph, in the manner of
+/// msdfgen. Each channel stores the distance to the nearest edge of its color, so corners stay
+/// sharp at large magnifications when a shader takes the median of the three channels. The
+/// field extends past the outline by the spread on every side, and the values map the same way
+/// as a single channel signed distance field. This returns the width, rows, left edge, and bottom
+/// of the field in pixels along with its interleaved RGB image, or `None` for an empty outline.
+fn create_multi_channel_signed_distance_field(
+    outline: &freetype::outline::Outline,
+    spread: usize) -> Option<(usize, usize, i32, i64, GlyphImage)> {
+
+    let contours = flatten_outline(outline);
+    let mut segments = vec![];
+    let mut area = 0.0;
+    for edges in contours.iter() {
+        let colors = color_contour_edges(edges);
+        for (edge, &color) in edges.iter().zip(colors.iter()) {
+            let pieces = edge.len() - 1;
+            for k in 0..pieces {
+                segments.push(EdgeSegment {
+                    start: edge[k],
+                    end: edge[k + 1],
+                    color: color,
+                    first: k == 0,
+                    last: k == pieces - 1,
+                });
+                area += edge[k].0 * edge[k + 1].1 - edge[k + 1].0 * edge[k].1;
+            }
+        }
+    }
+    if segments.is_empty() || area == 0.0 {
+        return None;
+    }
+
+    // TrueType outlines wind clockwise, with the filled area on the right of each edge, while
+    // PostScript outlines wind the other way.
+    let inside_sign = if area < 0.0 { -1.0 } else { 1.0 };
+    let points = segments.iter().map(|segment| segment.start);
+    let x_min = points.clone().map(|p| p.0).fold(std::f64::INFINITY, f64::min).floor();
+    let x_max = points.clone().map(|p| p.0).fold(std::f64::NEG_INFINITY, f64::max).ceil();
+    let y_min = points.clone().map(|p| p.1).fold(std::f64::INFINITY, f64::min).floor();
+    let y_max = points.map(|p| p.1).fold(std::f64::NEG_INFINITY, f64::max).ceil();
+    let width = (x_max - x_min) as usize + 2 * spread;
+    let rows = (y_max - y_min) as usize + 2 * spread;
+
+    let mut field = vec![[0.0; 3]; width * rows];
+    for row in 0..rows {
+        for column in 0..width {
+            let px = x_min - spread as f64 + column as f64 + 0.5;
+            let py = y_max + spread as f64 - row as f64 - 0.5;
+
+            // For each channel, the nearest edge by true distance, breaking ties at shared
+            // endpoints in favor of the edge the point lies most squarely in front of.
+            let mut nearest: [Option<(f64, f64, f64, &EdgeSegment)>; 3] = [None; 3];
+            for segment in segments.iter() {
+                let (dx, dy) = (segment.end.0 - segment.start.0, segment.end.1 - segment.start.1);
+                let length_squared = dx * dx + dy * dy;
+                let (ax, ay) = (px - segment.start.0, py - segment.start.1);
+                let t = (ax * dx + ay * dy) / length_squared;
+                let clamped = t.max(0.0).min(1.0);
+                let (cx, cy) = (segment.start.0 + clamped * dx - px, segment.start.1 + clamped * dy - py);
+                let distance = (cx * cx + cy * cy).sqrt();
+                let obliqueness = if distance > 0.0 {
+                    (cx * dx + cy * dy).abs() / (distance * length_squared.sqrt())
+                } else {
+                    0.0
+                };
+                for channel in 0..3 {
+                    if segment.color & (1 << channel) == 0 {
+                        continue;
+                    }
+                    let closer = match nearest[channel] {
+                        None => true,
+                        Some((best_distance, best_obliqueness, _, _)) => {
+                            distance < best_distance - 1e-9
+                                || (distance < best_distance + 1e-9 && obliqueness < best_obliqueness)
+                        }
+                    };
+                    if closer {
+                        nearest[channel] = Some((distance, obliqueness, t, segment));
+                    }
+                }
+            }
+
+            for channel in 0..3 {
+                let signed_distance = match nearest[channel] {
+                    Some((distance, _, t, segment)) => {
+                        let (dx, dy) = (segment.end.0 - segment.start.0, segment.end.1 - segment.start.1);
+                        let length = (dx * dx + dy * dy).sqrt();
+                        let cross = (dx * (py - segment.start.1) - dy * (px - segment.start.0)) / length;
+                        // Past the ends of an edge, the distance to the line extending the edge
+                        // takes over, which is what keeps the corners sharp.
+                        let extends = (segment.first && t < 0.0) || (segment.last && t > 1.0);
+                        let distance = if extends && cross.abs() <= distance { cross.abs() } else { distance };
+
+                        distance * if cross * inside_sign >= 0.0 { 1.0 } else { -1.0 }
+                    }
+                    None => -(spread as f64),
+                };
+                field[row * width + column][channel] = 0.5 + signed_distance / (2.0 * spread as f64);
+            }
+        }
+    }
+
+    correct_channel_clashes(&mut field, width, rows, spread);
+    let data = field.iter()
+        .flat_map(|texel| texel.iter().map(|value| (255.0 * value).round().max(0.0).min(255.0) as u8))
+        .collect();
+
+    let left = x_min as i32 - spread as i32;
+    let bottom = y_min as i64 - spread as i64;
+
+    Some((width, rows, left, bottom, GlyphImage::new(data)))
+}
+
+/// Thicken the outline of the glyph loaded into the glyph slot of a typeface by the given strength,
+/// in pixels, the way `FT_GlyphSlot_Embolden` does with its own strength. The outline grows to the
+/// right and upward, and the metrics and advance of the glyph grow along with it. Bitmap glyphs
+/// have no outline to thicken.
+fn embolden_glyph(face: &freetype::face::Face, strength: f32) -> Result<(), freetype::error::Error> {
+    let slot = face.raw().glyph;
+    let strength = (64.0 * strength).round() as freetype::ffi::FT_Pos;
+    unsafe {
+        if (*slot).format != freetype::ffi::FT_GLYPH_FORMAT_OUTLINE {
+            return Ok(());
+        }
+        let error = freetype::ffi::FT_Outline_Embolden(&mut (*slot).outline, strength);
+        if error != 0 {
+            return Err(freetype::error::Error::from(error));
+        }
+        (*slot).metrics.width += strength;
+        (*slot).metrics.height += strength;
+        (*slot).metrics.horiBearingY += strength;
+        (*slot).metrics.horiAdvance += strength;
+        if (*slot).advance.x != 0 {
+            (*slot).advance.x += strength;
+        }
+    }
+
+    Ok(())
+}
+
+/// The glyph index of a key of a glyph table in a typeface, i.e. the glyph the code point maps to,
+/// or the key itself for atlases sampled by glyph index. Keys the typeface has no glyph for map to
+/// the missing glyph at index zero.
+fn glyph_index(face: &freetype::face::Face, spec: AtlasSpec, key: usize) -> u32 {
+    if !spec.glyph_indices {
+        face.get_char_index(key)
+    } else if key < face.raw().num_glyphs.max(0) as usize {
+        key as u32
+    } else {
+        0
+    }
+}
+
+/// The horizontal advance of the glyph loaded into a glyph slot, rounded to whole pixels.
+fn pixel_advance(glyph: &freetype::glyph_slot::GlyphSlot) -> i32 {
+    ((glyph.advance().x + 32) >> 6) as i32
+}
+
+/// Describe a code point for diagnostics. Printable characters get shown along with their
+/// code point and Unicode name, e.g. `U+0041 'A' (LATIN CAPITAL LETTER A)`.
+pub fn describe_code_point(code_point: usize) -> String {
+    match std::char::from_u32(code_point as u32) {
+        Some(ch) => {
+            let name = match unicode_names2::name(ch) {
+                Some(name) => format!(" ({})", name),
+                None => String::new(),
+            };
+            if ch.is_control() || ch.is_whitespace() {
+                format!("U+{:04X}{}", code_point, name)
+            } else {
+                format!("U+{:04X} '{}'{}", code_point, ch, name)
+            }
+        }
+        None => format!("U+{:04X}", code_point),
+    }
+}
+
+/// The properties of a character that text itemization splits runs by, so runtimes can route
+/// each run to the right atlas and shaping path without a Unicode database of their own.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TextProperties {
+    /// The ISO 15924 code of the Unicode script of the character, e.g. `Latn`, `Arab`, or `Zyyy`
+    /// for the characters common to every script.
+    pub script: &'static str,
+    /// The Unicode bidirectional class of the character, e.g. `L`, `R`, `AL`, or `EN`.
+    pub bidi_class: String,
+}
+
+/// Look up the script and bidirectional class of a code point, if it is a character.
+pub fn text_properties(code_point: usize) -> Option<TextProperties> {
+    std::char::from_u32(code_point as u32).map(|ch| TextProperties {
+        script: ch.script().short_name(),
+        bidi_class: format!("{:?}", unicode_bidi::bidi_class(ch)),
+    })
+}
+
+/// The name of the font family of a typeface, for use in diagnostics.
+fn family_name(face: &freetype::face::Face) -> String {
+    face.family_name().unwrap_or_else(|| String::from("<unnamed typeface>"))
+}
+
+/// A 3x5 pixel font for the hexadecimal digits drawn in last resort glyphs. Each row of
+/// a digit is stored in the low three bits of a byte, most significant bit leftmost.
+const HEX_DIGIT_FONT: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+    [0b111, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b111, 0b100, 0b100, 0b100, 0b111],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b111, 0b100, 0b111],
+    [0b111, 0b100, 0b111, 0b100, 0b100],
+];
+
+/// Draw a last resort glyph for a code point that the typeface does not cover. The glyph
+/// is a box sitting on the baseline with the hexadecimal digits of the code point written
+/// inside it in two rows, so missing characters stay visible and identifiable on screen.
+/// This returns the width and height of the glyph image along with the image itself.
+fn create_last_resort_glyph_image(code_point: usize, glyph_size: usize) -> (usize, usize, GlyphImage) {
+    // The box is 16 units on a side: a one unit border, a one unit margin, and two rows
+    // of three digits, each digit taking up three by five units with one unit of spacing.
+    let unit = usize::max(1, (glyph_size * 3 / 4) / 16);
+    let side = 16 * unit;
+    let mut data = vec![0 as u8; side * side];
+
+    for y in 0..side {
+        for x in 0..side {
+            if x < unit || y < unit || x >= side - unit || y >= side - unit {
+                data[y * side + x] = 255;
+            }
+        }
+    }
+
+    let digits = format!("{:04X}", code_point);
+    let digits: Vec<usize> = digits.chars()
+        .map(|digit| digit.to_digit(16).unwrap() as usize)
+        .collect();
+    let digits_per_row = (digits.len() + 1) / 2;
+    for (index, digit) in digits.iter().enumerate() {
+        let row = index / digits_per_row;
+        let column = index % digits_per_row;
+        // Center the row of digits horizontally inside the box.
+        let row_width = 4 * digits_per_row - 1;
+        let left = (16 - row_width) / 2 + 4 * column;
+        let top = 2 + 6 * row + if digits.len() <= 4 { 1 } else { 0 };
+        for (dy, bits) in HEX_DIGIT_FONT[*digit].iter().enumerate() {
+            for dx in 0..3 {
+                if bits & (0b100 >> dx) == 0 {
+                    continue;
+                }
+                for py in 0..unit {
+                    for px in 0..unit {
+                        let x = (left + dx) * unit + px;
+                        let y = (top + dy) * unit + py;
+                        data[y * side + x] = 255;
+                    }
+                }
+            }
+        }
+    }
+
+    (side, side, GlyphImage::new(data))
+}
+
+/// The family name recorded for the glyphs drawn from the built-in fallback font.
+pub const BUILTIN_FALLBACK_FAMILY: &str = "fontgen builtin";
+
+/// A 5x9 pixel font for the printable ASCII characters, starting with the space, drawn for
+/// `fontgen` and licensed along with it. Each row of a character is stored in the low five bits
+/// of a byte, most significant bit leftmost. The first seven rows sit above the baseline, and
+/// the last two hold the descenders.
+const BUILTIN_FONT: [[u8; 9]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // space
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100, 0b00000, 0b00000], // !
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // "
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010, 0b00000, 0b00000], // #
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100, 0b00000, 0b00000], // $
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011, 0b00000, 0b00000], // %
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101, 0b00000, 0b00000], // &
+    [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // '
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010, 0b00000, 0b00000], // (
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000, 0b00000, 0b00000], // )
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000, 0b00000, 0b00000], // *
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000, 0b00000, 0b00000], // +
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100, 0b00100, 0b01000], // ,
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // -
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100, 0b00000, 0b00000], // .
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000, 0b00000, 0b00000], // /
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110, 0b00000, 0b00000], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000, 0b00000], // 1
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111, 0b00000, 0b00000], // 2
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110, 0b00000, 0b00000], // 3
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010, 0b00000, 0b00000], // 4
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110, 0b00000, 0b00000], // 5
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000], // 6
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00000, 0b00000], // 7
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000], // 8
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100, 0b00000, 0b00000], // 9
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000, 0b00000, 0b00000], // :
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00100, 0b01000, 0b00000], // ;
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00000, 0b00000], // <
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000, 0b00000], // =
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000, 0b00000, 0b00000], // >
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100, 0b00000, 0b00000], // ?
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110, 0b00000, 0b00000], // @
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b00000, 0b00000], // A
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000, 0b00000], // B
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110, 0b00000, 0b00000], // C
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100, 0b00000, 0b00000], // D
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111, 0b00000, 0b00000], // E
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000, 0b00000, 0b00000], // F
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111, 0b00000, 0b00000], // G
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001, 0b00000, 0b00000], // H
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000, 0b00000], // I
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100, 0b00000, 0b00000], // J
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001, 0b00000, 0b00000], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111, 0b00000, 0b00000], // L
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001, 0b00000, 0b00000], // M
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b00000, 0b00000], // N
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000], // O
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000, 0b00000, 0b00000], // P
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101, 0b00000, 0b00000], // Q
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001, 0b00000, 0b00000], // R
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110, 0b00000, 0b00000], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000], // T
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000], // U
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000, 0b00000], // V
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010, 0b00000, 0b00000], // W
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001, 0b00000, 0b00000], // X
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000], // Y
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111, 0b00000, 0b00000], // Z
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110, 0b00000, 0b00000], // [
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000, 0b00000, 0b00000], // \
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110, 0b00000, 0b00000], // ]
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // ^
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000], // _
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // `
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111, 0b00000, 0b00000], // a
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110, 0b00000, 0b00000], // b
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110, 0b00000, 0b00000], // c
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111, 0b00000, 0b00000], // d
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110, 0b00000, 0b00000], // e
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000, 0b00000, 0b00000], // f
+    [0b00000, 0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b10001, 0b01110], // g
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001, 0b00000, 0b00000], // h
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000, 0b00000], // i
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // j
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b00000, 0b00000], // k
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000, 0b00000], // l
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001, 0b00000, 0b00000], // m
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001, 0b00000, 0b00000], // n
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000], // o
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // p
+    [0b00000, 0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b00001, 0b00001], // q
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000, 0b00000, 0b00000], // r
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110, 0b00000, 0b00000], // s
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110, 0b00000, 0b00000], // t
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101, 0b00000, 0b00000], // u
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000, 0b00000], // v
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010, 0b00000, 0b00000], // w
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b00000, 0b00000], // x
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01111, 0b00001, 0b10001, 0b01110], // y
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111, 0b00000, 0b00000], // z
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010, 0b00000, 0b00000], // {
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000], // |
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000, 0b00000, 0b00000], // }
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // ~
+];
+
+/// Determine whether the built-in fallback font has a glyph for a code point, i.e. whether it is
+/// printable ASCII.
+pub fn builtin_fallback_covers(code_point: usize) -> bool {
+    code_point >= 0x20 && code_point <= 0x7E
+}
+
+/// Draw the glyph of a printable ASCII character from the built-in fallback font, scaled up by
+/// whole pixels so its capitals stand about seven tenths of the glyph size tall. This returns
+/// the width and height of the glyph image, the image itself, the offset of its bottom edge from
+/// the baseline, and the advance, or nothing for characters outside of printable ASCII.
+fn create_builtin_glyph_image(
+    code_point: usize, glyph_size: usize) -> Option<(usize, usize, GlyphImage, i64, i32)> {
+
+    if !builtin_fallback_covers(code_point) {
+        return None;
+    }
+    let unit = usize::max(1, glyph_size / 10);
+    let (width, rows) = (5 * unit, 9 * unit);
+    let mut data = vec![0 as u8; width * rows];
+    for (y, bits) in BUILTIN_FONT[code_point - 0x20].iter().enumerate() {
+        for x in 0..5 {
+            if bits & (0b10000 >> x) == 0 {
+                continue;
+            }
+            for py in 0..unit {
+                for px in 0..unit {
+                    data[(y * unit + py) * width + x * unit + px] = 255;
+                }
+            }
+        }
+    }
+
+    Some((width, rows, GlyphImage::new(data), -2 * unit as i64, 6 * unit as i32))
+}
+
+/// Find the capital letter that stands in for a lowercase letter when synthesizing small
+/// capitals. Letters whose uppercase form is not a single character, like `ß`, have none.
+fn small_capital(code_point: usize) -> Option<usize> {
+    let ch = std::char::from_u32(code_point as u32)?;
+    if !ch.is_lowercase() {
+        return None;
+    }
+
+    let mut uppercase = ch.to_uppercase();
+    match (uppercase.next(), uppercase.next()) {
+        (Some(capital), None) if capital != ch => Some(capital as usize),
+        _ => None,
+    }
+}
+
+/// An error that occurred while sampling glyph images from a typeface. Each error records
+/// the underlying FreeType error, the font family of the typeface, and the glyph size or
+/// code point being processed.
+#[derive(Clone, Debug)]
+pub enum SampleTypefaceError {
+    SetPixelSize(freetype::error::Error, String, usize),
+    LoadCharacter(freetype::error::Error, String, usize),
+    LoadGlyph(freetype::error::Error, String, usize),
+    RenderCharacter(freetype::error::Error, String, usize),
+    GetGlyphImage(freetype::error::Error, String, usize),
+    GetKerning(freetype::error::Error, String, usize, usize),
+    CreateStroker(freetype::error::Error, String),
+    StrokeCharacter(freetype::error::Error, String, usize),
+    EmboldenCharacter(freetype::error::Error, String, usize),
+    OpenTypeface(freetype::error::Error, PathBuf),
+    SetVariation(VariationError, PathBuf),
+}
+
+impl fmt::Display for SampleTypefaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SampleTypefaceError::SetPixelSize(ref e, ref family, pixels) => {
+                write!(
+                    f, "[{}] While setting up the typeface, the FreeType library failed to set \
+                    the glyph size to {} pixels: {}.",
+                    family, pixels, e
+                )
+            }
+            SampleTypefaceError::LoadCharacter(ref e, ref family, code_point) => {
+                write!(
+                    f, "[{}] While loading glyphs, the FreeType library failed to load the \
+                    character {}: {}.",
+                    family, describe_code_point(code_point), e
+                )
+            }
+            SampleTypefaceError::LoadGlyph(ref e, ref family, glyph_index) => {
+                write!(
+                    f, "[{}] While loading glyphs, the FreeType library failed to load the glyph \
+                    with index {}: {}.",
+                    family, glyph_index, e
+                )
+            }
+            SampleTypefaceError::RenderCharacter(ref e, ref family, code_point) => {
+                write!(
+                    f, "[{}] While rendering glyphs, the FreeType library could not render the \
+                    character {}: {}.",
+                    family, describe_code_point(code_point), e
+                )
+            }
+            SampleTypefaceError::GetGlyphImage(ref e, ref family, code_point) => {
+                write!(
+                    f, "[{}] While measuring glyph bounds, the FreeType library could not extract \
+                    the glyph image for the character {}: {}.",
+                    family, describe_code_point(code_point), e
+                )
+            }
+            SampleTypefaceError::GetKerning(ref e, ref family, first, second) => {
+                write!(
+                    f, "[{}] While reading kerning pairs, the FreeType library could not get the \
+                    kerning between the characters {} and {}: {}.",
+                    family, describe_code_point(first), describe_code_point(second), e
+                )
+            }
+            SampleTypefaceError::CreateStroker(ref e, ref family) => {
+                write!(
+                    f, "[{}] While setting up the typeface, the FreeType library could not create \
+                    the stroker for the glyph outlines: {}.",
+                    family, e
+                )
+            }
+            SampleTypefaceError::StrokeCharacter(ref e, ref family, code_point) => {
+                write!(
+                    f, "[{}] While stroking glyphs, the FreeType library could not stroke the outline \
+                    of the character {}: {}.",
+                    family, describe_code_point(code_point), e
+                )
+            }
+            SampleTypefaceError::EmboldenCharacter(ref e, ref family, code_point) => {
+                write!(
+                    f, "[{}] While emboldening glyphs, the FreeType library could not thicken the \
+                    outline of the character {}: {}.",
+                    family, describe_code_point(code_point), e
+                )
+            }
+            SampleTypefaceError::OpenTypeface(ref e, ref path) => {
+                write!(
+                    f, "While setting up a worker thread, the FreeType library failed to open the \
+                    typeface {}: {}.",
+                    path.display(), e
+                )
+            }
+            SampleTypefaceError::SetVariation(ref e, ref path) => {
+                write!(
+                    f, "While setting up a worker thread, the design coordinates of the typeface {} \
+                    could not be set: {}",
+                    path.display(), e
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for SampleTypefaceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            &SampleTypefaceError::SetPixelSize(ref e, _, _) => Some(e),
+            &SampleTypefaceError::LoadCharacter(ref e, _, _) => Some(e),
+            &SampleTypefaceError::LoadGlyph(ref e, _, _) => Some(e),
+            &SampleTypefaceError::RenderCharacter(ref e, _, _) => Some(e),
+            &SampleTypefaceError::GetGlyphImage(ref e, _, _) => Some(e),
+            &SampleTypefaceError::GetKerning(ref e, _, _, _) => Some(e),
+            &SampleTypefaceError::CreateStroker(ref e, _) => Some(e),
+            &SampleTypefaceError::StrokeCharacter(ref e, _, _) => Some(e),
+            &SampleTypefaceError::EmboldenCharacter(ref e, _, _) => Some(e),
+            &SampleTypefaceError::OpenTypeface(ref e, _) => Some(e),
+            &SampleTypefaceError::SetVariation(ref e, _) => Some(e),
+        }
+    }
+}
+
+/// A structural problem found in a font file before handing it to the rasterizer.
+#[derive(Clone, Debug)]
+pub enum FontValidationError {
+    CouldNotReadFontFile(String),
+    TruncatedHeader(usize),
+    TruncatedTableDirectory(usize, usize),
+    TableOutOfBounds(String, usize, usize, usize),
+    MissingTable(&'static str),
+    MissingOutlines,
+    MalformedTable(&'static str, &'static str),
+    NoGlyphs,
+    NoCharacterMap,
+    FaceIndexOutOfRange(usize, usize),
+}
+
+impl fmt::Display for FontValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FontValidationError::CouldNotReadFontFile(ref e) => {
+                write!(f, "The font file could not be read: {}.", e)
+            }
+            FontValidationError::TruncatedHeader(length) => {
+                write!(f, "The font file is too short to hold a font header ({} bytes).", length)
+            }
+            FontValidationError::TruncatedTableDirectory(tables, length) => {
+                write!(
+                    f, "The table directory lists {} tables but the font file ends after {} bytes.",
+                    tables, length
+                )
+            }
+            FontValidationError::TableOutOfBounds(ref tag, offset, table_length, length) => {
+                write!(
+                    f, "The '{}' table at offset {} with length {} extends past the end of \
+                    the font file ({} bytes).",
+                    tag, offset, table_length, length
+                )
+            }
+            FontValidationError::MissingTable(tag) => {
+                write!(f, "The font file has no '{}' table.", tag)
+            }
+            FontValidationError::MissingOutlines => {
+                write!(f, "The font file has no glyph outline or bitmap tables.")
+            }
+            FontValidationError::MalformedTable(tag, problem) => {
+                write!(f, "The '{}' table is malformed: {}.", tag, problem)
+            }
+            FontValidationError::NoGlyphs => {
+                write!(f, "The typeface contains no glyphs.")
+            }
+            FontValidationError::NoCharacterMap => {
+                write!(f, "The typeface has no Unicode character map.")
+            }
+            FontValidationError::FaceIndexOutOfRange(face_index, num_faces) => {
+                write!(
+                    f, "The font file has no face {}. Its faces are numbered from 0 to {}.",
+                    face_index, num_faces.saturating_sub(1)
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for FontValidationError {}
+
+/// The tables every TrueType and OpenType font must contain.
+const REQUIRED_TABLES: [&str; 5] = ["cmap", "head", "hhea", "hmtx", "maxp"];
+
+/// The tables holding glyph images. A font must contain at least one of them.
+const OUTLINE_TABLES: [&str; 6] = ["glyf", "CFF ", "CFF2", "CBDT", "EBDT", "sbix"];
+
+fn read_u16(data: &[u8], offset: usize) -> Option<usize> {
+    data.get(offset..offset + 2).map(|bytes| ((bytes[0] as usize) << 8) | (bytes[1] as usize))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    data.get(offset..offset + 4).map(|bytes| {
+        ((bytes[0] as usize) << 24) | ((bytes[1] as usize) << 16)
+            | ((bytes[2] as usize) << 8) | (bytes[3] as usize)
+    })
+}
+
+/// Sanity check the table directory and the core tables of a TrueType or OpenType font
+/// before it reaches FreeType. Truncated files, tables pointing outside the file, and
+/// inconsistent glyph counts are the usual ingredients of a malformed font, so they are
+/// rejected here with a diagnostic instead of being left for the rasterizer to trip over.
+/// Files that are not plain sfnt fonts, such as WOFF or Type 1 fonts, are left to FreeType.
+/// In a TrueType or OpenType collection, this checks the face at `face_index`.
+pub fn validate_font_file(path: &Path, face_index: usize) -> Result<(), FontValidationError> {
+    let data = match std::fs::read(path) {
+        Ok(val) => val,
+        Err(e) => return Err(FontValidationError::CouldNotReadFontFile(format!("{}", e))),
+    };
+    let font_offset = match data.get(0..4) {
+        Some(b"\x00\x01\x00\x00") | Some(b"OTTO") | Some(b"true") if face_index == 0 => 0,
+        Some(b"\x00\x01\x00\x00") | Some(b"OTTO") | Some(b"true") => {
+            return Err(FontValidationError::FaceIndexOutOfRange(face_index, 1));
+        }
+        Some(b"ttcf") => match read_u32(&data, 8) {
+            Some(num_faces) if face_index >= num_faces => {
+                return Err(FontValidationError::FaceIndexOutOfRange(face_index, num_faces));
+            }
+            Some(_) => match read_u32(&data, 12 + 4 * face_index) {
+                Some(offset) => offset,
+                None => return Err(FontValidationError::TruncatedHeader(data.len())),
+            },
+            None => return Err(FontValidationError::TruncatedHeader(data.len())),
+        },
+        Some(_) => return Ok(()),
+        None => return Err(FontValidationError::TruncatedHeader(data.len())),
+    };
+
+    let num_tables = match read_u16(&data, font_offset + 4) {
+        Some(val) => val,
+        None => return Err(FontValidationError::TruncatedHeader(data.len())),
+    };
+    let mut tables = HashMap::new();
+    for i in 0..num_tables {
+        let record = font_offset + 12 + 16 * i;
+        let tag = data.get(record..record + 4);
+        let (tag, offset, length) = match (tag, read_u32(&data, record + 8), read_u32(&data, record + 12)) {
+            (Some(tag), Some(offset), Some(length)) => {
+                (String::from_utf8_lossy(tag).into_owned(), offset, length)
+            }
+            _ => return Err(FontValidationError::TruncatedTableDirectory(num_tables, data.len())),
+        };
+        if offset.checked_add(length).map_or(true, |end| end > data.len()) {
+            return Err(FontValidationError::TableOutOfBounds(tag, offset, length, data.len()));
+        }
+        tables.insert(tag, &data[offset..offset + length]);
+    }
+
+    for tag in REQUIRED_TABLES.iter() {
+        if !tables.contains_key(*tag) {
+            return Err(FontValidationError::MissingTable(tag));
+        }
+    }
+    if !OUTLINE_TABLES.iter().any(|tag| tables.contains_key(*tag)) {
+        return Err(FontValidationError::MissingOutlines);
+    }
+
+    let head = tables["head"];
+    if head.len() < 54 || read_u32(head, 12) != Some(0x5F0F_3CF5) {
+        return Err(FontValidationError::MalformedTable("head", "the magic number is missing"));
+    }
+    let num_glyphs = match read_u16(tables["maxp"], 4) {
+        Some(0) => return Err(FontValidationError::NoGlyphs),
+        Some(val) => val,
+        None => return Err(FontValidationError::MalformedTable("maxp", "the glyph count is missing")),
+    };
+    let num_metrics = match read_u16(tables["hhea"], 34) {
+        Some(val) if val >= 1 && val <= num_glyphs => val,
+        _ => {
+            return Err(FontValidationError::MalformedTable(
+                "hhea", "the number of horizontal metrics does not match the glyph count"
+            ));
+        }
+    };
+    if tables["hmtx"].len() < 4 * num_metrics {
+        return Err(FontValidationError::MalformedTable(
+            "hmtx", "the table is too short for its metrics"
+        ));
+    }
+    if tables.contains_key("glyf") {
+        let loca = match tables.get("loca") {
+            Some(val) => val,
+            None => return Err(FontValidationError::MissingTable("loca")),
+        };
+        let entry_size = if read_u16(head, 50) == Some(0) { 2 } else { 4 };
+        if loca.len() < entry_size * (num_glyphs + 1) {
+            return Err(FontValidationError::MalformedTable(
+                "loca", "the table is too short for the glyph count"
+            ));
+        }
+    }
+
+    let cmap = tables["cmap"];
+    let num_subtables = match read_u16(cmap, 2) {
+        Some(0) | None => {
+            return Err(FontValidationError::MalformedTable("cmap", "the table has no subtables"));
+        }
+        Some(val) => val,
+    };
+    for i in 0..num_subtables {
+        match read_u32(cmap, 4 + 8 * i + 4) {
+            Some(offset) if offset < cmap.len() => {}
+            _ => {
+                return Err(FontValidationError::MalformedTable(
+                    "cmap", "a subtable lies outside of the table"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a typeface opened by FreeType has glyphs to sample and a Unicode character
+/// map to look them up with.
+pub fn validate_face(face: &freetype::face::Face) -> Result<(), FontValidationError> {
+    if face.raw().num_glyphs <= 0 {
+        return Err(FontValidationError::NoGlyphs);
+    }
+    if face.raw().charmap.is_null() {
+        return Err(FontValidationError::NoCharacterMap);
+    }
+
+    Ok(())
+}
+
+/// A coordinate on a design axis of a variable font, e.g. a weight of 650 on the `wght` axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariationCoordinate {
+    /// The four character tag of the axis.
+    pub tag: String,
+    /// The coordinate in the design units of the axis.
+    pub value: f32,
+}
+
+/// An error that occurred while choosing an instance of a variable font.
+#[derive(Clone, Debug)]
+pub enum VariationError {
+    NotAVariableFont(String),
+    UnknownAxis(String, String, Vec<String>),
+    CoordinateOutOfRange(String, String, f32, f32, f32),
+    SetCoordinates(freetype::error::Error, String),
+}
+
+impl fmt::Display for VariationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VariationError::NotAVariableFont(ref family) => {
+                write!(f, "[{}] The typeface is not a variable font, so it has no axes to set.", family)
+            }
+            VariationError::UnknownAxis(ref family, ref tag, ref axes) => {
+                write!(
+                    f, "[{}] The typeface has no `{}` axis. Its axes are: {}.",
+                    family, tag, axes.join(", ")
+                )
+            }
+            VariationError::CoordinateOutOfRange(ref family, ref tag, value, minimum, maximum) => {
+                write!(
+                    f, "[{}] The coordinate {} lies outside of the `{}` axis, which ranges from {} to {}.",
+                    family, value, tag, minimum, maximum
+                )
+            }
+            VariationError::SetCoordinates(ref e, ref family) => {
+                write!(
+                    f, "[{}] The FreeType library failed to set the design coordinates: {}.",
+                    family, e
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for VariationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            &VariationError::SetCoordinates(ref e, _) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[allow(non_camel_case_types, non_snake_case)]
+#[repr(C)]
+struct FT_Var_Axis {
+    name: *mut std::os::raw::c_char,
+    minimum: freetype::ffi::FT_Fixed,
+    def: freetype::ffi::FT_Fixed,
+    maximum: freetype::ffi::FT_Fixed,
+    tag: freetype::ffi::FT_ULong,
+    strid: freetype::ffi::FT_UInt,
+}
+
+#[allow(non_camel_case_types, non_snake_case)]
+#[repr(C)]
+struct FT_MM_Var {
+    num_axis: freetype::ffi::FT_UInt,
+    num_designs: freetype::ffi::FT_UInt,
+    num_namedstyles: freetype::ffi::FT_UInt,
+    axis: *mut FT_Var_Axis,
+    namedstyle: *mut std::os::raw::c_void,
+}
+
+extern "C" {
+    fn FT_Get_MM_Var(face: freetype::ffi::FT_Face, amaster: *mut *mut FT_MM_Var) -> freetype::ffi::FT_Error;
+    fn FT_Done_MM_Var(library: freetype::ffi::FT_Library, amaster: *mut FT_MM_Var) -> freetype::ffi::FT_Error;
+    fn FT_Set_Var_Design_Coordinates(
+        face: freetype::ffi::FT_Face,
+        num_coords: freetype::ffi::FT_UInt,
+        coords: *mut freetype::ffi::FT_Fixed) -> freetype::ffi::FT_Error;
+}
+
+/// The tag of a variation axis as text, e.g. `wght`.
+fn axis_tag(tag: freetype::ffi::FT_ULong) -> String {
+    (0..4).map(|i| ((tag >> (24 - 8 * i)) & 0xFF) as u8 as char).collect()
+}
+
+/// The tag, minimum, default, and maximum design coordinates of an axis of a variable font.
+type VariationAxis = (String, freetype::ffi::FT_Fixed, freetype::ffi::FT_Fixed, freetype::ffi::FT_Fixed);
+
+/// The axes of a variable font, or nothing if the typeface is not a variable font.
+fn variation_axes(library: &Library, face: &mut freetype::face::Face) -> Option<Vec<VariationAxis>> {
+
+    let mut master: *mut FT_MM_Var = std::ptr::null_mut();
+    let error = unsafe { FT_Get_MM_Var(face.raw_mut() as freetype::ffi::FT_Face, &mut master) };
+    if error != 0 || master.is_null() {
+        return None;
+    }
+    let axes = unsafe {
+        std::slice::from_raw_parts((*master).axis, (*master).num_axis as usize)
+            .iter()
+            .map(|axis| (axis_tag(axis.tag), axis.minimum, axis.def, axis.maximum))
+            .collect::<Vec<_>>()
+    };
+    unsafe {
+        FT_Done_MM_Var(library.raw(), master);
+    }
+
+    Some(axes)
+}
+
+/// Choose an instance of a variable font by setting the design coordinates of its axes. The
+/// axes left out keep their default coordinates. This has to happen before sampling the
+/// typeface, since FreeType rasterizes every glyph at the current coordinates.
+pub fn set_variation(
+    library: &Library, face: &mut freetype::face::Face,
+    coordinates: &[VariationCoordinate]) -> Result<(), VariationError> {
+
+    let family = face.family_name().unwrap_or_default();
+    let axes = match variation_axes(library, face) {
+        Some(val) => val,
+        None => return Err(VariationError::NotAVariableFont(family)),
+    };
+    let mut design_coordinates: Vec<freetype::ffi::FT_Fixed> = axes.iter().map(|axis| axis.2).collect();
+    for coordinate in coordinates.iter() {
+        let axis = match axes.iter().position(|axis| axis.0 == coordinate.tag) {
+            Some(val) => val,
+            None => {
+                let tags = axes.iter().map(|axis| axis.0.clone()).collect();
+                return Err(VariationError::UnknownAxis(family, coordinate.tag.clone(), tags));
+            }
+        };
+        let (_, minimum, _, maximum) = axes[axis];
+        let value = (coordinate.value * 65536.0).round() as freetype::ffi::FT_Fixed;
+        if value < minimum || value > maximum {
+            return Err(VariationError::CoordinateOutOfRange(
+                family, coordinate.tag.clone(), coordinate.value,
+                minimum as f32 / 65536.0, maximum as f32 / 65536.0
+            ));
+        }
+        design_coordinates[axis] = value;
+    }
+
+    let error = unsafe {
+        FT_Set_Var_Design_Coordinates(
+            face.raw_mut() as freetype::ffi::FT_Face,
+            design_coordinates.len() as freetype::ffi::FT_UInt,
+            design_coordinates.as_mut_ptr()
+        )
+    };
+    if error != 0 {
+        return Err(VariationError::SetCoordinates(freetype::error::Error::from(error), family));
+    }
+
+    Ok(())
+}
+
+/// Set the optical size axis (`opsz`) of a variable font to match the size the glyphs get
+/// rendered at, along with the other design coordinates, so small text gets drawn with the
+/// sturdier text design of the typeface and large text with its finer display design. The
+/// optical size gets clamped to the range of the axis, and is returned, or nothing if the
+/// typeface has no optical size axis, in which case the typeface is left alone.
+pub fn set_optical_size(
+    library: &Library, face: &mut freetype::face::Face,
+    coordinates: &[VariationCoordinate], optical_size: f32) -> Result<Option<f32>, VariationError> {
+
+    let axes = variation_axes(library, face).unwrap_or_default();
+    let (minimum, maximum) = match axes.iter().find(|axis| axis.0 == "opsz") {
+        Some(&(_, minimum, _, maximum)) => (minimum as f32 / 65536.0, maximum as f32 / 65536.0),
+        None => return Ok(None),
+    };
+    let optical_size = f32::min(f32::max(optical_size, minimum), maximum);
+    let mut coordinates = coordinates.to_vec();
+    coordinates.retain(|coordinate| coordinate.tag != "opsz");
+    coordinates.push(VariationCoordinate { tag: String::from("opsz"), value: optical_size });
+    set_variation(library, face, &coordinates)?;
+
+    Ok(Some(optical_size))
+}
+
+/// Generate the glyph image for each code point in the character set to be mapped into
+/// the final atlas image. The space character is skipped, since it always occupies the
+/// first slot of the atlas without an image.
+pub fn sample_typeface(
+    face: &freetype::face::Face, spec: AtlasSpec,
+    chars: &CharacterSet) -> Result<GlyphTable, SampleTypefaceError> {
+
+    // Tell FreeType the maximum size of each glyph, in pixels.
+    // The glyph height in pixels.
+    let mut glyph_rows = HashMap::new();
+    // The glyph width in pixels.
+    let mut glyph_width = HashMap::new();
+    // The bytes to per row of pixels per glyph.
+    let mut glyph_pitch = HashMap::new();
+    // The offset for letters that dip below the baseline like 'g' and 'y', for example.
+    let mut glyph_ymin = HashMap::new();
+    // The offset from the pen position to the left edge of the glyph.
+    let mut glyph_xbearing = HashMap::new();
+    // The distance the pen moves after each glyph.
+    let mut glyph_advance = HashMap::new();
+    // A table for storing the sampled glyph images.
+    let mut glyph_buffer = HashMap::new();
+    // The code points whose glyphs have no visible pixels.
+    let mut glyph_empty = vec![];
+    // The code points that take up no space and are not drawn on their own.
+    let mut glyph_zero_width = vec![];
+    // The rasterization time for each glyph.
+    let mut glyph_telemetry = vec![];
+    // The code points whose glyphs were drawn in color.
+    let mut glyph_color = vec![];
+    // The code points whose glyphs were drawn from the built-in fallback font.
+    let mut glyph_typefaces = BTreeMap::new();
+    // The typeface name to report in diagnostics.
+    let family = family_name(face);
+
+    // Set the height in pixels width 0 height 48 (48x48).
+    let render_size = spec.render_size();
+    set_render_size(face, spec, &family)?;
+    // Bitmap strikes only come in a few sizes, so their glyphs get scaled to the render size.
+    let strike_scale = nearest_strike(face, render_size).map(|(_, ppem)| render_size as f32 / ppem as f32);
+    // Distance fields measure the outline itself, so only coverage glyphs get a stroked border.
+    let stroker = match spec.distance_field {
+        None if spec.outline > 0 => {
+            let stroker = create_stroker(face, spec.outline).map_err(|e| {
+                SampleTypefaceError::CreateStroker(e, family.clone())
+            })?;
+            Some(stroker)
+        }
+        _ => None,
+    };
+    // Color glyphs get drawn as premultiplied RGBA images, unless they get turned into distance
+    // fields, which only measure the coverage of the outlines, or get a border.
+    let color = spec.distance_field.is_none() && spec.mono.is_none() && stroker.is_none() &&
+        spec.channels == AtlasChannels::Rgba && has_color_glyphs(face);
+    let channels = match spec.distance_field {
+        Some(distance_field) => distance_field.channels(),
+        None if color => 4,
+        None if stroker.is_some() && spec.channels == AtlasChannels::Rgba => 4,
+        None if spec.shadow.is_some() && spec.channels == AtlasChannels::Rgba => 4,
+        None => 1,
+    };
+    // A glyph with a border takes up the width of the border on either side.
+    let border_advance = stroker.as_ref().map_or(0, |_| 2 * spec.outline as i32);
+    let gamma_table = match spec.distance_field {
+        Some(_) => None,
+        None => gamma_table(spec.gamma),
+    };
+
+    // Squeeze or widen the glyph outlines horizontally, and slant them for a synthetic italic.
+    // FreeType applies the transform to the glyph advances as well, so they stay proportional to
+    // the stretched outlines, and the slant leaves them as they are.
+    let mut stretch_matrix = freetype::Matrix {
+        xx: (spec.stretch * 65536.0) as freetype::ffi::FT_Fixed,
+        xy: (spec.oblique * 65536.0) as freetype::ffi::FT_Fixed,
+        yx: 0,
+        yy: 0x10000,
+    };
+    let mut stretch_delta = freetype::Vector { x: 0, y: 0 };
+    face.set_transform(&mut stretch_matrix, &mut stretch_delta);
+
+    // The line metrics, in whole pixels.
+    let (ascender, line_height) = match face.size_metrics() {
+        Some(metrics) => (((metrics.ascender + 63) >> 6) as i32, ((metrics.height + 32) >> 6) as i32),
+        None => (render_size as i32, render_size as i32),
+    };
+    let (ascender, line_height) = match strike_scale {
+        Some(scale) => ((ascender as f32 * scale).ceil() as i32, (line_height as f32 * scale).round() as i32),
+        None => (ascender, line_height),
+    };
+
+    // The space character never gets an image, but text layout still needs its advance.
+    if !spec.glyph_indices {
+        face.load_char(32, freetype::face::LoadFlag::DEFAULT).map_err(|e| {
+            SampleTypefaceError::LoadCharacter(e, family.clone(), 32)
+        })?;
+        if spec.embolden > 0.0 {
+            embolden_glyph(face, spec.embolden).map_err(|e| {
+                SampleTypefaceError::EmboldenCharacter(e, family.clone(), 32)
+            })?;
+        }
+        glyph_advance.insert(32, pixel_advance(face.glyph()) + border_advance);
+    }
+
+    for i in chars.code_points().into_iter().filter(|&i| spec.glyph_indices || i != 32) {
+        if !spec.glyph_indices && spec.zero_width.contains(i) {
+            glyph_zero_width.push(i);
+            glyph_advance.insert(i, 0);
+            continue;
+        }
+
+        let start = Instant::now();
+        let missing = glyph_index(face, spec, i) == 0 && !spec.glyph_indices;
+        // The built-in fallback font covers printable ASCII, and last resort glyphs the rest.
+        let builtin_glyph = if spec.builtin_fallback && missing {
+            create_builtin_glyph_image(i, render_size)
+        } else {
+            None
+        };
+        let synthetic_glyph = match builtin_glyph {
+            Some(val) => Some((val, "builtin fallback glyph", Some(BUILTIN_FALLBACK_FAMILY))),
+            None if spec.last_resort && missing => {
+                let (width, rows, glyph_image_i) = create_last_resort_glyph_image(i, render_size);
+                Some(((width, rows, glyph_image_i, 0, width as i32), "last resort glyph", None))
+            }
+            None => None,
+        };
+        if let Some(((width, rows, glyph_image_i, y_min, advance), fallback, typeface)) = synthetic_glyph {
+            match spec.distance_field {
+                // There is no outline to measure, so the glyph gets a single channel distance field
+                // copied into all three channels.
+                Some(DistanceField::Msdf { spread }) => {
+                    let field = create_signed_distance_field(&glyph_image_i, width, rows, width, spread);
+                    let data = field.data.iter().flat_map(|&value| vec![value; 3]).collect();
+                    glyph_rows.insert(i, (rows + 2 * spread) as i32);
+                    glyph_width.insert(i, (width + 2 * spread) as i32);
+                    glyph_pitch.insert(i, (3 * (width + 2 * spread)) as i32);
+                    glyph_ymin.insert(i, y_min - spread as i64);
+                    glyph_xbearing.insert(i, -(spread as i32));
+                    glyph_buffer.insert(i, GlyphImage::new(data));
+                }
+                _ => {
+                    let data = glyph_image_i.data.iter()
+                        .flat_map(|&value| iter::repeat(value).take(channels))
+                        .collect();
+                    glyph_rows.insert(i, rows as i32);
+                    glyph_width.insert(i, width as i32);
+                    glyph_pitch.insert(i, (channels * width) as i32);
+                    glyph_ymin.insert(i, y_min);
+                    glyph_xbearing.insert(i, 0);
+                    glyph_buffer.insert(i, GlyphImage::new(data));
+                }
+            }
+            glyph_advance.insert(i, advance);
+            if let Some(typeface) = typeface {
+                glyph_typefaces.insert(i, String::from(typeface));
+            }
+            glyph_telemetry.push(GlyphTelemetry {
+                code_point: i,
+                elapsed: start.elapsed(),
+                fallback: Some(fallback),
+            });
+            continue;
+        }
+
+        // Lowercase letters become scaled down capitals when synthesizing small caps, provided
+        // the typeface has the capital letter to draw.
+        let small_capital = spec.small_caps_scale.filter(|_| !spec.glyph_indices).and_then(|scale| {
+            small_capital(i)
+                .filter(|&capital| face.get_char_index(capital) != 0)
+                .map(|capital| (capital, scale))
+        });
+
+        // Multi-channel distance fields get measured from the unhinted outline instead of a
+        // rendered bitmap.
+        let load_flags = match spec.distance_field {
+            Some(DistanceField::Msdf { .. }) => {
+                freetype::face::LoadFlag::NO_BITMAP | freetype::face::LoadFlag::NO_HINTING
+            }
+            _ if color => freetype::face::LoadFlag::RENDER | freetype::face::LoadFlag::COLOR,
+            _ if spec.mono == Some(Monochrome::Rasterizer) => {
+                freetype::face::LoadFlag::RENDER | freetype::face::LoadFlag::TARGET_MONO
+            }
+            _ => freetype::face::LoadFlag::RENDER,
+        };
+        // The border gets stroked from the outline and emboldening thickens the outline, so the
+        // glyph cannot be rendered while loading.
+        let load_flags = if stroker.is_some() || spec.embolden > 0.0 {
+            load_flags - freetype::face::LoadFlag::RENDER
+        } else {
+            load_flags
+        };
+        match small_capital {
+            Some((capital, scale)) => {
+                let small_cap_size = ((render_size as f32) * scale).round().max(1.0) as usize;
+                face.set_pixel_sizes(0, small_cap_size as u32).map_err(|e| {
+                    SampleTypefaceError::SetPixelSize(e, family.clone(), small_cap_size)
+                })?;
+                face.load_char(capital, load_flags).map_err(|e| {
+                    SampleTypefaceError::LoadCharacter(e, family.clone(), i)
+                })?;
+                set_render_size(face, spec, &family)?;
+            }
+            None if spec.glyph_indices => {
+                face.load_glyph(i as u32, load_flags).map_err(|e| {
+                    SampleTypefaceError::LoadGlyph(e, family.clone(), i)
+                })?;
+            }
+            None => {
+                face.load_char(i, load_flags).map_err(|e| {
+                    SampleTypefaceError::LoadCharacter(e, family.clone(), i)
+                })?;
+            }
+        }
+        if spec.embolden > 0.0 {
+            embolden_glyph(face, spec.embolden).map_err(|e| {
+                SampleTypefaceError::EmboldenCharacter(e, family.clone(), i)
+            })?;
+        }
+
+        if let Some(DistanceField::Msdf { spread }) = spec.distance_field {
+            let field = face.glyph().outline().and_then(|outline| {
+                create_multi_channel_signed_distance_field(&outline, spread)
+            });
+            match field {
+                Some((width, rows, x_bearing, y_min, glyph_image_i)) => {
+                    glyph_rows.insert(i, rows as i32);
+                    glyph_width.insert(i, width as i32);
+                    glyph_pitch.insert(i, (3 * width) as i32);
+                    glyph_ymin.insert(i, y_min);
+                    glyph_xbearing.insert(i, x_bearing);
+                    glyph_buffer.insert(i, glyph_image_i);
+                }
+                None => glyph_empty.push(i),
+            }
+            glyph_advance.insert(i, pixel_advance(face.glyph()));
+            glyph_telemetry.push(GlyphTelemetry {
+                code_point: i,
+                elapsed: start.elapsed(),
+                fallback: small_capital.map(|_| "synthetic small capital"),
+            });
+            continue;
+        }
+
+        // Draw a glyph image anti-aliased, or with the monochrome rasterizer. Color glyphs come
+        // out of FreeType already drawn.
+        let glyph_handle = face.glyph();
+        let render_mode = match spec.mono {
+            Some(Monochrome::Rasterizer) => freetype::render_mode::RenderMode::Mono,
+            _ => freetype::render_mode::RenderMode::Normal,
+        };
+        let border = match stroker {
+            Some(ref stroker) => create_stroked_glyph_image(glyph_handle, stroker).map_err(|e| {
+                SampleTypefaceError::StrokeCharacter(e, family.clone(), i)
+            })?,
+            None => None,
+        };
+
+        glyph_handle.render_glyph(render_mode).map_err(|e| {
+            SampleTypefaceError::RenderCharacter(e, family.clone(), i)
+        })?;
+
+        // Get the y-offset to place glyphs on baseline. This data lies in the bounding box.
+        let glyph = match glyph_handle.get_glyph() {
+            Ok(val) => val,
+            Err(e) => {
+                return Err(SampleTypefaceError::GetGlyphImage(e, family.clone(), i));
+            }
+        };
+
+        // Get the bounding box. Here "truncated" mode specifies that the dimensions
+        // of the bounding box are given in pixels.
+        let bbox = glyph.get_cbox(freetype::ffi::FT_GLYPH_BBOX_TRUNCATE);
+
+        // Get the dimensions of the bitmap.
+        let bitmap = glyph_handle.bitmap();
+        let mut rows = bitmap.rows();
+        let mut width = bitmap.width();
+        let mut pitch = bitmap.pitch();
+        let mut x_bearing = glyph_handle.bitmap_left();
+        let mut y_min = bbox.yMin;
+        let mut advance = pixel_advance(glyph_handle);
+        let color_glyph = color && match bitmap.pixel_mode() {
+            Ok(freetype::bitmap::PixelMode::Bgra) => true,
+            _ => false,
+        };
+        let mut glyph_image_i = if color {
+            if color_glyph {
+                glyph_color.push(i);
+            }
+            pitch = 4 * width;
+            create_color_glyph_image(glyph_handle)
+        } else if let Ok(freetype::bitmap::PixelMode::Mono) = bitmap.pixel_mode() {
+            pitch = width;
+            create_mono_glyph_image(glyph_handle)
+        } else {
+            create_glyph_image(glyph_handle)
+        };
+        if let Some((border_width, border_rows, border_left, border_top, border_image)) = border {
+            let fill_offset = (x_bearing - border_left, border_top - glyph_handle.bitmap_top());
+            glyph_image_i = create_outlined_glyph_image(
+                &glyph_image_i, width as usize, rows as usize, pitch as usize, fill_offset,
+                &border_image, border_width as usize, border_rows as usize, channels
+            );
+            rows = border_rows;
+            width = border_width;
+            pitch = channels as i32 * border_width;
+            x_bearing = border_left;
+            y_min = (border_top - border_rows) as freetype::ffi::FT_Pos;
+            advance += border_advance;
+        } else if !color && channels == 4 {
+            // Glyphs without a border still need all four channels for the borders and shadows of
+            // the other glyphs.
+            glyph_image_i = GlyphImage::new(
+                glyph_image_i.data.iter().flat_map(|&value| iter::repeat(value).take(4)).collect()
+            );
+            pitch *= 4;
+        }
+        if let Some(scale) = strike_scale {
+            let scaled_width = ((width as f32 * scale).round() as i32).max(1);
+            let scaled_rows = ((rows as f32 * scale).round() as i32).max(1);
+            glyph_image_i = resample_glyph_image(
+                &glyph_image_i, width as usize, rows as usize, channels,
+                scaled_width as usize, scaled_rows as usize, spec.resample_filter
+            );
+            rows = scaled_rows;
+            width = scaled_width;
+            pitch = channels as i32 * scaled_width;
+            x_bearing = (x_bearing as f32 * scale).round() as i32;
+            y_min = (y_min as f32 * scale).round() as freetype::ffi::FT_Pos;
+            advance = (advance as f32 * scale).round() as i32;
+        }
+        // The gamma curve applies to the coverage values before any threshold, and the white
+        // glyphs of color typefaces hold their coverage in every channel.
+        if let Some(ref gamma_table) = gamma_table {
+            if !color_glyph {
+                for value in glyph_image_i.data.iter_mut() {
+                    *value = gamma_table[*value as usize];
+                }
+            }
+        }
+        // Antialiased and resampled glyphs get cut off at the threshold to come out hard-edged.
+        if let Some(mono) = spec.mono {
+            let threshold = mono.threshold();
+            for value in glyph_image_i.data.iter_mut() {
+                *value = if *value >= threshold { 255 } else { 0 };
+            }
+        }
+        glyph_rows.insert(i, rows);
+        glyph_width.insert(i, width);
+        glyph_pitch.insert(i, pitch);
+        glyph_xbearing.insert(i, x_bearing);
+        glyph_ymin.insert(i, y_min);
+        glyph_advance.insert(i, advance);
+
+        // Glyphs without any visible pixels, like format characters, would only waste a slot.
+        if glyph_image_i.data.iter().all(|&pixel| pixel == 0) {
+            glyph_empty.push(i);
+        } else {
+            glyph_buffer.insert(i, glyph_image_i);
+        }
+
+        glyph_telemetry.push(GlyphTelemetry {
+            code_point: i,
+            elapsed: start.elapsed(),
+            fallback: small_capital.map(|_| "synthetic small capital"),
+        });
+    }
+
+    // Replace each glyph image with its distance field, which extends past the outline by the
+    // spread on every side. The bottom of the glyph moves down by the spread along with it.
+    if let Some(DistanceField::Sdf { spread }) = spec.distance_field {
+        for (code_point, glyph_image) in glyph_buffer.iter_mut() {
+            let width = glyph_width[code_point] as usize;
+            let rows = glyph_rows[code_point] as usize;
+            let pitch = glyph_pitch[code_point] as usize;
+            *glyph_image = create_signed_distance_field(glyph_image, width, rows, pitch, spread);
+            glyph_width.insert(*code_point, (width + 2 * spread) as i32);
+            glyph_rows.insert(*code_point, (rows + 2 * spread) as i32);
+            glyph_pitch.insert(*code_point, (width + 2 * spread) as i32);
+            *glyph_ymin.get_mut(code_point).unwrap() -= spread as i64;
+            *glyph_xbearing.get_mut(code_point).unwrap() -= spread as i32;
+        }
+    }
+
+    // Render the glow around each glyph, which grows the glyph image by its radius on every side.
+    if let (Some(glow), None) = (spec.glow, spec.distance_field) {
+        for (code_point, glyph_image) in glyph_buffer.iter_mut() {
+            let width = glyph_width[code_point] as usize;
+            let rows = glyph_rows[code_point] as usize;
+            let pitch = glyph_pitch[code_point] as usize;
+            *glyph_image = create_glowing_glyph_image(glyph_image, width, rows, pitch, channels, glow);
+            glyph_width.insert(*code_point, (width + 2 * glow.radius) as i32);
+            glyph_rows.insert(*code_point, (rows + 2 * glow.radius) as i32);
+            glyph_pitch.insert(*code_point, (channels * (width + 2 * glow.radius)) as i32);
+            *glyph_ymin.get_mut(code_point).unwrap() -= glow.radius as i64;
+            *glyph_xbearing.get_mut(code_point).unwrap() -= glow.radius as i32;
+        }
+    }
+
+    // Composite the shadow underneath each glyph, which grows the glyph image to hold both.
+    if let (Some(shadow), None) = (spec.shadow, spec.distance_field) {
+        for (code_point, glyph_image) in glyph_buffer.iter_mut() {
+            let width = glyph_width[code_point] as usize;
+            let rows = glyph_rows[code_point] as usize;
+            let pitch = glyph_pitch[code_point] as usize;
+            let (shadowed, bounds) = create_shadowed_glyph_image(
+                glyph_image, width, rows, pitch, channels, shadow
+            );
+            *glyph_image = shadowed;
+            glyph_width.insert(*code_point, bounds.width as i32);
+            glyph_rows.insert(*code_point, bounds.rows as i32);
+            glyph_pitch.insert(*code_point, (channels * bounds.width) as i32);
+            *glyph_ymin.get_mut(code_point).unwrap() -= bounds.below as i64;
+            *glyph_xbearing.get_mut(code_point).unwrap() -= bounds.left as i32;
+        }
+    }
+
+    let kerning = sample_kerning(face, chars, spec, &family)?;
+
+    Ok(GlyphTable {
+        rows: glyph_rows,
+        width: glyph_width,
+        pitch: glyph_pitch,
+        y_min: glyph_ymin,
+        x_bearing: glyph_xbearing,
+        advance: glyph_advance,
+        ascender: ascender,
+        line_height: line_height,
+        family: family,
+        buffer: glyph_buffer,
+        empty: glyph_empty,
+        zero_width: glyph_zero_width,
+        telemetry: glyph_telemetry,
+        channels: channels,
+        kerning: kerning,
+        render_modes: BTreeMap::new(),
+        glyph_typefaces: glyph_typefaces,
+        color_glyphs: glyph_color,
+    })
+}
+
+/// Sample a typeface like `sample_typeface`, spreading the characters over `threads` worker
+/// threads. FreeType faces cannot be shared across threads, so each worker opens the typeface from
+/// its file with a FreeType library, and a stroker for `--outline`, of its own. This pays off for
+/// the effects that make each glyph expensive, like stroking the borders of large glyphs. Each
+/// worker sets the design coordinates of `variation` on its face, so the glyphs of a variable font
+/// come out at the same instance on every thread. An optical size goes in as an `opsz` coordinate.
+/// The kerning pairs get sampled by the first worker, across all of the characters.
+pub fn sample_typeface_parallel(
+    font_path: &Path, face_index: usize, variation: &[VariationCoordinate],
+    spec: AtlasSpec, chars: &CharacterSet, threads: usize) -> Result<GlyphTable, SampleTypefaceError> {
+
+    let threads = threads.max(1);
+    let workers: Vec<_> = (0..threads)
+        .map(|worker| {
+            let font_path = font_path.to_path_buf();
+            let variation = variation.to_vec();
+            // Every worker takes every few characters, so the expensive ranges, like the CJK
+            // ideographs, get spread over all of them.
+            let worker_chars = chars.filter(|code_point| code_point % threads == worker);
+            let kerning_chars = if worker == 0 { Some(chars.clone()) } else { None };
+            std::thread::spawn(move || {
+                let open_error = |e| SampleTypefaceError::OpenTypeface(e, font_path.clone());
+                let library = Library::init().map_err(open_error)?;
+                let mut face = library.new_face(&font_path, face_index as isize).map_err(open_error)?;
+                if !variation.is_empty() {
+                    set_variation(&library, &mut face, &variation).map_err(|e| {
+                        SampleTypefaceError::SetVariation(e, font_path.clone())
+                    })?;
+                }
+                let mut glyph_tab = sample_typeface(&face, spec, &worker_chars)?;
+                glyph_tab.kerning = match kerning_chars {
+                    Some(ref chars) => sample_kerning(&face, chars, spec, &glyph_tab.family)?,
+                    None => vec![],
+                };
+
+                Ok(glyph_tab)
+            })
+        })
+        .collect();
+
+    let mut glyph_tabs = vec![];
+    for worker in workers.into_iter() {
+        match worker.join() {
+            Ok(glyph_tab) => glyph_tabs.push(glyph_tab?),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+    let mut glyph_tabs = glyph_tabs.into_iter();
+    let mut glyph_tab = glyph_tabs.next().expect("There is always at least one worker.");
+    for other in glyph_tabs {
+        glyph_tab.merge(other);
+    }
+    glyph_tab.telemetry.sort_by_key(|telemetry| telemetry.code_point);
+
+    Ok(glyph_tab)
+}
+
+/// Sample a chain of typefaces in priority order, drawing each character from the first
+/// typeface that covers it, e.g. to fill in the CJK and symbol characters a Latin typeface
+/// lacks. The characters of each substitution get drawn from its typeface instead, wherever
+/// it covers them, e.g. to take the ampersand and numerals from a brand typeface. The first
+/// substitution containing a character wins. The characters get rendered with the render mode
+/// overrides, if there are any, like `sample_typeface_with_modes`. The primary typeface
+/// provides the line metrics, and draws the last resort glyphs for the characters that no
+/// typeface covers. Kerning only applies between characters drawn from the same typeface.
+pub fn sample_typefaces(
+    faces: &[freetype::face::Face], substitutions: &[(CharacterSet, freetype::face::Face)],
+    spec: AtlasSpec, chars: &CharacterSet,
+    overrides: &[(CharacterSet, Option<DistanceField>)]) -> Result<GlyphTable, SampleTypefaceError> {
+
+    let sources: Vec<&freetype::face::Face> = faces.iter()
+        .chain(substitutions.iter().map(|&(_, ref face)| face))
+        .collect();
+    let mut groups = vec![vec![]; sources.len()];
+    for code_point in chars.code_points() {
+        let substitution = substitutions.iter().position(|&(ref substitution_chars, ref face)| {
+            substitution_chars.contains(code_point) && face.get_char_index(code_point) != 0
+        });
+        let source = match substitution {
+            Some(substitution) => faces.len() + substitution,
+            None => faces.iter().position(|face| face.get_char_index(code_point) != 0).unwrap_or(0),
+        };
+        groups[source].push((code_point, code_point));
+    }
+
+    let sample = |face: &freetype::face::Face, chars: &CharacterSet| {
+        if overrides.is_empty() {
+            sample_typeface(face, spec, chars)
+        } else {
+            sample_typeface_with_modes(face, spec, chars, overrides)
+        }
+    };
+    let mut secondary_tables = vec![];
+    for (&face, group) in sources.iter().zip(groups.iter()).skip(1) {
+        if group.is_empty() {
+            continue;
+        }
+        let mut secondary_tab = sample(face, &CharacterSet::new(group))?;
+        let family = secondary_tab.family.clone();
+        secondary_tab.glyph_typefaces = secondary_tab.buffer.keys()
+            .map(|&i| (i, family.clone()))
+            .collect();
+        secondary_tables.push(secondary_tab);
+    }
+    // The primary typeface goes last, so the face is left at the pixel size of the atlas.
+    let mut glyph_tab = sample(&faces[0], &CharacterSet::new(&groups[0]))?;
+    for secondary_tab in secondary_tables.into_iter() {
+        glyph_tab.merge(secondary_tab);
+    }
+
+    Ok(glyph_tab)
+}
+
+/// A single rasterized glyph, with the metrics an atlas would record for it.
+#[derive(Clone)]
+pub struct RasterizedGlyph {
+    /// The code point of the character.
+    pub code_point: usize,
+    /// The width of the glyph image in pixels.
+    pub width: i32,
+    /// The height of the glyph image in pixels.
+    pub rows: i32,
+    /// The number of bytes per row of the glyph image.
+    pub pitch: i32,
+    /// The horizontal offset in pixels from the pen position to the left edge of the glyph image.
+    pub x_bearing: i32,
+    /// The offset in pixels of the bottom of the glyph image from the baseline.
+    pub y_min: i64,
+    /// The distance in pixels the pen advances after drawing the character.
+    pub advance: i32,
+    /// The number of bytes per pixel of the glyph image, like `GlyphTable::channels`.
+    pub channels: usize,
+    /// The glyph image, or `None` for characters like the space that draw nothing.
+    pub image: Option<GlyphImage>,
+}
+
+/// Rasterize a single character of a typeface with the rendering configuration of an atlas
+/// specification, e.g. its glyph size, distance field, and effects, without packing an atlas.
+/// The glyph comes out just like the glyph of the same character in an atlas sampled with
+/// `sample_typeface`. Tools without an atlas can start from `AtlasSpec::for_glyph` instead.
+pub fn rasterize_glyph(
+    face: &freetype::face::Face, code_point: usize,
+    spec: AtlasSpec) -> Result<RasterizedGlyph, SampleTypefaceError> {
+
+    let glyph_tab = sample_typeface(face, spec, &CharacterSet::new(&[(code_point, code_point)]))?;
+    let metric = |table: &HashMap<usize, i32>| table.get(&code_point).cloned().unwrap_or(0);
+
+    Ok(RasterizedGlyph {
+        code_point: code_point,
+        width: metric(&glyph_tab.width),
+        rows: metric(&glyph_tab.rows),
+        pitch: metric(&glyph_tab.pitch),
+        x_bearing: metric(&glyph_tab.x_bearing),
+        y_min: glyph_tab.y_min.get(&code_point).cloned().unwrap_or(0),
+        advance: metric(&glyph_tab.advance),
+        channels: glyph_tab.channels,
+        image: glyph_tab.buffer.get(&code_point).cloned(),
+    })
+}
+
+/// Expand the glyph images of a glyph table to `channels` channels. Single channel values get
+/// copied into every channel, and multi-channel distance fields get the median of their color
+/// channels as their alpha channel, like in the atlas image.
+fn expand_channels(glyph_tab: &mut GlyphTable, channels: usize) {
+    let from_channels = glyph_tab.channels;
+    for (i, image) in glyph_tab.buffer.iter_mut() {
+        image.data = if from_channels == 3 {
+            image.data.chunks(3)
+                .flat_map(|pixel| {
+                    let median = pixel[0].max(pixel[1]).min(pixel[0].min(pixel[1]).max(pixel[2]));
+                    vec![pixel[0], pixel[1], pixel[2], median]
+                })
+                .collect()
+        } else {
+            image.data.iter()
+                .flat_map(|&value| iter::repeat(value).take(channels))
+                .collect()
+        };
+        glyph_tab.pitch.insert(*i, glyph_tab.width[i] * channels as i32);
+    }
+    glyph_tab.channels = channels;
+}
+
+/// Set the face to the render size of an atlas specification, either in pixels, or at the point
+/// size and resolution of the specification.
+fn set_render_size(
+    face: &freetype::face::Face, spec: AtlasSpec, family: &str) -> Result<(), SampleTypefaceError> {
+
+    let render_size = spec.render_size();
+    if render_size == 0 {
+        let e = freetype::error::Error::InvalidPixelSize;
+        return Err(SampleTypefaceError::SetPixelSize(e, family.to_string(), render_size));
+    }
+    if let Some((strike, _)) = nearest_strike(face, render_size) {
+        let error = unsafe { FT_Select_Size(face.raw() as *const _ as freetype::ffi::FT_Face, strike as _) };
+        if error != 0 {
+            let e = freetype::error::Error::from(error);
+            return Err(SampleTypefaceError::SetPixelSize(e, String::from(family), render_size));
+        }
+        return Ok(());
+    }
+    let result = match spec.point_size {
+        Some(point_size) => {
+            // Distance fields shrink the outlines to leave room for the spread, in points too.
+            let points = point_size.points * render_size as f32 / spec.glyph_size as f32;
+            let char_size = (points * 64.0).round() as isize;
+            face.set_char_size(0, char_size, point_size.dpi, point_size.dpi)
+        }
+        None => face.set_pixel_sizes(0, render_size as u32),
+    };
+
+    result.map_err(|e| SampleTypefaceError::SetPixelSize(e, String::from(family), render_size))
+}
+
+/// The face flag FreeType sets for typefaces with color glyph tables: `CBDT`, `sbix`, or `COLR`.
+const FT_FACE_FLAG_COLOR: freetype::ffi::FT_Long = 1 << 14;
+
+/// The face flag FreeType sets for typefaces with outlines that can be scaled to any size.
+const FT_FACE_FLAG_SCALABLE: freetype::ffi::FT_Long = 1;
+
+extern "C" {
+    fn FT_Select_Size(
+        face: freetype::ffi::FT_Face, strike_index: freetype::ffi::FT_Int) -> freetype::ffi::FT_Error;
+}
+
+extern "C" {
+    fn FT_Get_First_Char(
+        face: freetype::ffi::FT_Face, agindex: *mut freetype::ffi::FT_UInt) -> freetype::ffi::FT_ULong;
+    fn FT_Get_Next_Char(
+        face: freetype::ffi::FT_Face, char_code: freetype::ffi::FT_ULong,
+        agindex: *mut freetype::ffi::FT_UInt) -> freetype::ffi::FT_ULong;
+}
+
+/// The character set of every glyph in a typeface by glyph index, for sampling an atlas of all of
+/// its glyphs whether or not any code point maps to them.
+pub fn all_glyph_indices(face: &freetype::face::Face) -> CharacterSet {
+    match face.raw().num_glyphs {
+        num_glyphs if num_glyphs > 0 => CharacterSet::new(&[(0, num_glyphs as usize - 1)]),
+        _ => CharacterSet::default(),
+    }
+}
+
+/// The code points the character map of a typeface maps to each glyph, by glyph index. Glyphs no
+/// code point maps to, like ligatures and stylistic alternates, are left out.
+pub fn glyph_code_points(face: &freetype::face::Face) -> BTreeMap<usize, Vec<usize>> {
+    let raw_face = face.raw() as *const _ as freetype::ffi::FT_Face;
+    let mut code_points: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let mut glyph_index = 0;
+    let mut code_point = unsafe { FT_Get_First_Char(raw_face, &mut glyph_index) };
+    while glyph_index != 0 {
+        code_points.entry(glyph_index as usize).or_default().push(code_point as usize);
+        code_point = unsafe { FT_Get_Next_Char(raw_face, code_point, &mut glyph_index) };
+    }
+
+    code_points
+}
+
+/// Whether a typeface has color glyph tables, like the emoji of Noto Color Emoji.
+pub fn has_color_glyphs(face: &freetype::face::Face) -> bool {
+    face.raw().face_flags & FT_FACE_FLAG_COLOR != 0
+}
+
+/// The bitmap strike to draw the glyphs of a typeface without outlines from, like the `CBDT`
+/// and `sbix` color emoji typefaces, along with its size in pixels. This is the smallest strike
+/// at least as large as the render size, or the largest strike when there is none. Typefaces
+/// with outlines get rendered at the exact size instead.
+fn nearest_strike(face: &freetype::face::Face, render_size: usize) -> Option<(usize, usize)> {
+    let raw = face.raw();
+    let scalable = raw.face_flags & FT_FACE_FLAG_SCALABLE != 0;
+    if scalable || raw.num_fixed_sizes <= 0 || raw.available_sizes.is_null() {
+        return None;
+    }
+    let strikes = unsafe { std::slice::from_raw_parts(raw.available_sizes, raw.num_fixed_sizes as usize) };
+    let sizes: Vec<(usize, usize)> = strikes.iter().enumerate()
+        .map(|(strike, size)| (strike, (((size.y_ppem + 32) >> 6) as usize).max(1)))
+        .collect();
+
+    sizes.iter().filter(|&&(_, ppem)| ppem >= render_size).min_by_key(|&&(_, ppem)| ppem)
+        .or_else(|| sizes.iter().max_by_key(|&&(_, ppem)| ppem))
+        .cloned()
+}
+
+/// Read the kerning pairs between the characters of a character set at the current pixel
+/// size of the face. FreeType only has kerning for the pairs of glyphs listed in the `kern`
+/// table, and none for the pairs of the `GPOS` table, so only the pairs in the `kern` table
+/// get looked up, instead of every pair of characters. Typefaces without a `kern` table skip it
+/// entirely. When the table cannot be read, every pair of characters gets looked up.
+fn sample_kerning(
+    face: &freetype::face::Face, chars: &CharacterSet,
+    spec: AtlasSpec, family: &str) -> Result<Vec<KerningPair>, SampleTypefaceError> {
+
+    let mut kerning = vec![];
+    if !face.has_kerning() {
+        return Ok(kerning);
+    }
+    let mut code_points: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    for code_point in chars.code_points() {
+        let glyph_index = glyph_index(face, spec, code_point);
+        if glyph_index != 0 {
+            code_points.entry(glyph_index).or_default().push(code_point);
+        }
+    }
+    let glyph_pairs: Vec<(u32, u32)> = match kerning_table_pairs(face) {
+        Some(pairs) => pairs.into_iter()
+            .filter(|&(first, second)| code_points.contains_key(&first) && code_points.contains_key(&second))
+            .collect(),
+        None => code_points.keys()
+            .flat_map(|&first| code_points.keys().map(move |&second| (first, second)))
+            .collect(),
+    };
+    for (first_index, second_index) in glyph_pairs.into_iter() {
+        let first = code_points[&first_index][0];
+        let second = code_points[&second_index][0];
+        let delta = face.get_kerning(first_index, second_index, freetype::face::KerningMode::KerningDefault)
+            .map_err(|e| SampleTypefaceError::GetKerning(e, String::from(family), first, second))?;
+        let amount = (delta.x as f32 * spec.stretch / 64.0).round() as i32;
+        if amount == 0 {
+            continue;
+        }
+        for &first in code_points[&first_index].iter() {
+            for &second in code_points[&second_index].iter() {
+                kerning.push(KerningPair { first: first, second: second, amount: amount });
+            }
+        }
+    }
+    kerning.sort_by_key(|pair| (pair.first, pair.second));
+
+    Ok(kerning)
+}
+
+/// The pairs of glyph indices in the format 0 subtables of the `kern` table of a face, the only
+/// subtables FreeType reads kerning from, or `None` if the table cannot be read.
+fn kerning_table_pairs(face: &freetype::face::Face) -> Option<BTreeSet<(u32, u32)>> {
+    let table = load_sfnt_table(face, *b"kern")?;
+    let read_u16 = |offset: usize| -> Option<usize> {
+        table.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    // Only the version 0 tables of Microsoft hold kerning FreeType reads.
+    if read_u16(0)? != 0 {
+        return None;
+    }
+    let mut pairs = BTreeSet::new();
+    let mut offset = 4;
+    for _ in 0..read_u16(2)? {
+        let length = read_u16(offset + 2)?;
+        let coverage = read_u16(offset + 4)?;
+        // Format 0 subtables hold a sorted list of pairs after a binary search header.
+        if coverage >> 8 == 0 {
+            let pair_count = read_u16(offset + 6)?;
+            let pairs_offset = offset + 14;
+            for pair in 0..pair_count {
+                let pair_offset = pairs_offset + 6 * pair;
+                pairs.insert((read_u16(pair_offset)? as u32, read_u16(pair_offset + 2)? as u32));
+            }
+            // The length of a large subtable overflows its 16 bit field.
+            offset = pairs_offset + 6 * pair_count;
+        } else {
+            offset += length;
+        }
+    }
+
+    Some(pairs)
+}
+
+/// Sample a typeface like `sample_typeface`, but render the characters of each override with
+/// its own kind of distance field, or as plain coverage values for `None`, instead of the one
+/// of the atlas specification. The first override containing a character wins. When the
+/// overrides mix single and multi-channel images, the single channel images get copied into
+/// all three channels. The render mode of each glyph gets recorded in the glyph table.
+pub fn sample_typeface_with_modes(
+    face: &freetype::face::Face, spec: AtlasSpec, chars: &CharacterSet,
+    overrides: &[(CharacterSet, Option<DistanceField>)]) -> Result<GlyphTable, SampleTypefaceError> {
+
+    let mut groups = vec![vec![]; overrides.len()];
+    let mut base_code_points = vec![];
+    for code_point in chars.code_points() {
+        match overrides.iter().position(|&(ref override_chars, _)| override_chars.contains(code_point)) {
+            Some(group) => groups[group].push((code_point, code_point)),
+            None => base_code_points.push((code_point, code_point)),
+        }
+    }
+
+    let mut tables = vec![];
+    for (group, &(_, distance_field)) in groups.iter().zip(overrides.iter()) {
+        if group.is_empty() {
+            continue;
+        }
+        let mut group_spec = spec;
+        group_spec.distance_field = distance_field;
+        tables.push((sample_typeface(face, group_spec, &CharacterSet::new(group))?, distance_field));
+    }
+    // The base characters go last, so the face is left at the pixel size of the atlas.
+    let mut glyph_tab = sample_typeface(face, spec, &CharacterSet::new(&base_code_points))?;
+    let base_mode = spec.distance_field.map_or("coverage", |distance_field| distance_field.name());
+    for &i in glyph_tab.buffer.keys() {
+        glyph_tab.render_modes.insert(i, base_mode);
+    }
+    for (table, distance_field) in tables.into_iter() {
+        let mode = distance_field.map_or("coverage", |distance_field| distance_field.name());
+        for &i in table.buffer.keys() {
+            glyph_tab.render_modes.insert(i, mode);
+        }
+        glyph_tab.merge(table);
+    }
+    glyph_tab.kerning = sample_kerning(face, chars, spec, &glyph_tab.family)?;
+
+    Ok(glyph_tab)
+}
+
+/// The unscaled metrics of a glyph, in font units.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GlyphUnitMetrics {
+    /// The distance the pen advances after drawing the glyph.
+    pub advance: i64,
+    /// The horizontal distance from the pen position to the left edge of the glyph outline.
+    pub x_bearing: i64,
+    /// The vertical distance from the baseline to the top edge of the glyph outline.
+    pub y_bearing: i64,
+    /// The width of the glyph outline.
+    pub width: i64,
+    /// The height of the glyph outline.
+    pub height: i64,
+}
+
+/// The unscaled metrics of a typeface and the glyphs sampled from it, in font units. Dividing
+/// these by `units_per_em` and multiplying by a pixel size gives the layout at that size, so
+/// consumers can lay text out at any display size from a single atlas.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FontUnitMetrics {
+    /// The number of font units per em square.
+    pub units_per_em: u16,
+    /// The distance from the baseline to the top of a line of text.
+    pub ascender: i16,
+    /// The distance from the baseline to the bottom of a line of text, usually negative.
+    pub descender: i16,
+    /// The distance between the baselines of consecutive lines of text.
+    pub line_height: i16,
+    /// The metrics of each glyph the typeface covers, by code point.
+    pub glyphs: BTreeMap<usize, GlyphUnitMetrics>,
+}
+
+/// Sample the unscaled metrics of the characters in a glyph table from the typeface it was
+/// sampled from. Characters the typeface does not cover, such as last resort glyphs, are left
+/// out. The metrics do not include the synthetic stretch, slant, or small capitals.
+pub fn sample_font_unit_metrics(
+    face: &freetype::face::Face,
+    glyph_tab: &GlyphTable) -> Result<FontUnitMetrics, SampleTypefaceError> {
+
+    let mut glyphs = BTreeMap::new();
+    for code_point in covered_code_points(face, glyph_tab) {
+        face.load_char(code_point, freetype::face::LoadFlag::NO_SCALE).map_err(|e| {
+            SampleTypefaceError::LoadCharacter(e, family_name(face), code_point)
+        })?;
+        let metrics = face.glyph().metrics();
+        glyphs.insert(code_point, GlyphUnitMetrics {
+            advance: metrics.horiAdvance as i64,
+            x_bearing: metrics.horiBearingX as i64,
+            y_bearing: metrics.horiBearingY as i64,
+            width: metrics.width as i64,
+            height: metrics.height as i64,
+        });
+    }
+
+    Ok(FontUnitMetrics {
+        units_per_em: face.raw().units_per_EM,
+        ascender: face.ascender(),
+        descender: face.descender(),
+        line_height: face.height(),
+        glyphs: glyphs,
+    })
+}
+
+/// Where a typeface draws the lines of underlined, overlined, and struck through text, in pixels
+/// at the size the glyphs were rendered at. The positions are the centers of the lines, above the
+/// baseline, so an underline usually has a negative position.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuleMetrics {
+    /// The position of the underline.
+    pub underline_position: i32,
+    /// The thickness of the underline.
+    pub underline_thickness: i32,
+    /// The position of the strikeout line.
+    pub strikeout_position: i32,
+    /// The thickness of the strikeout line.
+    pub strikeout_thickness: i32,
+    /// The position of the overline, at the ascender of the typeface, with the thickness of the
+    /// underline.
+    pub overline_position: i32,
+}
+
+/// Scale the underline and strikeout metrics of a typeface to the size the glyphs of an atlas get
+/// rendered at. The underline comes from the `post` table and the strikeout line from the `OS/2`
+/// table. Typefaces without an `OS/2` table get a strikeout line a quarter em above the baseline,
+/// as thick as the underline. Every line is at least a pixel thick.
+pub fn sample_rule_metrics(face: &freetype::face::Face, spec: AtlasSpec) -> RuleMetrics {
+    let scale = spec.render_size() as f64 / face.raw().units_per_EM.max(1) as f64;
+    let pixels = |units: i16| (units as f64 * scale).round() as i32;
+    let underline_thickness = face.raw().underline_thickness;
+    // The strikeout size and position follow the average width, weight, width class, and the
+    // subscript and superscript metrics, at the start of every version of the table.
+    let strikeout = load_sfnt_table(face, *b"OS/2")
+        .filter(|os2| os2.len() >= 30)
+        .map(|os2| (i16::from_be_bytes([os2[26], os2[27]]), i16::from_be_bytes([os2[28], os2[29]])));
+    let (strikeout_thickness, strikeout_position) = match strikeout {
+        Some((thickness, position)) if thickness > 0 => (thickness, position),
+        _ => (underline_thickness, (face.raw().units_per_EM / 4) as i16),
+    };
+
+    RuleMetrics {
+        underline_position: pixels(face.raw().underline_position),
+        underline_thickness: pixels(underline_thickness).max(1),
+        strikeout_position: pixels(strikeout_position),
+        strikeout_thickness: pixels(strikeout_thickness).max(1),
+        overline_position: pixels(face.ascender()),
+    }
+}
+
+/// The code points of a glyph table that the typeface has glyphs for.
+fn covered_code_points(face: &freetype::face::Face, glyph_tab: &GlyphTable) -> Vec<usize> {
+    let mut code_points = glyph_tab.slots();
+    code_points.extend(glyph_tab.empty.iter().chain(glyph_tab.zero_width.iter()));
+    code_points.retain(|&code_point| face.get_char_index(code_point) != 0);
+
+    code_points
+}
+
+/// How the metrics of the glyphs rendered at one size compare with the ideal metrics, scaled
+/// straight from the font units without any rounding or hinting.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SizeMetrics {
+    /// The size tier of the atlas, `base` for body text or `ruby` for annotation text.
+    pub tier: &'static str,
+    /// The multiple of the glyph size of the main atlas the glyphs were rendered at.
+    pub scale: f32,
+    /// The size of an em in pixels, which can be fractional for sizes given in points.
+    pub pixels_per_em: f32,
+    /// The height of the lowercase `x` in pixels, if the atlas has one.
+    pub x_height: Option<i64>,
+    /// The height of the lowercase `x` scaled from the font units, if the atlas has one.
+    pub ideal_x_height: Option<f32>,
+    /// The sum of the advances of the characters, in pixels, i.e. the width of a line of text
+    /// holding each character once.
+    pub text_width: i64,
+    /// The sum of the advances of the characters scaled from the font units.
+    pub ideal_text_width: f32,
+    /// How far the text width strays from the ideal, as a fraction of the ideal width.
+    pub text_width_error: f32,
+    /// The difference between the advance and the ideal advance in pixels, by code point, for
+    /// the characters whose advances are off by more than rounding to whole pixels explains.
+    pub advance_errors: BTreeMap<usize, f32>,
+}
+
+/// A comparison of the glyph metrics across the sizes generated in the same run, showing how
+/// advances and the x-height scale, and where rounding and hinting break proportionality.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SizeReport {
+    /// The family name of the typeface.
+    pub family: String,
+    /// The number of font units per em square.
+    pub units_per_em: u16,
+    /// The metrics of each size, in the order the atlases were generated.
+    pub sizes: Vec<SizeMetrics>,
+}
+
+/// The size of an em in pixels that the glyphs of an atlas get rendered at.
+fn pixels_per_em(spec: AtlasSpec) -> f32 {
+    let render_size = spec.render_size() as f32;
+    match spec.point_size {
+        // Distance fields shrink the outlines to leave room for the spread.
+        Some(point_size) => {
+            point_size.points * point_size.dpi as f32 / 72.0 * render_size / spec.glyph_size as f32
+        }
+        None => render_size,
+    }
+}
+
+/// Compare the metrics of the glyphs in a glyph table with the ideal metrics of the typeface
+/// it was sampled from at the same size. Glyphs drawn from other typefaces are left out, and
+/// the ideal metrics do not include the synthetic small capitals.
+pub fn measure_size_metrics(
+    face: &freetype::face::Face, glyph_tab: &GlyphTable, spec: AtlasSpec,
+    tier: &'static str, scale: f32) -> Result<SizeMetrics, SampleTypefaceError> {
+
+    let font_units = sample_font_unit_metrics(face, glyph_tab)?;
+    let pixels_per_em = pixels_per_em(spec);
+    let pixels_per_unit = pixels_per_em / font_units.units_per_em as f32;
+    let spread = spec.distance_field.map_or(0, |distance_field| distance_field.spread()) as i64;
+    let x = 'x' as usize;
+    let x_height = match (glyph_tab.y_min.get(&x), glyph_tab.rows.get(&x)) {
+        (Some(&y_min), Some(&rows)) if !glyph_tab.glyph_typefaces.contains_key(&x) => {
+            Some(y_min + rows as i64 - spread)
+        }
+        _ => None,
+    };
+    let ideal_x_height = font_units.glyphs.get(&x).map(|metrics| metrics.y_bearing as f32 * pixels_per_unit);
+
+    let mut text_width = 0;
+    let mut ideal_text_width = 0.0;
+    let mut advance_errors = BTreeMap::new();
+    for (code_point, metrics) in font_units.glyphs.iter() {
+        let advance = match glyph_tab.advance.get(code_point) {
+            Some(&val) if !glyph_tab.glyph_typefaces.contains_key(code_point) => val as i64,
+            _ => continue,
+        };
+        let ideal_advance = metrics.advance as f32 * pixels_per_unit * spec.stretch;
+        text_width += advance;
+        ideal_text_width += ideal_advance;
+        let error = advance as f32 - ideal_advance;
+        if error.abs() > 0.5 {
+            advance_errors.insert(*code_point, error);
+        }
+    }
+    let text_width_error = if ideal_text_width > 0.0 {
+        (text_width as f32 - ideal_text_width) / ideal_text_width
+    } else {
+        0.0
+    };
+
+    Ok(SizeMetrics {
+        tier: tier,
+        scale: scale,
+        pixels_per_em: pixels_per_em,
+        x_height: x_height,
+        ideal_x_height: ideal_x_height,
+        text_width: text_width,
+        ideal_text_width: ideal_text_width,
+        text_width_error: text_width_error,
+        advance_errors: advance_errors,
+    })
+}
+
+/// Write a size report out to a JSON file.
+pub fn write_size_report<P: AsRef<Path>>(path: P, report: &SizeReport) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, report)?;
+
+        Ok(())
+    })
+}
+
+/// Which of the requested characters the typefaces of a run cover, so missing glyphs get caught
+/// when the atlas gets built rather than when the text shows up as boxes.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CoverageReport {
+    /// The family name of the primary typeface.
+    pub family: String,
+    /// The number of characters requested, leaving out the zero width and control characters,
+    /// which never get a glyph of their own.
+    pub requested: usize,
+    /// The number of requested characters that some typeface has a glyph for.
+    pub covered: usize,
+    /// The code points of the requested characters that no typeface has a glyph for.
+    pub missing: Vec<usize>,
+    /// What happened to the missing characters: `drawn` with the missing glyph of the typeface,
+    /// or a fallback glyph, `skipped`, or `failed` the run.
+    pub on_missing: &'static str,
+}
+
+/// Write a coverage report out to a JSON file.
+pub fn write_coverage_report<P: AsRef<Path>>(path: P, report: &CoverageReport) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, report)?;
+
+        Ok(())
+    })
+}
+
+/// The size of a string set on a single line in the glyphs of an atlas, for sizing the UI
+/// elements that hold it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StringMetrics {
+    /// The string.
+    pub text: String,
+    /// The width of the string in pixels, from the pen position of its first character to the pen
+    /// position after its last one, including the kerning between its characters.
+    pub width: i64,
+    /// The height of the string in pixels, i.e. the line height of the atlas.
+    pub height: i32,
+    /// The smallest box holding the pixels the string draws, as `[x_min, y_min, x_max, y_max]`
+    /// relative to the start of the string on the baseline, with y pointing up. Glyphs can reach
+    /// past the width, so text that must not get clipped should fit this box instead. Strings
+    /// drawing no pixels have no box.
+    pub ink_bounds: Option<[i64; 4]>,
+    /// The code points of the characters of the string missing from the atlas, which draw
+    /// nothing and do not advance the pen.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<usize>,
+}
+
+/// The sizes of a list of strings set in the glyphs of an atlas.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StringMetricsReport {
+    /// The family name of the typeface.
+    pub family: String,
+    /// The distance between baselines in pixels.
+    pub line_height: i32,
+    /// The distance from the top of a line to the baseline in pixels.
+    pub ascender: i32,
+    /// The sizes of the strings, in the order they were given.
+    pub strings: Vec<StringMetrics>,
+}
+
+/// Measure strings the way a runtime lays them out from an atlas, with whole pixel advances and
+/// the kerning recorded in the glyph table.
+pub fn measure_strings(glyph_tab: &GlyphTable, strings: &[String]) -> StringMetricsReport {
+    let kerning = kerning_table(glyph_tab);
+    let strings = strings.iter()
+        .map(|text| {
+            let (pen_positions, width) = pen_positions(glyph_tab, &kerning, text);
+            let mut ink_bounds: Option<[i64; 4]> = None;
+            let mut missing = vec![];
+            for (code_point, pen_x) in pen_positions {
+                if !glyph_tab.advance.contains_key(&code_point) {
+                    if !missing.contains(&code_point) {
+                        missing.push(code_point);
+                    }
+                    continue;
+                }
+                if !glyph_tab.buffer.contains_key(&code_point) {
+                    continue;
+                }
+                let x_min = pen_x + glyph_tab.x_bearing[&code_point] as i64;
+                let y_min = glyph_tab.y_min[&code_point];
+                let x_max = x_min + glyph_tab.width[&code_point] as i64;
+                let y_max = y_min + glyph_tab.rows[&code_point] as i64;
+                ink_bounds = Some(match ink_bounds {
+                    Some(bounds) => [
+                        bounds[0].min(x_min), bounds[1].min(y_min), bounds[2].max(x_max), bounds[3].max(y_max)
+                    ],
+                    None => [x_min, y_min, x_max, y_max],
+                });
+            }
+
+            StringMetrics {
+                text: text.clone(),
+                width: width,
+                height: glyph_tab.line_height,
+                ink_bounds: ink_bounds,
+                missing: missing,
+            }
+        })
+        .collect();
+
+    StringMetricsReport {
+        family: glyph_tab.family.clone(),
+        line_height: glyph_tab.line_height,
+        ascender: glyph_tab.ascender,
+        strings: strings,
+    }
+}
+
+/// Write the sizes of a list of strings out to a JSON file.
+pub fn write_string_metrics<P: AsRef<Path>>(path: P, report: &StringMetricsReport) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, report)?;
+
+        Ok(())
+    })
+}
+
+/// An atlas of a locale bundle, along with the characters it holds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BundleAtlas {
+    /// The locale of a supplement atlas, or `None` for the shared base atlas.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// The name of the file holding the atlas.
+    pub atlas: String,
+    /// The name of the `.meta.json` file of the atlas.
+    pub metadata: String,
+    /// The atlas the supplement atlas builds on, which holds the characters it leaves out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// The code point ranges of the characters in the atlas, e.g. `U+0370-U+03FF`.
+    pub chars: Vec<String>,
+}
+
+/// A manifest of a locale bundle: a shared base atlas of the Latin characters, punctuation, and
+/// digits, and a supplement atlas of the other characters of each locale. Text in a locale gets
+/// drawn from its supplement atlas along with the base atlas, so the base characters only take
+/// up texture memory once, however many locales are installed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BundleManifest {
+    /// The shared base atlas.
+    pub base: BundleAtlas,
+    /// The supplement atlas of each locale, in the order the locales were given.
+    pub locales: Vec<BundleAtlas>,
+}
+
+/// Write a locale bundle manifest out to a JSON file.
+pub fn write_bundle_manifest<P: AsRef<Path>>(path: P, manifest: &BundleManifest) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, manifest)?;
+
+        Ok(())
+    })
+}
+
+/// The vector outline of a glyph.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GlyphOutline {
+    /// The distance the pen advances after drawing the glyph, in font units.
+    pub advance: i64,
+    /// The outline as SVG path data in font units, with the baseline at `y = 0` and the
+    /// `y` axis pointing down as in SVG. Empty for glyphs without an outline, like the space.
+    pub path: String,
+}
+
+/// The vector outlines of the glyphs sampled from a typeface.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OutlineExport {
+    /// The number of font units per em square.
+    pub units_per_em: u16,
+    /// The outline of each glyph the typeface covers, by code point.
+    pub glyphs: BTreeMap<usize, GlyphOutline>,
+}
+
+/// Convert a glyph outline into SVG path data, flipping the `y` axis.
+fn svg_path_data(outline: &freetype::outline::Outline) -> String {
+    let point = |v: &freetype::Vector| format!("{} {}", v.x, -v.y);
+    let mut commands = vec![];
+    for contour in outline.contours_iter() {
+        commands.push(format!("M{}", point(contour.start())));
+        for curve in contour {
+            match curve {
+                freetype::outline::Curve::Line(p) => commands.push(format!("L{}", point(&p))),
+                freetype::outline::Curve::Bezier2(c, p) => {
+                    commands.push(format!("Q{} {}", point(&c), point(&p)));
+                }
+                freetype::outline::Curve::Bezier3(c1, c2, p) => {
+                    commands.push(format!("C{} {} {}", point(&c1), point(&c2), point(&p)));
+                }
+            }
+        }
+        commands.push(String::from("Z"));
+    }
+
+    commands.join(" ")
+}
+
+/// Extract the unscaled vector outlines of the characters in a glyph table from the typeface
+/// it was sampled from, e.g. for generating text meshes. Characters the typeface does not
+/// cover are left out, and the outlines do not include the synthetic stretch, slant, or small capitals.
+pub fn extract_glyph_outlines(
+    face: &freetype::face::Face,
+    glyph_tab: &GlyphTable) -> Result<OutlineExport, SampleTypefaceError> {
+
+    let mut glyphs = BTreeMap::new();
+    for code_point in covered_code_points(face, glyph_tab) {
+        face.load_char(code_point, freetype::face::LoadFlag::NO_SCALE).map_err(|e| {
+            SampleTypefaceError::LoadCharacter(e, family_name(face), code_point)
+        })?;
+        let glyph = face.glyph();
+        let path = glyph.outline().map(|outline| svg_path_data(&outline)).unwrap_or_default();
+        glyphs.insert(code_point, GlyphOutline {
+            advance: glyph.metrics().horiAdvance as i64,
+            path: path,
+        });
+    }
+
+    Ok(OutlineExport {
+        units_per_em: face.raw().units_per_EM,
+        glyphs: glyphs,
+    })
+}
+
+/// Write the glyph outlines of an atlas out to a JSON file.
+pub fn write_glyph_outlines<P: AsRef<Path>>(path: P, outlines: &OutlineExport) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, outlines)?;
+
+        Ok(())
+    })
+}
+
+/// The number of texels in each row of a curve texture.
+pub const CURVE_TEXTURE_WIDTH: usize = 4096;
+
+/// The range of a glyph's curves in a curve texture.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct CurveGlyph {
+    /// The index of the first texel of the glyph's first curve.
+    pub first_texel: usize,
+    /// The number of quadratic curves in the glyph. Each curve takes up two texels.
+    pub curve_count: usize,
+    /// The distance the pen advances after drawing the glyph, in ems.
+    pub advance: f32,
+    /// The box enclosing the points of all the glyph's curves as `[x_min, y_min, x_max, y_max]`
+    /// in ems, or `None` for glyphs without an outline, like the space.
+    pub bounds: Option<[f32; 4]>,
+}
+
+/// The glyph outlines of an atlas as quadratic Bezier curves packed into a data texture, for
+/// GPU text renderers that evaluate the curves in a shader, like Slug or Loop-Blinn renderers.
+/// Each curve takes up two consecutive RGBA texels, `(x0, y0, x1, y1)` and `(x2, y2, 0, 0)`,
+/// holding its start point, control point, and end point in ems with the `y` axis pointing up.
+/// Lines become curves with the control point halfway along them, and cubic curves get split
+/// into quadratic ones.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CurveAtlas {
+    /// The number of font units per em square of the typeface.
+    pub units_per_em: u16,
+    /// The width of the data texture in texels.
+    pub texture_width: usize,
+    /// The height of the data texture in texels.
+    pub texture_height: usize,
+    /// The curves of each glyph the typeface covers, by code point.
+    pub glyphs: BTreeMap<usize, CurveGlyph>,
+    /// The texels of the data texture, row by row.
+    #[serde(skip)]
+    pub texels: Vec<[f32; 4]>,
+}
+
+impl CurveAtlas {
+    /// The data texture as little endian 32 bit floats, four per texel, row by row.
+    pub fn texture_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.texels.len() * 16);
+        for texel in self.texels.iter() {
+            for value in texel.iter() {
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+}
+
+/// Convert the contours of a glyph outline into quadratic Bezier curves, in font units.
+fn quadratic_curves(outline: &freetype::outline::Outline) -> Vec<[(f32, f32); 3]> {
+    let point = |v: &freetype::Vector| (v.x as f32, v.y as f32);
+    let lerp = |a: (f32, f32), b: (f32, f32), t: f32| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+    let mut curves = vec![];
+    for contour in outline.contours_iter() {
+        let start = point(contour.start());
+        let mut current = start;
+        for curve in contour {
+            match curve {
+                freetype::outline::Curve::Line(p) => {
+                    let p = point(&p);
+                    curves.push([current, lerp(current, p, 0.5), p]);
+                    current = p;
+                }
+                freetype::outline::Curve::Bezier2(c, p) => {
+                    let p = point(&p);
+                    curves.push([current, point(&c), p]);
+                    current = p;
+                }
+                freetype::outline::Curve::Bezier3(c1, c2, p) => {
+                    // Split the cubic curve in half, and approximate each half by a quadratic curve
+                    // whose control point averages the two that the cubic's end tangents point to.
+                    let (c1, c2, p) = (point(&c1), point(&c2), point(&p));
+                    let ab = lerp(current, c1, 0.5);
+                    let bc = lerp(c1, c2, 0.5);
+                    let cd = lerp(c2, p, 0.5);
+                    let abc = lerp(ab, bc, 0.5);
+                    let bcd = lerp(bc, cd, 0.5);
+                    let middle = lerp(abc, bcd, 0.5);
+                    for &(p0, q1, q2, p3) in [(current, ab, abc, middle), (middle, bcd, cd, p)].iter() {
+                        let control = (
+                            (3.0 * (q1.0 + q2.0) - p0.0 - p3.0) / 4.0,
+                            (3.0 * (q1.1 + q2.1) - p0.1 - p3.1) / 4.0,
+                        );
+                        curves.push([p0, control, p3]);
+                    }
+                    current = p;
+                }
+            }
+        }
+        if current != start {
+            curves.push([current, lerp(current, start, 0.5), start]);
+        }
+    }
+
+    curves
+}
+
+/// Extract the outlines of the characters in a glyph table from the typeface it was sampled
+/// from as a curve atlas. Characters the typeface does not cover are left out, and the curves
+/// do not include the synthetic stretch, slant, or small capitals.
+pub fn extract_curve_atlas(
+    face: &freetype::face::Face,
+    glyph_tab: &GlyphTable) -> Result<CurveAtlas, SampleTypefaceError> {
+
+    let units_per_em = face.raw().units_per_EM;
+    let scale = 1.0 / units_per_em as f32;
+    let mut glyphs = BTreeMap::new();
+    let mut texels = vec![];
+    for code_point in covered_code_points(face, glyph_tab) {
+        face.load_char(code_point, freetype::face::LoadFlag::NO_SCALE).map_err(|e| {
+            SampleTypefaceError::LoadCharacter(e, family_name(face), code_point)
+        })?;
+        let glyph = face.glyph();
+        let curves = glyph.outline().map(|outline| quadratic_curves(&outline)).unwrap_or_default();
+        let first_texel = texels.len();
+        let mut bounds: Option<[f32; 4]> = None;
+        for curve in curves.iter() {
+            let [p0, p1, p2] = *curve;
+            texels.push([p0.0 * scale, p0.1 * scale, p1.0 * scale, p1.1 * scale]);
+            texels.push([p2.0 * scale, p2.1 * scale, 0.0, 0.0]);
+            for &(x, y) in [p0, p1, p2].iter() {
+                let (x, y) = (x * scale, y * scale);
+                bounds = Some(match bounds {
+                    Some([x_min, y_min, x_max, y_max]) => {
+                        [x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y)]
+                    }
+                    None => [x, y, x, y],
+                });
+            }
+        }
+        glyphs.insert(code_point, CurveGlyph {
+            first_texel: first_texel,
+            curve_count: curves.len(),
+            advance: glyph.metrics().horiAdvance as f32 * scale,
+            bounds: bounds,
+        });
+    }
+
+    let texture_height = (texels.len() + CURVE_TEXTURE_WIDTH - 1) / CURVE_TEXTURE_WIDTH;
+    texels.resize(texture_height * CURVE_TEXTURE_WIDTH, [0.0; 4]);
+
+    Ok(CurveAtlas {
+        units_per_em: units_per_em,
+        texture_width: CURVE_TEXTURE_WIDTH,
+        texture_height: texture_height,
+        glyphs: glyphs,
+        texels: texels,
+    })
+}
+
+/// Write a curve atlas out as a JSON index of the glyphs and a binary data texture.
+pub fn write_curve_atlas<P: AsRef<Path>, Q: AsRef<Path>>(
+    index_path: P, texture_path: Q, curve_atlas: &CurveAtlas) -> io::Result<()> {
+
+    write_atomically(index_path, |index_path| {
+        let file = File::create(index_path)?;
+        serde_json::to_writer_pretty(file, curve_atlas)?;
+
+        Ok(())
+    })?;
+
+    write_atomically(texture_path, |texture_path| fs::write(texture_path, curve_atlas.texture_bytes()))
+}
+
+/// The rectangle of the atlas image reserved for a glyph, including its padding, in pixels
+/// from the top left corner of the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GlyphCell {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The placement of the glyph images in an atlas image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlyphLayout {
+    /// The width of the atlas image in pixels.
+    pub width: usize,
+    /// The height of the atlas image in pixels.
+    pub height: usize,
+    /// The cell of each glyph with an image, by code point.
+    pub cells: HashMap<usize, GlyphCell>,
+}
+
+/// Place the glyphs of a glyph table in the grid of uniform slots of an atlas specification.
+/// The first slot is reserved for the space.
+fn layout_grid(glyph_tab: &GlyphTable, spec: AtlasSpec) -> GlyphLayout {
+    let mut cells = HashMap::new();
+    for (slot, i) in glyph_tab.slots().iter().enumerate().skip(1).take(spec.rows * spec.columns - 1) {
+        if !glyph_tab.buffer.contains_key(i) {
+            continue;
+        }
+        cells.insert(*i, GlyphCell {
+            x: (slot % spec.columns) * spec.slot_glyph_size,
+            y: (slot / spec.columns) * spec.slot_glyph_size,
+            width: spec.slot_glyph_size,
+            height: spec.slot_glyph_size,
+        });
+    }
+
+    GlyphLayout { width: spec.width, height: spec.height, cells: cells }
+}
+
+/// Pack rectangles into a strip of the given width with the bottom left skyline heuristic,
+/// returning the top left corner of each one by key.
+fn pack_skyline(rects: &[(usize, usize, usize)], width: usize) -> HashMap<usize, (usize, usize)> {
+    // The skyline is the top edge of the packed rectangles, as segments of (x, y, width).
+    let mut skyline = vec![(0, 0, width)];
+    let mut positions = HashMap::new();
+    for &(key, rect_width, rect_height) in rects.iter() {
+        let mut best: Option<(usize, usize, usize)> = None;
+        for start in 0..skyline.len() {
+            let x = skyline[start].0;
+            if x + rect_width > width {
+                break;
+            }
+            // The rectangle rests on the highest segment underneath it.
+            let mut y = 0;
+            let mut covered = 0;
+            for &(_, segment_y, segment_width) in skyline[start..].iter() {
+                y = y.max(segment_y);
+                covered += segment_width;
+                if covered >= rect_width {
+                    break;
+                }
+            }
+            let better = match best {
+                Some((_, best_x, best_y)) => (y + rect_height, x) < (best_y + rect_height, best_x),
+                None => true,
+            };
+            if better {
+                best = Some((start, x, y));
+            }
+        }
+
+        let (start, x, y) = best.expect("A rectangle is wider than the strip it is packed into.");
+        positions.insert(key, (x, y));
+        skyline.insert(start, (x, y + rect_height, rect_width));
+        let right = x + rect_width;
+        let mut next = start + 1;
+        while next < skyline.len() && skyline[next].0 < right {
+            let overlap = right - skyline[next].0;
+            if skyline[next].2 <= overlap {
+                skyline.remove(next);
+            } else {
+                skyline[next].0 += overlap;
+                skyline[next].2 -= overlap;
+                next += 1;
+            }
+        }
+        skyline.dedup_by(|right, left| {
+            if left.1 == right.1 {
+                left.2 += right.2;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    positions
+}
+
+/// Pack rectangles into a strip of the given width with the maximal rectangles algorithm and the
+/// bottom left placement rule, returning the top left corner of each one by key.
+fn pack_max_rects(rects: &[(usize, usize, usize)], width: usize) -> HashMap<usize, (usize, usize)> {
+    let height: usize = rects.iter().map(|&(_, _, rect_height)| rect_height).sum();
+    // The maximal free rectangles, as (x, y, width, height).
+    let mut free = vec![(0, 0, width, height)];
+    let mut positions = HashMap::new();
+    for &(key, rect_width, rect_height) in rects.iter() {
+        let (x, y) = free.iter()
+            .filter(|&&(_, _, free_width, free_height)| {
+                free_width >= rect_width && free_height >= rect_height
+            })
+            .map(|&(x, y, _, _)| (x, y))
+            .min_by_key(|&(x, y)| (y + rect_height, x))
+            .expect("A rectangle is wider than the strip it is packed into.");
+        positions.insert(key, (x, y));
+
+        // Split every free rectangle the placed one overlaps into the free space around it.
+        let (right, bottom) = (x + rect_width, y + rect_height);
+        let mut split = vec![];
+        for &(free_x, free_y, free_width, free_height) in free.iter() {
+            let (free_right, free_bottom) = (free_x + free_width, free_y + free_height);
+            if x >= free_right || right <= free_x || y >= free_bottom || bottom <= free_y {
+                split.push((free_x, free_y, free_width, free_height));
+                continue;
+            }
+            if x > free_x {
+                split.push((free_x, free_y, x - free_x, free_height));
+            }
+            if right < free_right {
+                split.push((right, free_y, free_right - right, free_height));
+            }
+            if y > free_y {
+                split.push((free_x, free_y, free_width, y - free_y));
+            }
+            if bottom < free_bottom {
+                split.push((free_x, bottom, free_width, free_bottom - bottom));
+            }
+        }
+        // Drop the free rectangles contained in other ones.
+        let contains = |a: &(usize, usize, usize, usize), b: &(usize, usize, usize, usize)| {
+            b.0 >= a.0 && b.1 >= a.1 && b.0 + b.2 <= a.0 + a.2 && b.1 + b.3 <= a.1 + a.3
+        };
+        split.sort();
+        split.dedup();
+        free = split.iter().enumerate()
+            .filter(|&(k, b)| !split.iter().enumerate().any(|(j, a)| j != k && contains(a, b)))
+            .map(|(_, b)| *b)
+            .collect();
+    }
+
+    positions
+}
+
+/// Pack rectangles into a strip of the given width on shelves, returning the top left corner of
+/// each one by key. Each rectangle goes on the shortest shelf it fits on, and a new shelf as tall
+/// as the rectangle opens below the others when none has room.
+fn pack_shelf(rects: &[(usize, usize, usize)], width: usize) -> HashMap<usize, (usize, usize)> {
+    // The shelves, as (y, height, used width).
+    let mut shelves: Vec<(usize, usize, usize)> = vec![];
+    let mut positions = HashMap::new();
+    for &(key, rect_width, rect_height) in rects.iter() {
+        assert!(rect_width <= width, "A rectangle is wider than the strip it is packed into.");
+        let shelf = shelves.iter()
+            .enumerate()
+            .filter(|&(_, &(_, shelf_height, used))| {
+                shelf_height >= rect_height && used + rect_width <= width
+            })
+            .min_by_key(|&(k, &(_, shelf_height, _))| (shelf_height, k))
+            .map(|(k, _)| k);
+        let k = match shelf {
+            Some(k) => k,
+            None => {
+                let y = shelves.last().map_or(0, |&(y, shelf_height, _)| y + shelf_height);
+                shelves.push((y, rect_height, 0));
+                shelves.len() - 1
+            }
+        };
+        positions.insert(key, (shelves[k].2, shelves[k].0));
+        shelves[k].2 += rect_width;
+    }
+
+    positions
+}
+
+/// Place the glyphs of a glyph table in the atlas image according to the packing of the
+/// atlas specification. Packed atlases are as wide as the atlas specification, or when its width
+/// is zero, as the smallest power of two that fits a square of the total glyph area. They are as
+/// tall as the packed glyphs, but no shorter than the atlas specification.
+pub fn layout_glyphs(glyph_tab: &GlyphTable, spec: AtlasSpec) -> GlyphLayout {
+    if spec.packing == Packing::Grid {
+        return layout_grid(glyph_tab, spec);
+    }
+
+    let mut rects: Vec<(usize, usize, usize)> = glyph_tab.buffer.keys()
+        .map(|&i| {
+            let padding = spec.free_padding();
+            (i, glyph_tab.width[&i] as usize + padding, glyph_tab.rows[&i] as usize + padding)
+        })
+        .collect();
+    // Tall and wide rectangles first, since they are the hardest to fit.
+    rects.sort_by_key(|&(i, rect_width, rect_height)| (usize::MAX - rect_height, usize::MAX - rect_width, i));
+    let area: usize = rects.iter().map(|&(_, rect_width, rect_height)| rect_width * rect_height).sum();
+    let widest = rects.iter().map(|&(_, rect_width, _)| rect_width).max().unwrap_or(1);
+    let width = if spec.width > 0 {
+        spec.width.max(widest)
+    } else {
+        ((area as f64).sqrt().ceil() as usize).max(widest).max(1).next_power_of_two()
+    };
+    let positions = match spec.packing {
+        Packing::MaxRects => pack_max_rects(&rects, width),
+        Packing::Shelf => pack_shelf(&rects, width),
+        _ => pack_skyline(&rects, width),
+    };
+
+    let mut cells = HashMap::new();
+    for &(i, rect_width, rect_height) in rects.iter() {
+        let (x, y) = positions[&i];
+        cells.insert(i, GlyphCell { x: x, y: y, width: rect_width, height: rect_height });
+    }
+    let used_height = cells.values().map(|cell| cell.y + cell.height).max().unwrap_or(0);
+    let height = used_height.max(spec.height).max(1);
+
+    GlyphLayout { width: width, height: height, cells: cells }
+}
+
+/// Shrink the atlas specification to the smallest atlas that fits the glyphs of a glyph table,
+/// optionally with power of two dimensions. A grid gets the fewest rows and columns that hold
+/// every slot, and a packed atlas gets the size of its packed glyphs.
+pub fn fit_atlas(glyph_tab: &GlyphTable, spec: AtlasSpec, power_of_two: bool) -> AtlasSpec {
+    let mut fitted = spec;
+    if spec.packing == Packing::Grid {
+        let slots = glyph_tab.slots().len();
+        let columns = (slots as f64).sqrt().ceil() as usize;
+        let rows = (slots + columns - 1) / columns;
+        fitted.width = spec.slot_glyph_size * columns;
+        fitted.height = spec.slot_glyph_size * rows;
+        if power_of_two {
+            fitted.width = fitted.width.next_power_of_two();
+            fitted.height = fitted.height.next_power_of_two();
+        }
+        fitted.columns = fitted.width / spec.slot_glyph_size;
+        fitted.rows = fitted.height / spec.slot_glyph_size;
+    } else {
+        fitted.width = 0;
+        fitted.height = 0;
+        let layout = layout_glyphs(glyph_tab, fitted);
+        fitted.width = layout.width;
+        fitted.height = if power_of_two { layout.height.next_power_of_two() } else { layout.height };
+    }
+
+    fitted
+}
+
+/// Shrink the height of an atlas specification to the rows the glyphs of a glyph table take up at
+/// its width, optionally rounded up to a power of two, e.g. for the last page of a multi-page
+/// atlas. The width stays the same, so the glyphs keep the layout they had in the full atlas.
+pub fn trim_atlas(glyph_tab: &GlyphTable, spec: AtlasSpec, power_of_two: bool) -> AtlasSpec {
+    let mut trimmed = spec;
+    if spec.packing == Packing::Grid {
+        let columns = spec.columns.max(1);
+        let rows = (glyph_tab.slots().len() + columns - 1) / columns;
+        trimmed.height = spec.slot_glyph_size * rows.max(1);
+        if power_of_two {
+            trimmed.height = trimmed.height.next_power_of_two();
+        }
+        trimmed.rows = trimmed.height / spec.slot_glyph_size;
+    } else {
+        trimmed.height = 0;
+        let layout = layout_glyphs(glyph_tab, trimmed);
+        trimmed.height = if power_of_two { layout.height.next_power_of_two() } else { layout.height };
+    }
+
+    trimmed
+}
+
+/// One page of a multi-page atlas.
+pub struct AtlasPage {
+    /// The glyph table holding the images of the glyphs on the page.
+    pub glyph_tab: GlyphTable,
+    /// The atlas specification of the page image.
+    pub spec: AtlasSpec,
+}
+
+/// Spill the glyphs of a glyph table across as many pages as it takes to keep each page
+/// image within `max_size` pixels on either side. An atlas that already fits stays a single
+/// page with the original specification. Otherwise the glyphs fill the pages in code point
+/// order, and every page is `max_size` pixels wide and tall, or as many whole slots as fit
+/// for a grid. A grid page needs room for at least two slots per side, one of them for the space,
+/// so a `max_size` under twice the slot glyph size still gets pages of two slots per side, larger
+/// than `max_size`.
+pub fn paginate(glyph_tab: &GlyphTable, spec: AtlasSpec, max_size: usize) -> Vec<AtlasPage> {
+    let layout = layout_glyphs(glyph_tab, spec);
+    if layout.width <= max_size && layout.height <= max_size {
+        return vec![AtlasPage { glyph_tab: glyph_tab.page(&glyph_tab.slots()), spec: spec }];
+    }
+
+    let mut page_spec = spec;
+    let code_points: Vec<usize> = glyph_tab.slots().into_iter().skip(1).collect();
+    let mut pages = vec![];
+    if spec.packing == Packing::Grid {
+        let slots_per_side = (max_size / spec.slot_glyph_size).max(2);
+        page_spec.rows = slots_per_side;
+        page_spec.columns = slots_per_side;
+        page_spec.width = slots_per_side * spec.slot_glyph_size;
+        page_spec.height = slots_per_side * spec.slot_glyph_size;
+        // Slot zero of every page is reserved for the space character.
+        for page_code_points in code_points.chunks(slots_per_side * slots_per_side - 1) {
+            pages.push(AtlasPage { glyph_tab: glyph_tab.page(page_code_points), spec: page_spec });
+        }
+    } else {
+        page_spec.width = max_size;
+        page_spec.height = 0;
+        let mut remaining = &code_points[..];
+        while !remaining.is_empty() {
+            // Find the longest run of glyphs that packs into a page, always taking at least one.
+            let (mut low, mut high) = (1, remaining.len());
+            while low < high {
+                let middle = (low + high + 1) / 2;
+                let layout = layout_glyphs(&glyph_tab.page(&remaining[..middle]), page_spec);
+                if layout.height <= max_size {
+                    low = middle;
+                } else {
+                    high = middle - 1;
+                }
+            }
+            pages.push(AtlasPage { glyph_tab: glyph_tab.page(&remaining[..low]), spec: page_spec });
+            remaining = &remaining[low..];
+        }
+        for page in pages.iter_mut() {
+            page.spec.height = max_size;
+        }
+    }
+
+    pages
+}
+
+/// Calculate the metadata for indexing into the atlas bitmap image.
+pub fn create_bitmap_metadata(glyph_tab: &GlyphTable, spec: AtlasSpec) -> HashMap<usize, GlyphMetadata> {
+    let layout = layout_glyphs(glyph_tab, spec);
+    let mut metadata = HashMap::new();
+    // The space has no image, so its width is its advance.
+    let space_width = glyph_tab.advance.get(&32).map_or(0.5, |&advance| {
+        advance as f32 / spec.slot_glyph_size as f32
+    });
+    let glyph_metadata_space = GlyphMetadata::new(32, 0, 0, space_width, 1.0, 0.0, 0.0, 0.0);
+    metadata.insert(32, glyph_metadata_space);
+    for (i, cell) in layout.cells.iter() {
+        // Packed glyphs are not in grid slots, so only grid atlases have rows and columns.
+        let (row, col) = match spec.packing {
+            Packing::Grid => (cell.y / spec.slot_glyph_size, cell.x / spec.slot_glyph_size),
+            _ => (0, 0),
+        };
+
+        // Glyph metadata parameters.
+        let x_min = cell.x as f32 / layout.width as f32;
+        let y_min = cell.y as f32 / layout.height as f32;
+        let padding = spec.free_padding();
+        let width = (glyph_tab.width[i] + padding as i32) as f32 / spec.slot_glyph_size as f32;
+        let height = (glyph_tab.rows[i] + padding as i32) as f32 / spec.slot_glyph_size as f32;
+        let y_offset = -(padding as f32 - glyph_tab.y_min[i] as f32) / spec.slot_glyph_size as f32;
+
+        let glyph_metadata_i = GlyphMetadata::new(*i, row, col, width, height, x_min, y_min, y_offset);
+        metadata.insert(*i, glyph_metadata_i);
+    }
+    // Empty and zero width glyphs keep a metadata entry so the atlas still knows the
+    // character, but they have no image to sample.
+    for i in glyph_tab.empty.iter().chain(glyph_tab.zero_width.iter()) {
+        let glyph_metadata_i = GlyphMetadata::new(*i, 0, 0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        metadata.insert(*i, glyph_metadata_i);
+    }
+
+    metadata
+}
+
+/// Convert the pixels of a glyph image into the layout of the atlas image, so its rows can get
+/// copied straight into the atlas. Glyph images already in that layout are borrowed as they are.
+fn atlas_pixels<'a>(glyph_data: &'a [u8], channels: usize, pixel_size: usize) -> Cow<'a, [u8]> {
+    if channels == pixel_size {
+        return Cow::Borrowed(glyph_data);
+    }
+    let mut pixels = Vec::with_capacity(pixel_size * glyph_data.len() / channels);
+    for pixel in glyph_data.chunks_exact(channels) {
+        let mut colour = [0 as u8; 4];
+        if channels == 4 {
+            // Color glyphs are already premultiplied RGBA.
+            colour.copy_from_slice(pixel);
+        } else if channels == 3 {
+            // The alpha channel of a multi-channel distance field holds the median
+            // of the color channels, which is the true distance away from corners.
+            colour[0] = pixel[0];
+            colour[1] = pixel[1];
+            colour[2] = pixel[2];
+            colour[3] = pixel[0].max(pixel[1]).min(pixel[0].min(pixel[1]).max(pixel[2]));
+        } else {
+            colour = [pixel[0]; 4];
+        }
+        pixels.extend_from_slice(&colour[(4 - pixel_size)..]);
+    }
+
+    Cow::Owned(pixels)
+}
+
+/// Pack the glyph bitmap images sampled from the typeface into a single bitmap image, with
+/// the channels of the atlas specification. An `A8` image only keeps the alpha channel.
+pub fn create_bitmap_image(glyph_tab: &GlyphTable, spec: AtlasSpec) -> bmfa::BitmapFontAtlasImage {
+    let layout = layout_glyphs(glyph_tab, spec);
+    let pixel_size = spec.channels.count();
+    let width_in_bytes = pixel_size * layout.width;
+    // Everything outside of the glyphs is a transparent black pixel (0,0,0,0).
+    let mut atlas_buffer = vec![
+        0 as u8; layout.width * layout.height * pixel_size * mem::size_of::<u8>()
+    ];
+    // Glyphs sit half the padding away from the corner of their cell, and get clipped to it.
+    let offset = spec.free_padding() / 2;
+    for (glyph_index, cell) in layout.cells.iter() {
+        let glyph_width = glyph_tab.width[glyph_index] as usize;
+        let glyph_rows = glyph_tab.rows[glyph_index] as usize;
+        let pixels = atlas_pixels(&glyph_tab.buffer[glyph_index].data, glyph_tab.channels, pixel_size);
+        let row_bytes = pixel_size * glyph_width.min(cell.width.saturating_sub(offset));
+        let x_start = pixel_size * (cell.x + offset);
+        for y_loc in 0..glyph_rows.min(cell.height.saturating_sub(offset)) {
+            let source_start = pixel_size * glyph_width * y_loc;
+            let destination_start = (cell.y + offset + y_loc) * width_in_bytes + x_start;
+            atlas_buffer[destination_start..(destination_start + row_bytes)]
+                .copy_from_slice(&pixels[source_start..(source_start + row_bytes)]);
+        }
+    }
+
+    if spec.origin == bmfa::Origin::BottomLeft {
+        // If the origin is the bottom left of the image, we need to flip the image back over
+        // before writing it out.
+        let height = layout.height;
+        let (top, bottom) = atlas_buffer.split_at_mut((height / 2) * width_in_bytes);
+        let bottom_start = (height % 2) * width_in_bytes;
+        for (top_row, bottom_row) in top.chunks_exact_mut(width_in_bytes)
+            .zip(bottom[bottom_start..].chunks_exact_mut(width_in_bytes).rev())
+        {
+            top_row.swap_with_slice(bottom_row);
+        }
+    }
+
+    bmfa::BitmapFontAtlasImage::new(
+        atlas_buffer, layout.width, layout.height, spec.origin
+    )
+}
+
+/// Create a bitmapped atlas from the glyphs sampled from a vector based font.
+pub fn create_bitmap_atlas(glyph_tab: &GlyphTable, spec: AtlasSpec) -> BitmapFontAtlas {
+    let glyph_metadata = create_bitmap_metadata(glyph_tab, spec);
+    let atlas_image = create_bitmap_image(glyph_tab, spec);
+
+    let metadata = BitmapFontAtlasMetadata {
+        origin: spec.origin,
+        width: atlas_image.width,
+        height: atlas_image.height,
+        columns: spec.columns,
+        rows: spec.rows,
+        padding: spec.padding,
+        slot_glyph_size: spec.slot_glyph_size,
+        glyph_size: spec.glyph_size,
+        glyph_metadata: glyph_metadata,
+    };
+
+    BitmapFontAtlas::new(metadata, atlas_image)
+}
+
+/// Write the atlas image out to a standalone PNG file, so it can be inspected or used by
+/// engines that do not read the `bmfa` format.
+pub fn write_atlas_image<P: AsRef<Path>>(
+    path: P, atlas: &BitmapFontAtlas, color_space: ColorSpace) -> io::Result<()> {
+
+    write_image(path, &atlas.image, color_space)
+}
+
+/// Write an image out to a PNG file, tagged with the color space of its pixel values. Images with
+/// one byte per pixel get written as grayscale PNG files.
+pub fn write_image<P: AsRef<Path>>(
+    path: P, image: &bmfa::BitmapFontAtlasImage, color_space: ColorSpace) -> io::Result<()> {
+
+    let png = with_color_space_chunks(&encode_png(image)?, color_space);
+
+    write_atomically(path, |path| fs::write(path, &png))
+}
+
+/// Tag a PNG file with the color space of its pixel values, so image viewers and texture tools
+/// decode them the way the engine will. sRGB images get an `sRGB` chunk, along with the `gAMA`
+/// chunk of the sRGB curve for decoders that only read that one, and linear images a `gAMA`
+/// chunk with a gamma of one. The chunks go right after the `IHDR` chunk, which always comes
+/// first.
+fn with_color_space_chunks(png: &[u8], color_space: ColorSpace) -> Vec<u8> {
+    // The signature takes 8 bytes and the header chunk 25.
+    let header_end = 33;
+    let mut tagged = png[..header_end].to_vec();
+    match color_space {
+        ColorSpace::Srgb => {
+            // The perceptual rendering intent.
+            write_png_chunk(&mut tagged, b"sRGB", &[0]);
+            write_png_chunk(&mut tagged, b"gAMA", &45455u32.to_be_bytes());
+        }
+        ColorSpace::Linear => write_png_chunk(&mut tagged, b"gAMA", &100_000u32.to_be_bytes()),
+    }
+    tagged.extend_from_slice(&png[header_end..]);
+
+    tagged
+}
+
+/// Append a chunk to a PNG file: its length, its type, its data, and the CRC-32 of the type and
+/// the data.
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let mut crc = !0u32;
+    for &byte in chunk_type.iter().chain(data.iter()) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    png.extend_from_slice(&(!crc).to_be_bytes());
+}
+
+/// The identifier at the start of every KTX2 file.
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Write the pages of an atlas out to a KTX2 texture array, one layer per page in page order, so an
+/// engine can bind a single array texture for every page of a multi-page atlas. The pages have to
+/// be the same size, with the same number of channels. The pixels get stored uncompressed, without
+/// mipmaps, in the color space of the atlas, and RGBA pages get marked as premultiplied, like the
+/// glyph images. Pages with a bottom left origin get the `ru` orientation.
+pub fn write_texture_array<P: AsRef<Path>>(
+    path: P, pages: &[bmfa::BitmapFontAtlasImage], color_space: ColorSpace) -> io::Result<()> {
+
+    let ktx2 = encode_texture_array(pages, color_space)?;
+
+    write_atomically(path, |path| fs::write(path, &ktx2))
+}
+
+/// Encode the pages of an atlas as a KTX2 texture array in memory.
+fn encode_texture_array(
+    pages: &[bmfa::BitmapFontAtlasImage], color_space: ColorSpace) -> io::Result<Vec<u8>> {
+
+    let first = match pages.first() {
+        Some(val) => val,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "A texture array needs a page.")),
+    };
+    let uniform = pages.iter().all(|page| {
+        page.width == first.width && page.height == first.height && page.data.len() == first.data.len()
+    });
+    if !uniform {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput, "The layers of a texture array have to be the same size."
+        ));
+    }
+    let pixel_size = first.data.len() / (first.width * first.height).max(1);
+    // The Vulkan formats R8_UNORM, R8_SRGB, R8G8B8A8_UNORM, and R8G8B8A8_SRGB.
+    let vk_format: u32 = match (pixel_size, color_space) {
+        (1, ColorSpace::Linear) => 9,
+        (1, ColorSpace::Srgb) => 15,
+        (_, ColorSpace::Linear) => 37,
+        (_, ColorSpace::Srgb) => 43,
+    };
+
+    // The data format descriptor holds a single basic block, with a sample per channel: red, green,
+    // blue, and alpha, or red alone. The alpha channel stays linear in sRGB images.
+    let channel_ids: &[u8] = if pixel_size == 1 { &[0] } else { &[0, 1, 2, 15] };
+    let mut dfd = vec![];
+    let block_size = 24 + 16 * channel_ids.len() as u16;
+    dfd.extend_from_slice(&(4 + block_size as u32).to_le_bytes());
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&block_size.to_le_bytes());
+    let transfer = match color_space {
+        ColorSpace::Linear => 1,
+        ColorSpace::Srgb => 2,
+    };
+    let premultiplied = if pixel_size == 1 { 0 } else { 1 };
+    // The RGBSDA color model and the BT.709 primaries.
+    dfd.extend_from_slice(&[1, 1, transfer, premultiplied]);
+    dfd.extend_from_slice(&[0, 0, 0, 0]);
+    dfd.extend_from_slice(&[pixel_size as u8, 0, 0, 0, 0, 0, 0, 0]);
+    for (channel, &channel_id) in channel_ids.iter().enumerate() {
+        let linear = if channel_id == 15 && color_space == ColorSpace::Srgb { 0x80 } else { 0 };
+        dfd.extend_from_slice(&(8 * channel as u16).to_le_bytes());
+        dfd.extend_from_slice(&[7, channel_id | linear]);
+        dfd.extend_from_slice(&[0, 0, 0, 0]);
+        dfd.extend_from_slice(&0u32.to_le_bytes());
+        dfd.extend_from_slice(&255u32.to_le_bytes());
+    }
+
+    // The key value pairs are sorted by key, and each one gets padded to four bytes.
+    let orientation = match first.origin {
+        bmfa::Origin::TopLeft => "rd",
+        bmfa::Origin::BottomLeft => "ru",
+    };
+    let writer = format!("fontgen {}", env!("CARGO_PKG_VERSION"));
+    let mut kvd = vec![];
+    for &(key, value) in [("KTXorientation", orientation), ("KTXwriter", &writer[..])].iter() {
+        let length = key.len() + value.len() + 2;
+        kvd.extend_from_slice(&(length as u32).to_le_bytes());
+        kvd.extend_from_slice(key.as_bytes());
+        kvd.push(0);
+        kvd.extend_from_slice(value.as_bytes());
+        kvd.push(0);
+        while kvd.len() % 4 != 0 {
+            kvd.push(0);
+        }
+    }
+
+    // The identifier, the header, the index, and the index of the single mip level come first.
+    let dfd_offset = 12 + 36 + 32 + 24;
+    let kvd_offset = dfd_offset + dfd.len();
+    let level_offset = kvd_offset + kvd.len();
+    let level_length = pages.iter().map(|page| page.data.len()).sum::<usize>();
+
+    let mut ktx2 = KTX2_IDENTIFIER.to_vec();
+    let header = [
+        vk_format, 1, first.width as u32, first.height as u32, 0, pages.len() as u32, 1, 1, 0,
+        dfd_offset as u32, dfd.len() as u32, kvd_offset as u32, kvd.len() as u32,
+    ];
+    for field in header.iter() {
+        ktx2.extend_from_slice(&field.to_le_bytes());
+    }
+    // There is no supercompression global data.
+    ktx2.extend_from_slice(&[0; 16]);
+    for field in [level_offset, level_length, level_length].iter() {
+        ktx2.extend_from_slice(&(*field as u64).to_le_bytes());
+    }
+    ktx2.extend_from_slice(&dfd);
+    ktx2.extend_from_slice(&kvd);
+    for page in pages.iter() {
+        ktx2.extend_from_slice(&page.data);
+    }
+
+    Ok(ktx2)
+}
+
+/// Cut the image of a glyph, along with the padding around it, out of an atlas. The image comes
+/// out upright with a top left origin, whatever the origin of the atlas. The space and the glyphs
+/// without an image give `None`.
+pub fn glyph_image(atlas: &BitmapFontAtlas, glyph: &GlyphMetadata) -> Option<bmfa::BitmapFontAtlasImage> {
+    // The metadata of the space holds its advance instead of a place in the atlas.
+    if glyph.code_point == 32 {
+        return None;
+    }
+    let image = &atlas.image;
+    let (x, y, width, height) = glyph_rect(&atlas.metadata, glyph, image.width, image.height)?;
+    let pixel_size = image.data.len() / (image.width * image.height);
+    let width_in_bytes = pixel_size * image.width;
+    let mut data = Vec::with_capacity(pixel_size * width * height);
+    for row in y..(y + height) {
+        // The rows of an atlas with a bottom left origin are stored bottom up.
+        let image_row = match image.origin {
+            bmfa::Origin::TopLeft => row,
+            bmfa::Origin::BottomLeft => image.height - 1 - row,
+        };
+        let start = image_row * width_in_bytes + pixel_size * x;
+        data.extend_from_slice(&image.data[start..(start + pixel_size * width)]);
+    }
+
+    Some(bmfa::BitmapFontAtlasImage::new(data, width, height, bmfa::Origin::TopLeft))
+}
+
+/// The rectangle a glyph image, along with the padding around it, occupies in an atlas image of
+/// the given size, as its left and top edges in pixels from the top left corner, its width, and
+/// its height. The rectangle is clipped to the image, and empty rectangles give `None`.
+fn glyph_rect(
+    metadata: &BitmapFontAtlasMetadata, glyph: &GlyphMetadata,
+    image_width: usize, image_height: usize) -> Option<(usize, usize, usize, usize)> {
+
+    let slot_glyph_size = metadata.slot_glyph_size as f32;
+    let x = (glyph.x_min * image_width as f32).round() as usize;
+    let y = (glyph.y_min * image_height as f32).round() as usize;
+    let width = ((glyph.width * slot_glyph_size).round() as usize).min(image_width.saturating_sub(x));
+    let height = ((glyph.height * slot_glyph_size).round() as usize).min(image_height.saturating_sub(y));
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    Some((x, y, width, height))
+}
+
+/// An error reading part of a `.bmfa` atlas file.
+#[derive(Clone, Debug)]
+pub enum AtlasReadError {
+    CouldNotOpenFile(String, String),
+    NotAnAtlas(String, String),
+    CouldNotReadMetadata(String, String),
+    CouldNotDecodeImage(String, String),
+    UnsupportedImage(String, &'static str),
+    RectOutOfBounds(String, [usize; 4], [usize; 2]),
+    PageOutOfRange(String, usize, usize),
+}
+
+impl fmt::Display for AtlasReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AtlasReadError::CouldNotOpenFile(ref path, ref e) => {
+                write!(f, "Could not open the file {}: {}.", path, e)
+            }
+            AtlasReadError::NotAnAtlas(ref path, ref e) => {
+                write!(f, "The file {} is not a bmfa atlas: {}.", path, e)
+            }
+            AtlasReadError::CouldNotReadMetadata(ref path, ref e) => {
+                write!(f, "Could not read the metadata of {}: {}.", path, e)
+            }
+            AtlasReadError::CouldNotDecodeImage(ref path, ref e) => {
+                write!(f, "Could not decode the atlas image of {}: {}.", path, e)
+            }
+            AtlasReadError::UnsupportedImage(ref path, problem) => {
+                write!(f, "The atlas image of {} cannot be read a piece at a time: {}.", path, problem)
+            }
+            AtlasReadError::RectOutOfBounds(ref path, [x, y, width, height], [image_width, image_height]) => {
+                write!(
+                    f, "The {}x{} rectangle at ({}, {}) lies outside the {}x{} atlas image of {}.",
+                    width, height, x, y, image_width, image_height, path
+                )
+            }
+            AtlasReadError::PageOutOfRange(ref path, page, pages) => {
+                write!(
+                    f, "The atlas described by {} has no page {}. Its pages are numbered from 0 to {}.",
+                    path, page, pages.saturating_sub(1)
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for AtlasReadError {}
+
+/// A `.bmfa` atlas file opened for reading its image a piece at a time. Only the metadata gets
+/// read up front, so a runtime short on memory can stream the glyph images it needs into a
+/// texture without ever holding the whole atlas image.
+pub struct AtlasReader {
+    path: String,
+    archive: zip::ZipArchive<File>,
+    metadata: BitmapFontAtlasMetadata,
+}
+
+impl AtlasReader {
+    /// The metadata of the atlas, holding the place of each glyph in the atlas image.
+    pub fn metadata(&self) -> &BitmapFontAtlasMetadata {
+        &self.metadata
+    }
+
+    /// Decode a rectangle of the atlas image, given by its left and top edges in pixels from the top
+    /// left corner, its width, and its height. The image comes out upright with a top left origin,
+    /// whatever the origin of the atlas. The atlas image gets decoded one row at a time, and
+    /// decoding stops after the last row of the rectangle, so only one row of the atlas image is
+    /// held in memory besides the rectangle.
+    pub fn decode_rect(
+        &mut self,
+        x: usize, y: usize,
+        width: usize, height: usize) -> Result<bmfa::BitmapFontAtlasImage, AtlasReadError> {
+
+        let path = &self.path;
+        let (image_width, image_height) = (self.metadata.width, self.metadata.height);
+        if x + width > image_width || y + height > image_height {
+            return Err(AtlasReadError::RectOutOfBounds(
+                path.clone(), [x, y, width, height], [image_width, image_height]
+            ));
+        }
+        // The rows of an atlas with a bottom left origin are stored bottom up.
+        let rows = match self.metadata.origin {
+            bmfa::Origin::TopLeft => y..(y + height),
+            bmfa::Origin::BottomLeft => (image_height - y - height)..(image_height - y),
+        };
+        let file = match self.archive.by_name("atlas.png") {
+            Ok(val) => val,
+            Err(e) => return Err(AtlasReadError::NotAnAtlas(path.clone(), format!("{}", e))),
+        };
+        let (info, mut reader) = match png::Decoder::new(file).read_info() {
+            Ok(val) => val,
+            Err(e) => return Err(AtlasReadError::CouldNotDecodeImage(path.clone(), format!("{}", e))),
+        };
+        if reader.info().interlaced {
+            return Err(AtlasReadError::UnsupportedImage(path.clone(), "the image is interlaced"));
+        }
+        if info.width as usize != image_width || info.height as usize != image_height {
+            return Err(AtlasReadError::UnsupportedImage(
+                path.clone(), "the image size differs from the size in the metadata"
+            ));
+        }
+        let pixel_size = info.line_size / image_width.max(1);
+        let mut data = Vec::with_capacity(pixel_size * width * height);
+        let mut row_index = 0;
+        while row_index < rows.end {
+            let row = match reader.next_row() {
+                Ok(Some(val)) => val,
+                Ok(None) => {
+                    return Err(AtlasReadError::CouldNotDecodeImage(
+                        path.clone(), String::from("the image ends early")
+                    ));
+                }
+                Err(e) => return Err(AtlasReadError::CouldNotDecodeImage(path.clone(), format!("{}", e))),
+            };
+            if row_index >= rows.start {
+                data.extend_from_slice(&row[(pixel_size * x)..(pixel_size * (x + width))]);
+            }
+            row_index += 1;
+        }
+        if self.metadata.origin == bmfa::Origin::BottomLeft {
+            let width_in_bytes = pixel_size * width;
+            data = data.chunks(width_in_bytes.max(1)).rev().flat_map(|row| row.iter().cloned()).collect();
+        }
+
+        Ok(bmfa::BitmapFontAtlasImage::new(data, width, height, bmfa::Origin::TopLeft))
+    }
+}
+
+/// Open a `.bmfa` atlas file for reading its image a piece at a time with [`decode_glyph_rect`]
+/// or [`AtlasReader::decode_rect`]. Only the metadata gets read.
+pub fn open_atlas<P: AsRef<Path>>(path: P) -> Result<AtlasReader, AtlasReadError> {
+    let path_str = format!("{}", path.as_ref().display());
+    let file = match File::open(path.as_ref()) {
+        Ok(val) => val,
+        Err(e) => return Err(AtlasReadError::CouldNotOpenFile(path_str, format!("{}", e))),
+    };
+    let mut archive = match zip::ZipArchive::new(file) {
+        Ok(val) => val,
+        Err(e) => return Err(AtlasReadError::NotAnAtlas(path_str, format!("{}", e))),
+    };
+    let metadata = match archive.by_name("metadata.json") {
+        Ok(file) => match serde_json::from_reader(file) {
+            Ok(val) => val,
+            Err(e) => return Err(AtlasReadError::CouldNotReadMetadata(path_str, format!("{}", e))),
+        },
+        Err(e) => return Err(AtlasReadError::NotAnAtlas(path_str, format!("{}", e))),
+    };
+
+    Ok(AtlasReader {
+        path: path_str,
+        archive: archive,
+        metadata: metadata,
+    })
+}
+
+/// Decode the image of a glyph, along with the padding around it, from an opened atlas, like
+/// [`glyph_image`] does for an atlas held in memory. The image comes out upright with a top left
+/// origin. The space and the glyphs without an image give `None`.
+pub fn decode_glyph_rect(
+    atlas: &mut AtlasReader,
+    glyph: &GlyphMetadata) -> Result<Option<bmfa::BitmapFontAtlasImage>, AtlasReadError> {
+
+    // The metadata of the space holds its advance instead of a place in the atlas.
+    if glyph.code_point == 32 {
+        return Ok(None);
+    }
+    let (width, height) = (atlas.metadata.width, atlas.metadata.height);
+    match glyph_rect(&atlas.metadata, glyph, width, height) {
+        Some((x, y, width, height)) => atlas.decode_rect(x, y, width, height).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Open one page of a multi-page atlas, given the extended metadata file written next to the
+/// atlas, e.g. `font.meta.json`. The page files are looked up next to the metadata file. The
+/// `glyph_pages` of the extended metadata tell which page holds each glyph.
+pub fn load_page<P: AsRef<Path>>(metadata_path: P, page: usize) -> Result<AtlasReader, AtlasReadError> {
+    let metadata_path = metadata_path.as_ref();
+    let path_str = format!("{}", metadata_path.display());
+    let file = match File::open(metadata_path) {
+        Ok(val) => val,
+        Err(e) => return Err(AtlasReadError::CouldNotOpenFile(path_str, format!("{}", e))),
+    };
+    let metadata: serde_json::Value = match serde_json::from_reader(io::BufReader::new(file)) {
+        Ok(val) => val,
+        Err(e) => return Err(AtlasReadError::CouldNotReadMetadata(path_str, format!("{}", e))),
+    };
+    let page_files: Vec<&str> = metadata.get("page_files")
+        .and_then(|page_files| page_files.as_array())
+        .map(|page_files| page_files.iter().filter_map(|page_file| page_file.as_str()).collect())
+        .unwrap_or_default();
+    let page_file = match page_files.get(page) {
+        Some(val) => val,
+        None => return Err(AtlasReadError::PageOutOfRange(path_str, page, page_files.len())),
+    };
+
+    open_atlas(metadata_path.with_file_name(page_file))
+}
+
+/// How far apart two images of the same size are, pixel by pixel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageDifference {
+    /// The number of pixels of each image.
+    pub pixels: usize,
+    /// The number of pixels with at least one channel that differs between the images by more than
+    /// the tolerance of the comparison.
+    pub differing_pixels: usize,
+    /// The largest difference between a channel of one image and the same channel of the other.
+    pub max_difference: u8,
+}
+
+impl ImageDifference {
+    /// Whether the images are the same, pixel for pixel.
+    pub fn is_empty(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+/// Compare two images pixel by pixel, going by rows from the top of each image no matter where
+/// its origin is. Images of different sizes or channel counts can't be compared, so this returns
+/// `None` for those.
+pub fn compare_images(
+    old: &bmfa::BitmapFontAtlasImage, new: &bmfa::BitmapFontAtlasImage) -> Option<ImageDifference> {
+
+    compare_images_with_tolerance(old, new, 0)
+}
+
+/// Compare two images pixel by pixel like `compare_images`, counting only the pixels with a channel
+/// that differs by more than `tolerance`, so rasterizer updates that nudge the antialiasing of the
+/// edges a little don't count as differences.
+pub fn compare_images_with_tolerance(
+    old: &bmfa::BitmapFontAtlasImage, new: &bmfa::BitmapFontAtlasImage,
+    tolerance: u8) -> Option<ImageDifference> {
+
+    let pixels = old.width * old.height;
+    if old.width != new.width || old.height != new.height || old.data.len() != new.data.len() {
+        return None;
+    }
+    let mut difference = ImageDifference { pixels: pixels, differing_pixels: 0, max_difference: 0 };
+    if pixels == 0 {
+        return Some(difference);
+    }
+    let pixel_size = old.data.len() / pixels;
+    let width_in_bytes = pixel_size * old.width;
+    fn top_down_row(image: &bmfa::BitmapFontAtlasImage, row: usize, width_in_bytes: usize) -> &[u8] {
+        let image_row = match image.origin {
+            bmfa::Origin::TopLeft => row,
+            bmfa::Origin::BottomLeft => image.height - 1 - row,
+        };
+        &image.data[(image_row * width_in_bytes)..((image_row + 1) * width_in_bytes)]
+    }
+    for row in 0..old.height {
+        let old_row = top_down_row(old, row, width_in_bytes);
+        let new_row = top_down_row(new, row, width_in_bytes);
+        for (old_pixel, new_pixel) in old_row.chunks(pixel_size).zip(new_row.chunks(pixel_size)) {
+            let pixel_difference = old_pixel.iter().zip(new_pixel.iter())
+                .map(|(&a, &b)| a.abs_diff(b))
+                .max()
+                .unwrap_or(0);
+            if pixel_difference > tolerance {
+                difference.differing_pixels += 1;
+            }
+            difference.max_difference = difference.max_difference.max(pixel_difference);
+        }
+    }
+
+    Some(difference)
+}
+
+/// The advances and kerning of the characters of an atlas, which the `bmfa` glyph metadata has no
+/// fields for. The `.meta.json` file of an atlas holds them, so they can be read straight from it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TextMetrics {
+    /// The horizontal advance of each character, by code point.
+    #[serde(default)]
+    pub advances: BTreeMap<usize, GlyphAdvance>,
+    /// The kerning pairs between the characters of the atlas.
+    #[serde(default)]
+    pub kerning: Vec<KerningPair>,
+    /// Where the typeface draws underlines, overlines, and strikeout lines, if the atlas recorded it.
+    #[serde(default)]
+    pub rules: Option<RuleMetrics>,
+}
+
+/// How far the highest glyph reaches above the baseline and the lowest one below it, in pixels.
+fn vertical_extent<'a, I>(glyphs: I, slot_glyph_size: f32) -> (i64, i64)
+    where I: Iterator<Item = &'a GlyphMetadata> {
+
+    glyphs.filter(|glyph| glyph.code_point != 32).fold((0, 0), |(ascent, descent), glyph| {
+        let top = ((glyph.y_offset + glyph.height) * slot_glyph_size).ceil() as i64;
+        let bottom = (-glyph.y_offset * slot_glyph_size).ceil() as i64;
+        (ascent.max(top), descent.max(bottom))
+    })
+}
+
+/// The margin in pixels around the text of a preview image.
+const PREVIEW_MARGIN: i64 = 8;
+
+/// How the lines of a text preview line up with each other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextAlignment {
+    /// Start each line at the left edge.
+    Left,
+    /// Center each line.
+    Center,
+    /// End each line at the right edge.
+    Right,
+}
+
+/// A line drawn along the text of a preview.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextRule {
+    /// A line under the text, at the underline position of the typeface.
+    Underline,
+    /// A line over the text, at the ascender of the typeface.
+    Overline,
+    /// A line through the text, at the strikeout position of the typeface.
+    Strikeout,
+}
+
+/// A line of a text preview, along with its width.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewLine {
+    /// The text of the line.
+    pub text: String,
+    /// The sum of the advances and kerning of the characters of the line, in pixels.
+    pub width: i64,
+}
+
+/// The lines of a text preview, broken at the line breaks of the text and wrapped to fit a width.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewLayout {
+    /// The lines of the preview, from top to bottom.
+    pub lines: Vec<PreviewLine>,
+    /// The width the lines got wrapped to, or else the width of the widest line, in pixels.
+    pub width: i64,
+    /// The distance between the baselines of consecutive lines, in pixels. Every line is tall
+    /// enough for the highest and the lowest glyph of the atlas.
+    pub line_height: i64,
+    /// The distance from the top of a line to its baseline, in pixels.
+    pub ascent: i64,
+}
+
+/// The kerning of the character pairs of an atlas, by the code points of the pair.
+fn kerning_amounts(metrics: &TextMetrics) -> HashMap<(usize, usize), i32> {
+    metrics.kerning.iter()
+        .map(|pair| ((pair.first, pair.second), pair.amount))
+        .collect()
+}
+
+/// Walk the characters of a line of text the way a runtime lays them out, calling `place` with
+/// each glyph and its pen position, and return the width of the line.
+fn walk_line<'a, F>(
+    atlas: &'a BitmapFontAtlas, metrics: &TextMetrics, kerning: &HashMap<(usize, usize), i32>,
+    text: &str, mut place: F) -> i64
+    where F: FnMut(&'a GlyphMetadata, i64) {
+
+    let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+    let mut pen_x = 0;
+    let mut previous = None;
+    for ch in text.chars() {
+        let code_point = ch as usize;
+        let glyph = match atlas.metadata.glyph_metadata.get(&code_point) {
+            Some(val) => val,
+            None => continue,
+        };
+        if let Some(previous) = previous {
+            pen_x += kerning.get(&(previous, code_point)).cloned().unwrap_or(0) as i64;
+        }
+        place(glyph, pen_x);
+        pen_x += match metrics.advances.get(&code_point) {
+            Some(advance) => advance.pixels as i64,
+            None => (glyph.width * slot_glyph_size).round() as i64,
+        };
+        previous = Some(code_point);
+    }
+
+    pen_x
+}
+
+/// Break text into the lines of a preview at its line breaks, and wrap each line at its spaces
+/// to fit `wrap_width` pixels, if given. A word wider than that gets a line of its own. The
+/// widths of the lines come out of the advances and kerning of the atlas, like in a runtime.
+pub fn layout_preview(
+    atlas: &BitmapFontAtlas, metrics: &TextMetrics, text: &str, wrap_width: Option<usize>) -> PreviewLayout {
+
+    let kerning = kerning_amounts(metrics);
+    let measure = |text: &str| walk_line(atlas, metrics, &kerning, text, |_, _| {});
+    let mut lines = vec![];
+    for text in text.lines() {
+        let wrap_width = match wrap_width {
+            Some(val) => val as i64,
+            None => {
+                lines.push(PreviewLine { text: String::from(text), width: measure(text) });
+                continue;
+            }
+        };
+        let mut line = String::new();
+        for word in text.split(' ') {
+            let candidate = if line.is_empty() { String::from(word) } else { format!("{} {}", line, word) };
+            if measure(&candidate) > wrap_width && !line.is_empty() {
+                let width = measure(&line);
+                lines.push(PreviewLine { text: line, width: width });
+                line = String::from(word);
+            } else {
+                line = candidate;
+            }
+        }
+        let width = measure(&line);
+        lines.push(PreviewLine { text: line, width: width });
+    }
+    let widest = lines.iter().map(|line| line.width).max().unwrap_or(0);
+    let (ascent, descent) = vertical_extent(
+        atlas.metadata.glyph_metadata.values(), atlas.metadata.slot_glyph_size as f32
+    );
+
+    PreviewLayout {
+        lines: lines,
+        width: wrap_width.map_or(widest, |wrap_width| wrap_width as i64),
+        line_height: ascent + descent,
+        ascent: ascent,
+    }
+}
+
+/// Lay out lines of text the way a runtime does with nothing but the metadata of an atlas, and
+/// draw them in white over a black background, to check the metrics of an atlas without an
+/// engine. Each glyph sits with the left edge of its padded image at the pen position, and the
+/// bottom of the image at its offset from the baseline. Characters without an advance advance by
+/// the width of their image, and characters missing from the atlas get skipped.
+pub fn render_preview(
+    atlas: &BitmapFontAtlas, metrics: &TextMetrics, text: &str) -> bmfa::BitmapFontAtlasImage {
+
+    let layout = layout_preview(atlas, metrics, text, None);
+    render_preview_layout(atlas, metrics, &layout, TextAlignment::Left)
+}
+
+/// The distance from the top of a preview image to the baseline of one of its lines, in pixels.
+fn preview_baseline(layout: &PreviewLayout, line: usize) -> i64 {
+    PREVIEW_MARGIN + line as i64 * layout.line_height + layout.ascent
+}
+
+/// The distance from the left edge of a preview image to the start of one of its lines, in pixels.
+fn preview_line_left(layout: &PreviewLayout, preview_line: &PreviewLine, alignment: TextAlignment) -> i64 {
+    // Lines wider than a wrapped layout, with a word too wide to wrap, stick out on the right.
+    let slack = (layout.width - preview_line.width).max(0);
+    PREVIEW_MARGIN + match alignment {
+        TextAlignment::Left => 0,
+        TextAlignment::Center => slack / 2,
+        TextAlignment::Right => slack,
+    }
+}
+
+/// Draw the lines of a preview layout like `render_preview`, lining them up with `alignment`
+/// within the width of the layout.
+pub fn render_preview_layout(
+    atlas: &BitmapFontAtlas, metrics: &TextMetrics, layout: &PreviewLayout,
+    alignment: TextAlignment) -> bmfa::BitmapFontAtlasImage {
+
+    let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+    let kerning = kerning_amounts(metrics);
+    let mut glyphs = vec![];
+    for (line, preview_line) in layout.lines.iter().enumerate() {
+        let baseline = preview_baseline(layout, line);
+        let left = preview_line_left(layout, preview_line, alignment);
+        walk_line(atlas, metrics, &kerning, &preview_line.text, |glyph, pen_x| {
+            glyphs.push((glyph, left + pen_x, baseline))
+        });
+    }
+    let widest = layout.lines.iter().map(|line| line.width).max().unwrap_or(0);
+    let width = (layout.width.max(widest) + 2 * PREVIEW_MARGIN) as usize;
+    let height = (2 * PREVIEW_MARGIN + layout.lines.len() as i64 * layout.line_height) as usize;
+
+    let mut data = [0, 0, 0, 255].repeat(width * height);
+    for (glyph, pen_x, baseline) in glyphs.into_iter() {
+        let image = match glyph_image(atlas, glyph) {
+            Some(val) => val,
+            None => continue,
+        };
+        let pixel_size = image.data.len() / (image.width * image.height);
+        let y = baseline - ((glyph.y_offset + glyph.height) * slot_glyph_size).round() as i64;
+        for row in 0..image.height {
+            for col in 0..image.width {
+                let (px, py) = (pen_x + col as i64, y + row as i64);
+                if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                    continue;
+                }
+                let source = &image.data[(row * image.width + col) * pixel_size..][..pixel_size];
+                // The colors of the glyph images are premultiplied by their coverage, and images with
+                // one byte per pixel only hold the coverage, which makes for white glyphs.
+                let (colour, alpha) = match pixel_size {
+                    1 => ([source[0]; 3], source[0] as u32),
+                    _ => ([source[0], source[1], source[2]], source[pixel_size - 1] as u32),
+                };
+                let destination = &mut data[(py as usize * width + px as usize) * 4..][..3];
+                for (channel, &value) in destination.iter_mut().zip(colour.iter()) {
+                    *channel = (value as u32 + *channel as u32 * (255 - alpha) / 255).min(255) as u8;
+                }
+            }
+        }
+    }
+
+    bmfa::BitmapFontAtlasImage::new(data, width, height, bmfa::Origin::TopLeft)
+}
+
+/// Draw underlines, overlines, or strikeout lines in white along each line of a preview drawn by
+/// `render_preview_layout` with the same layout and alignment, using the rule metrics the atlas
+/// recorded. This shows whether the lines sit right against the glyphs of the atlas, the way a
+/// runtime draws them for decorated text.
+pub fn draw_preview_rules(
+    preview: &mut bmfa::BitmapFontAtlasImage, layout: &PreviewLayout, alignment: TextAlignment,
+    metrics: &RuleMetrics, rules: &[TextRule]) {
+
+    let (width, height) = (preview.width as i64, preview.height as i64);
+    let pixel_size = preview.data.len() / (preview.width * preview.height).max(1);
+    for (line, preview_line) in layout.lines.iter().enumerate() {
+        let baseline = preview_baseline(layout, line);
+        let left = preview_line_left(layout, preview_line, alignment);
+        for rule in rules.iter() {
+            let (position, thickness) = match *rule {
+                TextRule::Underline => (metrics.underline_position, metrics.underline_thickness),
+                TextRule::Overline => (metrics.overline_position, metrics.underline_thickness),
+                TextRule::Strikeout => (metrics.strikeout_position, metrics.strikeout_thickness),
+            };
+            // The position is the center of the line, so thicker lines grow both ways.
+            let top = baseline - position as i64 - thickness as i64 / 2;
+            for y in (top..top + thickness as i64).filter(|&y| y >= 0 && y < height) {
+                for x in (left..left + preview_line.width).filter(|&x| x >= 0 && x < width) {
+                    let pixel = &mut preview.data[(y * width + x) as usize * pixel_size..][..pixel_size];
+                    for channel in pixel.iter_mut() {
+                        *channel = 255;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Collect the glyphs of several atlases into one glyph table, to pack them into a single atlas.
+/// A character in more than one atlas keeps the glyph of the first atlas that has it, along with
+/// its advance and its kerning with the other characters of that atlas. The glyph images keep the
+/// padding they have in their atlas, so the glyph table makes an atlas without padding of its own,
+/// with the glyphs in the same place relative to the baseline at any slot glyph size. The line
+/// metrics come from the first atlas.
+pub fn merge_atlas_glyphs(atlases: &[(&BitmapFontAtlas, &TextMetrics)]) -> GlyphTable {
+    let mut glyph_tab = GlyphTable {
+        rows: HashMap::new(),
+        width: HashMap::new(),
+        pitch: HashMap::new(),
+        y_min: HashMap::new(),
+        x_bearing: HashMap::new(),
+        advance: HashMap::new(),
+        ascender: 0,
+        line_height: 0,
+        family: String::new(),
+        buffer: HashMap::new(),
+        empty: vec![],
+        zero_width: vec![],
+        telemetry: vec![],
+        channels: 4,
+        kerning: vec![],
+        render_modes: BTreeMap::new(),
+        glyph_typefaces: BTreeMap::new(),
+        color_glyphs: vec![],
+    };
+    if let Some(&(atlas, _)) = atlases.first() {
+        let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+        let (ascent, descent) = vertical_extent(atlas.metadata.glyph_metadata.values(), slot_glyph_size);
+        glyph_tab.ascender = ascent as i32;
+        glyph_tab.line_height = (ascent + descent) as i32;
+    }
+    for &(atlas, metrics) in atlases.iter() {
+        let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+        let mut code_points: Vec<usize> = atlas.metadata.glyph_metadata.keys()
+            .filter(|code_point| !glyph_tab.advance.contains_key(code_point))
+            .cloned()
+            .collect();
+        code_points.sort();
+        for &code_point in code_points.iter() {
+            let glyph = &atlas.metadata.glyph_metadata[&code_point];
+            let advance = match metrics.advances.get(&code_point) {
+                Some(advance) => advance.pixels,
+                None => (glyph.width * slot_glyph_size).round() as i32,
+            };
+            glyph_tab.advance.insert(code_point, advance);
+            let image = match glyph_image(atlas, glyph) {
+                Some(val) => val,
+                None => {
+                    if code_point != 32 {
+                        glyph_tab.empty.push(code_point);
+                    }
+                    continue;
+                }
+            };
+            // The image goes all the way down to the bottom of the padding.
+            glyph_tab.y_min.insert(code_point, (glyph.y_offset * slot_glyph_size).round() as i64);
+            glyph_tab.x_bearing.insert(code_point, 0);
+            glyph_tab.width.insert(code_point, image.width as i32);
+            glyph_tab.rows.insert(code_point, image.height as i32);
+            glyph_tab.pitch.insert(code_point, 4 * image.width as i32);
+            let channels = image.data.len() / (image.width * image.height);
+            let data = atlas_pixels(&image.data, channels, 4).into_owned();
+            glyph_tab.buffer.insert(code_point, GlyphImage::new(data));
+        }
+        glyph_tab.kerning.extend(metrics.kerning.iter().filter(|pair| {
+            code_points.binary_search(&pair.first).is_ok() && code_points.binary_search(&pair.second).is_ok()
+        }));
+    }
+    glyph_tab.empty.sort();
+    glyph_tab.kerning.sort_by_key(|pair| (pair.first, pair.second));
+
+    glyph_tab
+}
+
+/// The text an HTML preview sets in the glyphs of the atlas, when the atlas has all of its
+/// characters.
+pub const PREVIEW_HTML_TEXT: &str = "The quick brown fox jumps over the lazy dog. \
+    SPHINX OF BLACK QUARTZ, JUDGE MY VOW! 0123456789";
+
+/// The number of characters of an atlas an HTML preview sets in place of the sample text, when
+/// the atlas lacks some of its characters.
+const PREVIEW_HTML_CHARACTER_COUNT: usize = 120;
+
+/// Escape the characters of a text that have a meaning in HTML.
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '\n' => escaped.push_str("&#10;"),
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// Encode bytes in base64, for the data URLs of the images of an HTML preview.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Encode an image as a PNG file in memory.
+fn encode_png(image: &bmfa::BitmapFontAtlasImage) -> io::Result<Vec<u8>> {
+    let color_type = if image.data.len() == image.width * image.height {
+        image::Gray(8)
+    } else {
+        image::RGBA(8)
+    };
+    let mut png = vec![];
+    image::png::PNGEncoder::new(&mut png)
+        .encode(&image.data, image.width as u32, image.height as u32, color_type)?;
+
+    Ok(png)
+}
+
+/// Describe a glyph of an atlas for the glyph table and the tooltips of an HTML preview.
+struct PreviewGlyph<'a> {
+    page: usize,
+    glyph: &'a GlyphMetadata,
+    /// The padded glyph image in pixels, from the top left corner of the atlas image as laid out.
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    advance: i64,
+}
+
+/// Write an HTML page for checking an atlas in a browser. It shows each page of the atlas with a
+/// box over each glyph that shows the glyph metadata when hovered, a sample paragraph set with
+/// CSS from the atlas images and metrics the way a runtime sets text, and a table of the glyph
+/// metadata. The images get embedded in the page, so it can be moved around on its own.
+pub fn preview_html(title: &str, pages: &[BitmapFontAtlas], metrics: &TextMetrics) -> io::Result<String> {
+    let mut glyphs: BTreeMap<usize, PreviewGlyph> = BTreeMap::new();
+    for (page, atlas) in pages.iter().enumerate() {
+        let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+        for glyph in atlas.metadata.glyph_metadata.values() {
+            let (width, height) = (glyph.width * slot_glyph_size, glyph.height * slot_glyph_size);
+            let advance = match metrics.advances.get(&glyph.code_point) {
+                Some(advance) => advance.pixels as i64,
+                None => width.round() as i64,
+            };
+            glyphs.insert(glyph.code_point, PreviewGlyph {
+                page: page,
+                glyph: glyph,
+                x: (glyph.x_min * atlas.image.width as f32).round(),
+                y: (glyph.y_min * atlas.image.height as f32).round(),
+                width: width.round(),
+                height: height.round(),
+                advance: advance,
+            });
+        }
+    }
+    let has_image = |glyph: &PreviewGlyph| {
+        glyph.glyph.code_point != 32 && glyph.width > 0.0 && glyph.height > 0.0
+    };
+    let describe = |glyph: &PreviewGlyph| {
+        format!(
+            "{}\npage {}, row {}, column {}\nuv {:.5}, {:.5}\nsize {} x {} px\ny offset {} px\nadvance {} px",
+            describe_code_point(glyph.glyph.code_point), glyph.page, glyph.glyph.row, glyph.glyph.column,
+            glyph.glyph.x_min, glyph.glyph.y_min, glyph.width, glyph.height,
+            (glyph.glyph.y_offset * pages[glyph.page].metadata.slot_glyph_size as f32).round() + 0.0,
+            glyph.advance
+        )
+    };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n<style>\n", html_escape(title)));
+    html.push_str(concat!(
+        "body { background: #202020; color: #e0e0e0; font-family: sans-serif; margin: 2em; }\n",
+        ".atlas { position: relative; display: inline-block; margin-bottom: 1em; }\n",
+        ".image, .glyph { image-rendering: pixelated; background-color: #000; }\n",
+        ".flipped { transform: scaleY(-1); }\n",
+        ".cell { position: absolute; box-sizing: border-box; }\n",
+        ".cell:hover { outline: 1px solid #ffd700; z-index: 1; }\n",
+        ".cell:hover::after { content: attr(data-info); position: absolute; left: 100%; top: 0; ",
+        "white-space: pre; background: #404040; color: #fff; padding: 4px 8px; font: 12px monospace; }\n",
+        ".sample { background: #000; padding: 8px; max-width: 60em; }\n",
+        ".glyph { display: inline-block; }\n",
+        "table { border-collapse: collapse; font: 12px monospace; }\n",
+        "th, td { padding: 2px 8px; text-align: right; }\n",
+        "tr:hover { background: #404040; }\n",
+    ));
+    for (page, atlas) in pages.iter().enumerate() {
+        let png = encode_png(&atlas.image)?;
+        html.push_str(&format!(
+            ".page{} {{ background-image: url(data:image/png;base64,{}); }}\n", page, base64(&png)
+        ));
+    }
+    html.push_str("</style>\n</head>\n<body>\n");
+
+    html.push_str(&format!("<h1>{}</h1>\n", html_escape(title)));
+    if let Some(atlas) = pages.first() {
+        let metadata = &atlas.metadata;
+        let page_count = match pages.len() {
+            1 => String::from("a page"),
+            count => format!("{} pages", count),
+        };
+        html.push_str(&format!(
+            "<p>{} glyphs on {} of {} x {} pixels, with a {} origin, a slot glyph size of {} pixels, \
+            and {} pixels of padding.</p>\n",
+            glyphs.len(), page_count, metadata.width, metadata.height,
+            match metadata.origin {
+                bmfa::Origin::BottomLeft => "bottom left",
+                bmfa::Origin::TopLeft => "top left",
+            },
+            metadata.slot_glyph_size, metadata.padding
+        ));
+    }
+
+    // The images of atlases with a bottom left origin are stored upside down, so they get flipped
+    // back to line up with the glyph boxes.
+    html.push_str("<h2>Atlas</h2>\n");
+    for (page, atlas) in pages.iter().enumerate() {
+        let flipped = if atlas.image.origin == bmfa::Origin::BottomLeft { " flipped" } else { "" };
+        html.push_str(&format!(
+            "<div class=\"atlas\" style=\"width: {}px; height: {}px;\">\n\
+            <div class=\"image page{}{}\" style=\"width: 100%; height: 100%;\"></div>\n",
+            atlas.image.width, atlas.image.height, page, flipped
+        ));
+        for glyph in glyphs.values().filter(|glyph| glyph.page == page && has_image(glyph)) {
+            html.push_str(&format!(
+                "<div class=\"cell\" style=\"left: {}px; top: {}px; width: {}px; height: {}px;\" \
+                data-info=\"{}\"></div>\n",
+                glyph.x, glyph.y, glyph.width, glyph.height, html_escape(&describe(glyph))
+            ));
+        }
+        html.push_str("</div>\n");
+    }
+
+    // Each glyph is an inline block showing its part of the atlas image, raised or lowered by its
+    // offset from the baseline, and followed by the rest of its advance.
+    let sample_text: String = if PREVIEW_HTML_TEXT.chars().all(|ch| glyphs.contains_key(&(ch as usize))) {
+        String::from(PREVIEW_HTML_TEXT)
+    } else {
+        glyphs.keys()
+            .filter_map(|&code_point| std::char::from_u32(code_point as u32))
+            .filter(|ch| !ch.is_control())
+            .take(PREVIEW_HTML_CHARACTER_COUNT)
+            .collect()
+    };
+    let kerning: HashMap<(usize, usize), i32> = metrics.kerning.iter()
+        .map(|pair| ((pair.first, pair.second), pair.amount))
+        .collect();
+    let slot_glyph_size = pages.first().map_or(0.0, |atlas| atlas.metadata.slot_glyph_size as f32);
+    let (ascent, descent) = vertical_extent(glyphs.values().map(|glyph| glyph.glyph), slot_glyph_size);
+    html.push_str("<h2>Sample text</h2>\n");
+    html.push_str(&format!("<div class=\"sample\" style=\"line-height: {}px;\">", ascent + descent));
+    let mut previous = None;
+    for ch in sample_text.chars() {
+        let code_point = ch as usize;
+        let glyph = match glyphs.get(&code_point) {
+            Some(val) => val,
+            None => continue,
+        };
+        let kern = previous.and_then(|previous| kerning.get(&(previous, code_point))).cloned().unwrap_or(0);
+        previous = Some(code_point);
+        if !has_image(glyph) {
+            html.push_str(&format!(
+                "<span class=\"glyph\" style=\"width: {}px; margin-left: {}px;\"></span>", glyph.advance, kern
+            ));
+            continue;
+        }
+        let atlas = &pages[glyph.page];
+        let (flipped, y) = match atlas.image.origin {
+            bmfa::Origin::BottomLeft => (" flipped", atlas.image.height as f32 - glyph.y - glyph.height),
+            bmfa::Origin::TopLeft => ("", glyph.y),
+        };
+        html.push_str(&format!(
+            "<span class=\"glyph page{}{}\" title=\"{}\" style=\"width: {}px; height: {}px; \
+            background-position: -{}px -{}px; margin-left: {}px; margin-right: {}px; \
+            vertical-align: {}px;\"></span>",
+            glyph.page, flipped, html_escape(&describe_code_point(code_point)), glyph.width, glyph.height,
+            glyph.x, y, kern, glyph.advance as f32 - glyph.width,
+            (glyph.glyph.y_offset * slot_glyph_size).round() + 0.0
+        ));
+    }
+    html.push_str("</div>\n");
+
+    html.push_str("<h2>Glyphs</h2>\n<table>\n");
+    html.push_str(
+        "<tr><th>Code point</th><th>Char</th><th>Page</th><th>Row</th><th>Column</th><th>U</th><th>V</th>\
+        <th>Width</th><th>Height</th><th>Y offset</th><th>Advance</th></tr>\n"
+    );
+    for glyph in glyphs.values() {
+        let ch = std::char::from_u32(glyph.glyph.code_point as u32)
+            .filter(|ch| !ch.is_control() && !ch.is_whitespace())
+            .map_or(String::new(), |ch| html_escape(&ch.to_string()));
+        html.push_str(&format!(
+            "<tr><td>U+{:04X}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.5}</td><td>{:.5}</td>\
+            <td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            glyph.glyph.code_point, ch, glyph.page, glyph.glyph.row, glyph.glyph.column,
+            glyph.glyph.x_min, glyph.glyph.y_min, glyph.width, glyph.height,
+            (glyph.glyph.y_offset * slot_glyph_size).round() + 0.0, glyph.advance
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+
+    Ok(html)
+}
+
+/// Write an HTML preview page of an atlas out to a file.
+pub fn write_preview_html<P: AsRef<Path>>(
+    path: P, title: &str, pages: &[BitmapFontAtlas], metrics: &TextMetrics) -> io::Result<()> {
+
+    let html = preview_html(title, pages, metrics)?;
+    write_atomically(path, |path| fs::write(path, html))
+}
+
+/// A version of an atlas in a comparison report: its name, e.g. its file name, the atlas, and the
+/// advances and kerning of its characters.
+pub type ReportAtlas<'a> = (&'a str, &'a BitmapFontAtlas, &'a TextMetrics);
+
+/// Write an HTML page comparing two versions of an atlas, for reviewing a font update. It shows
+/// a sample paragraph drawn from each version like `render_preview` draws it, and a table of the
+/// characters added, removed, or changed, with the glyph of each version side by side along with
+/// its size, offset, and advance. The images get embedded in the page, so it can be moved around
+/// on its own.
+pub fn comparison_html(old: ReportAtlas, new: ReportAtlas, text: &str) -> io::Result<String> {
+    let data_url = |image: &bmfa::BitmapFontAtlasImage| -> io::Result<String> {
+        Ok(format!("data:image/png;base64,{}", base64(&encode_png(image)?)))
+    };
+    let (old_name, old_atlas, old_metrics) = old;
+    let (new_name, new_atlas, new_metrics) = new;
+
+    let mut html = String::new();
+    let title = format!("{} -> {}", old_name, new_name);
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n<style>\n", html_escape(&title)));
+    html.push_str(concat!(
+        "body { background: #202020; color: #e0e0e0; font-family: sans-serif; margin: 2em; }\n",
+        "img { image-rendering: pixelated; background-color: #000; vertical-align: middle; }\n",
+        ".glyph { zoom: 2; }\n",
+        "table { border-collapse: collapse; font: 12px monospace; }\n",
+        "th, td { padding: 2px 8px; text-align: right; }\n",
+        "tr:hover { background: #404040; }\n",
+        ".changed { color: #ffd700; }\n",
+    ));
+    html.push_str("</style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>{}</h1>\n", html_escape(&title)));
+
+    html.push_str("<h2>Sample text</h2>\n");
+    for &(name, atlas, metrics) in [old, new].iter() {
+        let preview = render_preview(atlas, metrics, text);
+        html.push_str(&format!(
+            "<h3>{}</h3>\n<p><img src=\"{}\" alt=\"{}\"></p>\n",
+            html_escape(name), data_url(&preview)?, html_escape(text)
+        ));
+    }
+
+    // The sizes, offsets, and advances get compared in pixels, since the slot glyph sizes of the
+    // versions can differ.
+    let describe = |atlas: &BitmapFontAtlas, metrics: &TextMetrics, glyph: &GlyphMetadata| {
+        let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+        let advance = metrics.advances.get(&glyph.code_point)
+            .map_or(String::from("-"), |advance| advance.pixels.to_string());
+        [
+            format!("{:.1} x {:.1}", glyph.width * slot_glyph_size, glyph.height * slot_glyph_size),
+            format!("{:.1}", glyph.y_offset * slot_glyph_size + 0.0),
+            advance,
+        ]
+    };
+    let old_glyphs = &old_atlas.metadata.glyph_metadata;
+    let new_glyphs = &new_atlas.metadata.glyph_metadata;
+    let code_points: BTreeSet<usize> = old_glyphs.keys().chain(new_glyphs.keys()).cloned().collect();
+    let mut rows = vec![];
+    for &code_point in code_points.iter() {
+        let old_glyph = old_glyphs.get(&code_point);
+        let new_glyph = new_glyphs.get(&code_point);
+        let old_image = old_glyph.and_then(|glyph| glyph_image(old_atlas, glyph));
+        let new_image = new_glyph.and_then(|glyph| glyph_image(new_atlas, glyph));
+        let old_values = old_glyph.map(|glyph| describe(old_atlas, old_metrics, glyph));
+        let new_values = new_glyph.map(|glyph| describe(new_atlas, new_metrics, glyph));
+        let status = match (&old_values, &new_values) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            (Some(old_values), Some(new_values)) => {
+                let same_pixels = match (&old_image, &new_image) {
+                    (Some(old_image), Some(new_image)) => {
+                        compare_images(old_image, new_image).map_or(false, |difference| difference.is_empty())
+                    }
+                    (None, None) => true,
+                    _ => false,
+                };
+                if old_values == new_values && same_pixels {
+                    continue;
+                }
+                "changed"
+            }
+        };
+        let ch = std::char::from_u32(code_point as u32)
+            .filter(|ch| !ch.is_control() && !ch.is_whitespace())
+            .map_or(String::new(), |ch| html_escape(&ch.to_string()));
+        let mut row = format!("<tr><td>U+{:04X}</td><td>{}</td><td>{}</td>", code_point, ch, status);
+        for image in [&old_image, &new_image].iter() {
+            match image {
+                Some(image) => {
+                    row.push_str(&format!("<td><img class=\"glyph\" src=\"{}\"></td>", data_url(image)?))
+                }
+                None => row.push_str("<td></td>"),
+            }
+        }
+        for field in 0..3 {
+            let old_value = old_values.as_ref().map_or("", |values| values[field].as_str());
+            let new_value = new_values.as_ref().map_or("", |values| values[field].as_str());
+            let class = if status == "changed" && old_value != new_value { " class=\"changed\"" } else { "" };
+            row.push_str(&format!("<td{}>{}</td><td{}>{}</td>", class, old_value, class, new_value));
+        }
+        row.push_str("</tr>\n");
+        rows.push(row);
+    }
+
+    html.push_str(&format!(
+        "<h2>Glyphs</h2>\n<p>{} of {} characters differ between the versions.</p>\n<table>\n",
+        rows.len(), code_points.len()
+    ));
+    html.push_str(
+        "<tr><th>Code point</th><th>Char</th><th>Status</th><th>Old</th><th>New</th>\
+        <th>Old size</th><th>New size</th><th>Old y offset</th><th>New y offset</th>\
+        <th>Old advance</th><th>New advance</th></tr>\n"
+    );
+    for row in rows.iter() {
+        html.push_str(row);
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+
+    Ok(html)
+}
+
+/// Write an HTML page comparing two versions of an atlas out to a file.
+pub fn write_comparison_html<P: AsRef<Path>>(
+    path: P, old: ReportAtlas, new: ReportAtlas, text: &str) -> io::Result<()> {
+
+    let html = comparison_html(old, new, text)?;
+    write_atomically(path, |path| fs::write(path, html))
+}
+
+/// The ways FreeType can hint glyph outlines before rasterizing them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HintingMode {
+    /// No hinting.
+    None,
+    /// The typeface's own hinting instructions, falling back to the autohinter without them.
+    Native,
+    /// The FreeType autohinter, ignoring the typeface's own hinting instructions.
+    Auto,
+    /// Light autohinting, which only snaps glyphs vertically.
+    Light,
+}
+
+/// Every hinting mode, in the order they get compared.
+pub const HINTING_MODES: [HintingMode; 4] = [
+    HintingMode::None, HintingMode::Native, HintingMode::Auto, HintingMode::Light,
+];
+
+impl HintingMode {
+    /// The name of the hinting mode.
+    pub fn name(self) -> &'static str {
+        match self {
+            HintingMode::None => "none",
+            HintingMode::Native => "native",
+            HintingMode::Auto => "auto",
+            HintingMode::Light => "light",
+        }
+    }
+
+    fn load_flags(self) -> freetype::face::LoadFlag {
+        use freetype::face::LoadFlag;
+        match self {
+            HintingMode::None => LoadFlag::RENDER | LoadFlag::NO_HINTING,
+            HintingMode::Native => LoadFlag::RENDER,
+            HintingMode::Auto => LoadFlag::RENDER | LoadFlag::FORCE_AUTOHINT,
+            HintingMode::Light => LoadFlag::RENDER | LoadFlag::TARGET_LIGHT,
+        }
+    }
+}
+
+/// The sample text rendered by hinting comparisons.
+pub const HINTING_SAMPLE_TEXT: &str = "Sphinx of black quartz, judge my vow. 0123456789";
+
+/// A grayscale image of the same text rendered once per hinting mode, one line per mode,
+/// with dark text on a white background.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HintingComparison {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+/// Render a line of text labeled with the hinting mode for each hinting mode at the given pixel
+/// size, so the modes can be compared side by side without generating an atlas for each one.
+pub fn render_hinting_comparison(
+    face: &freetype::face::Face,
+    pixel_size: usize, text: &str) -> Result<HintingComparison, SampleTypefaceError> {
+
+    let family = family_name(face);
+    face.set_pixel_sizes(0, pixel_size as u32).map_err(|e| {
+        SampleTypefaceError::SetPixelSize(e, family.clone(), pixel_size)
+    })?;
+    let (ascender, line_height) = match face.size_metrics() {
+        Some(metrics) => (((metrics.ascender + 63) >> 6) as i64, ((metrics.height + 32) >> 6) as i64),
+        None => (pixel_size as i64, pixel_size as i64),
+    };
+    let margin = (pixel_size / 2) as i64;
+
+    // Render each glyph once, remembering where it goes on the page.
+    let mut placed = vec![];
+    let mut width = 0;
+    for (line, mode) in HINTING_MODES.iter().enumerate() {
+        let baseline = margin + line as i64 * line_height + ascender;
+        let mut pen_x = margin;
+        for ch in format!("{}: {}", mode.name(), text).chars() {
+            let code_point = ch as usize;
+            face.load_char(code_point, mode.load_flags()).map_err(|e| {
+                SampleTypefaceError::LoadCharacter(e, family.clone(), code_point)
+            })?;
+            let glyph = face.glyph();
+            let bitmap = glyph.bitmap();
+            let x = pen_x + glyph.bitmap_left() as i64;
+            let y = baseline - glyph.bitmap_top() as i64;
+            placed.push((
+                x, y, bitmap.width() as i64, bitmap.rows() as i64,
+                bitmap.pitch() as i64, bitmap.buffer().to_vec()
+            ));
+            pen_x += (glyph.advance().x as i64 + 32) >> 6;
+        }
+        width = width.max(pen_x + margin);
+    }
+    let height = 2 * margin + HINTING_MODES.len() as i64 * line_height;
+
+    let mut data = vec![255 as u8; (width * height) as usize];
+    for (x, y, glyph_width, glyph_rows, pitch, buffer) in placed {
+        for row in 0..glyph_rows {
+            for col in 0..glyph_width {
+                let (px, py) = (x + col, y + row);
+                if px < 0 || py < 0 || px >= width || py >= height {
+                    continue;
+                }
+                let coverage = buffer[(row * pitch + col) as usize];
+                let pixel = &mut data[(py * width + px) as usize];
+                *pixel = (*pixel).min(255 - coverage);
+            }
+        }
+    }
+
+    Ok(HintingComparison {
+        width: width as usize,
+        height: height as usize,
+        data: data,
+    })
+}
+
+/// Write a hinting comparison out to a grayscale PNG file.
+pub fn write_hinting_comparison<P: AsRef<Path>>(path: P, comparison: &HintingComparison) -> io::Result<()> {
+    write_atomically(path, |path| {
+        image::save_buffer(
+            path, &comparison.data,
+            comparison.width as u32, comparison.height as u32, image::Gray(8)
+        )
+    })
+}
+
+/// The reference strings coverage calibrations compare the atlas text and the native text with.
+pub const CALIBRATION_STRINGS: [&str; 3] = [
+    "The quick brown fox jumps over the lazy dog.",
+    "SPHINX OF BLACK QUARTZ, JUDGE MY VOW!",
+    "0123456789 (+-=%) [/] {&} #@?",
+];
+
+/// The smallest and largest gamma tried by coverage calibrations, and the step between them.
+const CALIBRATION_GAMMA_RANGE: (f32, f32, f32) = (0.5, 2.5, 0.05);
+
+/// How closely the text drawn from an atlas with one gamma matches the native text.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct CalibrationCandidate {
+    /// The gamma the glyphs of the atlas were drawn with.
+    pub gamma: f32,
+    /// The mean difference in coverage between the atlas text and the native text, blurred over
+    /// three by three pixels, as a fraction of full coverage, over the pixels either one covers.
+    pub mean_delta: f32,
+    /// The total coverage of the atlas text as a fraction of the total coverage of the native
+    /// text. Values above one mean the atlas text is heavier.
+    pub ink_ratio: f32,
+}
+
+/// The result of calibrating the coverage of atlas glyphs against the text FreeType renders
+/// directly with the hinting of a native text renderer.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CalibrationReport {
+    /// The hinting mode of the native text the atlas text was compared with.
+    pub reference_hinting: &'static str,
+    /// The pixel size the glyphs were rendered at.
+    pub pixel_size: usize,
+    /// The strings rendered both ways.
+    pub strings: Vec<String>,
+    /// How closely the atlas text matched the native text with each gamma tried.
+    pub candidates: Vec<CalibrationCandidate>,
+    /// The gamma whose atlas text came closest to the native text.
+    pub best_gamma: f32,
+}
+
+/// A grayscale coverage image of lines of text, with one byte per pixel.
+struct CoverageImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl CoverageImage {
+    fn new(width: usize, height: usize) -> CoverageImage {
+        CoverageImage {
+            width: width,
+            height: height,
+            data: vec![0; width * height],
+        }
+    }
+
+    /// Draw a glyph image with `channels` channels per pixel, the last of which holds the
+    /// coverage, with its top left corner at `(x, y)`, keeping the larger coverage where
+    /// glyphs overlap.
+    fn draw(&mut self, x: i64, y: i64, width: usize, rows: usize, channels: usize, data: &[u8]) {
+        for row in 0..rows {
+            for col in 0..width {
+                let (px, py) = (x + col as i64, y + row as i64);
+                if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                    continue;
+                }
+                let coverage = data[(row * width + col) * channels + channels - 1];
+                let pixel = &mut self.data[py as usize * self.width + px as usize];
+                *pixel = (*pixel).max(coverage);
+            }
+        }
+    }
+
+    /// The coverage of each pixel averaged with its eight neighbors, as the sum of the nine
+    /// coverage values. Comparing blurred images keeps the stems that hinting moves by a pixel
+    /// from counting as entirely different.
+    fn blurred(&self) -> Vec<u32> {
+        let mut blurred = vec![0; self.data.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(self.height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(self.width) {
+                        sum += self.data[ny * self.width + nx] as u32;
+                    }
+                }
+                blurred[y * self.width + x] = sum;
+            }
+        }
+
+        blurred
+    }
+}
+
+/// Draw lines of text, the way a runtime draws text from an atlas, with whole pixel advances and
+/// the kerning recorded in the glyph table. The glyph images come from `draw_glyph`, which
+/// draws the glyph of a code point with its pen position on the baseline at `(x, y)`.
+fn draw_text<F>(
+    glyph_tab: &GlyphTable, strings: &[String], margin: i64,
+    mut draw_glyph: F) -> Result<(), SampleTypefaceError>
+    where F: FnMut(usize, i64, i64) -> Result<(), SampleTypefaceError> {
+
+    let kerning = kerning_table(glyph_tab);
+    for (line, text) in strings.iter().enumerate() {
+        let baseline = margin + line as i64 * glyph_tab.line_height as i64 + glyph_tab.ascender as i64;
+        let (pen_positions, _) = pen_positions(glyph_tab, &kerning, text);
+        for (code_point, pen_x) in pen_positions {
+            draw_glyph(code_point, margin + pen_x, baseline)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The kerning pairs of a glyph table, by the code points of the pair.
+fn kerning_table(glyph_tab: &GlyphTable) -> HashMap<(usize, usize), i32> {
+    glyph_tab.kerning.iter()
+        .map(|pair| ((pair.first, pair.second), pair.amount))
+        .collect()
+}
+
+/// The pen position of each character of a line of text, relative to the start of the line, along
+/// with the pen position after the last character. Characters missing from the glyph table
+/// advance the pen by nothing.
+fn pen_positions(
+    glyph_tab: &GlyphTable, kerning: &HashMap<(usize, usize), i32>, text: &str) -> (Vec<(usize, i64)>, i64) {
+
+    let mut positions = vec![];
+    let mut pen_x = 0;
+    let mut previous = None;
+    for ch in text.chars() {
+        let code_point = ch as usize;
+        if let Some(previous) = previous {
+            pen_x += kerning.get(&(previous, code_point)).cloned().unwrap_or(0) as i64;
+        }
+        positions.push((code_point, pen_x));
+        pen_x += glyph_tab.advance.get(&code_point).cloned().unwrap_or(0) as i64;
+        previous = Some(code_point);
+    }
+
+    (positions, pen_x)
+}
+
+/// Draw lines of text from the glyph images of a glyph table.
+fn draw_atlas_text(glyph_tab: &GlyphTable, strings: &[String], image: &mut CoverageImage, margin: i64) {
+    let _ = draw_text(glyph_tab, strings, margin, |code_point, pen_x, baseline| {
+        if let Some(glyph_image) = glyph_tab.buffer.get(&code_point) {
+            let rows = glyph_tab.rows[&code_point] as i64;
+            let x = pen_x + glyph_tab.x_bearing[&code_point] as i64;
+            let y = baseline - (glyph_tab.y_min[&code_point] + rows);
+            image.draw(
+                x, y, glyph_tab.width[&code_point] as usize, rows as usize,
+                glyph_tab.channels, &glyph_image.data
+            );
+        }
+        Ok(())
+    });
+}
+
+/// Draw lines of text with glyphs rendered straight from the typeface by FreeType, the way a
+/// native text renderer with the given hinting mode renders them. The glyphs sit at the pen
+/// positions of the atlas text, so only the glyph images differ between the two.
+fn draw_native_text(
+    face: &freetype::face::Face, hinting: HintingMode, glyph_tab: &GlyphTable,
+    strings: &[String], image: &mut CoverageImage, margin: i64) -> Result<(), SampleTypefaceError> {
+
+    let family = family_name(face);
+    draw_text(glyph_tab, strings, margin, |code_point, pen_x, baseline| {
+        face.load_char(code_point, hinting.load_flags()).map_err(|e| {
+            SampleTypefaceError::LoadCharacter(e, family.clone(), code_point)
+        })?;
+        let glyph = face.glyph();
+        let bitmap = glyph.bitmap();
+        let width = bitmap.width() as usize;
+        let pitch = bitmap.pitch().abs() as usize;
+        let data: Vec<u8> = bitmap.buffer().chunks(pitch.max(1))
+            .flat_map(|row| row[..width.min(row.len())].to_vec())
+            .collect();
+        let x = pen_x + glyph.bitmap_left() as i64;
+        let y = baseline - glyph.bitmap_top() as i64;
+        image.draw(x, y, width, bitmap.rows() as usize, 1, &data);
+
+        Ok(())
+    })
+}
+
+/// Tune the gamma of the glyphs of an atlas so text drawn from the atlas matches the text
+/// FreeType renders directly with the hinting mode of a native text renderer, like the one of
+/// the UI toolkit the atlas text gets mixed with. The strings get drawn both ways with every
+/// gamma in the calibration range, and the report lists how far apart they came out.
+pub fn calibrate_coverage(
+    face: &freetype::face::Face, spec: AtlasSpec,
+    hinting: HintingMode, strings: &[String]) -> Result<CalibrationReport, SampleTypefaceError> {
+
+    let text: String = strings.concat();
+    let chars = CharacterSet::from_text(&text);
+    let (min_gamma, max_gamma, step) = CALIBRATION_GAMMA_RANGE;
+    let steps = ((max_gamma - min_gamma) / step).round() as usize;
+    let margin = spec.render_size() as i64 / 2;
+
+    // Sampling the glyph table leaves the typeface at the render size and stretch of the atlas,
+    // so the native text gets rendered the same way.
+    let glyph_tab = sample_typeface(face, spec, &chars)?;
+    let widest = strings.iter().map(|text| text.chars().count()).max().unwrap_or(0);
+    let width = 2 * margin as usize + widest * 2 * spec.render_size();
+    let height = 2 * margin as usize + strings.len() * glyph_tab.line_height.max(1) as usize;
+    let mut native = CoverageImage::new(width, height);
+    draw_native_text(face, hinting, &glyph_tab, strings, &mut native, margin)?;
+    let native_ink: u64 = native.data.iter().map(|&value| value as u64).sum();
+    let native_blurred = native.blurred();
+
+    let mut candidates = vec![];
+    for k in 0..(steps + 1) {
+        let gamma = ((min_gamma + k as f32 * step) * 100.0).round() / 100.0;
+        let mut gamma_spec = spec;
+        gamma_spec.gamma = gamma;
+        let glyph_tab = sample_typeface(face, gamma_spec, &chars)?;
+        let mut atlas = CoverageImage::new(width, height);
+        draw_atlas_text(&glyph_tab, strings, &mut atlas, margin);
+
+        let (mut delta, mut covered) = (0, 0);
+        for (&a, &b) in atlas.blurred().iter().zip(native_blurred.iter()) {
+            if a > 0 || b > 0 {
+                delta += (a as i64 - b as i64).abs() as u64;
+                covered += 1;
+            }
+        }
+        let atlas_ink: u64 = atlas.data.iter().map(|&value| value as u64).sum();
+        candidates.push(CalibrationCandidate {
+            gamma: gamma,
+            mean_delta: delta as f32 / (9.0 * 255.0 * covered.max(1) as f32),
+            ink_ratio: atlas_ink as f32 / native_ink.max(1) as f32,
+        });
+    }
+    let best_gamma = candidates.iter()
+        .min_by(|a, b| a.mean_delta.partial_cmp(&b.mean_delta).unwrap_or(std::cmp::Ordering::Equal))
+        .map_or(1.0, |candidate| candidate.gamma);
+
+    Ok(CalibrationReport {
+        reference_hinting: hinting.name(),
+        pixel_size: spec.render_size(),
+        strings: strings.to_vec(),
+        candidates: candidates,
+        best_gamma: best_gamma,
+    })
+}
+
+/// Write a coverage calibration report out to a JSON file.
+pub fn write_calibration_report<P: AsRef<Path>>(path: P, report: &CalibrationReport) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, report)?;
+
+        Ok(())
+    })
+}
+
+/// A single character record in an AngelCode BMFont descriptor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BmfontChar {
+    /// The code point of the character.
+    pub id: usize,
+    /// The left edge of the glyph image on its page, in pixels.
+    pub x: usize,
+    /// The top edge of the glyph image on its page, in pixels.
+    pub y: usize,
+    /// The width of the glyph image in pixels.
+    pub width: usize,
+    /// The height of the glyph image in pixels.
+    pub height: usize,
+    /// The horizontal offset from the pen position to the left edge of the glyph image.
+    pub x_offset: i32,
+    /// The vertical offset from the top of the line to the top of the glyph image.
+    pub y_offset: i32,
+    /// The distance the pen advances after drawing the character.
+    pub x_advance: i32,
+    /// The page holding the glyph image.
+    pub page: usize,
+    /// The image channels holding the glyph, as a bit mask. `15` means all four channels.
+    pub channel: u8,
+}
+
+/// The atlas parameters in the msdf-atlas-gen JSON layout.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MsdfAtlasInfo {
+    /// The kind of image, `softmask` for coverage values, `sdf`, or `msdf`.
+    #[serde(rename = "type")]
+    pub image_type: &'static str,
+    /// The width in pixels of the range of distances the distance field covers.
+    pub distance_range: usize,
+    /// The number of pixels per em.
+    pub size: usize,
+    /// The width of the atlas image in pixels.
+    pub width: usize,
+    /// The height of the atlas image in pixels.
+    pub height: usize,
+    /// The edge of the atlas image that the atlas bounds are measured from.
+    pub y_origin: &'static str,
+}
+
+/// The typeface metrics in the msdf-atlas-gen JSON layout, in ems.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MsdfAtlasMetrics {
+    pub em_size: f64,
+    pub line_height: f64,
+    pub ascender: f64,
+    pub descender: f64,
+    pub underline_y: f64,
+    pub underline_thickness: f64,
+}
+
+/// A rectangle in the msdf-atlas-gen JSON layout.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct MsdfAtlasBounds {
+    pub left: f64,
+    pub bottom: f64,
+    pub right: f64,
+    pub top: f64,
+}
+
+/// A glyph in the msdf-atlas-gen JSON layout. Glyphs without an image have no bounds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MsdfAtlasGlyph {
+    pub unicode: usize,
+    /// The distance the pen advances after drawing the glyph, in ems.
+    pub advance: f64,
+    /// The quad to draw the glyph in relative to the pen position, in ems.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plane_bounds: Option<MsdfAtlasBounds>,
+    /// The rectangle of the glyph image in the atlas, in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub atlas_bounds: Option<MsdfAtlasBounds>,
+}
+
+/// A kerning pair in the msdf-atlas-gen JSON layout.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct MsdfAtlasKerning {
+    pub unicode1: usize,
+    pub unicode2: usize,
+    /// The adjustment to the advance of the first character, in ems.
+    pub advance: f64,
+}
+
+/// An atlas description in the JSON layout written by msdf-atlas-gen, so web and WebGL text
+/// renderers built for msdf-atlas-gen can use `fontgen` atlases. Glyph images are stored top down.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MsdfAtlasLayout {
+    pub atlas: MsdfAtlasInfo,
+    pub metrics: MsdfAtlasMetrics,
+    pub glyphs: Vec<MsdfAtlasGlyph>,
+    pub kerning: Vec<MsdfAtlasKerning>,
+}
+
+impl MsdfAtlasLayout {
+    /// Describe an atlas packed from a glyph table with a top left origin. The typeface
+    /// metrics come from the typeface the glyph table was sampled from.
+    pub fn new(face: &freetype::face::Face, glyph_tab: &GlyphTable, spec: AtlasSpec) -> MsdfAtlasLayout {
+        let descriptor = BmfontDescriptor::new(glyph_tab, spec, "");
+        let size = descriptor.size as f64;
+        let units_per_em = face.raw().units_per_EM as f64;
+        let glyphs = descriptor.chars.iter().map(|ch| {
+            let has_image = ch.width > 0 && ch.height > 0;
+            let left = ch.x_offset as f64 / size;
+            let top = (descriptor.base - ch.y_offset) as f64 / size;
+            MsdfAtlasGlyph {
+                unicode: ch.id,
+                advance: ch.x_advance as f64 / size,
+                plane_bounds: if has_image {
+                    Some(MsdfAtlasBounds {
+                        left: left,
+                        bottom: top - ch.height as f64 / size,
+                        right: left + ch.width as f64 / size,
+                        top: top,
+                    })
+                } else {
+                    None
+                },
+                atlas_bounds: if has_image {
+                    Some(MsdfAtlasBounds {
+                        left: ch.x as f64,
+                        bottom: (ch.y + ch.height) as f64,
+                        right: (ch.x + ch.width) as f64,
+                        top: ch.y as f64,
+                    })
+                } else {
+                    None
+                },
+            }
+        }).collect();
+        let kerning = descriptor.kernings.iter().map(|kerning| {
+            MsdfAtlasKerning {
+                unicode1: kerning.first,
+                unicode2: kerning.second,
+                advance: kerning.amount as f64 / size,
+            }
+        }).collect();
+
+        MsdfAtlasLayout {
+            atlas: MsdfAtlasInfo {
+                image_type: spec.distance_field.map_or("softmask", |distance_field| distance_field.name()),
+                distance_range: spec.distance_field.map_or(0, |distance_field| 2 * distance_field.spread()),
+                size: descriptor.size,
+                width: descriptor.scale_w,
+                height: descriptor.scale_h,
+                y_origin: "top",
+            },
+            metrics: MsdfAtlasMetrics {
+                em_size: 1.0,
+                line_height: face.raw().height as f64 / units_per_em,
+                ascender: face.raw().ascender as f64 / units_per_em,
+                descender: face.raw().descender as f64 / units_per_em,
+                underline_y: face.raw().underline_position as f64 / units_per_em,
+                underline_thickness: face.raw().underline_thickness as f64 / units_per_em,
+            },
+            glyphs: glyphs,
+            kerning: kerning,
+        }
+    }
+}
+
+/// Write an atlas description in the msdf-atlas-gen JSON layout out to a file.
+pub fn write_msdf_atlas_layout<P: AsRef<Path>>(path: P, layout: &MsdfAtlasLayout) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, layout)?;
+
+        Ok(())
+    })
+}
+
+/// A kerning pair in an AngelCode BMFont descriptor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BmfontKerning {
+    /// The code point of the first character of the pair.
+    pub first: usize,
+    /// The code point of the second character of the pair.
+    pub second: usize,
+    /// The adjustment to the advance of the first character when followed by the second.
+    pub amount: i32,
+}
+
+/// An AngelCode BMFont descriptor for an atlas, so engines with BMFont loaders can use the
+/// atlas without reading the `bmfa` format. All sizes are in pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BmfontDescriptor {
+    /// The family name of the typeface.
+    pub face: String,
+    /// The pixel size the glyphs were rendered at.
+    pub size: usize,
+    /// The horizontal stretch of the glyphs, in percent.
+    pub stretch_h: usize,
+    /// Whether the glyphs were slanted into a synthetic italic.
+    pub italic: bool,
+    /// The distance between the baselines of consecutive lines of text.
+    pub line_height: i32,
+    /// The distance from the top of a line of text to its baseline.
+    pub base: i32,
+    /// The width of each page image.
+    pub scale_w: usize,
+    /// The height of each page image.
+    pub scale_h: usize,
+    /// The file names of the page images, by page index.
+    pub pages: Vec<String>,
+    /// The character records, sorted by code point.
+    pub chars: Vec<BmfontChar>,
+    /// The kerning pairs, sorted by code point.
+    pub kernings: Vec<BmfontKerning>,
+}
+
+impl BmfontDescriptor {
+    /// Describe an atlas packed from a glyph table with a top left origin, whose image is
+    /// stored in the single page file `page_file`.
+    pub fn new(glyph_tab: &GlyphTable, spec: AtlasSpec, page_file: &str) -> BmfontDescriptor {
+        BmfontDescriptor::from_pages(glyph_tab, &[(glyph_tab, spec, page_file)])
+    }
+
+    /// Describe a multi-page atlas with a top left origin, whose page images are stored in
+    /// the files `page_files`, in page order.
+    pub fn new_paged(glyph_tab: &GlyphTable, pages: &[AtlasPage], page_files: &[String]) -> BmfontDescriptor {
+        let pages: Vec<(&GlyphTable, AtlasSpec, &str)> = pages.iter().zip(page_files.iter())
+            .map(|(page, page_file)| (&page.glyph_tab, page.spec, page_file.as_str()))
+            .collect();
+
+        BmfontDescriptor::from_pages(glyph_tab, &pages)
+    }
+
+    fn from_pages(glyph_tab: &GlyphTable, pages: &[(&GlyphTable, AtlasSpec, &str)]) -> BmfontDescriptor {
+        let base = glyph_tab.ascender;
+        let mut chars = vec![];
+        let (mut scale_w, mut scale_h) = (0, 0);
+        // Every page reserves a slot for the space, but the space only needs describing once.
+        let mut space_described = false;
+        for (page, &(page_tab, spec, _)) in pages.iter().enumerate() {
+            let layout = layout_glyphs(page_tab, spec);
+            scale_w = scale_w.max(layout.width);
+            scale_h = scale_h.max(layout.height);
+            for i in page_tab.slots().iter() {
+                let x_advance = glyph_tab.advance.get(i).cloned().unwrap_or(0);
+                let cell = match layout.cells.get(i) {
+                    Some(cell) => cell,
+                    None => {
+                        if *i != 32 || !space_described {
+                            chars.push(BmfontChar {
+                                id: *i, x: 0, y: 0, width: 0, height: 0,
+                                x_offset: 0, y_offset: 0, x_advance: x_advance, page: 0, channel: 15,
+                            });
+                        }
+                        space_described = space_described || *i == 32;
+                        continue;
+                    }
+                };
+                let x = cell.x + spec.free_padding() / 2;
+                let y = cell.y + spec.free_padding() / 2;
+
+                let top = glyph_tab.y_min[i] + glyph_tab.rows[i] as i64;
+                chars.push(BmfontChar {
+                    id: *i,
+                    x: x,
+                    y: y,
+                    width: glyph_tab.width[i] as usize,
+                    height: glyph_tab.rows[i] as usize,
+                    x_offset: glyph_tab.x_bearing[i],
+                    y_offset: base - top as i32,
+                    x_advance: x_advance,
+                    page: page,
+                    channel: 15,
+                });
+            }
+        }
+        // Empty and zero width glyphs have no image, but text layout still needs their advances.
+        for i in glyph_tab.empty.iter().chain(glyph_tab.zero_width.iter()) {
+            let x_advance = glyph_tab.advance.get(i).cloned().unwrap_or(0);
+            chars.push(BmfontChar {
+                id: *i, x: 0, y: 0, width: 0, height: 0,
+                x_offset: 0, y_offset: 0, x_advance: x_advance, page: 0, channel: 15,
+            });
+        }
+        chars.sort_by_key(|ch| ch.id);
+
+        let spec = pages[0].1;
+        BmfontDescriptor {
+            face: glyph_tab.family.clone(),
+            size: spec.render_size(),
+            stretch_h: (spec.stretch * 100.0).round() as usize,
+            italic: spec.oblique != 0.0,
+            line_height: glyph_tab.line_height,
+            base: base,
+            scale_w: scale_w,
+            scale_h: scale_h,
+            pages: pages.iter().map(|&(_, _, page_file)| String::from(page_file)).collect(),
+            chars: chars,
+            kernings: glyph_tab.kerning.iter().map(|pair| {
+                BmfontKerning { first: pair.first, second: pair.second, amount: pair.amount }
+            }).collect(),
+        }
+    }
+
+    /// Render the descriptor in the BMFont text format.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!(
+            "info face=\"{}\" size={} bold=0 italic={} charset=\"\" unicode=1 stretchH={} \
+             smooth=1 aa=1 padding=0,0,0,0 spacing=0,0\n",
+            self.face, self.size, self.italic as u8, self.stretch_h
+        ));
+        text.push_str(&format!(
+            "common lineHeight={} base={} scaleW={} scaleH={} pages={} packed=0\n",
+            self.line_height, self.base, self.scale_w, self.scale_h, self.pages.len()
+        ));
+        for (id, page) in self.pages.iter().enumerate() {
+            text.push_str(&format!("page id={} file=\"{}\"\n", id, page));
+        }
+        text.push_str(&format!("chars count={}\n", self.chars.len()));
+        for ch in self.chars.iter() {
+            text.push_str(&format!(
+                "char id={} x={} y={} width={} height={} xoffset={} yoffset={} xadvance={} page={} chnl={}\n",
+                ch.id, ch.x, ch.y, ch.width, ch.height,
+                ch.x_offset, ch.y_offset, ch.x_advance, ch.page, ch.channel
+            ));
+        }
+        if !self.kernings.is_empty() {
+            text.push_str(&format!("kernings count={}\n", self.kernings.len()));
+            for kerning in self.kernings.iter() {
+                text.push_str(&format!(
+                    "kerning first={} second={} amount={}\n",
+                    kerning.first, kerning.second, kerning.amount
+                ));
+            }
+        }
+
+        text
+    }
+
+    /// Render the descriptor in the BMFont XML format, as read by Starling and Sparrow.
+    pub fn to_xml(&self) -> String {
+        fn escape(value: &str) -> String {
+            value.replace('&', "&amp;")
+                .replace('"', "&quot;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\"?>\n<font>\n");
+        xml.push_str(&format!(
+            "  <info face=\"{}\" size=\"{}\" bold=\"0\" italic=\"{}\" charset=\"\" unicode=\"1\" \
+             stretchH=\"{}\" smooth=\"1\" aa=\"1\" padding=\"0,0,0,0\" spacing=\"0,0\"/>\n",
+            escape(&self.face), self.size, self.italic as u8, self.stretch_h
+        ));
+        xml.push_str(&format!(
+            "  <common lineHeight=\"{}\" base=\"{}\" scaleW=\"{}\" scaleH=\"{}\" pages=\"{}\" packed=\"0\"/>\n",
+            self.line_height, self.base, self.scale_w, self.scale_h, self.pages.len()
+        ));
+        xml.push_str("  <pages>\n");
+        for (id, page) in self.pages.iter().enumerate() {
+            xml.push_str(&format!("    <page id=\"{}\" file=\"{}\"/>\n", id, escape(page)));
+        }
+        xml.push_str("  </pages>\n");
+        xml.push_str(&format!("  <chars count=\"{}\">\n", self.chars.len()));
+        for ch in self.chars.iter() {
+            xml.push_str(&format!(
+                "    <char id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" xoffset=\"{}\" \
+                 yoffset=\"{}\" xadvance=\"{}\" page=\"{}\" chnl=\"{}\"/>\n",
+                ch.id, ch.x, ch.y, ch.width, ch.height,
+                ch.x_offset, ch.y_offset, ch.x_advance, ch.page, ch.channel
+            ));
+        }
+        xml.push_str("  </chars>\n");
+        if !self.kernings.is_empty() {
+            xml.push_str(&format!("  <kernings count=\"{}\">\n", self.kernings.len()));
+            for kerning in self.kernings.iter() {
+                xml.push_str(&format!(
+                    "    <kerning first=\"{}\" second=\"{}\" amount=\"{}\"/>\n",
+                    kerning.first, kerning.second, kerning.amount
+                ));
+            }
+            xml.push_str("  </kernings>\n");
+        }
+        xml.push_str("</font>\n");
+
+        xml
+    }
+
+    /// Render the descriptor in the version 3 BMFont binary format. The kerning block is left
+    /// out when there are no kerning pairs.
+    pub fn to_binary(&self) -> Vec<u8> {
+        fn push_block(data: &mut Vec<u8>, block_type: u8, block: &[u8]) {
+            data.push(block_type);
+            data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            data.extend_from_slice(block);
+        }
+
+        let mut data = vec![b'B', b'M', b'F', 3];
+
+        let mut info = vec![];
+        info.extend_from_slice(&(self.size as i16).to_le_bytes());
+        // Smooth, unicode, and italic.
+        info.push(0b0000_0011 | (self.italic as u8) << 2);
+        info.push(0);
+        info.extend_from_slice(&(self.stretch_h as u16).to_le_bytes());
+        // Supersampling, padding up, right, down, and left, spacing horizontal and vertical, and outline.
+        info.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        info.extend_from_slice(self.face.as_bytes());
+        info.push(0);
+        push_block(&mut data, 1, &info);
+
+        let mut common = vec![];
+        common.extend_from_slice(&(self.line_height as u16).to_le_bytes());
+        common.extend_from_slice(&(self.base as u16).to_le_bytes());
+        common.extend_from_slice(&(self.scale_w as u16).to_le_bytes());
+        common.extend_from_slice(&(self.scale_h as u16).to_le_bytes());
+        common.extend_from_slice(&(self.pages.len() as u16).to_le_bytes());
+        // Unpacked, with the glyphs in every channel.
+        common.extend_from_slice(&[0, 0, 0, 0, 0]);
+        push_block(&mut data, 2, &common);
+
+        let mut pages = vec![];
+        for page in self.pages.iter() {
+            pages.extend_from_slice(page.as_bytes());
+            pages.push(0);
+        }
+        push_block(&mut data, 3, &pages);
+
+        let mut chars = vec![];
+        for ch in self.chars.iter() {
+            chars.extend_from_slice(&(ch.id as u32).to_le_bytes());
+            chars.extend_from_slice(&(ch.x as u16).to_le_bytes());
+            chars.extend_from_slice(&(ch.y as u16).to_le_bytes());
+            chars.extend_from_slice(&(ch.width as u16).to_le_bytes());
+            chars.extend_from_slice(&(ch.height as u16).to_le_bytes());
+            chars.extend_from_slice(&(ch.x_offset as i16).to_le_bytes());
+            chars.extend_from_slice(&(ch.y_offset as i16).to_le_bytes());
+            chars.extend_from_slice(&(ch.x_advance as i16).to_le_bytes());
+            chars.push(ch.page as u8);
+            chars.push(ch.channel);
+        }
+        push_block(&mut data, 4, &chars);
+
+        if !self.kernings.is_empty() {
+            let mut kernings = vec![];
+            for kerning in self.kernings.iter() {
+                kernings.extend_from_slice(&(kerning.first as u32).to_le_bytes());
+                kernings.extend_from_slice(&(kerning.second as u32).to_le_bytes());
+                kernings.extend_from_slice(&(kerning.amount as i16).to_le_bytes());
+            }
+            push_block(&mut data, 5, &kernings);
+        }
+
+        data
+    }
+}
+
+/// The size in bytes of a glyph record in a glyph lookup table. The records have the layout
+/// of the character records of the BMFont binary format.
+pub const GLYPH_RECORD_SIZE: usize = 20;
+
+/// The first bytes of a glyph lookup table file.
+const GLYPH_LOOKUP_MAGIC: &[u8; 4] = b"FGLT";
+
+/// The version of the glyph lookup table format.
+const GLYPH_LOOKUP_VERSION: u8 = 1;
+
+/// The size in bytes of the header of a glyph lookup table.
+const GLYPH_LOOKUP_HEADER_SIZE: usize = 12;
+
+/// The index marking an empty entry in either level of a glyph lookup table.
+const GLYPH_LOOKUP_EMPTY: u16 = 0xFFFF;
+
+/// A table mapping each code point of an atlas to its glyph record, for runtimes that need to
+/// look glyphs up in constant time without building a hash map when loading the atlas.
+///
+/// The binary form starts with a 12 byte header: the magic bytes `FGLT`, the format version,
+/// the origin (`0` for `top-left`, `1` for `bottom-left`), and then the number of blocks,
+/// leaves, and records as 16 bit integers. The first level of the table follows, with an
+/// entry for every block of 256 code points up to the last one in the atlas, holding the index
+/// of the leaf for the block. Each leaf holds 256 entries with the index of the record of each
+/// code point in the block. Empty entries in either level hold `0xFFFF`. The records come last,
+/// in the 20 byte layout of the BMFont binary format. Every integer is little endian.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphLookupTable {
+    /// The origin the positions of the glyph images are measured from. With a `bottom-left`
+    /// origin, the `y` position is the distance from the bottom edge of the page to the bottom
+    /// edge of the glyph image.
+    pub origin: bmfa::Origin,
+    /// The glyph records, sorted by code point.
+    pub records: Vec<BmfontChar>,
+}
+
+impl GlyphLookupTable {
+    /// Build the lookup table for a single page atlas packed from a glyph table.
+    pub fn new(glyph_tab: &GlyphTable, spec: AtlasSpec) -> GlyphLookupTable {
+        GlyphLookupTable::from_pages(glyph_tab, &[(glyph_tab, spec)])
+    }
+
+    /// Build the lookup table for a multi-page atlas.
+    pub fn new_paged(glyph_tab: &GlyphTable, pages: &[AtlasPage]) -> GlyphLookupTable {
+        let pages: Vec<(&GlyphTable, AtlasSpec)> = pages.iter()
+            .map(|page| (&page.glyph_tab, page.spec))
+            .collect();
+
+        GlyphLookupTable::from_pages(glyph_tab, &pages)
+    }
+
+    fn from_pages(glyph_tab: &GlyphTable, pages: &[(&GlyphTable, AtlasSpec)]) -> GlyphLookupTable {
+        let named_pages: Vec<(&GlyphTable, AtlasSpec, &str)> = pages.iter()
+            .map(|&(page_tab, spec)| (page_tab, spec, ""))
+            .collect();
+        let descriptor = BmfontDescriptor::from_pages(glyph_tab, &named_pages);
+        let origin = pages[0].1.origin;
+        let page_heights: Vec<usize> = pages.iter()
+            .map(|&(page_tab, spec)| layout_glyphs(page_tab, spec).height)
+            .collect();
+        let records = descriptor.chars.into_iter()
+            .map(|mut record| {
+                if origin == bmfa::Origin::BottomLeft && record.height > 0 {
+                    record.y = page_heights[record.page] - record.y - record.height;
+                }
+                record
+            })
+            .collect();
+
+        GlyphLookupTable {
+            origin: origin,
+            records: records,
+        }
+    }
+
+    /// The binary form of the table, or `None` when it holds more records than the 16 bit
+    /// indices of the table can address.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        if self.records.len() >= GLYPH_LOOKUP_EMPTY as usize {
+            return None;
+        }
+        let block_count = self.records.last().map_or(0, |record| (record.id >> 8) + 1);
+        let mut blocks = vec![GLYPH_LOOKUP_EMPTY; block_count];
+        let mut leaves: Vec<[u16; 256]> = vec![];
+        for (index, record) in self.records.iter().enumerate() {
+            let block = record.id >> 8;
+            if blocks[block] == GLYPH_LOOKUP_EMPTY {
+                blocks[block] = leaves.len() as u16;
+                leaves.push([GLYPH_LOOKUP_EMPTY; 256]);
+            }
+            leaves[blocks[block] as usize][record.id & 0xFF] = index as u16;
+        }
+
+        let mut data = GLYPH_LOOKUP_MAGIC.to_vec();
+        data.push(GLYPH_LOOKUP_VERSION);
+        data.push(match self.origin {
+            bmfa::Origin::TopLeft => 0,
+            bmfa::Origin::BottomLeft => 1,
+        });
+        data.extend_from_slice(&(block_count as u16).to_le_bytes());
+        data.extend_from_slice(&(leaves.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(self.records.len() as u16).to_le_bytes());
+        for entry in blocks.iter().chain(leaves.iter().flat_map(|leaf| leaf.iter())) {
+            data.extend_from_slice(&entry.to_le_bytes());
+        }
+        for record in self.records.iter() {
+            data.extend_from_slice(&(record.id as u32).to_le_bytes());
+            data.extend_from_slice(&(record.x as u16).to_le_bytes());
+            data.extend_from_slice(&(record.y as u16).to_le_bytes());
+            data.extend_from_slice(&(record.width as u16).to_le_bytes());
+            data.extend_from_slice(&(record.height as u16).to_le_bytes());
+            data.extend_from_slice(&(record.x_offset as i16).to_le_bytes());
+            data.extend_from_slice(&(record.y_offset as i16).to_le_bytes());
+            data.extend_from_slice(&(record.x_advance as i16).to_le_bytes());
+            data.push(record.page as u8);
+            data.push(record.channel);
+        }
+
+        Some(data)
+    }
+}
+
+/// Write a glyph lookup table out to a binary file.
+pub fn write_glyph_lookup_table<P: AsRef<Path>>(path: P, table: &GlyphLookupTable) -> io::Result<()> {
+    let data = match table.to_bytes() {
+        Some(data) => data,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "the atlas holds too many glyphs for a lookup table"
+            ));
+        }
+    };
+
+    write_atomically(path, |path| fs::write(path, &data))
+}
+
+/// A reader looking glyphs up in the binary form of a glyph lookup table in place, the way a
+/// runtime would, without decoding the whole table first.
+#[derive(Copy, Clone, Debug)]
+pub struct GlyphLookup<'a> {
+    data: &'a [u8],
+    block_count: usize,
+    records_offset: usize,
+}
+
+impl<'a> GlyphLookup<'a> {
+    /// Read the header of a glyph lookup table, or `None` if the data is not a glyph lookup table
+    /// or is truncated.
+    pub fn parse(data: &'a [u8]) -> Option<GlyphLookup<'a>> {
+        if data.len() < GLYPH_LOOKUP_HEADER_SIZE || &data[0..4] != GLYPH_LOOKUP_MAGIC {
+            return None;
+        }
+        if data[4] != GLYPH_LOOKUP_VERSION || data[5] > 1 {
+            return None;
+        }
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+        let (block_count, leaf_count, record_count) = (read_u16(6), read_u16(8), read_u16(10));
+        let records_offset = GLYPH_LOOKUP_HEADER_SIZE + 2 * block_count + 512 * leaf_count;
+        if data.len() < records_offset + GLYPH_RECORD_SIZE * record_count {
+            return None;
+        }
+
+        Some(GlyphLookup {
+            data: data,
+            block_count: block_count,
+            records_offset: records_offset,
+        })
+    }
+
+    /// The origin the positions of the glyph images are measured from.
+    pub fn origin(&self) -> bmfa::Origin {
+        match self.data[5] {
+            0 => bmfa::Origin::TopLeft,
+            _ => bmfa::Origin::BottomLeft,
+        }
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    /// The offset in bytes of the record of a code point, if the atlas has the character.
+    pub fn record_offset(&self, code_point: usize) -> Option<usize> {
+        let block = code_point >> 8;
+        if block >= self.block_count {
+            return None;
+        }
+        let leaf = self.read_u16(GLYPH_LOOKUP_HEADER_SIZE + 2 * block);
+        if leaf == GLYPH_LOOKUP_EMPTY {
+            return None;
+        }
+        let leaves_offset = GLYPH_LOOKUP_HEADER_SIZE + 2 * self.block_count;
+        let index = self.read_u16(leaves_offset + 512 * leaf as usize + 2 * (code_point & 0xFF));
+        if index == GLYPH_LOOKUP_EMPTY {
+            return None;
+        }
+
+        Some(self.records_offset + GLYPH_RECORD_SIZE * index as usize)
+    }
+
+    /// Look up the record of a code point, if the atlas has the character.
+    pub fn find(&self, code_point: usize) -> Option<BmfontChar> {
+        let offset = self.record_offset(code_point)?;
+        let record = &self.data[offset..(offset + GLYPH_RECORD_SIZE)];
+        let read_u16 = |offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+
+        Some(BmfontChar {
+            id: u32::from_le_bytes([record[0], record[1], record[2], record[3]]) as usize,
+            x: read_u16(4) as usize,
+            y: read_u16(6) as usize,
+            width: read_u16(8) as usize,
+            height: read_u16(10) as usize,
+            x_offset: read_u16(12) as i16 as i32,
+            y_offset: read_u16(14) as i16 as i32,
+            x_advance: read_u16(16) as i16 as i32,
+            page: record[18] as usize,
+            channel: record[19],
+        })
+    }
+}
+
+/// The magic bytes identifying a cached glyph table.
+const GLYPH_CACHE_MAGIC: &[u8; 4] = b"FGGC";
+
+/// The version of the cached glyph table format. Cache entries of any other version get sampled
+/// again.
+const GLYPH_CACHE_VERSION: u8 = 1;
+
+/// A directory of previously sampled glyph tables, so repeated runs rendering the same glyphs
+/// from the same font files only have to pack them again. Each entry is a `<key>.glyphs` file,
+/// where the key is a hash of the versions of `fontgen` and the renderer, the font files, the
+/// fields of the atlas spec that change the glyph images, the characters, and any other sampling
+/// options. Changing the origin, the packing, or the size of the atlas reuses the cached glyphs.
+///
+/// The binary form starts with the magic bytes `FGGC` and the format version, followed by the
+/// ascender and line height as 32 bit integers, the number of bytes per pixel, and the family
+/// name. Next come the glyph records in code point order, each one holding the code point, a
+/// byte with a bit set for each of the rows, width, pitch, offset from the baseline, bearing,
+/// advance, and image the glyph has, and then those values in that order. The offset from the
+/// baseline is a 64 bit integer and images are prefixed by their length. The empty, zero width,
+/// and color glyph code points, the kerning pairs, the render modes, and the typefaces of the
+/// glyphs drawn from fallback or substitute typefaces follow, each prefixed by their count.
+/// Strings are prefixed by their length in bytes, and every integer is a little endian 32 bit
+/// integer unless noted otherwise. The rasterization times of the glyphs are not cached.
+#[derive(Clone, Debug)]
+pub struct GlyphCache {
+    /// The directory holding the cache entries.
+    dir: PathBuf,
+}
+
+impl GlyphCache {
+    /// How long to wait for another run storing the same entry to finish.
+    const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Use `dir` as the cache directory, creating it if it does not exist yet.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<GlyphCache> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(extended_length_path(&dir))?;
+
+        Ok(GlyphCache { dir: dir })
+    }
+
+    /// Compute the cache key of the glyphs sampled from the typefaces in `font_files` with
+    /// `spec` by `renderer`. The `options` describe anything else that changes the glyph images,
+    /// like the render mode overrides or the design coordinates of a variable font.
+    pub fn key(
+        font_files: &[&Path], spec: AtlasSpec, chars: &CharacterSet, renderer: &Renderer,
+        options: &str) -> io::Result<String> {
+
+        let mut hasher = Fnv1a::new();
+        hasher.write(&[GLYPH_CACHE_VERSION]);
+        hasher.write_str(env!("CARGO_PKG_VERSION"));
+        hasher.write_str(&renderer.to_string());
+        for font_file in font_files.iter() {
+            hasher.write_bytes(&fs::read(extended_length_path(font_file))?);
+        }
+        // The origin, the packing, and the size of the atlas only change where the glyphs go.
+        let rendering = format!(
+            "{} {} {} {:?} {:?} {} {} {:?} {:?} {:?} {} {:?} {:?} {:?} {:?} {:?} {} {:?} {:?} {:?}",
+            spec.padding, spec.slot_glyph_size, spec.glyph_size, spec.stretch, spec.small_caps_scale,
+            spec.last_resort, spec.builtin_fallback, spec.mono, spec.oblique, spec.embolden, spec.outline,
+            spec.shadow, spec.glow, spec.zero_width, spec.distance_field, spec.gamma, spec.glyph_indices,
+            spec.channels, spec.point_size, spec.resample_filter
+        );
+        hasher.write_str(&rendering);
+        hasher.write(&(chars.ranges().len() as u64).to_le_bytes());
+        for &(start, end) in chars.ranges().iter() {
+            hasher.write(&(start as u64).to_le_bytes());
+            hasher.write(&(end as u64).to_le_bytes());
+        }
+        hasher.write_str(options);
+
+        Ok(format!("{:032x}", hasher.finish()))
+    }
+
+    /// The path of the cache entry for a key.
+    pub fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.glyphs", key))
+    }
+
+    /// Load the glyph table cached under a key, or `None` if there is no such entry, or it is
+    /// unreadable, truncated, or of another format version.
+    pub fn load(&self, key: &str) -> Option<GlyphTable> {
+        let data = fs::read(extended_length_path(&self.entry_path(key))).ok()?;
+        let mut reader = CacheReader { data: &data, offset: 0 };
+        if reader.bytes(4)? != GLYPH_CACHE_MAGIC || reader.u8()? != GLYPH_CACHE_VERSION {
+            return None;
+        }
+        let ascender = reader.i32()?;
+        let line_height = reader.i32()?;
+        let channels = reader.u8()? as usize;
+        let family = reader.string()?;
+        let mut glyph_tab = GlyphTable {
+            rows: HashMap::new(),
+            width: HashMap::new(),
+            pitch: HashMap::new(),
+            y_min: HashMap::new(),
+            x_bearing: HashMap::new(),
+            advance: HashMap::new(),
+            ascender: ascender,
+            line_height: line_height,
+            family: family,
+            buffer: HashMap::new(),
+            empty: vec![],
+            zero_width: vec![],
+            telemetry: vec![],
+            channels: channels,
+            kerning: vec![],
+            render_modes: BTreeMap::new(),
+            glyph_typefaces: BTreeMap::new(),
+            color_glyphs: vec![],
+        };
+        for _ in 0..reader.u32()? {
+            let code_point = reader.u32()? as usize;
+            let fields = reader.u8()?;
+            if fields & 0x01 != 0 {
+                glyph_tab.rows.insert(code_point, reader.i32()?);
+            }
+            if fields & 0x02 != 0 {
+                glyph_tab.width.insert(code_point, reader.i32()?);
+            }
+            if fields & 0x04 != 0 {
+                glyph_tab.pitch.insert(code_point, reader.i32()?);
+            }
+            if fields & 0x08 != 0 {
+                glyph_tab.y_min.insert(code_point, reader.i64()?);
+            }
+            if fields & 0x10 != 0 {
+                glyph_tab.x_bearing.insert(code_point, reader.i32()?);
+            }
+            if fields & 0x20 != 0 {
+                glyph_tab.advance.insert(code_point, reader.i32()?);
+            }
+            if fields & 0x40 != 0 {
+                let length = reader.u32()? as usize;
+                glyph_tab.buffer.insert(code_point, GlyphImage::new(reader.bytes(length)?.to_vec()));
+            }
+        }
+        glyph_tab.empty = reader.code_points()?;
+        glyph_tab.zero_width = reader.code_points()?;
+        glyph_tab.color_glyphs = reader.code_points()?;
+        for _ in 0..reader.u32()? {
+            glyph_tab.kerning.push(KerningPair {
+                first: reader.u32()? as usize,
+                second: reader.u32()? as usize,
+                amount: reader.i32()?,
+            });
+        }
+        for _ in 0..reader.u32()? {
+            let code_point = reader.u32()? as usize;
+            let render_mode = match reader.u8()? {
+                0 => "coverage",
+                1 => "sdf",
+                2 => "msdf",
+                _ => return None,
+            };
+            glyph_tab.render_modes.insert(code_point, render_mode);
+        }
+        for _ in 0..reader.u32()? {
+            let code_point = reader.u32()? as usize;
+            glyph_tab.glyph_typefaces.insert(code_point, reader.string()?);
+        }
+
+        Some(glyph_tab)
+    }
+
+    /// Cache a glyph table under a key. Runs storing the same entry at once take turns through an
+    /// `OutputLock` on the entry, and the last one to finish wins.
+    pub fn store(&self, key: &str, glyph_tab: &GlyphTable) -> io::Result<()> {
+        let mut data = vec![];
+        data.extend_from_slice(GLYPH_CACHE_MAGIC);
+        data.push(GLYPH_CACHE_VERSION);
+        data.extend_from_slice(&glyph_tab.ascender.to_le_bytes());
+        data.extend_from_slice(&glyph_tab.line_height.to_le_bytes());
+        data.push(glyph_tab.channels as u8);
+        write_cache_string(&mut data, &glyph_tab.family);
+        let code_points: BTreeSet<usize> = glyph_tab.advance.keys()
+            .chain(glyph_tab.rows.keys())
+            .chain(glyph_tab.width.keys())
+            .chain(glyph_tab.pitch.keys())
+            .chain(glyph_tab.y_min.keys())
+            .chain(glyph_tab.x_bearing.keys())
+            .chain(glyph_tab.buffer.keys())
+            .cloned()
+            .collect();
+        data.extend_from_slice(&(code_points.len() as u32).to_le_bytes());
+        for &code_point in code_points.iter() {
+            let rows = glyph_tab.rows.get(&code_point);
+            let width = glyph_tab.width.get(&code_point);
+            let pitch = glyph_tab.pitch.get(&code_point);
+            let y_min = glyph_tab.y_min.get(&code_point);
+            let x_bearing = glyph_tab.x_bearing.get(&code_point);
+            let advance = glyph_tab.advance.get(&code_point);
+            let image = glyph_tab.buffer.get(&code_point);
+            let fields = [
+                rows.is_some(), width.is_some(), pitch.is_some(), y_min.is_some(),
+                x_bearing.is_some(), advance.is_some(), image.is_some()
+            ];
+            let fields = fields.iter().enumerate()
+                .fold(0, |bits, (bit, &present)| if present { bits | (1 << bit) } else { bits });
+            data.extend_from_slice(&(code_point as u32).to_le_bytes());
+            data.push(fields);
+            for value in [rows, width, pitch].iter().flatten() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            if let Some(y_min) = y_min {
+                data.extend_from_slice(&y_min.to_le_bytes());
+            }
+            for value in [x_bearing, advance].iter().flatten() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            if let Some(image) = image {
+                data.extend_from_slice(&(image.data.len() as u32).to_le_bytes());
+                data.extend_from_slice(&image.data);
+            }
+        }
+        for code_points in [&glyph_tab.empty, &glyph_tab.zero_width, &glyph_tab.color_glyphs].iter() {
+            data.extend_from_slice(&(code_points.len() as u32).to_le_bytes());
+            for &code_point in code_points.iter() {
+                data.extend_from_slice(&(code_point as u32).to_le_bytes());
+            }
+        }
+        data.extend_from_slice(&(glyph_tab.kerning.len() as u32).to_le_bytes());
+        for pair in glyph_tab.kerning.iter() {
+            data.extend_from_slice(&(pair.first as u32).to_le_bytes());
+            data.extend_from_slice(&(pair.second as u32).to_le_bytes());
+            data.extend_from_slice(&pair.amount.to_le_bytes());
+        }
+        data.extend_from_slice(&(glyph_tab.render_modes.len() as u32).to_le_bytes());
+        for (&code_point, &render_mode) in glyph_tab.render_modes.iter() {
+            data.extend_from_slice(&(code_point as u32).to_le_bytes());
+            data.push(match render_mode {
+                "sdf" => 1,
+                "msdf" => 2,
+                _ => 0,
+            });
+        }
+        data.extend_from_slice(&(glyph_tab.glyph_typefaces.len() as u32).to_le_bytes());
+        for (&code_point, typeface) in glyph_tab.glyph_typefaces.iter() {
+            data.extend_from_slice(&(code_point as u32).to_le_bytes());
+            write_cache_string(&mut data, typeface);
+        }
+
+        let entry_path = self.entry_path(key);
+        let _lock = OutputLock::acquire(&entry_path, GlyphCache::LOCK_TIMEOUT)?;
+
+        write_atomically(entry_path, |path| fs::write(path, &data))
+    }
+}
+
+/// Compute the key of an incremental build target from the contents of its input files, like
+/// fonts and charset files, the options it gets generated with, and the renderer generating it.
+/// A target whose key changed since it was last built is out of date.
+pub fn build_key(input_files: &[&Path], options: &[String], renderer: &Renderer) -> io::Result<String> {
+    let mut hasher = Fnv1a::new();
+    hasher.write_str(env!("CARGO_PKG_VERSION"));
+    hasher.write_str(&renderer.to_string());
+    hasher.write(&(input_files.len() as u64).to_le_bytes());
+    for input_file in input_files.iter() {
+        hasher.write_str(&input_file.to_string_lossy());
+        hasher.write_bytes(&fs::read(extended_length_path(input_file))?);
+    }
+    hasher.write(&(options.len() as u64).to_le_bytes());
+    for option in options.iter() {
+        hasher.write_str(option);
+    }
+
+    Ok(format!("{:032x}", hasher.finish()))
+}
+
+/// The 128 bit FNV-1a hash the glyph cache keys are made of. Unlike the hasher of the standard
+/// library, it gives the same hash in every build on every platform, so cache keys stay valid
+/// from one build of `fontgen` to the next.
+struct Fnv1a {
+    hash: u128,
+}
+
+impl Fnv1a {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    fn new() -> Fnv1a {
+        Fnv1a { hash: Fnv1a::OFFSET_BASIS }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter() {
+            self.hash ^= u128::from(byte);
+            self.hash = self.hash.wrapping_mul(Fnv1a::PRIME);
+        }
+    }
+
+    /// Hash a run of bytes prefixed by its length, so consecutive runs cannot run into each other.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    fn write_str(&mut self, string: &str) {
+        self.write_bytes(string.as_bytes());
+    }
+
+    fn finish(&self) -> u128 {
+        self.hash
+    }
+}
+
+/// Append a string to a cache entry, prefixed by its length in bytes.
+fn write_cache_string(data: &mut Vec<u8>, string: &str) {
+    data.extend_from_slice(&(string.len() as u32).to_le_bytes());
+    data.extend_from_slice(string.as_bytes());
+}
+
+/// Reads the values of a cache entry in order. Every read returns `None` once the entry runs out.
+struct CacheReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..(self.offset.checked_add(length)?))?;
+        self.offset += length;
+
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|value| value as i32)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        let mut bytes = [0 as u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Some(i64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec()).ok()
+    }
+
+    fn code_points(&mut self) -> Option<Vec<usize>> {
+        (0..self.u32()?).map(|_| self.u32().map(|code_point| code_point as usize)).collect()
+    }
+}
+
+/// An error that occurred while loading or running a glyph script.
+#[cfg(feature = "scripting")]
+#[derive(Clone, Debug)]
+pub enum GlyphScriptError {
+    CouldNotLoadScript(PathBuf, String),
+    ScriptFailed(usize, String),
+    InvalidGlyph(usize, String),
+}
+
+#[cfg(feature = "scripting")]
+impl fmt::Display for GlyphScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GlyphScriptError::CouldNotLoadScript(ref path, ref e) => {
+                write!(f, "Could not load the glyph script {}: {}", path.display(), e)
+            }
+            GlyphScriptError::ScriptFailed(code_point, ref e) => {
+                write!(
+                    f, "The glyph script failed on the character {}: {}",
+                    describe_code_point(code_point), e
+                )
+            }
+            GlyphScriptError::InvalidGlyph(code_point, ref reason) => {
+                write!(
+                    f, "The glyph script returned an invalid glyph for the character {}: {}",
+                    describe_code_point(code_point), reason
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl error::Error for GlyphScriptError {}
+
+/// A user supplied Rhai script that post-processes each glyph before packing. The script
+/// defines a function `process_glyph(glyph)` that receives a map with the fields `code_point`,
+/// `width`, `rows`, `y_min`, `channels`, and `pixels`, the row-major values of the glyph image
+/// with `channels` values per pixel,
+/// and returns the glyph map with any of the `width`, `rows`, `y_min`, or `pixels` fields
+/// modified.
+#[cfg(feature = "scripting")]
+pub struct GlyphScript {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+#[cfg(feature = "scripting")]
+impl GlyphScript {
+    /// Load and compile a glyph script from a file.
+    pub fn load(path: &Path) -> Result<GlyphScript, GlyphScriptError> {
+        let engine = rhai::Engine::new();
+        let ast = engine.compile_file(path.to_path_buf()).map_err(|e| {
+            GlyphScriptError::CouldNotLoadScript(path.to_path_buf(), format!("{}", e))
+        })?;
+
+        Ok(GlyphScript {
+            engine: engine,
+            ast: ast,
+        })
+    }
+
+    /// Run the script over every glyph image in the glyph table, in code point order.
+    pub fn apply(&self, glyph_tab: &mut GlyphTable) -> Result<(), GlyphScriptError> {
+        let mut code_points: Vec<usize> = glyph_tab.buffer.keys().cloned().collect();
+        code_points.sort();
+        for code_point in code_points {
+            let mut glyph = rhai::Map::new();
+            let pixels: rhai::Array = glyph_tab.buffer[&code_point].data.iter()
+                .map(|&pixel| rhai::Dynamic::from(pixel as i64))
+                .collect();
+            glyph.insert("code_point".into(), rhai::Dynamic::from(code_point as i64));
+            glyph.insert("width".into(), rhai::Dynamic::from(glyph_tab.width[&code_point] as i64));
+            glyph.insert("rows".into(), rhai::Dynamic::from(glyph_tab.rows[&code_point] as i64));
+            glyph.insert("y_min".into(), rhai::Dynamic::from(glyph_tab.y_min[&code_point]));
+            glyph.insert("channels".into(), rhai::Dynamic::from(glyph_tab.channels as i64));
+            glyph.insert("pixels".into(), rhai::Dynamic::from(pixels));
+
+            let glyph: rhai::Map = self.engine
+                .call_fn(&mut rhai::Scope::new(), &self.ast, "process_glyph", (glyph,))
+                .map_err(|e| GlyphScriptError::ScriptFailed(code_point, format!("{}", e)))?;
+
+            let field = |name: &str| {
+                glyph.get(name).and_then(|value| value.as_int().ok()).ok_or_else(|| {
+                    GlyphScriptError::InvalidGlyph(code_point, format!("missing integer field `{}`", name))
+                })
+            };
+            let width = field("width")?;
+            let rows = field("rows")?;
+            let y_min = field("y_min")?;
+            let pixels = glyph.get("pixels")
+                .and_then(|value| value.clone().try_cast::<rhai::Array>())
+                .ok_or_else(|| {
+                    GlyphScriptError::InvalidGlyph(code_point, String::from("missing array field `pixels`"))
+                })?;
+            let channels = glyph_tab.channels as i64;
+            if width < 0 || rows < 0 || pixels.len() as i64 != width * rows * channels {
+                return Err(GlyphScriptError::InvalidGlyph(
+                    code_point, format!("{} pixels do not fill a {}x{} glyph", pixels.len(), width, rows)
+                ));
+            }
+
+            let data = pixels.iter()
+                .map(|pixel| pixel.as_int().unwrap_or(0).max(0).min(255) as u8)
+                .collect();
+            glyph_tab.width.insert(code_point, width as i32);
+            glyph_tab.rows.insert(code_point, rows as i32);
+            glyph_tab.pitch.insert(code_point, (width * channels) as i32);
+            glyph_tab.y_min.insert(code_point, y_min);
+            glyph_tab.buffer.insert(code_point, GlyphImage::new(data));
+        }
+
+        Ok(())
+    }
+}
+
+/// The functions of the public FreeType API that `freetype::ffi` has no bindings for, in the
+/// `freetype-sys` versions `freetype-rs` pulls in. The FreeType library `freetype-sys` links
+/// against exports them, so they only need declaring. Once `freetype::ffi` binds them, these
+/// declarations should go in favor of its own.
+mod freetype_ext {
+    use freetype::ffi::{FT_Byte, FT_Error, FT_Face, FT_Int, FT_Library, FT_Long, FT_ULong};
+
+    extern "C" {
+        pub fn FT_Library_Version(
+            library: FT_Library, major: *mut FT_Int, minor: *mut FT_Int, patch: *mut FT_Int);
+
+        pub fn FT_Load_Sfnt_Table(
+            face: FT_Face, tag: FT_ULong, offset: FT_Long,
+            buffer: *mut FT_Byte, length: *mut FT_ULong) -> FT_Error;
+    }
+}
+
+/// Read a table of an SFNT based typeface, like a TrueType or OpenType font, or `None` if the
+/// typeface has no such table.
+fn load_sfnt_table(face: &freetype::face::Face, tag: [u8; 4]) -> Option<Vec<u8>> {
+    let tag = u32::from_be_bytes(tag) as freetype::ffi::FT_ULong;
+    // Loading a table only reads from the stream of the face, which is not shared across threads.
+    let raw_face = face.raw() as *const freetype::ffi::FT_FaceRec as freetype::ffi::FT_Face;
+    let mut length = 0;
+    unsafe {
+        if freetype_ext::FT_Load_Sfnt_Table(raw_face, tag, 0, ptr::null_mut(), &mut length) != 0 {
+            return None;
+        }
+    }
+    let mut table = vec![0; length as usize];
+    unsafe {
+        if freetype_ext::FT_Load_Sfnt_Table(raw_face, tag, 0, table.as_mut_ptr(), &mut length) != 0 {
+            return None;
+        }
+    }
+
+    Some(table)
+}
+
+/// The scripts of an OpenType layout table, `GSUB` or `GPOS`, along with the language systems of
+/// each one, and the features the table has for them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LayoutFeatures {
+    /// The script tags, e.g. `latn`, each with its language system tags, e.g. `TRK `. A script
+    /// with a default language system lists `dflt` first.
+    pub scripts: Vec<(String, Vec<String>)>,
+    /// The distinct feature tags, e.g. `liga` and `kern`, sorted.
+    pub features: Vec<String>,
+}
+
+/// An axis of a variable font, with its design coordinates.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FontAxis {
+    /// The tag of the axis, e.g. `wght`, which `--variation` takes.
+    pub tag: String,
+    /// The name of the axis, e.g. `Weight`, from the `name` table of the font.
+    pub name: String,
+    /// The smallest design coordinate.
+    pub minimum: f32,
+    /// The design coordinate of the default instance.
+    pub default: f32,
+    /// The largest design coordinate.
+    pub maximum: f32,
+}
+
+/// A named instance of a variable font, like `Bold Condensed`, with the design coordinate of each
+/// axis in the order of the axes.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NamedInstance {
+    /// The subfamily name of the instance, from the `name` table of the font.
+    pub name: String,
+    /// The design coordinate of each axis of the font.
+    pub coordinates: Vec<f32>,
+}
+
+/// The OpenType features, scripts, and languages of a typeface, along with the variation axes and
+/// named instances of a variable font, read straight from its `GSUB`, `GPOS`, and `fvar` tables.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FontFeatures {
+    /// The glyph substitution features, if the typeface has a `GSUB` table.
+    pub gsub: Option<LayoutFeatures>,
+    /// The glyph positioning features, if the typeface has a `GPOS` table.
+    pub gpos: Option<LayoutFeatures>,
+    /// The variation axes. These are empty unless the typeface is a variable font.
+    pub axes: Vec<FontAxis>,
+    /// The named instances of a variable font.
+    pub instances: Vec<NamedInstance>,
+}
+
+/// The text of a four byte tag, e.g. `liga`.
+fn tag_text(data: &[u8], offset: usize) -> Option<String> {
+    data.get(offset..offset + 4).map(|tag| tag.iter().map(|&byte| byte as char).collect())
+}
+
+/// The scripts, language systems, and features of a `GSUB` or `GPOS` table.
+fn layout_features(table: &[u8]) -> Option<LayoutFeatures> {
+    let script_list = read_u16(table, 4)?;
+    let feature_list = read_u16(table, 6)?;
+    let mut scripts = vec![];
+    for script in 0..read_u16(table, script_list)? {
+        let record = script_list + 2 + 6 * script;
+        let script_table = script_list + read_u16(table, record + 4)?;
+        let mut languages = vec![];
+        if read_u16(table, script_table)? != 0 {
+            languages.push(String::from("dflt"));
+        }
+        for language in 0..read_u16(table, script_table + 2)? {
+            languages.push(tag_text(table, script_table + 4 + 6 * language)?);
+        }
+        scripts.push((tag_text(table, record)?, languages));
+    }
+    let mut features = vec![];
+    for feature in 0..read_u16(table, feature_list)? {
+        features.push(tag_text(table, feature_list + 2 + 6 * feature)?);
+    }
+    features.sort();
+    features.dedup();
+
+    Some(LayoutFeatures { scripts: scripts, features: features })
+}
+
+/// Look up a name of a font by its name ID. The US English name of the Windows platform comes
+/// first, then the Unicode names, then the English name of the Macintosh platform, then any other.
+fn font_name(name_table: &[u8], name_id: usize) -> Option<String> {
+    let count = read_u16(name_table, 2)?;
+    let storage = read_u16(name_table, 4)?;
+    let mut best: Option<(usize, String)> = None;
+    for record in 0..count {
+        let offset = 6 + 12 * record;
+        if read_u16(name_table, offset + 6)? != name_id {
+            continue;
+        }
+        let (platform, language) = (read_u16(name_table, offset)?, read_u16(name_table, offset + 4)?);
+        let rank = match (platform, language) {
+            (3, 0x0409) => 0,
+            (0, _) => 1,
+            (1, 0) => 2,
+            _ => 3,
+        };
+        if best.as_ref().map_or(false, |&(best_rank, _)| best_rank <= rank) {
+            continue;
+        }
+        let length = read_u16(name_table, offset + 8)?;
+        let start = storage + read_u16(name_table, offset + 10)?;
+        let bytes = name_table.get(start..start + length)?;
+        // The Windows and Unicode names are UTF-16, and the Macintosh names mostly ASCII.
+        let name = if platform == 1 {
+            bytes.iter().map(|&byte| byte as char).collect()
+        } else {
+            let units: Vec<u16> = bytes.chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        };
+        best = Some((rank, name));
+    }
+
+    best.map(|(_, name)| name)
+}
+
+/// The axes and named instances of an `fvar` table, with their names from the `name` table.
+fn variation_instances(fvar: &[u8], name_table: &[u8]) -> Option<(Vec<FontAxis>, Vec<NamedInstance>)> {
+    let fixed = |offset: usize| read_u32(fvar, offset).map(|value| value as u32 as i32 as f32 / 65536.0);
+    let name = |name_id: usize| font_name(name_table, name_id).unwrap_or_else(|| format!("name {}", name_id));
+    let axes_offset = read_u16(fvar, 4)?;
+    let axis_count = read_u16(fvar, 8)?;
+    let axis_size = read_u16(fvar, 10)?;
+    let instance_count = read_u16(fvar, 12)?;
+    let instance_size = read_u16(fvar, 14)?;
+    let mut axes = vec![];
+    for axis in 0..axis_count {
+        let offset = axes_offset + axis * axis_size;
+        axes.push(FontAxis {
+            tag: tag_text(fvar, offset)?,
+            name: name(read_u16(fvar, offset + 18)?),
+            minimum: fixed(offset + 4)?,
+            default: fixed(offset + 8)?,
+            maximum: fixed(offset + 12)?,
+        });
+    }
+    let mut instances = vec![];
+    let instances_offset = axes_offset + axis_count * axis_size;
+    for instance in 0..instance_count {
+        let offset = instances_offset + instance * instance_size;
+        let coordinates = (0..axis_count)
+            .map(|axis| fixed(offset + 4 + 4 * axis))
+            .collect::<Option<Vec<f32>>>()?;
+        instances.push(NamedInstance { name: name(read_u16(fvar, offset)?), coordinates: coordinates });
+    }
+
+    Some((axes, instances))
+}
+
+/// Read the OpenType features, scripts, and languages of a typeface, and the variation axes and
+/// named instances of a variable font. The tables a typeface lacks, or that are malformed, leave
+/// their part of the listing empty.
+pub fn font_features(face: &freetype::face::Face) -> FontFeatures {
+    let layout = |tag: [u8; 4]| load_sfnt_table(face, tag).and_then(|table| layout_features(&table));
+    let name_table = load_sfnt_table(face, *b"name").unwrap_or_default();
+    let (axes, instances) = load_sfnt_table(face, *b"fvar")
+        .and_then(|fvar| variation_instances(&fvar, &name_table))
+        .unwrap_or_default();
+
+    FontFeatures {
+        gsub: layout(*b"GSUB"),
+        gpos: layout(*b"GPOS"),
+        axes: axes,
+        instances: instances,
+    }
+}
+
+/// A character whose glyph a set of OpenType features replaces with another glyph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GlyphSubstitution {
+    /// The code point of the character.
+    pub code_point: usize,
+    /// The glyph the character map maps the character to.
+    pub glyph_index: usize,
+    /// The glyph the features substitute for it.
+    pub substitute: usize,
+}
+
+/// The glyphs of a coverage table of a `GSUB` table, in coverage index order.
+fn coverage_glyphs(table: &[u8], coverage: usize) -> Option<Vec<usize>> {
+    let mut glyphs = vec![];
+    match read_u16(table, coverage)? {
+        1 => {
+            for glyph in 0..read_u16(table, coverage + 2)? {
+                glyphs.push(read_u16(table, coverage + 4 + 2 * glyph)?);
+            }
+        }
+        2 => {
+            // The ranges come in glyph order, so their coverage indices follow on from each other.
+            for range in 0..read_u16(table, coverage + 2)? {
+                let record = coverage + 4 + 6 * range;
+                glyphs.extend(read_u16(table, record)?..=read_u16(table, record + 2)?);
+            }
+        }
+        _ => return None,
+    }
+
+    Some(glyphs)
+}
+
+/// Add the substitutions of a single (type 1) or alternate (type 3) substitution subtable of a
+/// `GSUB` lookup to `substitutions`, by glyph index, following extension (type 7) subtables to the
+/// subtables they wrap. Alternate substitutions take the first alternate. The glyphs an earlier
+/// subtable of the lookup covers already keep their substitute.
+fn subtable_substitutions(
+    table: &[u8], lookup_type: usize,
+    subtable: usize, substitutions: &mut BTreeMap<usize, usize>) -> Option<()> {
+
+    let format = read_u16(table, subtable)?;
+    match (lookup_type, format) {
+        (1, 1) => {
+            let delta = read_u16(table, subtable + 4)? as u16 as i16 as isize;
+            for glyph in coverage_glyphs(table, subtable + read_u16(table, subtable + 2)?)? {
+                // Glyph indices wrap around modulo 65536.
+                let substitute = (glyph as isize + delta).rem_euclid(65536) as usize;
+                substitutions.entry(glyph).or_insert(substitute);
+            }
+        }
+        (1, 2) => {
+            let glyphs = coverage_glyphs(table, subtable + read_u16(table, subtable + 2)?)?;
+            for (index, glyph) in glyphs.into_iter().enumerate() {
+                let substitute = read_u16(table, subtable + 6 + 2 * index)?;
+                substitutions.entry(glyph).or_insert(substitute);
+            }
+        }
+        (3, 1) => {
+            let glyphs = coverage_glyphs(table, subtable + read_u16(table, subtable + 2)?)?;
+            for (index, glyph) in glyphs.into_iter().enumerate() {
+                let alternate_set = subtable + read_u16(table, subtable + 6 + 2 * index)?;
+                if read_u16(table, alternate_set)? > 0 {
+                    let substitute = read_u16(table, alternate_set + 2)?;
+                    substitutions.entry(glyph).or_insert(substitute);
+                }
+            }
+        }
+        (7, 1) => {
+            let extension_type = read_u16(table, subtable + 2)?;
+            if extension_type == 7 {
+                return None;
+            }
+            let extension = subtable + read_u32(table, subtable + 4)?;
+            subtable_substitutions(table, extension_type, extension, substitutions)?;
+        }
+        (1, _) | (3, _) | (7, _) => return None,
+        // The other lookup types substitute sequences of glyphs, or depend on their context.
+        _ => {}
+    }
+
+    Some(())
+}
+
+/// The single and alternate substitutions of the lookups of a `GSUB` table that the features with
+/// the given tags refer to, under any script and language system, one map of glyph indices to
+/// their substitutes per lookup, in the order the lookups get applied.
+fn feature_lookups(table: &[u8], features: &[String]) -> Option<Vec<BTreeMap<usize, usize>>> {
+    let feature_list = read_u16(table, 6)?;
+    let lookup_list = read_u16(table, 8)?;
+    let mut lookup_indices = BTreeSet::new();
+    for feature in 0..read_u16(table, feature_list)? {
+        let record = feature_list + 2 + 6 * feature;
+        let tag = tag_text(table, record)?;
+        if !features.iter().any(|feature| feature.trim_end() == tag.trim_end()) {
+            continue;
+        }
+        let feature_table = feature_list + read_u16(table, record + 4)?;
+        for lookup in 0..read_u16(table, feature_table + 2)? {
+            lookup_indices.insert(read_u16(table, feature_table + 4 + 2 * lookup)?);
+        }
+    }
+    let mut lookups = vec![];
+    for lookup_index in lookup_indices {
+        let lookup_table = lookup_list + read_u16(table, lookup_list + 2 + 2 * lookup_index)?;
+        let lookup_type = read_u16(table, lookup_table)?;
+        let mut substitutions = BTreeMap::new();
+        for subtable in 0..read_u16(table, lookup_table + 4)? {
+            let subtable = lookup_table + read_u16(table, lookup_table + 6 + 2 * subtable)?;
+            subtable_substitutions(table, lookup_type, subtable, &mut substitutions)?;
+        }
+        lookups.push(substitutions);
+    }
+
+    Some(lookups)
+}
+
+/// The characters whose glyphs change under a set of OpenType features, e.g. `ss01` and `smcp`,
+/// in code point order. The glyph of each character of the character map goes through the single
+/// and alternate substitutions of the features, under any script and language system, with the
+/// first alternate standing in for alternate substitutions. Ligatures and contextual
+/// substitutions take a shaper and a run of text, so they are left out. A typeface without a
+/// `GSUB` table, or with a malformed one, substitutes nothing.
+pub fn feature_substitutions(face: &freetype::face::Face, features: &[String]) -> Vec<GlyphSubstitution> {
+    let lookups = load_sfnt_table(face, *b"GSUB")
+        .and_then(|table| feature_lookups(&table, features))
+        .unwrap_or_default();
+    let mut substitutions = vec![];
+    for (glyph_index, code_points) in glyph_code_points(face) {
+        let substitute = lookups.iter().fold(glyph_index, |glyph, lookup| {
+            lookup.get(&glyph).cloned().unwrap_or(glyph)
+        });
+        if substitute == glyph_index {
+            continue;
+        }
+        for code_point in code_points {
+            substitutions.push(GlyphSubstitution {
+                code_point: code_point,
+                glyph_index: glyph_index,
+                substitute: substitute,
+            });
+        }
+    }
+    substitutions.sort_by_key(|substitution| substitution.code_point);
+
+    substitutions
+}
+
+/// The number of glyph substitutions per row of a substitution preview.
+const SUBSTITUTION_PREVIEW_COLUMNS: usize = 8;
+
+/// A grayscale image with the glyph before and after each glyph substitution side by side,
+/// with dark glyphs on a white background.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubstitutionPreview {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+/// Render thumbnails of the glyph before and after each glyph substitution at the given pixel
+/// size, the pairs filling rows of eight in the order of the substitutions, so the effect of a
+/// feature across a whole atlas can be checked before turning it on.
+pub fn render_substitution_preview(
+    face: &freetype::face::Face, pixel_size: usize,
+    substitutions: &[GlyphSubstitution]) -> Result<SubstitutionPreview, SampleTypefaceError> {
+
+    let family = family_name(face);
+    face.set_pixel_sizes(0, pixel_size as u32).map_err(|e| {
+        SampleTypefaceError::SetPixelSize(e, family.clone(), pixel_size)
+    })?;
+    let (ascender, line_height) = match face.size_metrics() {
+        Some(metrics) => (((metrics.ascender + 63) >> 6) as i64, ((metrics.height + 32) >> 6) as i64),
+        None => (pixel_size as i64, pixel_size as i64),
+    };
+    let margin = (pixel_size / 2) as i64;
+    let cell_width = pixel_size as i64 + margin;
+    let pair_width = 2 * cell_width + margin;
+    let row_height = line_height + margin;
+    let columns = substitutions.len().min(SUBSTITUTION_PREVIEW_COLUMNS).max(1) as i64;
+    let rows = (substitutions.len() + SUBSTITUTION_PREVIEW_COLUMNS - 1) / SUBSTITUTION_PREVIEW_COLUMNS;
+    let width = margin + columns * pair_width;
+    let height = margin + rows as i64 * row_height;
+
+    let mut data = vec![255 as u8; (width * height) as usize];
+    for (i, substitution) in substitutions.iter().enumerate() {
+        let pair_x = margin + (i as i64 % columns) * pair_width;
+        let baseline = margin + (i as i64 / columns) * row_height + ascender;
+        for (cell, &glyph_index) in [substitution.glyph_index, substitution.substitute].iter().enumerate() {
+            face.load_glyph(glyph_index as u32, freetype::face::LoadFlag::RENDER).map_err(|e| {
+                SampleTypefaceError::LoadCharacter(e, family.clone(), substitution.code_point)
+            })?;
+            let glyph = face.glyph();
+            let bitmap = glyph.bitmap();
+            let (glyph_width, glyph_rows) = (bitmap.width() as i64, bitmap.rows() as i64);
+            let pitch = bitmap.pitch() as i64;
+            // Center each glyph in its cell.
+            let x = pair_x + cell as i64 * cell_width + (cell_width - glyph_width) / 2;
+            let y = baseline - glyph.bitmap_top() as i64;
+            let buffer = bitmap.buffer();
+            for row in 0..glyph_rows {
+                for col in 0..glyph_width {
+                    let (px, py) = (x + col, y + row);
+                    if px < 0 || py < 0 || px >= width || py >= height {
+                        continue;
+                    }
+                    let coverage = buffer[(row * pitch + col) as usize];
+                    let pixel = &mut data[(py * width + px) as usize];
+                    *pixel = (*pixel).min(255 - coverage);
+                }
+            }
+        }
+    }
+
+    Ok(SubstitutionPreview {
+        width: width as usize,
+        height: height as usize,
+        data: data,
+    })
+}
+
+/// Write a substitution preview out to a grayscale PNG file.
+pub fn write_substitution_preview<P: AsRef<Path>>(path: P, preview: &SubstitutionPreview) -> io::Result<()> {
+    write_atomically(path, |path| {
+        image::save_buffer(path, &preview.data, preview.width as u32, preview.height as u32, image::Gray(8))
+    })
+}
+
+/// The version number of a glyph rasterizer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RendererVersion {
+    /// The major version number.
+    pub major: u32,
+    /// The minor version number.
+    pub minor: u32,
+    /// The patch version number.
+    pub patch: u32,
+}
+
+impl RendererVersion {
+    /// Construct a renderer version from its major, minor, and patch version numbers.
+    pub fn new(major: u32, minor: u32, patch: u32) -> RendererVersion {
+        RendererVersion {
+            major: major,
+            minor: minor,
+            patch: patch,
+        }
+    }
+
+    /// The major, minor, and patch version numbers, in that order.
+    pub fn components(&self) -> [u32; 3] {
+        [self.major, self.minor, self.patch]
+    }
+}
+
+impl fmt::Display for RendererVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The rasterizer backend that renders the glyph images. Different versions of a
+/// rasterizer can produce different pixels for the same typeface, so the renderer
+/// gets recorded alongside each atlas.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Renderer {
+    /// The name of the rasterizer, e.g. `freetype`.
+    pub backend: String,
+    /// The version of the rasterizer.
+    pub version: RendererVersion,
+}
+
+impl Renderer {
+    /// Determine the version of the FreeType library that `fontgen` is linked against.
+    pub fn freetype(library: &Library) -> Renderer {
+        let mut major = 0;
+        let mut minor = 0;
+        let mut patch = 0;
+        unsafe {
+            freetype_ext::FT_Library_Version(library.raw(), &mut major, &mut minor, &mut patch);
+        }
+
+        Renderer {
+            backend: String::from("freetype"),
+            version: RendererVersion::new(major as u32, minor as u32, patch as u32),
+        }
+    }
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.backend, self.version)
+    }
+}
+
+/// The comparison a renderer requirement makes against the version of the renderer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VersionComparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl fmt::Display for VersionComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VersionComparison::Equal => write!(f, "="),
+            VersionComparison::Less => write!(f, "<"),
+            VersionComparison::LessOrEqual => write!(f, "<="),
+            VersionComparison::Greater => write!(f, ">"),
+            VersionComparison::GreaterOrEqual => write!(f, ">="),
+        }
+    }
+}
+
+/// A constraint on the renderer that the atlas must be generated with, e.g. `freetype>=2.13`.
+/// Only the version components given in the requirement take part in the comparison, so
+/// `freetype=2.13` accepts any `2.13.x` release.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RendererRequirement {
+    backend: String,
+    comparison: VersionComparison,
+    version: Vec<u32>,
+}
+
+impl RendererRequirement {
+    /// Construct a renderer requirement. The version holds up to three components: the major,
+    /// minor, and patch version numbers.
+    pub fn new(backend: &str, comparison: VersionComparison, version: Vec<u32>) -> RendererRequirement {
+        RendererRequirement {
+            backend: backend.to_lowercase(),
+            comparison: comparison,
+            version: version,
+        }
+    }
+
+    /// Determine whether a renderer meets the requirement.
+    pub fn is_satisfied_by(&self, renderer: &Renderer) -> bool {
+        if self.backend != renderer.backend {
+            return false;
+        }
+
+        let actual = &renderer.version.components()[..self.version.len()];
+        let ordering = actual.cmp(&self.version[..]);
+        match self.comparison {
+            VersionComparison::Equal => ordering == std::cmp::Ordering::Equal,
+            VersionComparison::Less => ordering == std::cmp::Ordering::Less,
+            VersionComparison::LessOrEqual => ordering != std::cmp::Ordering::Greater,
+            VersionComparison::Greater => ordering == std::cmp::Ordering::Greater,
+            VersionComparison::GreaterOrEqual => ordering != std::cmp::Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for RendererRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let version: Vec<String> = self.version.iter().map(|c| format!("{}", c)).collect();
+        write!(f, "{}{}{}", self.backend, self.comparison, version.join("."))
+    }
+}
+
+/// The color space that the pixel values of an atlas image are encoded in. Glyph coverage
+/// values are linear, so an engine that samples them as sRGB data produces antialiasing
+/// that looks too heavy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+/// The atlas parameters that `fontgen` records in addition to the ones the `bmfa`
+/// format has fields for. These get written next to the atlas file.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExtendedMetadata {
+    /// The rasterizer backend and its version, e.g. `freetype 2.13.2`.
+    pub renderer: String,
+    /// The size tier of the glyphs in the atlas, `base` for body text or `ruby` for the smaller
+    /// annotation text of a companion atlas.
+    pub tier: &'static str,
+    /// Whether the glyphs of the atlas are keyed by their glyph index in the typeface instead of
+    /// by code point, in this file as well as the atlas.
+    pub glyph_indices: bool,
+    /// The multiple of the glyph size of the main atlas that the glyphs were rendered at.
+    pub scale: f32,
+    /// The synthetic horizontal stretch factor applied to the typeface.
+    pub stretch: f32,
+    /// The synthetic horizontal shear applied to the typeface, or zero for upright glyphs.
+    pub oblique: f32,
+    /// The gamma curve applied to the coverage values of the glyphs.
+    pub gamma: f32,
+    /// How far the glyph outlines were thickened, in pixels, or zero for the regular weight.
+    pub embolden: f32,
+    /// The width of the border stroked around each glyph, in pixels, or zero without borders.
+    pub outline: usize,
+    /// The drop shadow composited underneath each glyph, if any.
+    pub shadow: Option<Shadow>,
+    /// The glow rendered around each glyph, if any.
+    pub glow: Option<Glow>,
+    /// The color space the atlas image should be interpreted in.
+    pub color_space: ColorSpace,
+    /// The channels each pixel of the atlas image holds, `rgba` or `a8`.
+    pub channels: &'static str,
+    /// The relative size of the synthetic small capitals, if lowercase letters were replaced by them.
+    pub small_caps_scale: Option<f32>,
+    /// The code points whose glyphs rasterized to empty bitmaps and have no image in the atlas.
+    pub empty_glyphs: Vec<usize>,
+    /// The zero width code points that have advance-only entries and no image in the atlas.
+    pub zero_width_glyphs: Vec<usize>,
+    /// The kind of signed distance field stored in the atlas image, `sdf` or `msdf`, if the
+    /// atlas holds distance fields instead of coverage values.
+    pub distance_field: Option<&'static str>,
+    /// The spread in pixels of the signed distance field stored in the atlas image.
+    pub sdf_spread: Option<usize>,
+    /// The unscaled metrics of the typeface and each glyph, if they were requested.
+    pub font_units: Option<FontUnitMetrics>,
+    /// The kerning pairs between the characters in the atlas.
+    pub kerning: Vec<KerningPair>,
+    /// The horizontal advance of each character, by code point. The `bmfa` glyph metadata has
+    /// no field for it.
+    pub advances: BTreeMap<usize, GlyphAdvance>,
+    /// The script and bidirectional class of each character, by code point. Atlases keyed by
+    /// glyph index have none.
+    pub text_properties: BTreeMap<usize, TextProperties>,
+    /// The CJK punctuation whose advances were halved.
+    pub compressed_punctuation: Vec<usize>,
+    /// The corrections of the advance override file applied to the characters of the atlas, by
+    /// code point, in pixels at the slot glyph size of the main atlas.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyph_overrides: BTreeMap<usize, GlyphOverride>,
+    /// The files holding the pages of a multi-page atlas, in page order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub page_files: Vec<String>,
+    /// The width and height in pixels of each page of a multi-page atlas, in page order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub page_sizes: Vec<[usize; 2]>,
+    /// The page holding each glyph image of a multi-page atlas, by code point.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyph_pages: BTreeMap<usize, usize>,
+    /// The file holding the companion atlas of ruby annotation glyphs, if one was generated.
+    pub ruby_atlas: Option<String>,
+    /// The files holding the atlases rendered at other multiples of the glyph size.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scaled_atlases: Vec<String>,
+    /// The render mode of each glyph image, by code point, when the render modes were chosen
+    /// per character.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub render_modes: BTreeMap<usize, &'static str>,
+    /// The family name of the typeface each glyph was drawn from, by code point, for the glyphs
+    /// drawn from a fallback or substitute typeface instead of the primary one.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyph_typefaces: BTreeMap<usize, String>,
+    /// The code points whose glyphs were drawn in color, like emoji. Their images hold
+    /// premultiplied colors, and should not get tinted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub color_glyphs: Vec<usize>,
+    /// The code points mapping to each glyph, by glyph index, for atlases keyed by glyph index.
+    /// Glyphs no code point maps to are left out.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub glyph_code_points: BTreeMap<usize, Vec<usize>>,
+    /// The optical size the glyphs of a variable font were drawn at, in points, when it was set
+    /// to match the glyph size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optical_size: Option<f32>,
+    /// Where the typeface draws underlines, overlines, and strikeout lines, in pixels at the size
+    /// the glyphs were rendered at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rules: Option<RuleMetrics>,
+    /// The files holding the atlas of each effect layer, by layer name, with the same layout as the
+    /// atlas. Multi-page layers list their pages in page order.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub layer_files: BTreeMap<&'static str, Vec<String>>,
+    /// The KTX2 file holding the pages of the atlas as the layers of a texture array, if one was
+    /// written. The layer of each glyph is its page, and single page atlases have a single layer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub texture_array: Option<String>,
+}
+
+/// The distance the pen advances after drawing a character.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GlyphAdvance {
+    /// The advance in pixels.
+    pub pixels: i32,
+    /// The advance as a fraction of the slot glyph size, the unit of the widths and heights in
+    /// the `bmfa` glyph metadata.
+    pub normalized: f32,
+}
+
+impl ExtendedMetadata {
+    /// Collect the extended metadata for an atlas generated from a glyph table.
+    pub fn new(
+        spec: AtlasSpec, renderer: &Renderer,
+        color_space: ColorSpace, glyph_tab: &GlyphTable) -> ExtendedMetadata {
+
+        ExtendedMetadata {
+            renderer: format!("{}", renderer),
+            tier: "base",
+            glyph_indices: spec.glyph_indices,
+            scale: 1.0,
+            stretch: spec.stretch,
+            oblique: spec.oblique,
+            gamma: spec.gamma,
+            embolden: spec.embolden,
+            outline: spec.outline,
+            shadow: spec.shadow,
+            glow: spec.glow,
+            color_space: color_space,
+            channels: spec.channels.name(),
+            small_caps_scale: spec.small_caps_scale,
+            empty_glyphs: glyph_tab.empty.clone(),
+            zero_width_glyphs: glyph_tab.zero_width.clone(),
+            distance_field: spec.distance_field.map(|distance_field| distance_field.name()),
+            sdf_spread: spec.distance_field.map(|distance_field| distance_field.spread()),
+            font_units: None,
+            kerning: glyph_tab.kerning.clone(),
+            advances: glyph_tab.advance.iter().map(|(&code_point, &advance)| {
+                (code_point, GlyphAdvance {
+                    pixels: advance,
+                    normalized: advance as f32 / spec.slot_glyph_size as f32,
+                })
+            }).collect(),
+            text_properties: glyph_tab.advance.keys()
+                .filter(|_| !spec.glyph_indices)
+                .filter_map(|&code_point| {
+                    text_properties(code_point).map(|properties| (code_point, properties))
+                })
+                .collect(),
+            compressed_punctuation: vec![],
+            glyph_overrides: BTreeMap::new(),
+            page_files: vec![],
+            page_sizes: vec![],
+            glyph_pages: BTreeMap::new(),
+            ruby_atlas: None,
+            scaled_atlases: vec![],
+            render_modes: glyph_tab.render_modes.clone(),
+            glyph_typefaces: glyph_tab.glyph_typefaces.clone(),
+            color_glyphs: glyph_tab.color_glyphs.clone(),
+            glyph_code_points: BTreeMap::new(),
+            optical_size: None,
+            rules: None,
+            layer_files: BTreeMap::new(),
+            texture_array: None,
+        }
+    }
+}
+
+/// Write the extended metadata for an atlas out to a JSON file.
+pub fn write_extended_metadata<P: AsRef<Path>>(path: P, metadata: &ExtendedMetadata) -> io::Result<()> {
+    write_atomically(path, |path| {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, metadata)?;
+
+        Ok(())
+    })
+}
+
+/// The number of temporary files and directories this process has named so far.
+static TEMPORARY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A prefix for the name of a temporary file or directory, e.g. `.fontgen-1234-0-`, which no other
+/// run, or other thread of the same run, uses at the same time.
+fn temporary_prefix() -> String {
+    format!(".fontgen-{}-{}-", process::id(), TEMPORARY_COUNT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Write a file by having `write` write it out to a temporary file next to `path`, and then
+/// renaming the temporary file over `path`. The rename is atomic, so readers either see the
+/// old file or the complete new one, and a run that gets interrupted partway through never
+/// leaves a truncated file behind. The temporary file keeps the extension of `path`, since
+/// some writers pick the file format by the extension.
+pub fn write_atomically<P, F>(path: P, write: F) -> io::Result<()>
+    where P: AsRef<Path>, F: FnOnce(&Path) -> io::Result<()> {
+
+    let path = extended_length_path(path.as_ref());
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name")),
+    };
+    let mut temp_name = OsString::from(temporary_prefix());
+    temp_name.push(file_name);
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = write(&temp_path)
+        .and_then(|_| OpenOptions::new().write(true).open(&temp_path)?.sync_all())
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    written
+}
+
+/// Turn a path on Windows into an extended-length path, e.g. `\\?\C:\assets\font.bmfa`, which
+/// is not limited to `MAX_PATH` characters. Extended-length paths skip the usual normalization,
+/// so the path gets made absolute and normalized here first.
+#[cfg(windows)]
+fn extended_length_path(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match std::env::current_dir() {
+            Ok(current_dir) => current_dir.join(path),
+            Err(_) => return path.to_path_buf(),
+        }
+    };
+    let mut components = absolute.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut extended = OsString::from(r"\\?\");
+                extended.push(prefix.as_os_str());
+                PathBuf::from(extended)
+            }
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                PathBuf::from(extended)
+            }
+            // The path is already an extended-length or device path.
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    for component in components {
+        match component {
+            Component::RootDir => extended.push(r"\"),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                extended.pop();
+            }
+            Component::Normal(name) => extended.push(name),
+            Component::Prefix(_) => {}
+        }
+    }
+
+    extended
+}
+
+/// Paths on other platforms are not limited in length, so they are left alone.
+#[cfg(not(windows))]
+fn extended_length_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// A set of output files that get written out together or not at all. Each file gets written to
+/// a staging directory next to its final location first, and `commit` moves all of them into
+/// place at the end. When a run fails partway through, dropping the transaction deletes the
+/// staging directories, so a set of atlas pages and metadata files never gets mixed up with the
+/// files of an earlier run.
+#[derive(Debug, Default)]
+pub struct OutputTransaction {
+    /// The staging directory of each output directory.
+    staging_dirs: BTreeMap<PathBuf, PathBuf>,
+    /// Whether the files `commit` replaces get kept as `.bak` files.
+    keep_backups: bool,
+}
+
+impl OutputTransaction {
+    /// Start a transaction with no files.
+    pub fn new() -> OutputTransaction {
+        OutputTransaction { staging_dirs: BTreeMap::new(), keep_backups: false }
+    }
+
+    /// Keep each file `commit` replaces next to its replacement as a `.bak` file, e.g.
+    /// `atlas.bmfa.bak`, replacing the backups of earlier runs. Files only get backed up once
+    /// every staged file is written, so a failed run leaves the files it would replace alone.
+    pub fn keep_backups(&mut self, keep_backups: bool) {
+        self.keep_backups = keep_backups;
+    }
+
+    /// The path to write an output file to instead of `path`. Files derived from the staged
+    /// path by changing its extension or file name also get moved into place with the others.
+    pub fn stage<P: AsRef<Path>>(&mut self, path: P) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name")),
+        };
+        let output_dir = path.parent().map(|parent| parent.to_path_buf()).unwrap_or_default();
+        if !self.staging_dirs.contains_key(&output_dir) {
+            let staging_dir = output_dir.join(format!("{}staging", temporary_prefix()));
+            fs::create_dir(extended_length_path(&staging_dir))?;
+            self.staging_dirs.insert(output_dir.clone(), staging_dir);
+        }
+
+        Ok(self.staging_dirs[&output_dir].join(file_name))
+    }
+
+    /// Move every staged file into place, replacing any files already there. When a file cannot
+    /// be moved, the files moved so far get moved back out, and the files they replaced restored.
+    pub fn commit(mut self) -> io::Result<()> {
+        let mut moves = vec![];
+        for (output_dir, staging_dir) in self.staging_dirs.iter() {
+            for entry in fs::read_dir(extended_length_path(staging_dir))? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    let file_name = entry.file_name();
+                    moves.push((staging_dir.join(&file_name), output_dir.join(&file_name), staging_dir));
+                }
+            }
+        }
+
+        let mut committed = vec![];
+        for (staged_file, output_file, staging_dir) in moves.into_iter() {
+            let mut backup_name = OsString::from(".backup-");
+            backup_name.push(output_file.file_name().unwrap_or_default());
+            let backup_file = staging_dir.join(backup_name);
+            let moved = replace_file(&staged_file, &output_file, &backup_file);
+            match moved {
+                Ok(backed_up) => committed.push((output_file, backup_file, backed_up)),
+                Err(e) => {
+                    for (output_file, backup_file, backed_up) in committed.into_iter().rev() {
+                        let _ = fs::remove_file(extended_length_path(&output_file));
+                        if backed_up {
+                            let _ = fs::rename(
+                                extended_length_path(&backup_file), extended_length_path(&output_file)
+                            );
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        if self.keep_backups {
+            for (output_file, backup_file, backed_up) in committed.into_iter() {
+                if !backed_up {
+                    continue;
+                }
+                let mut kept_backup_file = output_file.into_os_string();
+                kept_backup_file.push(".bak");
+                let kept_backup_file = PathBuf::from(kept_backup_file);
+                fs::rename(extended_length_path(&backup_file), extended_length_path(&kept_backup_file))?;
+            }
+        }
+        self.remove_staging_dirs();
+
+        Ok(())
+    }
+
+    fn remove_staging_dirs(&mut self) {
+        for staging_dir in self.staging_dirs.values() {
+            let _ = fs::remove_dir_all(extended_length_path(staging_dir));
+        }
+        self.staging_dirs.clear();
+    }
+}
+
+impl Drop for OutputTransaction {
+    fn drop(&mut self) {
+        self.remove_staging_dirs();
+    }
+}
+
+/// An advisory lock on the output files of a run, so concurrent runs generating the same atlas,
+/// like parallel CI jobs, take turns instead of mixing up each other's files. Runs writing other
+/// atlases into the same directory go ahead. The lock is a `.fontgen-<stem>.lock` directory next
+/// to the output, since creating a directory is atomic on every platform, holding a file with the
+/// process id of its owner. Dropping the lock removes the directory.
+#[derive(Debug)]
+pub struct OutputLock {
+    /// The lock directory.
+    lock_dir: PathBuf,
+}
+
+impl OutputLock {
+    /// The interval between attempts to take a lock held by another run.
+    const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Lock the output files sharing the file stem of `path`, waiting up to `timeout` for another
+    /// run holding the lock to finish. This fails with `io::ErrorKind::WouldBlock` when the lock
+    /// is still held by then. A lock whose owner is no longer running gets taken over right away.
+    pub fn acquire<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<OutputLock> {
+        let path = path.as_ref();
+        let mut lock_name = OsString::from(".fontgen-");
+        lock_name.push(path.file_stem().unwrap_or_default());
+        lock_name.push(".lock");
+        let lock_dir = path.with_file_name(lock_name);
+        let start = Instant::now();
+        loop {
+            match fs::create_dir(extended_length_path(&lock_dir)) {
+                Ok(()) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            // A run that got killed leaves its lock behind, which the next run takes over.
+            match OutputLock::owner(&lock_dir) {
+                Some(owner) if !process_is_running(owner) => OutputLock::reclaim(&lock_dir, owner),
+                _ if start.elapsed() < timeout => std::thread::sleep(OutputLock::RETRY_INTERVAL),
+                owner => {
+                    let owner = owner.map(|owner| owner.to_string()).unwrap_or_default();
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!("{} is held by process {}", lock_dir.display(), owner)
+                    ));
+                }
+            }
+        }
+        let lock = OutputLock { lock_dir: lock_dir };
+        fs::write(extended_length_path(&lock.lock_dir.join("owner")), format!("{}\n", process::id()))?;
+
+        Ok(lock)
+    }
+
+    /// The process id of the run holding a lock, or `None` if it has not written it yet.
+    fn owner(lock_dir: &Path) -> Option<u32> {
+        let owner = fs::read_to_string(extended_length_path(&lock_dir.join("owner"))).ok()?;
+
+        owner.trim().parse::<u32>().ok()
+    }
+
+    /// Remove the lock left behind by the process `owner`, which is no longer running. The lock
+    /// gets renamed out of the way first, so when several runs reclaim it at once, only one of them
+    /// removes it, and a lock another run took in the meantime gets put back.
+    fn reclaim(lock_dir: &Path, owner: u32) {
+        let mut stale_lock_dir = lock_dir.as_os_str().to_os_string();
+        stale_lock_dir.push(format!(".stale-{}", process::id()));
+        let stale_lock_dir = PathBuf::from(stale_lock_dir);
+        if fs::rename(extended_length_path(lock_dir), extended_length_path(&stale_lock_dir)).is_err() {
+            return;
+        }
+        if OutputLock::owner(&stale_lock_dir) == Some(owner) {
+            let _ = fs::remove_dir_all(extended_length_path(&stale_lock_dir));
+        } else {
+            let _ = fs::rename(extended_length_path(&stale_lock_dir), extended_length_path(lock_dir));
+        }
+    }
+}
+
+/// Whether a process is still running.
+#[cfg(target_os = "linux")]
+fn process_is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Whether a process is still running. Signal 0 only checks that the process exists.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_is_running(pid: u32) -> bool {
+    let output = process::Command::new("kill").arg("-0").arg(pid.to_string()).output();
+    match output {
+        Ok(output) => !String::from_utf8_lossy(&output.stderr).contains("No such process"),
+        Err(_) => true,
+    }
+}
+
+/// Whether a process is still running.
+#[cfg(windows)]
+fn process_is_running(pid: u32) -> bool {
+    let filter = format!("PID eq {}", pid);
+    match process::Command::new("tasklist").args(&["/FI", &filter, "/NH"]).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()),
+        Err(_) => true,
+    }
+}
+
+/// Processes are taken to be running where there is no way to tell, so locks never get reclaimed.
+#[cfg(not(any(unix, windows)))]
+fn process_is_running(_pid: u32) -> bool {
+    true
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(extended_length_path(&self.lock_dir));
+    }
+}
+
+/// Move a staged file to its output path, moving the file already there, if any, to the backup
+/// path first. This returns whether there was a file to back up.
+fn replace_file(staged_file: &Path, output_file: &Path, backup_file: &Path) -> io::Result<bool> {
+    let staged_file = extended_length_path(staged_file);
+    let output_file = extended_length_path(output_file);
+    let backup_file = extended_length_path(backup_file);
+    if output_file.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists, format!("{} is a directory", output_file.display())
+        ));
+    }
+    let backed_up = output_file.exists();
+    if backed_up {
+        fs::rename(&output_file, &backup_file)?;
+    }
+    if let Err(e) = fs::rename(&staged_file, &output_file) {
+        if backed_up {
+            let _ = fs::rename(&backup_file, &output_file);
+        }
+        return Err(e);
+    }
+
+    Ok(backed_up)
+}
@@ -1,527 +1,5134 @@
 extern crate bmfa;
+extern crate fontgen;
 extern crate freetype;
-extern crate image;
+extern crate serde;
+extern crate serde_json;
+#[macro_use]
+extern crate serde_derive;
 extern crate structopt;
+extern crate toml;
 
 
-use bmfa::{BitmapFontAtlas, BitmapFontAtlasMetadata, GlyphMetadata};
+use fontgen::{
+    AtlasChannels, AtlasPage, AtlasSpec, BmfontDescriptor, BundleAtlas, BundleManifest, CharacterSet,
+    CHARACTER_SET_PRESETS, ColorSpace, CoverageReport, DistanceField, EffectLayer, ExtendedMetadata, Glow,
+    HintingMode,
+    FontValidationError, GlyphAdvance, GlyphCache, GlyphLookupTable, GlyphOverride, GlyphTable,
+    LOCALE_SUPPLEMENTS, Monochrome, MsdfAtlasLayout, OutputLock, OutputTransaction, Packing, PointSize,
+    Renderer, RendererRequirement, ResampleFilter, Shadow, SizeMetrics, SizeReport, TextAlignment,
+    TextMetrics, TextRule, VariationCoordinate, VariationError, VersionComparison, ZeroWidthClasses,
+};
+#[cfg(feature = "scripting")]
+use fontgen::GlyphScript;
 use freetype::Library;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error;
+use std::ffi::OsString;
 use std::fmt;
-use std::mem;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::iter;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use structopt::StructOpt;
+use structopt::clap::{App, AppSettings};
 
 
-/// The atlas specification is a description of the dimensions of the atlas
-/// and the dimensions of each glyph in the atlas. This comes in as input at
-/// runtime.
-#[derive(Copy, Clone)]
-struct AtlasSpec {
-    /// The origin and coordinate chart for the atlas image.
+#[derive(Clone, Debug)]
+enum OptError {
+    InputFileDoesNotExist(PathBuf),
+    InputFileIsNotAFile(PathBuf),
+    OutputFileExists(PathBuf),
+    CacheDirIsNotADirectory(PathBuf),
+    SharedOutputFile(PathBuf),
+    SlotGlyphSizeCannotBeZero(usize),
+    PaddingLargerThanSlotGlyphSize(usize, usize),
+    InvalidOrigin(String),
+    InvalidStretch(f32),
+    InvalidOblique(String),
+    InvalidGamma(f32),
+    InvalidPointSize(f32, u32),
+    InvalidRendererRequirement(String),
+    InvalidColorSpace(String),
+    InvalidResampleFilter(String),
+    InvalidSmallCaps(String),
+    InvalidZeroWidthClass(String),
+    InvalidCharacterRange(String),
+    CharsetFileDoesNotExist(PathBuf),
+    StringsFileDoesNotExist(PathBuf),
+    OverridesFileDoesNotExist(PathBuf),
+    InvalidPreset(String),
+    InvalidSdfSpread(usize, usize),
+    MonoDistanceField,
+    InvalidMonoThreshold(u8),
+    InvalidEmbolden(String),
+    InvalidOutline(String),
+    InvalidShadow(String),
+    InvalidGlow(String),
+    GlowWiderThanPadding(usize, usize),
+    EffectsTooLarge(usize, usize),
+    DistanceFieldEffect(&'static str),
+    InvalidOutputFormat(String),
+    InvalidOutlineFormat(String),
+    InvalidPacking(String),
+    InvalidChannels(String),
+    RgbaOnlyFormat(&'static str),
+    SingleChannelMsdf,
+    InvalidRenderMode(String),
+    MaxSizeTooSmall(usize, usize),
+    NothingToRoundUp,
+    UniformPageFormat(&'static str),
+    InvalidRubyScale(f32),
+    InvalidScale(String),
+    SinglePageFormat(&'static str),
+    InvalidVariation(String),
+    InvalidSubstitution(String),
+    InvalidOpticalSizing(String),
+    InvalidMissingGlyphs(String),
+    InvalidCollision(String),
+    InvalidAlignment(String),
+    LastResortMissingGlyphs,
+    InvalidLocale(String),
+    NoSizesToCompare,
+    InvalidHintingMode(String),
+    NothingToCalibrate,
+    GlyphIndexOption(&'static str),
+    BatchOption(&'static str),
+    FontsDirDoesNotExist(PathBuf),
+    CalibratedGamma,
+    InvalidMemorySize(String),
+    InvalidRule(String),
+    InvalidLayer(String),
+    LayerWithoutEffect(&'static str, &'static str),
+    InvalidFeatureTag(String),
+}
+
+impl fmt::Display for OptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptError::InputFileDoesNotExist(ref path) => {
+                write!(f, "The font file {} could not be found.", path.display())
+            }
+            OptError::InputFileIsNotAFile(ref path) => {
+                write!(f, "The path {} is not a file.", path.display())
+            }
+            OptError::OutputFileExists(ref path) => {
+                write!(f, "A file already exists in the location {}", path.display())
+            }
+            OptError::SharedOutputFile(ref path) => {
+                write!(f, "More than one atlas of the config file gets written to {}.", path.display())
+            }
+            OptError::CacheDirIsNotADirectory(ref path) => {
+                write!(f, "The cache directory {} is not a directory.", path.display())
+            }
+            OptError::SlotGlyphSizeCannotBeZero(_) => {
+                write!(f, "The slot glyph size cannot be zero.")
+            }
+            OptError::PaddingLargerThanSlotGlyphSize(padding, glyph_size) => {
+                write!(
+                    f,
+                    "The padding ({} pixels) for each glyph is \
+                    larger than the glyph slot size ({} pixels).",
+                    padding, glyph_size
+                )
+            }
+            OptError::InvalidOrigin(ref origin) => {
+                write!(f, "Selection for image origin invalid. Got {}", origin)
+            }
+            OptError::InvalidStretch(stretch) => {
+                write!(f, "The stretch factor must be a positive number. Got {}", stretch)
+            }
+            OptError::InvalidOblique(ref shear) => {
+                write!(
+                    f, "The oblique shear must be a nonzero number between -1 and 1, e.g. `0.2`. Got {}",
+                    shear
+                )
+            }
+            OptError::InvalidGamma(gamma) => {
+                write!(f, "The gamma must be a positive number. Got {}", gamma)
+            }
+            OptError::InvalidPointSize(points, dpi) => {
+                write!(
+                    f, "The point size must be a positive number that is at least a pixel at the \
+                    resolution. Got {} points at {} dpi", points, dpi
+                )
+            }
+            OptError::InvalidRendererRequirement(ref requirement) => {
+                write!(
+                    f,
+                    "Renderer requirements have the form `freetype>=2.13`. Got {}",
+                    requirement
+                )
+            }
+            OptError::InvalidColorSpace(ref color_space) => {
+                write!(f, "Selection for color space invalid. Got {}", color_space)
+            }
+            OptError::InvalidResampleFilter(ref filter) => {
+                write!(
+                    f, "Resampling filters are `box`, `nearest`, `triangle`, `catmull-rom`, or `lanczos3`. \
+                    Got {}", filter
+                )
+            }
+            OptError::InvalidSmallCaps(ref small_caps) => {
+                write!(
+                    f,
+                    "Synthetic small caps have the form `scale=0.75` with a scale \
+                    between zero and one. Got {}",
+                    small_caps
+                )
+            }
+            OptError::InvalidZeroWidthClass(ref class) => {
+                write!(
+                    f,
+                    "Zero width classes are `joiners`, `combining`, `format`, or `none`. Got {}",
+                    class
+                )
+            }
+            OptError::InvalidCharacterRange(ref range) => {
+                write!(
+                    f,
+                    "Character ranges have the form `U+0400-U+04FF` or `U+0041`, with code points \
+                    no larger than U+10FFFF. Got {}",
+                    range
+                )
+            }
+            OptError::CharsetFileDoesNotExist(ref path) => {
+                write!(f, "The charset file {} does not exist.", path.display())
+            }
+            OptError::StringsFileDoesNotExist(ref path) => {
+                write!(f, "The strings file {} does not exist.", path.display())
+            }
+            OptError::OverridesFileDoesNotExist(ref path) => {
+                write!(f, "The advance override file {} does not exist.", path.display())
+            }
+            OptError::InvalidPreset(ref preset) => {
+                let presets: Vec<&str> = CHARACTER_SET_PRESETS.iter().map(|&(name, _)| name).collect();
+                write!(f, "Character set presets are {}. Got {}", presets.join(", "), preset)
+            }
+            OptError::InvalidSdfSpread(spread, glyph_size) => {
+                write!(
+                    f,
+                    "The signed distance field spread must be positive and leave room for the glyph \
+                    in a {} pixel glyph size on either side. Got {}",
+                    glyph_size, spread
+                )
+            }
+            OptError::MonoDistanceField => {
+                write!(
+                    f, "Distance fields are computed from antialiased glyphs, so they cannot be \
+                    combined with --mono."
+                )
+            }
+            OptError::InvalidEmbolden(ref strength) => {
+                write!(
+                    f, "The embolden strength is a positive number of pixels, e.g. `1` or `0.5px`. Got {}",
+                    strength
+                )
+            }
+            OptError::InvalidOutline(ref outline) => {
+                write!(f, "The outline width is a positive number of pixels, e.g. `2px`. Got {}", outline)
+            }
+            OptError::InvalidShadow(ref shadow) => {
+                write!(
+                    f, "Shadows have the form `dx,dy,blur,alpha`, e.g. `2,2,3,0.5`, with a blur radius \
+                    in whole pixels and an opacity between zero and one. Got {}", shadow
+                )
+            }
+            OptError::InvalidGlow(ref glow) => {
+                write!(
+                    f, "Glows have the form `radius,intensity`, e.g. `4,1.5`, with a radius in whole \
+                    pixels and a positive intensity. Got {}", glow
+                )
+            }
+            OptError::GlowWiderThanPadding(radius, padding) => {
+                write!(
+                    f, "The glow gets rendered into the padding, so the padding must be at least twice \
+                    the glow radius. Got a radius of {} pixels with {} pixels of padding", radius, padding
+                )
+            }
+            OptError::InvalidLayer(ref layer) => {
+                write!(f, "The effect layers are `fill`, `outline`, and `shadow`. Got {}", layer)
+            }
+            OptError::LayerWithoutEffect(layer, option) => {
+                write!(f, "The {} layer of --layers needs {} to draw it.", layer, option)
+            }
+            OptError::InvalidFeatureTag(ref tag) => {
+                write!(f, "OpenType feature tags have one to four characters, e.g. ss01 or smcp. Got {}", tag)
+            }
+            OptError::EffectsTooLarge(size, glyph_size) => {
+                write!(
+                    f, "The distance field spread, emboldening, outline, and shadow must leave room for \
+                    the glyph in a {} pixel glyph size. They take up {} pixels", glyph_size, size
+                )
+            }
+            OptError::DistanceFieldEffect(option) => {
+                write!(
+                    f, "Distance fields are computed from the glyph outlines themselves, so they cannot \
+                    be combined with {}.", option
+                )
+            }
+            OptError::InvalidMonoThreshold(threshold) => {
+                write!(f, "The monochrome threshold must be between 1 and 255. Got {}", threshold)
+            }
+            OptError::InvalidOutputFormat(ref format) => {
+                write!(
+                    f,
+                    "Output formats are `bmfa`, `bmfont-text`, `bmfont-binary`, `bmfont-xml`, \
+                    or `json`. Got {}",
+                    format
+                )
+            }
+            OptError::InvalidOutlineFormat(ref format) => {
+                write!(f, "The only outline export format is `svg`. Got {}", format)
+            }
+            OptError::InvalidPacking(ref packing) => {
+                write!(f, "Packings are `grid`, `skyline`, `maxrects`, or `shelf`. Got {}", packing)
+            }
+            OptError::InvalidChannels(ref channels) => {
+                write!(f, "Atlas channels are `rgba` or `a8`. Got {}", channels)
+            }
+            OptError::RgbaOnlyFormat(format) => {
+                write!(
+                    f, "The {} format always stores RGBA images, so it cannot be combined with \
+                    --channels a8.", format
+                )
+            }
+            OptError::SingleChannelMsdf => {
+                write!(
+                    f, "Multi-channel signed distance fields need the color channels, so they cannot \
+                    be combined with --channels a8."
+                )
+            }
+            OptError::InvalidRenderMode(ref render_mode) => {
+                write!(
+                    f,
+                    "Render modes have the form `<ranges>=<mode>`, where the modes are `coverage`, \
+                    `sdf`, or `msdf`. Got {}",
+                    render_mode
+                )
+            }
+            OptError::MaxSizeTooSmall(max_size, slot_glyph_size) => {
+                write!(
+                    f,
+                    "The maximum page size ({} pixels) must fit at least two \
+                    glyph slots ({} pixels) per side.",
+                    max_size, slot_glyph_size
+                )
+            }
+            OptError::InvalidRubyScale(scale) => {
+                write!(
+                    f, "The ruby scale must be between zero and one, and leave room for the glyphs \
+                    inside their padding. Got {}", scale
+                )
+            }
+            OptError::NothingToRoundUp => {
+                write!(
+                    f, "--power-of-two needs --auto-size or --trim-last-page to choose the \
+                    dimensions to round up."
+                )
+            }
+            OptError::UniformPageFormat(format) => {
+                write!(
+                    f, "The {} format gives every page the same size, so it cannot be combined \
+                    with --trim-last-page.", format
+                )
+            }
+            OptError::InvalidScale(ref scale) => {
+                write!(
+                    f, "Scales must be positive numbers that leave room for the glyphs inside \
+                    their padding. Got {}", scale
+                )
+            }
+            OptError::SinglePageFormat(format) => {
+                write!(
+                    f, "The {} format holds a single page, so it cannot be combined with --max-size.",
+                    format
+                )
+            }
+            OptError::InvalidVariation(ref variation) => {
+                write!(
+                    f, "Variations must have the form <axis>=<value>, with a four character axis \
+                    tag, e.g. wght=650. Got {}", variation
+                )
+            }
+            OptError::InvalidOpticalSizing(ref optical_sizing) => {
+                write!(f, "Selection for optical sizing invalid. Got {}", optical_sizing)
+            }
+            OptError::InvalidMissingGlyphs(ref missing_glyphs) => {
+                write!(f, "Missing glyphs get handled with `draw`, `skip`, or `fail`. Got {}", missing_glyphs)
+            }
+            OptError::InvalidCollision(ref collision) => {
+                write!(
+                    f, "Characters in several atlases get handled with `first`, `last`, or `fail`. Got {}",
+                    collision
+                )
+            }
+            OptError::InvalidAlignment(ref alignment) => {
+                write!(f, "Preview lines get aligned `left`, `center`, or `right`. Got {}", alignment)
+            }
+            OptError::InvalidRule(ref rule) => {
+                write!(
+                    f, "The lines drawn along preview text are `underline`, `overline`, or `strikeout`. \
+                    Got {}", rule
+                )
+            }
+            OptError::InvalidMemorySize(ref size) => {
+                write!(
+                    f, "Memory sizes are a number of bytes, optionally followed by `KB`, `MB`, or `GB`, \
+                    e.g. `8MB`. Got {}", size
+                )
+            }
+            OptError::LastResortMissingGlyphs => {
+                write!(
+                    f, "--last-resort draws a glyph for every character the typefaces do not cover, so \
+                    there are no missing glyphs for --on-missing to skip or fail on."
+                )
+            }
+            OptError::InvalidLocale(ref locale) => {
+                let locales: Vec<&str> = LOCALE_SUPPLEMENTS.iter().map(|&(name, _)| name).collect();
+                write!(f, "The bundle locales are {}. Got {}", locales.join(", "), locale)
+            }
+            OptError::NoSizesToCompare => {
+                write!(
+                    f, "A size report compares the sizes generated in a run, so it needs --ruby-scale \
+                    or --scales with a scale other than 1."
+                )
+            }
+            OptError::InvalidHintingMode(ref hinting) => {
+                write!(f, "Hinting modes are `none`, `native`, `auto`, or `light`. Got {}", hinting)
+            }
+            OptError::NothingToCalibrate => {
+                write!(
+                    f, "Calibration tunes the coverage values of antialiased glyphs, so it cannot be \
+                    combined with distance fields, --mono, --embolden, --outline, --shadow, or --glow."
+                )
+            }
+            OptError::GlyphIndexOption(option) => {
+                write!(
+                    f, "--all-glyphs keys the atlas by glyph index instead of by code point, so it cannot \
+                    be combined with {}.", option
+                )
+            }
+            OptError::BatchOption(option) => {
+                write!(
+                    f, "fontgen batch names the files of each atlas after its font file, so it cannot be \
+                    combined with {}.", option
+                )
+            }
+            OptError::FontsDirDoesNotExist(ref path) => {
+                write!(f, "The font directory {} could not be found.", path.display())
+            }
+            OptError::CalibratedGamma => {
+                write!(f, "--calibrate chooses the gamma itself, so it cannot be combined with --gamma.")
+            }
+            OptError::InvalidSubstitution(ref substitution) => {
+                write!(
+                    f, "Substitutions must have the form <ranges>=<font file>, e.g. \
+                    U+0026,U+0030-U+0039=brand.ttf. Got {}", substitution
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for OptError {}
+
+fn parse_origin(st: &str) -> Result<bmfa::Origin, OptError> {
+    match st {
+        "bottom-left" => Ok(bmfa::Origin::BottomLeft),
+        "top-left" => Ok(bmfa::Origin::TopLeft),
+        _ => Err(OptError::InvalidOrigin(format!("{}", st))),
+    }
+}
+
+/// The file formats `fontgen` can write an atlas in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// A `.bmfa` atlas file.
+    Bmfa,
+    /// An AngelCode BMFont text descriptor with a PNG page image.
+    BmfontText,
+    /// An AngelCode BMFont binary descriptor with a PNG page image.
+    BmfontBinary,
+    /// An AngelCode BMFont XML descriptor with a PNG page image.
+    BmfontXml,
+    /// A JSON description in the msdf-atlas-gen layout with a PNG atlas image.
+    Json,
+}
+
+impl OutputFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Bmfa => "bmfa",
+            OutputFormat::BmfontText => "bmfont-text",
+            OutputFormat::BmfontBinary => "bmfont-binary",
+            OutputFormat::BmfontXml => "bmfont-xml",
+            OutputFormat::Json => "json",
+        }
+    }
+}
+
+fn parse_output_format(st: &str) -> Result<OutputFormat, OptError> {
+    match st {
+        "bmfa" => Ok(OutputFormat::Bmfa),
+        "bmfont-text" => Ok(OutputFormat::BmfontText),
+        "bmfont-binary" => Ok(OutputFormat::BmfontBinary),
+        "bmfont-xml" => Ok(OutputFormat::BmfontXml),
+        "json" => Ok(OutputFormat::Json),
+        _ => Err(OptError::InvalidOutputFormat(format!("{}", st))),
+    }
+}
+
+/// The formats `fontgen` can export glyph outlines in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OutlineFormat {
+    /// SVG path data, collected in a JSON file.
+    Svg,
+}
+
+impl OutlineFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutlineFormat::Svg => "svg",
+        }
+    }
+}
+
+fn parse_outline_format(st: &str) -> Result<OutlineFormat, OptError> {
+    match st {
+        "svg" => Ok(OutlineFormat::Svg),
+        _ => Err(OptError::InvalidOutlineFormat(format!("{}", st))),
+    }
+}
+
+/// The ways a range of characters can be rendered into the atlas.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RenderMode {
+    /// Plain antialiased coverage values.
+    Coverage,
+    /// A single channel signed distance field.
+    Sdf,
+    /// A multi-channel signed distance field.
+    Msdf,
+}
+
+impl RenderMode {
+    fn name(self) -> &'static str {
+        match self {
+            RenderMode::Coverage => "coverage",
+            RenderMode::Sdf => "sdf",
+            RenderMode::Msdf => "msdf",
+        }
+    }
+
+    fn distance_field(self, spread: usize) -> Option<DistanceField> {
+        match self {
+            RenderMode::Coverage => None,
+            RenderMode::Sdf => Some(DistanceField::Sdf { spread: spread }),
+            RenderMode::Msdf => Some(DistanceField::Msdf { spread: spread }),
+        }
+    }
+}
+
+/// A typeface to draw a set of characters from instead of the primary typeface.
+#[derive(Clone, Debug)]
+struct Substitution {
+    chars: CharacterSet,
+    font_path: PathBuf,
+}
+
+fn parse_substitution(st: &str) -> Result<Substitution, OptError> {
+    let mut parts = st.splitn(2, '=');
+    let chars = match parts.next() {
+        Some(ranges) => parse_character_set(ranges)?,
+        None => return Err(OptError::InvalidSubstitution(format!("{}", st))),
+    };
+    let font_path = match parts.next() {
+        Some(font_path) if !font_path.trim().is_empty() => PathBuf::from(font_path.trim()),
+        _ => return Err(OptError::InvalidSubstitution(format!("{}", st))),
+    };
+
+    Ok(Substitution { chars: chars, font_path: font_path })
+}
+
+/// A render mode for a set of characters, overriding the one of the rest of the atlas.
+#[derive(Clone, Debug)]
+struct RenderModeOverride {
+    chars: CharacterSet,
+    mode: RenderMode,
+}
+
+fn parse_render_mode(st: &str) -> Result<RenderModeOverride, OptError> {
+    let mut parts = st.rsplitn(2, '=');
+    let mode = match parts.next().map(|mode| mode.trim()) {
+        Some("coverage") => RenderMode::Coverage,
+        Some("sdf") => RenderMode::Sdf,
+        Some("msdf") => RenderMode::Msdf,
+        _ => return Err(OptError::InvalidRenderMode(format!("{}", st))),
+    };
+    let chars = match parts.next() {
+        Some(ranges) => parse_character_set(ranges)?,
+        None => return Err(OptError::InvalidRenderMode(format!("{}", st))),
+    };
+
+    Ok(RenderModeOverride { chars: chars, mode: mode })
+}
+
+fn parse_scale(st: &str) -> Result<f32, OptError> {
+    match st.trim().parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        _ => Err(OptError::InvalidScale(format!("{}", st))),
+    }
+}
+
+fn parse_variation(st: &str) -> Result<VariationCoordinate, OptError> {
+    let mut parts = st.trim().splitn(2, '=');
+    let tag = parts.next().unwrap_or_default();
+    let value = parts.next().and_then(|value| value.trim().parse::<f32>().ok());
+    match value {
+        Some(value) if tag.len() == 4 && tag.is_ascii() && value.is_finite() => {
+            Ok(VariationCoordinate { tag: String::from(tag), value: value })
+        }
+        _ => Err(OptError::InvalidVariation(format!("{}", st))),
+    }
+}
+
+/// Whether the optical size axis of a variable font follows the size the glyphs get rendered at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OpticalSizing {
+    /// Set the optical size to the glyph size of each atlas.
+    Auto,
+    /// Leave the optical size at its default, or at the coordinate given with `--variation`.
+    None,
+}
+
+impl OpticalSizing {
+    fn name(self) -> &'static str {
+        match self {
+            OpticalSizing::Auto => "auto",
+            OpticalSizing::None => "none",
+        }
+    }
+}
+
+fn parse_optical_sizing(st: &str) -> Result<OpticalSizing, OptError> {
+    match st {
+        "auto" => Ok(OpticalSizing::Auto),
+        "none" => Ok(OpticalSizing::None),
+        _ => Err(OptError::InvalidOpticalSizing(format!("{}", st))),
+    }
+}
+
+/// What to do with the requested characters no typeface has a glyph for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum MissingGlyphs {
+    /// Draw them with the missing glyph of the primary typeface, or a fallback glyph.
+    Draw,
+    /// Leave them out of the atlas.
+    Skip,
+    /// Fail the run.
+    Fail,
+}
+
+impl MissingGlyphs {
+    fn name(self) -> &'static str {
+        match self {
+            MissingGlyphs::Draw => "draw",
+            MissingGlyphs::Skip => "skip",
+            MissingGlyphs::Fail => "fail",
+        }
+    }
+}
+
+fn parse_missing_glyphs(st: &str) -> Result<MissingGlyphs, OptError> {
+    match st {
+        "draw" => Ok(MissingGlyphs::Draw),
+        "skip" => Ok(MissingGlyphs::Skip),
+        "fail" => Ok(MissingGlyphs::Fail),
+        _ => Err(OptError::InvalidMissingGlyphs(format!("{}", st))),
+    }
+}
+
+/// Which glyph a merged atlas keeps for a character that more than one of the atlases has.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Collision {
+    /// Keep the glyph of the first atlas that has the character.
+    First,
+    /// Keep the glyph of the last atlas that has the character.
+    Last,
+    /// Fail the merge.
+    Fail,
+}
+
+fn parse_collision(st: &str) -> Result<Collision, OptError> {
+    match st {
+        "first" => Ok(Collision::First),
+        "last" => Ok(Collision::Last),
+        "fail" => Ok(Collision::Fail),
+        _ => Err(OptError::InvalidCollision(format!("{}", st))),
+    }
+}
+
+fn parse_alignment(st: &str) -> Result<TextAlignment, OptError> {
+    match st {
+        "left" => Ok(TextAlignment::Left),
+        "center" => Ok(TextAlignment::Center),
+        "right" => Ok(TextAlignment::Right),
+        _ => Err(OptError::InvalidAlignment(format!("{}", st))),
+    }
+}
+
+fn parse_rule(st: &str) -> Result<TextRule, OptError> {
+    match st {
+        "underline" => Ok(TextRule::Underline),
+        "overline" => Ok(TextRule::Overline),
+        "strikeout" => Ok(TextRule::Strikeout),
+        _ => Err(OptError::InvalidRule(format!("{}", st))),
+    }
+}
+
+/// Parse a memory size like `8MB` into bytes. The suffixes count in powers of 1024, the way GPU
+/// memory gets reported.
+fn parse_memory_size(st: &str) -> Result<usize, OptError> {
+    let upper = st.trim().to_uppercase();
+    let (number, unit) = match upper.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => upper.split_at(index),
+        None => (upper.as_str(), ""),
+    };
+    let multiplier = match unit.trim() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(OptError::InvalidMemorySize(format!("{}", st))),
+    };
+    match number.parse::<usize>().ok().and_then(|val| val.checked_mul(multiplier)) {
+        Some(val) if val > 0 => Ok(val),
+        _ => Err(OptError::InvalidMemorySize(format!("{}", st))),
+    }
+}
+
+fn parse_oblique(st: &str) -> Result<f32, OptError> {
+    match st.parse::<f32>() {
+        Ok(shear) if shear != 0.0 && shear.abs() <= 1.0 => Ok(shear),
+        _ => Err(OptError::InvalidOblique(format!("{}", st))),
+    }
+}
+
+fn parse_embolden(st: &str) -> Result<f32, OptError> {
+    match st.trim_end_matches("px").parse::<f32>() {
+        Ok(strength) if strength > 0.0 && strength.is_finite() => Ok(strength),
+        _ => Err(OptError::InvalidEmbolden(format!("{}", st))),
+    }
+}
+
+fn parse_outline(st: &str) -> Result<usize, OptError> {
+    match st.trim_end_matches("px").parse::<usize>() {
+        Ok(outline) if outline > 0 => Ok(outline),
+        _ => Err(OptError::InvalidOutline(format!("{}", st))),
+    }
+}
+
+fn parse_shadow(st: &str) -> Result<Shadow, OptError> {
+    let fields: Vec<&str> = st.split(',').map(|field| field.trim()).collect();
+    if fields.len() != 4 {
+        return Err(OptError::InvalidShadow(format!("{}", st)));
+    }
+    let shadow = match (fields[0].parse(), fields[1].parse(), fields[2].parse(), fields[3].parse()) {
+        (Ok(dx), Ok(dy), Ok(blur), Ok(alpha)) => Shadow { dx: dx, dy: dy, blur: blur, alpha: alpha },
+        _ => return Err(OptError::InvalidShadow(format!("{}", st))),
+    };
+    if !(shadow.alpha > 0.0 && shadow.alpha <= 1.0) {
+        return Err(OptError::InvalidShadow(format!("{}", st)));
+    }
+
+    Ok(shadow)
+}
+
+fn parse_glow(st: &str) -> Result<Glow, OptError> {
+    let fields: Vec<&str> = st.split(',').map(|field| field.trim()).collect();
+    if fields.len() != 2 {
+        return Err(OptError::InvalidGlow(format!("{}", st)));
+    }
+    match (fields[0].parse::<usize>(), fields[1].parse::<f32>()) {
+        (Ok(radius), Ok(intensity)) if radius > 0 && intensity > 0.0 && intensity.is_finite() => {
+            Ok(Glow { radius: radius, intensity: intensity })
+        }
+        _ => Err(OptError::InvalidGlow(format!("{}", st))),
+    }
+}
+
+fn parse_layer(st: &str) -> Result<EffectLayer, OptError> {
+    match st {
+        "fill" => Ok(EffectLayer::Fill),
+        "outline" => Ok(EffectLayer::Outline),
+        "shadow" => Ok(EffectLayer::Shadow),
+        _ => Err(OptError::InvalidLayer(format!("{}", st))),
+    }
+}
+
+fn parse_feature_tags(st: &str) -> Result<Vec<String>, OptError> {
+    let mut tags = vec![];
+    for tag in st.split(',').map(|tag| tag.trim()) {
+        if tag.is_empty() || tag.len() > 4 || !tag.is_ascii() {
+            return Err(OptError::InvalidFeatureTag(format!("{}", tag)));
+        }
+        tags.push(String::from(tag));
+    }
+
+    Ok(tags)
+}
+
+fn parse_bundle_locale(st: &str) -> Result<&'static str, OptError> {
+    match LOCALE_SUPPLEMENTS.iter().find(|&&(name, _)| name == st) {
+        Some(&(name, _)) => Ok(name),
+        None => Err(OptError::InvalidLocale(format!("{}", st))),
+    }
+}
+
+fn parse_hinting_mode(st: &str) -> Result<HintingMode, OptError> {
+    match st {
+        "none" => Ok(HintingMode::None),
+        "native" => Ok(HintingMode::Native),
+        "auto" => Ok(HintingMode::Auto),
+        "light" => Ok(HintingMode::Light),
+        _ => Err(OptError::InvalidHintingMode(format!("{}", st))),
+    }
+}
+
+fn parse_packing(st: &str) -> Result<Packing, OptError> {
+    match st {
+        "grid" => Ok(Packing::Grid),
+        "skyline" => Ok(Packing::Skyline),
+        "maxrects" => Ok(Packing::MaxRects),
+        "shelf" => Ok(Packing::Shelf),
+        _ => Err(OptError::InvalidPacking(format!("{}", st))),
+    }
+}
+
+fn parse_channels(st: &str) -> Result<AtlasChannels, OptError> {
+    match st {
+        "rgba" => Ok(AtlasChannels::Rgba),
+        "a8" => Ok(AtlasChannels::A8),
+        _ => Err(OptError::InvalidChannels(format!("{}", st))),
+    }
+}
+
+fn parse_color_space(st: &str) -> Result<ColorSpace, OptError> {
+    match st {
+        "srgb" => Ok(ColorSpace::Srgb),
+        "linear" => Ok(ColorSpace::Linear),
+        _ => Err(OptError::InvalidColorSpace(format!("{}", st))),
+    }
+}
+
+fn parse_resample_filter(st: &str) -> Result<ResampleFilter, OptError> {
+    match st {
+        "box" => Ok(ResampleFilter::Box),
+        "nearest" => Ok(ResampleFilter::Nearest),
+        "triangle" => Ok(ResampleFilter::Triangle),
+        "catmull-rom" => Ok(ResampleFilter::CatmullRom),
+        "lanczos3" => Ok(ResampleFilter::Lanczos3),
+        _ => Err(OptError::InvalidResampleFilter(format!("{}", st))),
+    }
+}
+
+fn parse_small_caps(st: &str) -> Result<f32, OptError> {
+    let invalid = || OptError::InvalidSmallCaps(format!("{}", st));
+    let mut parts = st.splitn(2, '=');
+    match (parts.next().map(|key| key.trim()), parts.next()) {
+        (Some("scale"), Some(value)) => {
+            let scale = value.trim().parse::<f32>().map_err(|_| invalid())?;
+            if scale > 0.0 && scale <= 1.0 {
+                Ok(scale)
+            } else {
+                Err(invalid())
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_code_point(st: &str) -> Option<usize> {
+    let digits = st.strip_prefix("U+").or_else(|| st.strip_prefix("u+"))?;
+    match usize::from_str_radix(digits, 16) {
+        Ok(code_point) if code_point <= 0x10FFFF => Some(code_point),
+        _ => None,
+    }
+}
+
+fn parse_character_set(st: &str) -> Result<CharacterSet, OptError> {
+    let mut ranges = vec![];
+    for range in st.split(',').map(|range| range.trim()) {
+        let mut bounds = range.splitn(2, '-').map(|bound| parse_code_point(bound.trim()));
+        let parsed = match (bounds.next(), bounds.next()) {
+            (Some(Some(start)), None) => Some((start, start)),
+            (Some(Some(start)), Some(Some(end))) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        match parsed {
+            Some(bounds) => ranges.push(bounds),
+            None => return Err(OptError::InvalidCharacterRange(format!("{}", range))),
+        }
+    }
+
+    Ok(CharacterSet::new(&ranges))
+}
+
+fn parse_presets(st: &str) -> Result<CharacterSet, OptError> {
+    let mut chars = CharacterSet::new(&[]);
+    for preset in st.split(',').map(|preset| preset.trim()) {
+        match CharacterSet::preset(preset) {
+            Some(preset_chars) => chars = chars.union(&preset_chars),
+            None => return Err(OptError::InvalidPreset(format!("{}", preset))),
+        }
+    }
+
+    Ok(chars)
+}
+
+fn parse_zero_width_classes(st: &str) -> Result<ZeroWidthClasses, OptError> {
+    let mut classes = ZeroWidthClasses::default();
+    for class in st.split(',').map(|class| class.trim()) {
+        match class {
+            "joiners" => classes.joiners = true,
+            "combining" => classes.combining = true,
+            "format" => classes.format = true,
+            "none" => {}
+            _ => return Err(OptError::InvalidZeroWidthClass(format!("{}", class))),
+        }
+    }
+
+    Ok(classes)
+}
+
+fn parse_renderer_requirement(st: &str) -> Result<RendererRequirement, OptError> {
+    let invalid = || OptError::InvalidRendererRequirement(format!("{}", st));
+    let operator_start = st.find(|c| c == '<' || c == '>' || c == '=').ok_or_else(invalid)?;
+    let (backend, rest) = st.split_at(operator_start);
+    let (comparison, version) = if rest.starts_with(">=") {
+        (VersionComparison::GreaterOrEqual, &rest[2..])
+    } else if rest.starts_with("<=") {
+        (VersionComparison::LessOrEqual, &rest[2..])
+    } else if rest.starts_with("==") {
+        (VersionComparison::Equal, &rest[2..])
+    } else if rest.starts_with('>') {
+        (VersionComparison::Greater, &rest[1..])
+    } else if rest.starts_with('<') {
+        (VersionComparison::Less, &rest[1..])
+    } else {
+        (VersionComparison::Equal, &rest[1..])
+    };
+
+    let backend = backend.trim();
+    if backend.is_empty() {
+        return Err(invalid());
+    }
+    let mut components = vec![];
+    for component in version.trim().split('.') {
+        components.push(component.parse::<u32>().map_err(|_| invalid())?);
+    }
+    if components.len() > 3 {
+        return Err(invalid());
+    }
+
+    Ok(RendererRequirement::new(&backend, comparison, components))
+}
+
+/// The options of `fontgen generate`, which generates an atlas from one or more fonts.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen generate",
+    about = "Generate an atlas from one or more TrueType or OpenType fonts."
+)]
+struct Opt {
+    /// The paths to the input files, in priority order. Characters the first typeface lacks get
+    /// drawn from the next typeface in the list that has them.
+    #[structopt(parse(from_os_str))]
+    #[structopt(short = "i", long = "input", raw(required = "true"))]
+    input_paths: Vec<PathBuf>,
+    /// The face to use when the first input file is a TrueType or OpenType collection, like the
+    /// `.ttc` and `.otc` files of many CJK system fonts, counting from 0. The other input files
+    /// and the substitute fonts always use their first face.
+    #[structopt(long = "face-index", default_value = "0")]
+    face_index: usize,
+    /// Draw a range of characters from another font file wherever it covers them, e.g.
+    /// `U+0026,U+0030-U+0039=brand.ttf` for the ampersand and numerals of a brand typeface. The
+    /// ranges take the form of `--chars`, and the first matching substitution wins.
+    #[structopt(long = "substitute")]
+    #[structopt(parse(try_from_str = "parse_substitution"))]
+    substitutions: Vec<Substitution>,
+    #[structopt(parse(from_os_str))]
+    #[structopt(short = "o", long = "output")]
+    /// The path to the output file.
+    output_path: PathBuf,
+    /// An optional path to write the packed RGBA atlas image to as a standalone PNG file. The image
+    /// has the same orientation as the one inside the atlas file, so it is stored upside down
+    /// unless the origin is `top-left`.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "image-out")]
+    image_out: Option<PathBuf>,
+    /// The file format to write the atlas in: `bmfa`, `bmfont-text`, `bmfont-binary`,
+    /// `bmfont-xml`, or `json`. The BMFont formats write an AngelCode BMFont `.fnt` descriptor and
+    /// a `_0.png` page image next to the output path. The `json` format writes a `.json` file in the
+    /// msdf-atlas-gen layout and a `.png` atlas image. These formats always use a `top-left` origin.
+    #[structopt(long = "format", default_value = "bmfa")]
+    #[structopt(parse(try_from_str = "parse_output_format"))]
+    format: OutputFormat,
+    /// The size, in pixels, of a glyph slot in the font sheet. The slot glyph
+    /// is not necessarily the same as the glyph size because a glyph slot can contain padding.
+    #[structopt(long = "slot-glyph-size", default_value = "64")]
+    slot_glyph_size: usize,
+    /// The glyph size in typographic points, as an alternative to the slot glyph size in pixels,
+    /// for matching the logical font sizes of a UI platform. The slot glyph size becomes the
+    /// size of an em at `--dpi`, plus the padding.
+    #[structopt(long = "size-pt", conflicts_with = "slot_glyph_size")]
+    size_pt: Option<f32>,
+    /// The display resolution in dots per inch that `--size-pt` gets converted to pixels at.
+    #[structopt(long = "dpi", default_value = "72")]
+    dpi: u32,
+    /// The glyph slot padding size, in pixels. This is the number of pixels away from the
+    /// boundary of a glyph slot a glyph will be placed.
+    #[structopt(short = "p", long = "padding", default_value = "0")]
+    padding: usize,
+    /// The origin of the coordinate system for the atlas image. This describes the coordinate system
+    /// used to index into the image for each glyph.
+    #[structopt(long = "origin", default_value = "bottom-left")]
+    #[structopt(parse(try_from_str = "parse_origin"))]
     origin: bmfa::Origin,
-    /// The width of the atlas in pixels.
-    width: usize,
-    /// The height of the atls in pixels.
-    height: usize,
-    /// The number of glyphs per column in the atlas.
+    /// How to arrange the glyphs in the atlas image: `grid`, `skyline`, `maxrects`, or `shelf`.
+    /// The grid puts each glyph in a slot of the slot glyph size. The other packings place glyphs
+    /// by their actual sizes, which usually makes the atlas much smaller for proportional
+    /// typefaces. The shelf packing groups glyphs of similar heights into rows.
+    #[structopt(long = "pack", default_value = "grid")]
+    #[structopt(parse(try_from_str = "parse_packing"))]
+    packing: Packing,
+    /// Shrink the atlas to the smallest size that fits the glyphs actually rendered, instead of
+    /// a grid sized for every selected character, and report the chosen dimensions.
+    #[structopt(long = "auto-size")]
+    auto_size: bool,
+    /// Round the dimensions chosen by `--auto-size` and `--trim-last-page` up to powers of two,
+    /// for GPUs and texture formats that require them.
+    #[structopt(long = "power-of-two")]
+    power_of_two: bool,
+    /// The largest width and height of an atlas image, in pixels. Glyphs that do not fit spill
+    /// over onto further pages, each written to its own numbered file, e.g. for large CJK
+    /// character sets that exceed the texture size limit of a GPU. This applies to the `--scales`
+    /// and `--ruby-scale` atlases too.
+    #[structopt(long = "max-size")]
+    max_size: Option<usize>,
+    /// Shrink the height of the last page of a multi-page atlas to the rows its glyphs take up,
+    /// keeping the width of every other page. The size of each page gets recorded in the
+    /// `.meta.json` file.
+    #[structopt(long = "trim-last-page", requires = "max_size")]
+    trim_last_page: bool,
+    /// Also write the pages of the atlas to a KTX2 texture array next to it, one layer per page, so
+    /// an engine can bind a single array texture for every page. The layer of each glyph is its
+    /// page in the `.meta.json` file. The layers of an array are the same size, so this cannot be
+    /// combined with `--trim-last-page`.
+    #[structopt(long = "texture-array", conflicts_with = "trim_last_page")]
+    texture_array: bool,
+    /// Also generate a companion atlas of the same characters at this fraction of the slot glyph
+    /// size, e.g. `0.5` for furigana and other ruby annotation text. It gets written next to the
+    /// atlas with a `.ruby` suffix, and is always a single page.
+    #[structopt(long = "ruby-scale")]
+    ruby_scale: Option<f32>,
+    /// Also generate atlases at these multiples of the glyph size, e.g. `1,2,3` for the `@2x` and
+    /// `@3x` assets of high resolution displays, from the same loaded typeface. Each one gets
+    /// written next to the atlas with an `@<scale>x` suffix, and is always a single page.
+    #[structopt(long = "scales", raw(use_delimiter = "true"))]
+    #[structopt(parse(try_from_str = "parse_scale"))]
+    scales: Vec<f32>,
+    /// Generate a locale bundle: the atlas becomes a shared base of the Latin characters,
+    /// punctuation, and digits, and each of these locales, e.g. `el,th,ko`, gets a supplement
+    /// atlas of its other characters that the typefaces cover, written next to the atlas with a
+    /// `.<locale>` suffix. A `.bundle.json` manifest lists the atlases. Selected characters replace
+    /// the default base characters.
+    #[structopt(long = "bundle", raw(use_delimiter = "true"))]
+    #[structopt(parse(try_from_str = "parse_bundle_locale"))]
+    bundle: Vec<&'static str>,
+    /// The design coordinates of the instance of a variable font to render, e.g. `wght=650,wdth=85`
+    /// for a semibold condensed instance. The axes left out keep their default coordinates.
+    #[structopt(long = "variation", raw(use_delimiter = "true"))]
+    #[structopt(parse(try_from_str = "parse_variation"))]
+    variation: Vec<VariationCoordinate>,
+    /// Whether to set the optical size axis (`opsz`) of a variable font to the glyph size of each
+    /// atlas, in points, either `auto` or `none`. An `opsz` coordinate given with `--variation`
+    /// always takes precedence.
+    #[structopt(long = "optical-sizing", default_value = "auto")]
+    #[structopt(parse(try_from_str = "parse_optical_sizing"))]
+    optical_sizing: OpticalSizing,
+    /// The horizontal scale factor for each glyph. Values below one produce a faux-condensed
+    /// typeface and values above one produce a faux-expanded typeface.
+    #[structopt(long = "stretch", default_value = "1.0")]
+    stretch: f32,
+    /// Slant each glyph outline by this horizontal shear for a synthetic italic of typefaces that
+    /// ship without an italic face, e.g. `0.2` for a slant of about 11 degrees. Negative values
+    /// slant the glyphs backward. The glyph bounds in the metadata widen with the slant, while
+    /// the advances stay as they are.
+    #[structopt(long = "oblique")]
+    #[structopt(parse(try_from_str = "parse_oblique"))]
+    oblique: Option<f32>,
+    /// Apply a gamma curve to the coverage values of the glyphs before packing, to match the
+    /// blending of the target pipeline. Values above one make antialiased edges heavier, e.g.
+    /// `1.43` for text blended in linear space that looks too thin, and values below one lighter.
+    #[structopt(long = "gamma", default_value = "1.0")]
+    gamma: f32,
+    /// Choose the gamma that makes text drawn from the atlas match the text FreeType renders
+    /// directly with this hinting mode, `none`, `native`, `auto`, or `light`, like the native
+    /// text of a UI it gets mixed with. The comparison gets written to a `.calibration.json` report.
+    #[structopt(long = "calibrate")]
+    #[structopt(parse(try_from_str = "parse_hinting_mode"))]
+    calibrate: Option<HintingMode>,
+    /// Refuse to generate the atlas unless the glyph rasterizer satisfies this requirement,
+    /// e.g. `freetype>=2.13`. Different rasterizer versions can produce different pixels.
+    #[structopt(long = "require-renderer")]
+    #[structopt(parse(try_from_str = "parse_renderer_requirement"))]
+    require_renderer: Option<RendererRequirement>,
+    /// The channels each pixel of the atlas image holds: `rgba`, or `a8` for a single alpha
+    /// channel holding the coverage, a quarter the size. The `bmfa` format always stores RGBA,
+    /// and color glyphs get drawn like the other glyphs with `a8`.
+    #[structopt(long = "channels", default_value = "rgba")]
+    #[structopt(parse(try_from_str = "parse_channels"))]
+    channels: AtlasChannels,
+    /// The color space to tag the PNG images and the metadata of the atlas with, either `srgb`
+    /// or `linear`. The PNG image inside a `.bmfa` file is left untagged.
+    #[structopt(long = "color-space", default_value = "linear")]
+    #[structopt(parse(try_from_str = "parse_color_space"))]
+    color_space: ColorSpace,
+    /// The filter to resample glyph images with when they get scaled, like the bitmap strikes of
+    /// color emoji typefaces: `box`, `nearest`, `triangle`, `catmull-rom`, or `lanczos3`. The
+    /// default box filter averages the pixels, which can look muddy for detailed emoji.
+    #[structopt(long = "resample-filter", default_value = "box")]
+    #[structopt(parse(try_from_str = "parse_resample_filter"))]
+    resample_filter: ResampleFilter,
+    /// Draw lowercase letters as capitals scaled down by the given factor, e.g. `scale=0.75`.
+    /// This is a stopgap for typefaces without real small capitals.
+    #[structopt(long = "synthetic-small-caps")]
+    #[structopt(parse(try_from_str = "parse_small_caps"))]
+    synthetic_small_caps: Option<f32>,
+    /// Draw a box displaying the hexadecimal code point for each character the typeface does
+    /// not cover, instead of the typeface's missing glyph symbol.
+    #[structopt(long = "last-resort")]
+    last_resort: bool,
+    /// Draw the printable ASCII characters the typefaces do not cover from a small pixel font
+    /// built into `fontgen`, so diagnostic text always stays legible.
+    #[structopt(long = "builtin-fallback")]
+    builtin_fallback: bool,
+    /// What to do with the requested characters no typeface has a glyph for: `draw` them with
+    /// the missing glyph of the typeface and warn about them, `skip` them, leaving them out of
+    /// the atlas, or `fail` the run.
+    #[structopt(long = "on-missing", default_value = "draw")]
+    #[structopt(parse(try_from_str = "parse_missing_glyphs"))]
+    on_missing: MissingGlyphs,
+    /// Write a JSON report of which requested characters the typefaces cover to this path, with
+    /// the code points of the missing ones. The report gets written even when the run fails on
+    /// missing glyphs.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "coverage-report")]
+    coverage_report: Option<PathBuf>,
+    /// A comma separated list of the code point classes that get metadata entries with no image,
+    /// instead of being rasterized: `joiners`, `combining`, `format`, or `none`.
+    #[structopt(long = "zero-width", default_value = "joiners,combining")]
+    #[structopt(parse(try_from_str = "parse_zero_width_classes"))]
+    zero_width: ZeroWidthClasses,
+    /// A comma separated list of the code point ranges to include in the atlas, e.g.
+    /// `U+0020-U+007E,U+0400-U+04FF`. The atlas grid is sized to fit the selected characters.
+    /// By default, the printable ASCII and Latin-1 characters fill a 16x16 grid.
+    #[structopt(long = "chars")]
+    #[structopt(parse(try_from_str = "parse_character_set"))]
+    chars: Option<CharacterSet>,
+    /// A comma separated list of built-in character sets to include in the atlas: `ascii`,
+    /// `latin1`, `latin-ext`, `cyrillic`, `greek`, `hiragana`, `katakana`, or `cjk-common`.
+    /// The script presets do not include ASCII, so pair them with `ascii`, e.g. `ascii,greek`.
+    #[structopt(long = "preset")]
+    #[structopt(parse(try_from_str = "parse_presets"))]
+    preset: Option<CharacterSet>,
+    /// A UTF-8 text file whose distinct characters make up the atlas, e.g. the translated strings
+    /// of a game. When combined with `--chars` or `--preset`, the atlas holds the characters
+    /// selected by all of them.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "charset-file")]
+    charset_file: Option<PathBuf>,
+    /// Put every glyph of the typeface in the atlas by glyph index instead of selecting
+    /// characters by code point, e.g. for inspecting a typeface or for icon fonts with glyphs no
+    /// code point maps to. The atlas and metadata get keyed by glyph index, and the `.meta.json`
+    /// file maps each glyph index to the code points of the glyph.
+    #[structopt(long = "all-glyphs")]
+    all_glyphs: bool,
+    /// Convert each glyph into a signed distance field before packing, so the atlas can be
+    /// scaled smoothly in shaders. The outline of a glyph lies at the value 128, and the
+    /// glyphs get rendered smaller to leave room for the spread inside the glyph size.
+    #[structopt(long = "sdf")]
+    sdf: bool,
+    /// Compute a multi-channel signed distance field from each glyph outline, like msdfgen, so
+    /// corners stay sharp at large magnifications. Shaders should sample the median of the red,
+    /// green, and blue channels. The alpha channel holds that median.
+    #[structopt(long = "msdf", conflicts_with = "sdf")]
+    msdf: bool,
+    /// The distance in pixels that the signed distance field extends on either side of the
+    /// outline of a glyph.
+    #[structopt(long = "sdf-spread", default_value = "8")]
+    sdf_spread: usize,
+    /// Render a range of characters with its own mode, `coverage`, `sdf`, or `msdf`, instead of
+    /// the mode of the rest of the atlas, e.g. `U+0020-U+007E=msdf`. The ranges take the form of
+    /// `--chars`, and the first matching override wins. The mode of each glyph gets recorded in
+    /// the `.meta.json` file.
+    #[structopt(long = "render-mode")]
+    #[structopt(parse(try_from_str = "parse_render_mode"))]
+    render_modes: Vec<RenderModeOverride>,
+    /// Draw hard-edged glyphs without antialiasing, with the monochrome rasterizer of FreeType,
+    /// for pixel art games and one bit displays.
+    #[structopt(long = "mono")]
+    mono: bool,
+    /// Draw monochrome glyphs by cutting off the antialiased glyphs at this coverage value
+    /// instead, from 1 to 255. Lower values make bolder glyphs.
+    #[structopt(long = "mono-threshold", requires = "mono")]
+    mono_threshold: Option<u8>,
+    /// Thicken each glyph outline by this many pixels, e.g. `1` or `0.5px`, for a heavier variant
+    /// of typefaces that ship without a bold face. The advances grow by the same amount, and the
+    /// glyphs get drawn smaller to fit the extra weight in the slots. Bitmap glyphs stay as they
+    /// are.
+    #[structopt(long = "embolden")]
+    #[structopt(parse(try_from_str = "parse_embolden"))]
+    embolden: Option<f32>,
+    /// Stroke a border of this width around each glyph, e.g. `2px`, for game HUD text that needs
+    /// to stand out against any background. The glyphs get drawn smaller to fit their borders in
+    /// the slots. RGBA atlases hold white glyphs over black borders, and `a8` atlases the
+    /// coverage of the glyphs along with their borders.
+    #[structopt(long = "outline")]
+    #[structopt(parse(try_from_str = "parse_outline"))]
+    outline: Option<usize>,
+    /// Stroke the borders of `--outline` on this many threads, each with a FreeType face of its
+    /// own. Atlases drawn from more than one typeface, or with per-character render modes, get
+    /// sampled on a single thread.
+    #[structopt(long = "threads", requires = "outline")]
+    threads: Option<usize>,
+    /// Composite a drop shadow underneath each glyph, given as `dx,dy,blur,alpha`, e.g.
+    /// `2,2,3,0.5` for a half transparent shadow two pixels right of and below the glyph, blurred
+    /// over three pixels. The glyphs get drawn smaller to fit their shadows in the slots.
+    #[structopt(long = "shadow")]
+    #[structopt(parse(try_from_str = "parse_shadow"))]
+    shadow: Option<Shadow>,
+    /// Render a glow around each glyph, given as `radius,intensity`, e.g. `4,1.5` for a bright
+    /// halo reaching four pixels past the glyph. The glow gets rendered into the padding, so the
+    /// padding has to be at least twice the radius.
+    #[structopt(long = "glow")]
+    #[structopt(parse(try_from_str = "parse_glow"))]
+    glow: Option<Glow>,
+    /// Also write the effects of the glyphs to atlases of their own, e.g. `fill,outline,shadow`, with
+    /// the same layout as the atlas, so runtimes can color and composite them. The `fill` layer
+    /// holds the glyphs, the `outline` layer the glyphs along with their borders, and the `shadow`
+    /// layer their drop shadows. Each one gets written next to the atlas with the layer as a suffix,
+    /// e.g. `atlas.outline.bmfa`.
+    #[structopt(long = "layers", raw(use_delimiter = "true"))]
+    #[structopt(parse(try_from_str = "parse_layer"))]
+    layers: Vec<EffectLayer>,
+    /// Also record the unscaled metrics of the typeface and each glyph in font units in the
+    /// `.meta.json` file, so text can be laid out at any display size from a single atlas.
+    #[structopt(long = "font-units")]
+    font_units: bool,
+    /// Also export the vector outline of each glyph, in font units, to an `.outlines.json` file
+    /// next to the atlas. The only format is `svg`, which stores each outline as SVG path data.
+    #[structopt(long = "export-outlines")]
+    #[structopt(parse(try_from_str = "parse_outline_format"))]
+    export_outlines: Option<OutlineFormat>,
+    /// Also export the glyph outlines as quadratic Bezier curves for GPU vector text renderers:
+    /// a `.curves.bin` data texture of 32 bit float RGBA texels, and a `.curves.json` index with
+    /// the range of texels holding each glyph's curves.
+    #[structopt(long = "export-curves")]
+    export_curves: bool,
+    /// Also export a `.lookup.bin` table mapping each code point to a binary glyph record in
+    /// constant time, for embedded runtimes that cannot afford to build a hash map at load time.
+    #[structopt(long = "export-lookup")]
+    export_lookup: bool,
+    /// Also render a sample line of text with each FreeType hinting mode at the glyph size, and
+    /// write them one above the other to a `.hinting.png` image for choosing between them.
+    #[structopt(long = "compare-hinting")]
+    compare_hinting: bool,
+    /// Also compare how the advances and x-height scale across the sizes generated with
+    /// `--ruby-scale` and `--scales`, and where rounding and hinting break proportionality,
+    /// writing the comparison to a `.sizes.json` report.
+    #[structopt(long = "size-report")]
+    size_report: bool,
+    /// Also write a `.preview.html` page for checking the atlas in a browser. It shows the atlas
+    /// with the metadata of each glyph on hover, a sample paragraph set from the atlas with CSS,
+    /// and a table of the glyph metadata.
+    #[structopt(long = "preview-html")]
+    preview_html: bool,
+    /// A UTF-8 text file of UI strings, one per line, to measure in the glyphs of the atlas. The
+    /// width, height, and ink bounds of each string, with kerning, get written to a
+    /// `.strings.json` report, so layouts can size their elements and catch truncated text when
+    /// the atlas gets built.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "measure-strings")]
+    measure_strings: Option<PathBuf>,
+    /// Halve the advances of full width CJK punctuation, like brackets, commas, and periods, so
+    /// Japanese and Chinese text sets tighter in dialog boxes. The compressed advances get
+    /// recorded in the metadata and descriptors.
+    #[structopt(long = "compress-cjk-punctuation")]
+    compress_cjk_punctuation: bool,
+    /// A TOML file of corrections to the characters the typeface gets wrong, in pixels at the
+    /// slot glyph size, applied after the glyphs are drawn, e.g. `"U+0020" = { advance = 6 }` or
+    /// `"U+0041" = { x_offset = 1, y_offset = -1 }`. The corrections get recorded in the metadata.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "advance-overrides")]
+    advance_overrides: Option<PathBuf>,
+    /// A Rhai script defining `process_glyph(glyph)`, which can modify the pixels and metrics
+    /// of each glyph before it gets packed into the atlas.
+    #[cfg(feature = "scripting")]
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "glyph-script")]
+    glyph_script: Option<PathBuf>,
+    /// A directory to cache the sampled glyphs in. Later runs with the same font files, glyph
+    /// size, characters, and effects reuse the cached glyphs and only pack them again.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "cache-dir")]
+    cache_dir: Option<PathBuf>,
+    /// A TOML or JSON file holding the options of one or more atlases, keyed by their long names,
+    /// e.g. `slot-glyph-size = 64`. Each table of an `atlas` array describes an atlas of its own,
+    /// with the options at the top level shared by all of them. Options given on the command line
+    /// replace the ones in the file.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "config")]
+    config: Option<PathBuf>,
+    /// Keep running after writing the atlas, and write it again every time one of the font files,
+    /// or the character set, strings, or config file, changes. Stop it with Ctrl-C.
+    #[structopt(long = "watch")]
+    watch: bool,
+    /// How many seconds to wait for another run generating the same output files to finish.
+    /// Without it, a run fails right away when another run holds the lock on its outputs.
+    #[structopt(long = "wait-for-lock")]
+    wait_for_lock: Option<u64>,
+    /// Overwrite the atlas, the `--image-out` image, and the `--coverage-report` report when they
+    /// exist already, instead of failing.
+    #[structopt(long = "force", raw(conflicts_with_all = r#"&["skip_existing", "backup"]"#))]
+    force: bool,
+    /// Leave the atlas alone, and write nothing, when the atlas file exists already.
+    #[structopt(long = "skip-existing", conflicts_with = "backup")]
+    skip_existing: bool,
+    /// Keep every file a run replaces, like the atlas, its metadata, and the `--image-out` image,
+    /// as a `.bak` file, e.g. `atlas.bmfa.bak`, once the new files are all written.
+    #[structopt(long = "backup")]
+    backup: bool,
+    /// Report rasterization times. Pass it twice to list the fallbacks used and the slowest glyphs.
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: u8,
+}
+
+/// The options of `fontgen batch`, which generates an atlas from each font in a directory.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen batch",
+    about = "Generate an atlas from every TrueType or OpenType font in a directory with the same options.",
+    raw(setting = "structopt::clap::AppSettings::TrailingVarArg"),
+    raw(setting = "structopt::clap::AppSettings::AllowLeadingHyphen")
+)]
+struct BatchOpt {
+    /// The directory holding the `.ttf`, `.otf`, `.ttc`, and `.otc` files.
+    #[structopt(parse(from_os_str))]
+    fonts_dir: PathBuf,
+    /// The directory to write the atlases to. Each atlas gets named after its font file, e.g.
+    /// `atlases/FreeMono.bmfa` for `fonts/FreeMono.ttf`.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out")]
+    out_dir: PathBuf,
+    /// How many atlases to generate at once. This defaults to the number of CPUs.
+    #[structopt(long = "jobs")]
+    jobs: Option<usize>,
+    /// The options to generate every atlas with, e.g. `--padding 4 --slot-glyph-size 64`.
+    #[structopt(parse(from_os_str), raw(allow_hyphen_values = "true"))]
+    options: Vec<OsString>,
+}
+
+/// The options of `fontgen build`, which brings the atlases of a project up to date.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen build",
+    about = "Generate the atlases of a config file whose fonts, character sets, or options changed."
+)]
+struct BuildOpt {
+    /// The config file describing the atlases, in the format of `fontgen generate --config`.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "config")]
+    config: PathBuf,
+    /// List the atlases that are out of date without generating them.
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+/// The options of `fontgen inspect`, which prints what an atlas file holds.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen inspect",
+    about = "Print the metadata and the glyph table of a .bmfa atlas file."
+)]
+struct InspectOpt {
+    /// The atlas file to inspect.
+    #[structopt(parse(from_os_str))]
+    atlas_path: PathBuf,
+}
+
+/// The options of `fontgen audit`, which checks an atlas against translation files.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen audit",
+    about = "Report the characters of translation files that a .bmfa atlas file lacks, by locale."
+)]
+struct AuditOpt {
+    /// The atlas file to check.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "atlas")]
+    atlas_path: PathBuf,
+    /// The translation files, one per locale, named after their locale, e.g. `locales/de.json`.
+    /// Every string value of a JSON file gets checked, whatever its key, and every line of any
+    /// other file.
+    #[structopt(parse(from_os_str), raw(required = "true", min_values = "1"))]
+    #[structopt(long = "strings")]
+    strings_paths: Vec<PathBuf>,
+}
+
+/// The options of `fontgen features`, which lists what a font offers before generating from it.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen features",
+    about = "List the OpenType features, scripts, and languages, and the variation axes and named \
+             instances, of a font file."
+)]
+struct FeaturesOpt {
+    /// The font file to list the features of.
+    #[structopt(parse(from_os_str))]
+    font_path: PathBuf,
+    /// The face to list when the font file is a TrueType or OpenType collection, counting from 0.
+    #[structopt(long = "face-index", default_value = "0")]
+    face_index: usize,
+    /// List the characters whose glyphs change under a comma separated list of OpenType features,
+    /// e.g. `ss01,smcp`, going by their single and alternate substitutions.
+    #[structopt(long = "preview-features", parse(try_from_str = "parse_feature_tags"))]
+    preview_features: Option<Vec<String>>,
+    /// The size in pixels of the thumbnails of the substitution preview.
+    #[structopt(long = "glyph-size", default_value = "32")]
+    glyph_size: usize,
+    /// The PNG file to write thumbnails of the glyphs before and after each substitution of
+    /// `--preview-features` to, side by side.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out", requires = "preview_features")]
+    out_path: Option<PathBuf>,
+}
+
+/// The options of `fontgen extract`, which unpacks an atlas file for other pipelines.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen extract",
+    about = "Unpack a .bmfa atlas file into a PNG image and a JSON metadata file."
+)]
+struct ExtractOpt {
+    /// The atlas file to unpack.
+    #[structopt(parse(from_os_str))]
+    atlas_path: PathBuf,
+    /// The directory to write `atlas.png` and `metadata.json` to. It gets created when it does
+    /// not exist yet.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out")]
+    out_dir: PathBuf,
+    /// Also write the image of each glyph to `glyphs/U+0041.png` and so on in the output
+    /// directory, upright and with the padding around it.
+    #[structopt(long = "glyphs")]
+    glyphs: bool,
+}
+
+/// The options of `fontgen preview`, which draws sample text from an atlas.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen preview",
+    about = "Draw sample text from a .bmfa atlas file, laid out with nothing but its metadata."
+)]
+struct PreviewOpt {
+    /// The atlas file to draw the text from.
+    #[structopt(parse(from_os_str))]
+    atlas_path: PathBuf,
+    /// The text to draw. Line breaks in it start new lines.
+    #[structopt(long = "text")]
+    text: String,
+    /// Wrap the lines of the text at their spaces to fit this many pixels. A word wider than that
+    /// gets a line of its own.
+    #[structopt(long = "wrap-width")]
+    wrap_width: Option<usize>,
+    /// How to line up the lines of the text: `left`, `center`, or `right`. The lines line up within
+    /// the wrap width, or else within the widest line.
+    #[structopt(long = "align", default_value = "left")]
+    #[structopt(parse(try_from_str = "parse_alignment"))]
+    alignment: TextAlignment,
+    /// Draw lines along the text with the rule metrics recorded in the metadata of the atlas:
+    /// `underline`, `overline`, or `strikeout`, e.g. `underline,strikeout`.
+    #[structopt(long = "rules", raw(use_delimiter = "true"))]
+    #[structopt(parse(try_from_str = "parse_rule"))]
+    rules: Vec<TextRule>,
+    /// Fail unless the lines of the text come out this many pixels wide, e.g. `310,288`, adding up
+    /// the advances and kerning of their characters, which tests the metrics of the atlas end to end.
+    #[structopt(long = "expect-line-widths", raw(use_delimiter = "true"))]
+    expected_line_widths: Vec<i64>,
+    /// Fail unless the lines of the text are this many pixels apart.
+    #[structopt(long = "expect-line-height")]
+    expected_line_height: Option<i64>,
+    /// The PNG file to write the preview to.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out")]
+    out_path: PathBuf,
+    /// The `.meta.json` file holding the advances and kerning of the atlas. This defaults to the
+    /// one next to the atlas file. Without one, each character advances by the width of its glyph.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "metadata")]
+    metadata_path: Option<PathBuf>,
+}
+
+/// The options of `fontgen sheet`, which draws a sample text in several fonts for comparison.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen sheet",
+    about = "Draw a sample text in each of several fonts, one font per row, into a single image."
+)]
+struct SheetOpt {
+    /// The font files to compare, drawn top to bottom in this order.
+    #[structopt(parse(from_os_str), raw(required = "true", min_values = "1"))]
+    #[structopt(long = "inputs")]
+    input_paths: Vec<PathBuf>,
+    /// The sample text to draw in each font. Line breaks in it start new lines.
+    #[structopt(long = "text", default_value = "Sphinx of black quartz, judge my vow.")]
+    text: String,
+    /// The slot glyph size, in pixels, of the atlas generated from each font to draw the text with.
+    #[structopt(long = "slot-glyph-size", default_value = "48")]
+    slot_glyph_size: usize,
+    /// The PNG file to write the comparison image to.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out")]
+    out_path: PathBuf,
+}
+
+/// The options of `fontgen merge`, which packs the glyphs of several atlases into one.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen merge",
+    about = "Pack the glyphs of several .bmfa atlas files into a single atlas."
+)]
+struct MergeOpt {
+    /// The atlas files to merge, in priority order.
+    #[structopt(parse(from_os_str), raw(required = "true", min_values = "2"))]
+    atlas_paths: Vec<PathBuf>,
+    /// The merged atlas file. The advances and kerning of its characters get written to a
+    /// `.meta.json` file next to it.
+    #[structopt(parse(from_os_str))]
+    #[structopt(short = "o", long = "output")]
+    output_path: PathBuf,
+    /// Which glyph to keep for the characters more than one atlas has: the one of the `first`
+    /// atlas that has it, the one of the `last` atlas that has it, or `fail` the merge.
+    #[structopt(long = "on-collision", default_value = "first")]
+    #[structopt(parse(try_from_str = "parse_collision"))]
+    on_collision: Collision,
+    /// How many seconds to wait for another run writing the same merged atlas to finish. Without
+    /// it, the merge fails right away when another run holds the lock on its outputs.
+    #[structopt(long = "wait-for-lock")]
+    wait_for_lock: Option<u64>,
+    /// Overwrite the merged atlas and its metadata when they exist already, instead of failing.
+    #[structopt(long = "force", raw(conflicts_with_all = r#"&["skip_existing", "backup"]"#))]
+    force: bool,
+    /// Leave the merged atlas alone, and write nothing, when it exists already.
+    #[structopt(long = "skip-existing", conflicts_with = "backup")]
+    skip_existing: bool,
+    /// Keep the merged atlas and metadata a merge replaces as `.bak` files, e.g. `merged.bmfa.bak`,
+    /// once the new files are both written.
+    #[structopt(long = "backup")]
+    backup: bool,
+}
+
+/// The options of `fontgen diff`, which compares two versions of an atlas.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen diff",
+    about = "Report the glyphs and metadata that differ between two .bmfa atlas files."
+)]
+struct DiffOpt {
+    /// The old version of the atlas.
+    #[structopt(parse(from_os_str))]
+    old_path: PathBuf,
+    /// The new version of the atlas.
+    #[structopt(parse(from_os_str))]
+    new_path: PathBuf,
+    /// Only count the pixels of a glyph with a channel that differs by more than this much, out of
+    /// 255, as changed, so small antialiasing changes of a rasterizer update don't count.
+    #[structopt(long = "tolerance")]
+    tolerance: Option<u8>,
+    /// Exit with status 2 when more than this many pixels of the glyphs differ by more than the
+    /// tolerance, or when glyphs got added, removed, resized, or shifted. Giving `--tolerance` alone
+    /// allows no differing pixels.
+    #[structopt(long = "max-diff-pixels")]
+    max_diff_pixels: Option<usize>,
+}
+
+/// The number of glyphs listed in the slowest glyph report.
+const SLOWEST_GLYPH_COUNT: usize = 10;
+
+/// The options of `fontgen report`, which writes an HTML page comparing two versions of an atlas.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen report",
+    about = "Write an HTML page comparing the glyphs, metrics, and sample text of two .bmfa atlas files."
+)]
+struct ReportOpt {
+    /// The old version of the atlas.
+    #[structopt(parse(from_os_str))]
+    old_path: PathBuf,
+    /// The new version of the atlas.
+    #[structopt(parse(from_os_str))]
+    new_path: PathBuf,
+    /// The HTML file to write the report to.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "out")]
+    out_path: PathBuf,
+    /// The sample text to draw from each version. Line breaks in it start new lines.
+    #[structopt(long = "text", raw(default_value = "fontgen::PREVIEW_HTML_TEXT"))]
+    text: String,
+}
+
+/// The options of `fontgen plan`, which searches for atlas configurations that fit a memory budget.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen plan",
+    about = "List the slot sizes, packings, and channels that fit an atlas of a font into a memory budget."
+)]
+struct PlanOpt {
+    /// The font file to plan the atlas of.
+    #[structopt(parse(from_os_str))]
+    #[structopt(short = "i", long = "input")]
+    input_path: PathBuf,
+    /// The face to use when the input file is a TrueType or OpenType collection, counting from 0.
+    #[structopt(long = "face-index", default_value = "0")]
+    face_index: usize,
+    /// The code point ranges to put in the atlas, like the `--chars` option of `generate`.
+    #[structopt(long = "chars")]
+    #[structopt(parse(try_from_str = "parse_character_set"))]
+    chars: Option<CharacterSet>,
+    /// The built-in character sets to put in the atlas, like the `--preset` option of `generate`.
+    #[structopt(long = "preset")]
+    #[structopt(parse(try_from_str = "parse_presets"))]
+    preset: Option<CharacterSet>,
+    /// A UTF-8 text file whose distinct characters make up the atlas. Without any of `--chars`,
+    /// `--preset`, or `--charset-file`, the atlas holds the printable ASCII and Latin-1 characters.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "charset-file")]
+    charset_file: Option<PathBuf>,
+    /// A comma separated list of the slot glyph sizes to try, in pixels, e.g. `32,48,64`.
+    #[structopt(long = "sizes", raw(use_delimiter = "true", required = "true"))]
+    sizes: Vec<usize>,
+    /// The glyph slot padding, in pixels, of every configuration.
+    #[structopt(short = "p", long = "padding", default_value = "0")]
+    padding: usize,
+    /// Round the dimensions of every atlas up to powers of two, for GPUs that need them.
+    #[structopt(long = "power-of-two")]
+    power_of_two: bool,
+    /// The most memory the atlas image may take up, in bytes, or with a `KB`, `MB`, or `GB`
+    /// suffix, e.g. `8MB`. The suffixes count in powers of 1024.
+    #[structopt(long = "budget")]
+    #[structopt(parse(try_from_str = "parse_memory_size"))]
+    budget: usize,
+}
+
+/// The options of `fontgen plan-update`, which works out what a new character set changes about
+/// an atlas before regenerating it.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "fontgen plan-update",
+    about = "Report the glyphs a new character set adds to, removes from, or changes in a .bmfa atlas file, \
+             and whether they fit in place."
+)]
+struct PlanUpdateOpt {
+    /// The atlas file to update. The `.fontgen.toml` file written next to it says how to
+    /// regenerate its glyphs.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "atlas")]
+    atlas_path: PathBuf,
+    /// The code point ranges of the new character set, like the `--chars` option of `generate`.
+    #[structopt(long = "chars")]
+    #[structopt(parse(try_from_str = "parse_character_set"))]
+    chars: Option<CharacterSet>,
+    /// The built-in character sets of the new character set, like the `--preset` option of `generate`.
+    #[structopt(long = "preset")]
+    #[structopt(parse(try_from_str = "parse_presets"))]
+    preset: Option<CharacterSet>,
+    /// A UTF-8 text file whose distinct characters make up the new character set.
+    #[structopt(parse(from_os_str))]
+    #[structopt(long = "charset-file")]
+    charset_file: Option<PathBuf>,
+}
+
+/// Report how long rasterizing the typeface took. At verbosity one this prints a summary,
+/// and at verbosity two and above it lists every fallback and the slowest glyphs as well,
+/// since a handful of pathological glyphs can dominate the generation time of a font.
+fn report_telemetry(glyph_tab: &GlyphTable, verbosity: u8) {
+    if verbosity == 0 {
+        return;
+    }
+
+    let total: Duration = glyph_tab.telemetry.iter().map(|telemetry| telemetry.elapsed).sum();
+    eprintln!(
+        "Rasterized {} glyphs in {:.3} ms.",
+        glyph_tab.telemetry.len(), total.as_secs_f64() * 1000.0
+    );
+    if verbosity < 2 {
+        return;
+    }
+
+    for telemetry in glyph_tab.telemetry.iter() {
+        if let Some(fallback) = telemetry.fallback {
+            eprintln!("Used a {} for the character {}.", fallback, fontgen::describe_code_point(telemetry.code_point));
+        }
+    }
+
+    let mut slowest = glyph_tab.telemetry.clone();
+    slowest.sort_by(|a, b| b.elapsed.cmp(&a.elapsed));
+    eprintln!("The slowest glyphs to rasterize were:");
+    for telemetry in slowest.iter().take(SLOWEST_GLYPH_COUNT) {
+        eprintln!(
+            "    {:>10.3} ms  {}",
+            telemetry.elapsed.as_secs_f64() * 1000.0, fontgen::describe_code_point(telemetry.code_point)
+        );
+    }
+}
+
+impl Opt {
+    /// The size of a glyph slot in pixels, either as given, or from the point size.
+    fn slot_glyph_size(&self) -> usize {
+        match self.point_size() {
+            Some(point_size) => point_size.pixels() + self.padding,
+            None => self.slot_glyph_size,
+        }
+    }
+
+    /// The path to the primary input file.
+    fn input_path(&self) -> &PathBuf {
+        &self.input_paths[0]
+    }
+
+    /// The glyph size in points at the display resolution, if it was given in points.
+    fn point_size(&self) -> Option<PointSize> {
+        self.size_pt.map(|points| PointSize { points: points, dpi: self.dpi })
+    }
+
+    /// How to draw hard-edged glyphs, if the glyphs should not be antialiased.
+    fn mono(&self) -> Option<Monochrome> {
+        match (self.mono, self.mono_threshold) {
+            (true, Some(threshold)) => Some(Monochrome::Threshold(threshold)),
+            (true, None) => Some(Monochrome::Rasterizer),
+            (false, _) => None,
+        }
+    }
+
+    /// The optical size of glyphs rendered at a multiple of the glyph size, in points. Glyph
+    /// sizes given in pixels count as points, the way browsers treat CSS pixels.
+    fn optical_size(&self, scale: f32) -> f32 {
+        match self.size_pt {
+            Some(points) => points * scale,
+            None => (self.slot_glyph_size - self.padding) as f32 * scale,
+        }
+    }
+}
+
+/// Verify the input options.
+fn verify_opt(opt: &Opt) -> Result<(), OptError> {
+    verify_settings(opt)?;
+    verify_outputs(opt)
+}
+
+/// The output files a run refuses to overwrite, unless told otherwise with `--force` or `--backup`.
+/// These are the files the output format writes, not the output path as given, which only names
+/// them, e.g. `FreeMono.png` for an atlas written to `FreeMono.bmfa`.
+fn protected_outputs(opt: &Opt) -> Vec<PathBuf> {
+    atlas_files(&opt.output_path, opt.format).into_iter()
+        .chain(iter::once(opt.output_path.with_extension("meta.json")))
+        .chain(opt.image_out.iter().cloned())
+        .chain(opt.coverage_report.iter().cloned())
+        .collect()
+}
+
+/// Make sure a run does not overwrite any existing files, unless its overwrite policy allows it.
+fn verify_outputs(opt: &Opt) -> Result<(), OptError> {
+    if opt.force || opt.backup {
+        return Ok(());
+    }
+    match protected_outputs(opt).into_iter().find(|output_path| output_path.exists()) {
+        Some(output_path) => Err(OptError::OutputFileExists(output_path)),
+        None => Ok(()),
+    }
+}
+
+/// Whether `--skip-existing` makes a run do nothing, because its atlas exists already.
+fn skips_existing_atlas(opt: &Opt) -> bool {
+    opt.skip_existing && atlas_exists(&opt.output_path, opt.format)
+}
+
+/// Verify every option apart from the output files, which runs writing an atlas again, like the
+/// runs of watch mode, are free to replace.
+fn verify_settings(opt: &Opt) -> Result<(), OptError> {
+    let substitute_paths = opt.substitutions.iter().map(|substitution| &substitution.font_path);
+    for input_path in opt.input_paths.iter().chain(substitute_paths) {
+        if !input_path.exists() {
+            return Err(OptError::InputFileDoesNotExist(input_path.clone()));
+        }
+        if !input_path.is_file() {
+            return Err(OptError::InputFileIsNotAFile(input_path.clone()));
+        }
+    }
+    if let Some(ref cache_dir) = opt.cache_dir {
+        if cache_dir.exists() && !cache_dir.is_dir() {
+            return Err(OptError::CacheDirIsNotADirectory(cache_dir.clone()));
+        }
+    }
+    if opt.last_resort && opt.on_missing != MissingGlyphs::Draw {
+        return Err(OptError::LastResortMissingGlyphs);
+    }
+    if let Some(point_size) = opt.point_size() {
+        if !(point_size.points > 0.0) || !point_size.points.is_finite() || point_size.pixels() == 0 {
+            return Err(OptError::InvalidPointSize(point_size.points, point_size.dpi));
+        }
+    }
+    if !(opt.slot_glyph_size() > 0) {
+        return Err(OptError::SlotGlyphSizeCannotBeZero(opt.slot_glyph_size()));
+    }
+    if opt.padding > opt.slot_glyph_size() {
+        return Err(OptError::PaddingLargerThanSlotGlyphSize(opt.padding, opt.slot_glyph_size()));
+    }
+    if !(opt.stretch > 0.0) || !opt.stretch.is_finite() {
+        return Err(OptError::InvalidStretch(opt.stretch));
+    }
+    if !(opt.gamma > 0.0) || !opt.gamma.is_finite() {
+        return Err(OptError::InvalidGamma(opt.gamma));
+    }
+    let glyph_size = opt.slot_glyph_size() - opt.padding;
+    let distance_field = opt.sdf || opt.msdf || opt.render_modes.iter().any(|render_mode| {
+        render_mode.mode != RenderMode::Coverage
+    });
+    if distance_field && (opt.sdf_spread == 0 || 2 * opt.sdf_spread >= glyph_size) {
+        return Err(OptError::InvalidSdfSpread(opt.sdf_spread, glyph_size));
+    }
+    if distance_field && opt.mono {
+        return Err(OptError::MonoDistanceField);
+    }
+    if distance_field && opt.outline.is_some() {
+        return Err(OptError::DistanceFieldEffect("--outline"));
+    }
+    if distance_field && opt.shadow.is_some() {
+        return Err(OptError::DistanceFieldEffect("--shadow"));
+    }
+    if distance_field && opt.glow.is_some() {
+        return Err(OptError::DistanceFieldEffect("--glow"));
+    }
+    if distance_field && !opt.layers.is_empty() {
+        return Err(OptError::DistanceFieldEffect("--layers"));
+    }
+    if opt.layers.contains(&EffectLayer::Outline) && opt.outline.is_none() {
+        return Err(OptError::LayerWithoutEffect("outline", "--outline"));
+    }
+    if opt.layers.contains(&EffectLayer::Shadow) && opt.shadow.is_none() {
+        return Err(OptError::LayerWithoutEffect("shadow", "--shadow"));
+    }
+    if let Some(glow) = opt.glow {
+        if 2 * glow.radius > opt.padding {
+            return Err(OptError::GlowWiderThanPadding(glow.radius, opt.padding));
+        }
+    }
+    let spread = if distance_field { opt.sdf_spread } else { 0 };
+    let effects_size = 2 * (spread + opt.outline.unwrap_or(0)) +
+        opt.shadow.map_or(0, |shadow| shadow.extent()) +
+        opt.embolden.map_or(0, |strength| strength.ceil() as usize);
+    if effects_size >= glyph_size {
+        return Err(OptError::EffectsTooLarge(effects_size, glyph_size));
+    }
+    let effects = opt.embolden.is_some() || opt.outline.is_some() ||
+        opt.shadow.is_some() || opt.glow.is_some();
+    if opt.calibrate.is_some() && (distance_field || opt.mono || effects) {
+        return Err(OptError::NothingToCalibrate);
+    }
+    if opt.calibrate.is_some() && opt.gamma != 1.0 {
+        return Err(OptError::CalibratedGamma);
+    }
+    if opt.all_glyphs {
+        let code_point_options = [
+            (opt.chars.is_some(), "--chars"),
+            (opt.preset.is_some(), "--preset"),
+            (opt.charset_file.is_some(), "--charset-file"),
+            (opt.input_paths.len() > 1, "fallback typefaces"),
+            (!opt.substitutions.is_empty(), "--substitute"),
+            (!opt.render_modes.is_empty(), "--render-mode"),
+            (opt.synthetic_small_caps.is_some(), "--synthetic-small-caps"),
+            (opt.last_resort, "--last-resort"),
+            (opt.builtin_fallback, "--builtin-fallback"),
+            (!opt.bundle.is_empty(), "--bundle"),
+            (opt.calibrate.is_some(), "--calibrate"),
+            (opt.font_units, "--font-units"),
+            (opt.export_outlines.is_some(), "--export-outlines"),
+            (opt.export_curves, "--export-curves"),
+            (opt.size_report, "--size-report"),
+            (opt.preview_html, "--preview-html"),
+            (opt.measure_strings.is_some(), "--measure-strings"),
+            (opt.compress_cjk_punctuation, "--compress-cjk-punctuation"),
+            (opt.advance_overrides.is_some(), "--advance-overrides"),
+            (opt.on_missing != MissingGlyphs::Draw, "--on-missing"),
+            (opt.coverage_report.is_some(), "--coverage-report"),
+        ];
+        if let Some(&(_, option)) = code_point_options.iter().find(|&&(given, _)| given) {
+            return Err(OptError::GlyphIndexOption(option));
+        }
+    }
+    if opt.mono_threshold == Some(0) {
+        return Err(OptError::InvalidMonoThreshold(0));
+    }
+    if opt.channels == AtlasChannels::A8 {
+        if opt.format == OutputFormat::Bmfa {
+            return Err(OptError::RgbaOnlyFormat(opt.format.name()));
+        }
+        if opt.msdf || opt.render_modes.iter().any(|render_mode| render_mode.mode == RenderMode::Msdf) {
+            return Err(OptError::SingleChannelMsdf);
+        }
+    }
+    if let Some(ref charset_file) = opt.charset_file {
+        if !charset_file.is_file() {
+            return Err(OptError::CharsetFileDoesNotExist(charset_file.clone()));
+        }
+    }
+    if let Some(ref strings_file) = opt.measure_strings {
+        if !strings_file.is_file() {
+            return Err(OptError::StringsFileDoesNotExist(strings_file.clone()));
+        }
+    }
+    if let Some(ref overrides_file) = opt.advance_overrides {
+        if !overrides_file.is_file() {
+            return Err(OptError::OverridesFileDoesNotExist(overrides_file.clone()));
+        }
+    }
+    if let Some(ruby_scale) = opt.ruby_scale {
+        if !(ruby_scale > 0.0 && ruby_scale < 1.0) {
+            return Err(OptError::InvalidRubyScale(ruby_scale));
+        }
+    }
+    if let Some(max_size) = opt.max_size {
+        // The `--scales` and `--ruby-scale` atlases get spread over pages of the same size.
+        let scales = iter::once(1.0).chain(opt.scales.iter().cloned()).chain(opt.ruby_scale);
+        for scale in scales {
+            let slot_glyph_size = (opt.slot_glyph_size() as f32 * scale).round() as usize;
+            if max_size < 2 * slot_glyph_size {
+                return Err(OptError::MaxSizeTooSmall(max_size, slot_glyph_size));
+            }
+        }
+        if opt.format == OutputFormat::Json {
+            return Err(OptError::SinglePageFormat(opt.format.name()));
+        }
+    }
+    if opt.power_of_two && !opt.auto_size && !opt.trim_last_page {
+        return Err(OptError::NothingToRoundUp);
+    }
+    if opt.trim_last_page && opt.format != OutputFormat::Bmfa {
+        return Err(OptError::UniformPageFormat(opt.format.name()));
+    }
+    if opt.size_report && opt.ruby_scale.is_none() && opt.scales.iter().all(|&scale| scale == 1.0) {
+        return Err(OptError::NoSizesToCompare);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum AppError {
+    CouldNotReadFontFile(PathBuf, io::Error),
+    CouldNotOpenFontFile(PathBuf, freetype::error::Error),
+    CouldNotSetVariation(PathBuf, VariationError),
+    MalformedFontFile(PathBuf, FontValidationError),
+    NoSuchFace(PathBuf, usize, usize),
+    TypefacePanicked(PathBuf),
+    CouldNotCreateBitmapFont(Box<dyn std::error::Error>),
+    CouldNotCreateAtlasFile(PathBuf),
+    CouldNotCreateMetadataFile(PathBuf),
+    CouldNotCreateImageFile(PathBuf),
+    CouldNotCreateParametersFile(PathBuf),
+    CouldNotCreateDescriptorFile(PathBuf),
+    CouldNotCreateOutlinesFile(PathBuf),
+    CouldNotCreateCurvesFile(PathBuf),
+    CouldNotCreateLookupFile(PathBuf),
+    CouldNotCreateHintingComparisonFile(PathBuf),
+    CouldNotCreateSubstitutionPreviewFile(PathBuf),
+    CouldNotCreateSizeReportFile(PathBuf),
+    CouldNotCreatePreviewFile(PathBuf),
+    CouldNotCreateTextureArrayFile(PathBuf),
+    CouldNotCreateReportFile(PathBuf),
+    CouldNotCreateCalibrationFile(PathBuf),
+    CouldNotCreateBundleFile(PathBuf),
+    CouldNotCreateStringMetricsFile(PathBuf),
+    CouldNotCreateCoverageReportFile(PathBuf),
+    CouldNotLockOutputFiles(PathBuf, io::Error),
+    CouldNotStageOutputFiles(io::Error),
+    CouldNotCommitOutputFiles(io::Error),
+    CouldNotOpenCacheDir(PathBuf, io::Error),
+    RendererRequirementNotMet(RendererRequirement, Renderer),
+    CouldNotReadCharsetFile(PathBuf, io::Error),
+    EmptyCharsetFile(PathBuf),
+    CouldNotReadStringsFile(PathBuf, io::Error),
+    InvalidStringsFile(PathBuf, String),
+    EmptyStringsFile(PathBuf),
+    CouldNotReadOverridesFile(PathBuf, io::Error),
+    InvalidOverridesFile(PathBuf, String),
+    MissingGlyphs(CharacterSet),
+    CouldNotReadFontsDir(PathBuf, io::Error),
+    NoFontFiles(PathBuf),
+    DuplicateAtlasName(PathBuf),
+    CouldNotCreateOutDir(PathBuf, io::Error),
+    BatchFailed(usize, usize),
+    CouldNotReadConfigFile(PathBuf, io::Error),
+    InvalidConfigFile(PathBuf, String),
+    CouldNotLoadAtlasFile(PathBuf, bmfa::Error),
+    InvalidMetadataFile(PathBuf, String),
+    GlyphCollision(CharacterSet),
+    UnexpectedPreviewMetrics(Vec<String>),
+    CouldNotReadBuildInputs(PathBuf, io::Error),
+    CouldNotWriteBuildKey(PathBuf, io::Error),
+    MissingLocaleCharacters(usize, usize),
+    NoPlanFitsBudget(usize),
+    NoRuleMetrics,
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::CouldNotReadFontFile(input_path, e) => {
+                write!(f, "Could not read font file {}: {}.", input_path.display(), e)
+            }
+            AppError::CouldNotOpenFontFile(input_path, e) => {
+                write!(f, "Could not open font file {}: {}.", input_path.display(), e)
+            }
+            AppError::CouldNotSetVariation(input_path, e) => {
+                write!(f, "Could not set the variation of the font file {}. {}", input_path.display(), e)
+            }
+            AppError::MalformedFontFile(input_path, e) => {
+                write!(f, "The font file {} is malformed. {}", input_path.display(), e)
+            }
+            AppError::NoSuchFace(input_path, face_index, num_faces) => {
+                write!(
+                    f, "The font file {} has no face {}. Its faces are numbered from 0 to {}.",
+                    input_path.display(), face_index, num_faces.saturating_sub(1)
+                )
+            }
+            AppError::TypefacePanicked(input_path) => {
+                write!(f, "Sampling the typeface in {} failed unexpectedly.", input_path.display())
+            }
+            AppError::CouldNotCreateBitmapFont(e) => {
+                write!(f, "Could not create bitmap font. Got error: {}", e)
+            }
+            AppError::CouldNotCreateAtlasFile(atlas_file) => {
+                write!(f, "Could not create atlas file: {}.", atlas_file.display())
+            }
+            AppError::CouldNotCreateMetadataFile(metadata_file) => {
+                write!(f, "Could not create metadata file: {}.", metadata_file.display())
+            }
+            AppError::CouldNotCreateImageFile(image_file) => {
+                write!(f, "Could not create image file: {}.", image_file.display())
+            }
+            AppError::CouldNotCreateParametersFile(parameters_file) => {
+                write!(f, "Could not create parameters file: {}.", parameters_file.display())
+            }
+            AppError::CouldNotCreateDescriptorFile(descriptor_file) => {
+                write!(f, "Could not create descriptor file: {}.", descriptor_file.display())
+            }
+            AppError::CouldNotCreateOutlinesFile(outlines_file) => {
+                write!(f, "Could not create outlines file: {}.", outlines_file.display())
+            }
+            AppError::CouldNotCreateCurvesFile(curves_file) => {
+                write!(f, "Could not create curves file: {}.", curves_file.display())
+            }
+            AppError::CouldNotCreateLookupFile(lookup_file) => {
+                write!(f, "Could not create glyph lookup table file: {}.", lookup_file.display())
+            }
+            AppError::CouldNotCreateHintingComparisonFile(comparison_file) => {
+                write!(f, "Could not create hinting comparison file: {}.", comparison_file.display())
+            }
+            AppError::CouldNotCreateSubstitutionPreviewFile(preview_file) => {
+                write!(f, "Could not create substitution preview file: {}.", preview_file.display())
+            }
+            AppError::CouldNotCreateSizeReportFile(report_file) => {
+                write!(f, "Could not create size report file: {}.", report_file.display())
+            }
+            AppError::CouldNotCreatePreviewFile(preview_file) => {
+                write!(f, "Could not create HTML preview file: {}.", preview_file.display())
+            }
+            AppError::CouldNotCreateTextureArrayFile(texture_array_file) => {
+                write!(f, "Could not create texture array file: {}.", texture_array_file.display())
+            }
+            AppError::CouldNotCreateReportFile(report_file) => {
+                write!(f, "Could not create HTML report file: {}.", report_file.display())
+            }
+            AppError::CouldNotCreateCalibrationFile(report_file) => {
+                write!(f, "Could not create calibration report file: {}.", report_file.display())
+            }
+            AppError::CouldNotCreateBundleFile(bundle_file) => {
+                write!(f, "Could not create bundle manifest file: {}.", bundle_file.display())
+            }
+            AppError::CouldNotCreateStringMetricsFile(report_file) => {
+                write!(f, "Could not create string metrics file: {}.", report_file.display())
+            }
+            AppError::CouldNotCreateCoverageReportFile(report_file) => {
+                write!(f, "Could not create coverage report file: {}.", report_file.display())
+            }
+            AppError::CouldNotLockOutputFiles(output_path, e) => {
+                write!(
+                    f, "Another run is generating {}: {}. Pass --wait-for-lock to wait for it to \
+                    finish, or remove the lock if no other run is writing to it.",
+                    output_path.display(), e
+                )
+            }
+            AppError::CouldNotStageOutputFiles(e) => {
+                write!(f, "Could not create a staging directory for the output files: {}.", e)
+            }
+            AppError::CouldNotCommitOutputFiles(e) => {
+                write!(f, "Could not move the output files into place: {}.", e)
+            }
+            AppError::CouldNotOpenCacheDir(cache_dir, e) => {
+                write!(f, "Could not open the cache directory {}: {}.", cache_dir.display(), e)
+            }
+            AppError::RendererRequirementNotMet(requirement, renderer) => {
+                write!(
+                    f, "The renderer {} does not satisfy the requirement {}.",
+                    renderer, requirement
+                )
+            }
+            AppError::CouldNotReadCharsetFile(charset_file, e) => {
+                write!(f, "Could not read charset file {}: {}.", charset_file.display(), e)
+            }
+            AppError::EmptyCharsetFile(charset_file) => {
+                write!(f, "The charset file {} contains no printable characters.", charset_file.display())
+            }
+            AppError::CouldNotReadStringsFile(strings_file, e) => {
+                write!(f, "Could not read strings file {}: {}.", strings_file.display(), e)
+            }
+            AppError::InvalidStringsFile(strings_file, e) => {
+                write!(f, "The strings file {} is invalid. {}", strings_file.display(), e)
+            }
+            AppError::EmptyStringsFile(strings_file) => {
+                write!(f, "The strings file {} contains no strings.", strings_file.display())
+            }
+            AppError::CouldNotReadOverridesFile(overrides_file, e) => {
+                write!(f, "Could not read the advance override file {}: {}.", overrides_file.display(), e)
+            }
+            AppError::InvalidOverridesFile(overrides_file, e) => {
+                write!(f, "The advance override file {} is invalid. {}", overrides_file.display(), e)
+            }
+            AppError::MissingGlyphs(missing) => {
+                write!(
+                    f, "The typefaces have no glyphs for {} of the requested characters: {}.",
+                    missing.len(), format_ranges(missing).join(", ")
+                )
+            }
+            AppError::CouldNotReadFontsDir(fonts_dir, e) => {
+                write!(f, "Could not read the font directory {}: {}.", fonts_dir.display(), e)
+            }
+            AppError::NoFontFiles(fonts_dir) => {
+                write!(f, "The directory {} has no .ttf, .otf, .ttc, or .otc files.", fonts_dir.display())
+            }
+            AppError::DuplicateAtlasName(font_file) => {
+                write!(
+                    f, "More than one font file is named {} apart from the extension, so their atlases \
+                    would have the same name.", font_file.display()
+                )
+            }
+            AppError::CouldNotCreateOutDir(out_dir, e) => {
+                write!(f, "Could not create the output directory {}: {}.", out_dir.display(), e)
+            }
+            AppError::BatchFailed(failed, total) => {
+                write!(f, "{} of the {} atlases could not be generated.", failed, total)
+            }
+            AppError::CouldNotReadConfigFile(config_file, e) => {
+                write!(f, "Could not read the config file {}: {}.", config_file.display(), e)
+            }
+            AppError::InvalidConfigFile(config_file, e) => {
+                write!(f, "The config file {} is invalid. {}", config_file.display(), e)
+            }
+            AppError::CouldNotLoadAtlasFile(atlas_path, e) => {
+                write!(f, "Could not load the atlas file {}: {}.", atlas_path.display(), e)
+            }
+            AppError::InvalidMetadataFile(metadata_file, e) => {
+                write!(f, "Could not read the advances and kerning of {}: {}.", metadata_file.display(), e)
+            }
+            AppError::GlyphCollision(collisions) => {
+                write!(
+                    f, "More than one atlas has glyphs for {} characters: {}.",
+                    collisions.len(), format_ranges(collisions).join(", ")
+                )
+            }
+            AppError::MissingLocaleCharacters(locales, total) => {
+                write!(f, "The atlas lacks characters used by {} of the {} locales.", locales, total)
+            }
+            AppError::NoPlanFitsBudget(budget) => {
+                write!(f, "No atlas configuration fits the budget of {}.", format_memory_size(*budget))
+            }
+            AppError::NoRuleMetrics => {
+                write!(
+                    f, "The metadata of the atlas has no rule metrics to draw --rules with. Regenerate \
+                    the atlas to record them."
+                )
+            }
+            AppError::CouldNotReadBuildInputs(output_path, e) => {
+                write!(f, "Could not read the input files of {}: {}.", output_path.display(), e)
+            }
+            AppError::CouldNotWriteBuildKey(key_path, e) => {
+                write!(f, "Could not record the build key in {}: {}.", key_path.display(), e)
+            }
+            AppError::UnexpectedPreviewMetrics(mismatches) => {
+                write!(f, "The preview did not come out as expected: {}.", mismatches.join(", "))
+            }
+        }
+    }
+}
+
+impl error::Error for AppError {}
+
+/// Collect the distinct characters used in a UTF-8 text file.
+fn read_charset_file(path: &Path) -> Result<CharacterSet, AppError> {
+    let text = match fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadCharsetFile(path.to_path_buf(), e)),
+    };
+    let chars = CharacterSet::from_text(&text);
+    if chars.is_empty() {
+        return Err(AppError::EmptyCharsetFile(path.to_path_buf()));
+    }
+
+    Ok(chars)
+}
+
+/// Combine the characters selected by `--chars`, `--preset`, and `--charset-file`, if any were.
+fn select_chars(
+    chars: &Option<CharacterSet>, preset: &Option<CharacterSet>,
+    charset_file: &Option<PathBuf>) -> Result<Option<CharacterSet>, AppError> {
+
+    let charset_file_chars = match *charset_file {
+        Some(ref charset_file) => Some(read_charset_file(charset_file)?),
+        None => None,
+    };
+
+    Ok(vec![chars.clone(), preset.clone(), charset_file_chars]
+        .into_iter()
+        .flatten()
+        .fold(None, |selected: Option<CharacterSet>, chars| match selected {
+            Some(selected) => Some(selected.union(&chars)),
+            None => Some(chars),
+        }))
+}
+
+/// Read the strings to measure from a UTF-8 text file, one per line, skipping blank lines.
+fn read_strings_file(path: &Path) -> Result<Vec<String>, AppError> {
+    let text = match fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadStringsFile(path.to_path_buf(), e)),
+    };
+    let strings: Vec<String> = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect();
+    if strings.is_empty() {
+        return Err(AppError::EmptyStringsFile(path.to_path_buf()));
+    }
+
+    Ok(strings)
+}
+
+/// Read the text of a translation file: every string value of a JSON file, wherever it is nested,
+/// or else the whole file.
+fn read_translation_file(path: &Path) -> Result<String, AppError> {
+    let text = match fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadStringsFile(path.to_path_buf(), e)),
+    };
+    if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+        return Ok(text);
+    }
+    let value: serde_json::Value = match serde_json::from_str(&text) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::InvalidStringsFile(path.to_path_buf(), e.to_string())),
+    };
+    fn collect_strings(value: &serde_json::Value, strings: &mut Vec<String>) {
+        match value {
+            serde_json::Value::String(string) => strings.push(string.clone()),
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| collect_strings(value, strings))
+            }
+            serde_json::Value::Object(map) => map.values().for_each(|value| collect_strings(value, strings)),
+            _ => {}
+        }
+    }
+    let mut strings = vec![];
+    collect_strings(&value, &mut strings);
+
+    Ok(strings.join("\n"))
+}
+
+/// Read the corrections of an advance override file, a TOML table of them keyed by code point,
+/// e.g. `"U+0020" = { advance = 6 }`.
+fn read_overrides_file(path: &Path) -> Result<BTreeMap<usize, GlyphOverride>, AppError> {
+    let text = match fs::read_to_string(path) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadOverridesFile(path.to_path_buf(), e)),
+    };
+    let invalid = |e: String| AppError::InvalidOverridesFile(path.to_path_buf(), e);
+    let overrides: BTreeMap<String, GlyphOverride> = match toml::from_str(&text) {
+        Ok(val) => val,
+        Err(e) => return Err(invalid(e.to_string())),
+    };
+    let mut parsed = BTreeMap::new();
+    for (code_point, glyph_override) in overrides.into_iter() {
+        match parse_code_point(&code_point) {
+            Some(val) => parsed.insert(val, glyph_override),
+            None => return Err(invalid(format!("{} is not a code point like U+0041.", code_point))),
+        };
+    }
+
+    Ok(parsed)
+}
+
+/// The fully resolved parameters of a run, with presets, charset files, and defaults expanded.
+/// These get written next to the atlas so that the run can be reproduced later. Each field is
+/// named after the option that sets it, so the file can be passed back to `--config`.
+#[derive(Clone, Debug, Serialize)]
+struct GenerationParameters {
+    input: Vec<String>,
+    face_index: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    substitute: Vec<String>,
+    output: String,
+    image_out: Option<String>,
+    format: &'static str,
+    origin: &'static str,
+    pack: &'static str,
+    auto_size: bool,
+    power_of_two: bool,
+    max_size: Option<usize>,
+    trim_last_page: bool,
+    texture_array: bool,
+    ruby_scale: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    scales: Vec<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bundle: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    variation: Vec<String>,
+    optical_sizing: &'static str,
+    slot_glyph_size: Option<usize>,
+    size_pt: Option<f32>,
+    dpi: Option<u32>,
+    padding: usize,
+    all_glyphs: bool,
+    chars: Option<String>,
+    stretch: f32,
+    oblique: Option<f32>,
+    gamma: f32,
+    channels: &'static str,
+    color_space: ColorSpace,
+    resample_filter: &'static str,
+    synthetic_small_caps: Option<String>,
+    last_resort: bool,
+    builtin_fallback: bool,
+    mono: bool,
+    mono_threshold: Option<u8>,
+    on_missing: &'static str,
+    embolden: Option<f32>,
+    outline: Option<usize>,
+    shadow: Option<String>,
+    glow: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    layers: Vec<&'static str>,
+    zero_width: String,
+    sdf: bool,
+    msdf: bool,
+    sdf_spread: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    render_mode: Vec<String>,
+    font_units: bool,
+    export_outlines: Option<&'static str>,
+    export_curves: bool,
+    export_lookup: bool,
+    compare_hinting: bool,
+    size_report: bool,
+    preview_html: bool,
+    measure_strings: Option<String>,
+    compress_cjk_punctuation: bool,
+    advance_overrides: Option<String>,
+    require_renderer: Option<String>,
+    #[cfg(feature = "scripting")]
+    glyph_script: Option<String>,
+    /// How the atlas came about, as opposed to how to generate it. `--config` skips this table.
+    provenance: Provenance,
+}
+
+/// The provenance of an atlas: the versions that generated it, and what the run worked out on its
+/// own, like the grid of the atlas and the gamma of `--calibrate`.
+#[derive(Clone, Debug, Serialize)]
+struct Provenance {
+    fontgen_version: &'static str,
+    renderer: String,
+    slot_glyph_size: Option<usize>,
     rows: usize,
-    /// The number of glyphs per row in the atlas.
     columns: usize,
-    /// The amount of padding available for outlines in the glyph, in pixels.
-    padding: usize,
-    /// The maximum size of a glyph slot in pixels.
-    slot_glyph_size: usize,
-    /// The size of a glyph inside the slot, leaving room for padding for outlines.
-    glyph_size: usize,
+    calibrated_against: Option<&'static str>,
+}
+
+/// Write out the ranges of a character set in the form `--chars` takes them, e.g. `U+0041-U+005A`.
+fn format_ranges(chars: &CharacterSet) -> Vec<String> {
+    chars.ranges().iter()
+        .map(|&(start, end)| {
+            if start == end {
+                format!("U+{:04X}", start)
+            } else {
+                format!("U+{:04X}-U+{:04X}", start, end)
+            }
+        })
+        .collect()
+}
+
+impl GenerationParameters {
+    fn new(opt: &Opt, spec: AtlasSpec, chars: &CharacterSet, renderer: &Renderer) -> GenerationParameters {
+        // Atlases of every glyph select glyph indices rather than characters.
+        let chars = if spec.glyph_indices { None } else { Some(format_ranges(chars).join(",")) };
+        let zero_width: Vec<&str> = vec![
+            (spec.zero_width.joiners, "joiners"),
+            (spec.zero_width.combining, "combining"),
+            (spec.zero_width.format, "format"),
+        ]
+        .into_iter()
+        .filter(|&(on, _)| on)
+        .map(|(_, class)| class)
+        .collect();
+        let distance_field = spec.distance_field.is_some() || !opt.render_modes.is_empty();
+
+        GenerationParameters {
+            input: opt.input_paths.iter().map(|path| path_string(path)).collect(),
+            face_index: opt.face_index,
+            substitute: opt.substitutions.iter()
+                .map(|substitution| {
+                    let ranges = format_ranges(&substitution.chars).join(",");
+                    format!("{}={}", ranges, path_string(&substitution.font_path))
+                })
+                .collect(),
+            output: path_string(&opt.output_path),
+            image_out: opt.image_out.as_ref().map(|path| path_string(path)),
+            format: opt.format.name(),
+            origin: origin_name(spec.origin),
+            pack: spec.packing.name(),
+            auto_size: opt.auto_size,
+            power_of_two: opt.power_of_two,
+            max_size: opt.max_size,
+            trim_last_page: opt.trim_last_page,
+            texture_array: opt.texture_array,
+            ruby_scale: opt.ruby_scale,
+            scales: opt.scales.clone(),
+            bundle: opt.bundle.clone(),
+            variation: opt.variation.iter()
+                .map(|coordinate| format!("{}={}", coordinate.tag, coordinate.value))
+                .collect(),
+            optical_sizing: opt.optical_sizing.name(),
+            // A size in points sets the slot glyph size, and the two options rule each other out.
+            slot_glyph_size: if spec.point_size.is_none() { Some(spec.slot_glyph_size) } else { None },
+            size_pt: spec.point_size.map(|point_size| point_size.points),
+            dpi: spec.point_size.map(|point_size| point_size.dpi),
+            padding: spec.padding,
+            all_glyphs: spec.glyph_indices,
+            chars: chars,
+            stretch: spec.stretch,
+            oblique: opt.oblique,
+            gamma: spec.gamma,
+            channels: spec.channels.name(),
+            color_space: opt.color_space,
+            resample_filter: spec.resample_filter.name(),
+            synthetic_small_caps: spec.small_caps_scale.map(|scale| format!("scale={}", scale)),
+            last_resort: spec.last_resort,
+            builtin_fallback: spec.builtin_fallback,
+            mono: opt.mono,
+            mono_threshold: opt.mono_threshold,
+            on_missing: opt.on_missing.name(),
+            embolden: opt.embolden,
+            outline: opt.outline,
+            shadow: spec.shadow.map(|shadow| shadow.to_string()),
+            glow: spec.glow.map(|glow| glow.to_string()),
+            layers: opt.layers.iter().map(|layer| layer.name()).collect(),
+            zero_width: if zero_width.is_empty() { String::from("none") } else { zero_width.join(",") },
+            sdf: opt.sdf,
+            msdf: opt.msdf,
+            sdf_spread: if distance_field { Some(opt.sdf_spread) } else { None },
+            render_mode: opt.render_modes.iter()
+                .map(|render_mode| {
+                    format!("{}={}", format_ranges(&render_mode.chars).join(","), render_mode.mode.name())
+                })
+                .collect(),
+            font_units: opt.font_units,
+            export_outlines: opt.export_outlines.map(|format| format.name()),
+            export_curves: opt.export_curves,
+            export_lookup: opt.export_lookup,
+            compare_hinting: opt.compare_hinting,
+            size_report: opt.size_report,
+            preview_html: opt.preview_html,
+            measure_strings: opt.measure_strings.as_ref().map(|path| path_string(path)),
+            compress_cjk_punctuation: opt.compress_cjk_punctuation,
+            advance_overrides: opt.advance_overrides.as_ref().map(|path| path_string(path)),
+            require_renderer: opt.require_renderer.as_ref().map(|requirement| format!("{}", requirement)),
+            #[cfg(feature = "scripting")]
+            glyph_script: opt.glyph_script.as_ref().map(|path| path_string(path)),
+            provenance: Provenance {
+                fontgen_version: env!("CARGO_PKG_VERSION"),
+                renderer: format!("{}", renderer),
+                // The slot glyph size a size in points works out to.
+                slot_glyph_size: spec.point_size.map(|_| spec.slot_glyph_size),
+                rows: spec.rows,
+                columns: spec.columns,
+                calibrated_against: opt.calibrate.map(|hinting| hinting.name()),
+            },
+        }
+    }
+}
+
+/// The name `--origin` takes for an atlas origin.
+fn origin_name(origin: bmfa::Origin) -> &'static str {
+    match origin {
+        bmfa::Origin::BottomLeft => "bottom-left",
+        bmfa::Origin::TopLeft => "top-left",
+    }
+}
+
+/// A path as a string for the generation parameters. TOML strings are always UTF-8, so paths that
+/// are not get their invalid bytes replaced.
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Write the generation parameters of a run out to a TOML file.
+fn write_generation_parameters(path: &Path, parameters: &GenerationParameters) -> io::Result<()> {
+    let contents = toml::to_string(parameters).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+    fontgen::write_atomically(path, |path| fs::write(path, &contents))
+}
+
+/// Write an atlas out as an AngelCode BMFont descriptor `<output>.fnt` in the text, binary, or
+/// XML format, and the page image `<output>_0.png` next to it.
+fn write_bmfont(
+    output_path: &Path, format: OutputFormat, glyph_tab: &GlyphTable,
+    spec: AtlasSpec, atlas: &bmfa::BitmapFontAtlas, color_space: ColorSpace) -> Result<(), AppError> {
+
+    let page_file = with_stem_suffix(output_path, "_0.png");
+    let page_name = file_name(&page_file);
+
+    if fontgen::write_atlas_image(&page_file, atlas, color_space).is_err() {
+        return Err(AppError::CouldNotCreateImageFile(page_file));
+    }
+    let descriptor = BmfontDescriptor::new(glyph_tab, spec, &page_name);
+
+    write_bmfont_descriptor(output_path, format, &descriptor)
+}
+
+/// Write a BMFont descriptor out to `<output>.fnt` in the text, binary, or XML format.
+fn write_bmfont_descriptor(
+    output_path: &Path, format: OutputFormat, descriptor: &BmfontDescriptor) -> Result<(), AppError> {
+
+    let descriptor_file = output_path.with_extension("fnt");
+    let contents = match format {
+        OutputFormat::BmfontBinary => descriptor.to_binary(),
+        OutputFormat::BmfontXml => descriptor.to_xml().into_bytes(),
+        _ => descriptor.to_text().into_bytes(),
+    };
+    if fontgen::write_atomically(&descriptor_file, |path| fs::write(path, &contents)).is_err() {
+        return Err(AppError::CouldNotCreateDescriptorFile(descriptor_file));
+    }
+
+    Ok(())
+}
+
+/// The typefaces the runs of a configuration file or a batch have loaded, by font file, face
+/// index, design coordinates, and whether the run sets the optical size, so the runs sharing a
+/// font at different sizes or with different character sets reuse the face instead of reading
+/// and validating the font file again. Every run sets the pixel size and the transform of a face
+/// before sampling it, so nothing else a run does to a face carries over to the next run.
+#[derive(Default)]
+struct FaceCache {
+    faces: HashMap<(PathBuf, usize, Vec<(String, u32)>, bool), freetype::Face>,
+}
+
+impl FaceCache {
+    /// Load a typeface like `load_typeface`, or reuse the face an earlier run loaded the same way.
+    fn load(
+        &mut self, ft: &Library, path: &Path, face_index: usize,
+        variation: &[VariationCoordinate], optical_sizing: bool) -> Result<freetype::Face, AppError> {
+
+        let coordinates = variation.iter()
+            .map(|coordinate| (coordinate.tag.clone(), coordinate.value.to_bits()))
+            .collect();
+        let key = (path.to_path_buf(), face_index, coordinates, optical_sizing);
+        if let Some(face) = self.faces.get(&key) {
+            return Ok(face.clone());
+        }
+        let face = load_typeface(ft, path, face_index, variation)?;
+        self.faces.insert(key, face.clone());
+
+        Ok(face)
+    }
+}
+
+/// The typefaces of a run: the fallback chain, starting with the primary typeface, and the
+/// substitute typefaces along with the characters they replace.
+struct Typefaces {
+    faces: Vec<freetype::Face>,
+    substitutions: Vec<(CharacterSet, freetype::Face)>,
+    /// The optical size the primary typeface is set to, if any.
+    optical_size: Option<f32>,
+}
+
+impl Typefaces {
+    /// Determine whether any of the typefaces has a glyph for a code point.
+    fn covers(&self, code_point: usize) -> bool {
+        let substitute_faces = self.substitutions.iter().map(|&(_, ref face)| face);
+        self.faces.iter().chain(substitute_faces).any(|face| face.get_char_index(code_point) != 0)
+    }
+}
+
+/// Open a font file, and check that it has glyphs to sample, setting the design coordinates of
+/// a variable font, if any were given.
+fn load_typeface(
+    ft: &Library, path: &Path, face_index: usize,
+    variation: &[VariationCoordinate]) -> Result<freetype::Face, AppError> {
+
+    match fontgen::validate_font_file(path, face_index) {
+        Ok(()) => {}
+        Err(FontValidationError::FaceIndexOutOfRange(_, num_faces)) => {
+            return Err(AppError::NoSuchFace(path.to_path_buf(), face_index, num_faces));
+        }
+        Err(e) => return Err(AppError::MalformedFontFile(path.to_path_buf(), e)),
+    }
+    // FreeType only opens font files by UTF-8 paths, so the font gets read in here instead.
+    let data = match fs::read(path) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadFontFile(path.to_path_buf(), e)),
+    };
+    let mut face = match ft.new_memory_face(data.clone(), face_index as isize) {
+        Ok(val) => val,
+        Err(e) => {
+            // Validation only counts the faces of TrueType and OpenType files, so ask FreeType how
+            // many faces the other kinds of font files hold.
+            let num_faces = ft.new_memory_face(data, 0).map(|face| face.num_faces().max(1) as usize);
+            return match num_faces {
+                Ok(num_faces) if face_index >= num_faces => {
+                    Err(AppError::NoSuchFace(path.to_path_buf(), face_index, num_faces))
+                }
+                _ => Err(AppError::CouldNotOpenFontFile(path.to_path_buf(), e)),
+            };
+        }
+    };
+    if let Err(e) = fontgen::validate_face(&face) {
+        return Err(AppError::MalformedFontFile(path.to_path_buf(), e));
+    }
+    if !variation.is_empty() {
+        if let Err(e) = fontgen::set_variation(ft, &mut face, variation) {
+            return Err(AppError::CouldNotSetVariation(path.to_path_buf(), e));
+        }
+    }
+
+    Ok(face)
+}
+
+/// Set the optical size of the primary typeface to match glyphs rendered at a multiple of the
+/// glyph size, unless optical sizing is off or `--variation` sets it. This returns the optical
+/// size, if the typeface has an optical size axis.
+fn set_optical_size(
+    ft: &Library, opt: &Opt, typefaces: &mut Typefaces, scale: f32) -> Result<Option<f32>, AppError> {
+
+    let pinned = opt.variation.iter().any(|coordinate| coordinate.tag == "opsz");
+    if opt.optical_sizing == OpticalSizing::None || pinned {
+        return Ok(None);
+    }
+    let optical_size = opt.optical_size(scale);
+    match fontgen::set_optical_size(ft, &mut typefaces.faces[0], &opt.variation, optical_size) {
+        Ok(val) => {
+            typefaces.optical_size = val;
+            Ok(val)
+        }
+        Err(e) => Err(AppError::CouldNotSetVariation(opt.input_path().clone(), e)),
+    }
+}
+
+/// Sample the glyphs of the typefaces for an atlas, drawing each character from its substitute
+/// typeface, or else from the first typeface in the fallback chain that covers it.
+fn sample_typeface(
+    opt: &Opt, typefaces: &Typefaces, spec: AtlasSpec, chars: &CharacterSet,
+    renderer: &Renderer) -> Result<GlyphTable, AppError> {
+
+    // Distance field spreads scale along with the slots of a companion atlas.
+    let scale = spec.slot_glyph_size as f32 / opt.slot_glyph_size() as f32;
+    let overrides: Vec<(CharacterSet, Option<DistanceField>)> = opt.render_modes.iter()
+        .map(|render_mode| {
+            let distance_field = render_mode.mode.distance_field(opt.sdf_spread)
+                .map(|distance_field| distance_field.scaled(scale));
+            (render_mode.chars.clone(), distance_field)
+        })
+        .collect();
+    let cache = match opt.cache_dir {
+        Some(ref cache_dir) => match GlyphCache::open(cache_dir) {
+            Ok(val) => Some(val),
+            Err(e) => return Err(AppError::CouldNotOpenCacheDir(cache_dir.clone(), e)),
+        },
+        None => None,
+    };
+    // Besides the font files and the spec, the face index and the design coordinates of the primary
+    // typeface and the characters drawn from each substitute typeface change the glyphs.
+    let cache_key = cache.as_ref().and_then(|_| {
+        let font_files: Vec<&Path> = opt.input_paths.iter()
+            .chain(opt.substitutions.iter().map(|substitution| &substitution.font_path))
+            .map(|path| path.as_path())
+            .collect();
+        let substitute_chars: Vec<&CharacterSet> = opt.substitutions.iter()
+            .map(|substitution| &substitution.chars)
+            .collect();
+        let options = format!(
+            "{} {:?} {:?} {:?} {:?}",
+            opt.face_index, opt.variation, typefaces.optical_size, substitute_chars, overrides
+        );
+        GlyphCache::key(&font_files, spec, chars, renderer, &options).ok()
+    });
+    if let (Some(cache), Some(key)) = (cache.as_ref(), cache_key.as_ref()) {
+        if let Some(glyph_tab) = cache.load(key) {
+            if opt.verbose > 0 {
+                eprintln!("Reused the glyphs cached in {}.", cache.entry_path(key).display());
+            }
+            return Ok(glyph_tab);
+        }
+    }
+    // A malformed glyph that slips past validation and trips a panic in the Rust code sampling it
+    // fails the run with an error instead of unwinding further. This does not guard against crashes
+    // inside FreeType itself, which still end the process.
+    let threads = opt.threads.unwrap_or(1);
+    let sampled = panic::catch_unwind(AssertUnwindSafe(|| {
+        let faces = &typefaces.faces;
+        if faces.len() > 1 || !typefaces.substitutions.is_empty() {
+            fontgen::sample_typefaces(faces, &typefaces.substitutions, spec, chars, &overrides)
+        } else if overrides.is_empty() && spec.outline > 0 && threads > 1 {
+            // The workers open the typeface anew, so they need the design coordinates of this one.
+            let mut variation = opt.variation.clone();
+            if let Some(optical_size) = typefaces.optical_size {
+                variation.retain(|coordinate| coordinate.tag != "opsz");
+                variation.push(VariationCoordinate { tag: String::from("opsz"), value: optical_size });
+            }
+            let (input_path, face_index) = (opt.input_path(), opt.face_index);
+            fontgen::sample_typeface_parallel(input_path, face_index, &variation, spec, chars, threads)
+        } else if overrides.is_empty() {
+            fontgen::sample_typeface(&faces[0], spec, chars)
+        } else {
+            fontgen::sample_typeface_with_modes(&faces[0], spec, chars, &overrides)
+        }
+    }));
+    let glyph_tab = match sampled {
+        Ok(Ok(val)) => val,
+        Ok(Err(e)) => return Err(AppError::CouldNotCreateBitmapFont(Box::new(e))),
+        Err(_) => return Err(AppError::TypefacePanicked(opt.input_path().clone())),
+    };
+    // The cache only saves time, so a cache that cannot be written to does not fail the run.
+    if let (Some(cache), Some(key)) = (cache.as_ref(), cache_key.as_ref()) {
+        if let Err(e) = cache.store(key, &glyph_tab) {
+            eprintln!("Could not cache the glyphs in {}: {}", cache.entry_path(key).display(), e);
+        }
+    }
+
+    Ok(glyph_tab)
+}
+
+/// Write a single page atlas out to files next to `output_path` in the output format, along
+/// with the standalone atlas image `image_out`, if one was requested. The PNG images get tagged
+/// with the color space.
+fn write_atlas(
+    output_path: &Path, format: OutputFormat, face: &freetype::Face, glyph_tab: &GlyphTable,
+    spec: AtlasSpec, image_out: Option<&PathBuf>, color_space: ColorSpace) -> Result<(), AppError> {
+
+    let atlas = fontgen::create_bitmap_atlas(glyph_tab, spec);
+    match format {
+        OutputFormat::Bmfa => {
+            let atlas_file = output_path.with_extension("bmfa");
+            if fontgen::write_atomically(&atlas_file, |path| bmfa::write_to_file(path, &atlas)).is_err() {
+                return Err(AppError::CouldNotCreateAtlasFile(atlas_file));
+            }
+        }
+        OutputFormat::BmfontText | OutputFormat::BmfontBinary | OutputFormat::BmfontXml => {
+            write_bmfont(output_path, format, glyph_tab, spec, &atlas, color_space)?
+        }
+        OutputFormat::Json => {
+            let image_file = output_path.with_extension("png");
+            if fontgen::write_atlas_image(&image_file, &atlas, color_space).is_err() {
+                return Err(AppError::CouldNotCreateImageFile(image_file));
+            }
+            let layout_file = output_path.with_extension("json");
+            let layout = MsdfAtlasLayout::new(face, glyph_tab, spec);
+            if fontgen::write_msdf_atlas_layout(&layout_file, &layout).is_err() {
+                return Err(AppError::CouldNotCreateDescriptorFile(layout_file));
+            }
+        }
+    }
+
+    if let Some(image_file) = image_out {
+        if fontgen::write_atlas_image(image_file, &atlas, color_space).is_err() {
+            return Err(AppError::CouldNotCreateImageFile(image_file.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The file an atlas written to `output_path` in the output format gets loaded from.
+fn atlas_file(output_path: &Path, format: OutputFormat) -> PathBuf {
+    let extension = match format {
+        OutputFormat::Bmfa => "bmfa",
+        OutputFormat::BmfontText | OutputFormat::BmfontBinary | OutputFormat::BmfontXml => "fnt",
+        OutputFormat::Json => "json",
+    };
+
+    output_path.with_extension(extension)
+}
+
+/// The name of the file an atlas written to `output_path` in the output format gets loaded from.
+fn atlas_file_name(output_path: &Path, format: OutputFormat) -> String {
+    file_name(&atlas_file(output_path, format))
+}
+
+/// The files an atlas written to `output_path` in the output format can occupy: the file it gets
+/// loaded from, the image of the `json` format, and the first page file, which the pages of a
+/// multi-page atlas and the images of the BMFont formats start with. Page numbers get as many
+/// digits as the last page needs, so the first page has a name for each number of digits.
+fn atlas_files(output_path: &Path, format: OutputFormat) -> Vec<PathBuf> {
+    let page_extension = match format {
+        OutputFormat::Bmfa => "bmfa",
+        _ => "png",
+    };
+    let mut atlas_files = vec![atlas_file(output_path, format)];
+    if format == OutputFormat::Json {
+        atlas_files.push(output_path.with_extension("png"));
+    }
+    for digits in 1..=3 {
+        let suffix = format!("_{:0width$}.{}", 0, page_extension, width = digits);
+        atlas_files.push(with_stem_suffix(output_path, &suffix));
+    }
+
+    atlas_files
+}
+
+/// Whether an atlas written to `output_path` in the output format exists already.
+fn atlas_exists(output_path: &Path, format: OutputFormat) -> bool {
+    atlas_files(output_path, format).iter().any(|atlas_file| atlas_file.exists())
+}
+
+/// The file name of a path, for recording in descriptors and metadata. File names that are not
+/// valid UTF-8 get their invalid bytes replaced.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Append a suffix to the file stem of a path, e.g. `font.png` becomes `font_0.png` with the
+/// suffix `_0.png`. The stem is kept as is, even when it is not valid UTF-8.
+fn with_stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_stem().map(|stem| stem.to_os_string()).unwrap_or_default();
+    file_name.push(suffix);
+
+    path.with_file_name(file_name)
+}
+
+/// The atlas specification of a supplement atlas of a locale bundle. A grid gets sized to fit
+/// the characters of the supplement, while packed atlases get sized by their glyphs.
+fn supplement_spec(spec: AtlasSpec, packing: Packing, chars: &CharacterSet) -> AtlasSpec {
+    let mut supplement_spec = spec;
+    if packing == Packing::Grid {
+        let (rows, columns) = chars.grid_size();
+        supplement_spec.rows = rows;
+        supplement_spec.columns = columns;
+        supplement_spec.width = columns * spec.slot_glyph_size;
+        supplement_spec.height = rows * spec.slot_glyph_size;
+    } else {
+        supplement_spec.width = 0;
+        supplement_spec.height = 0;
+    }
+
+    supplement_spec
+}
+
+/// Generate a companion atlas of the same characters at a multiple of the glyph size, and
+/// write it out to `output_path`, along with its own metadata, tagged with the size tier. This
+/// returns the name of the file holding the companion atlas, along with its metrics for the
+/// size report, if one was requested.
+fn write_scaled_atlas(
+    opt: &Opt, typefaces: &Typefaces, spec: AtlasSpec, chars: &CharacterSet, renderer: &Renderer,
+    scale: f32, optical_size: Option<f32>, overrides: &BTreeMap<usize, GlyphOverride>,
+    output_path: &Path,
+    tier: &'static str) -> Result<(String, Option<SizeMetrics>), Box<dyn std::error::Error>> {
+
+    let scaled_spec = match spec.scaled(scale) {
+        Some(val) => val,
+        None => return Err(Box::new(OptError::InvalidScale(format!("{}", scale)))),
+    };
+    let mut scaled_tab = sample_typeface(opt, typefaces, scaled_spec, chars, renderer)?;
+    let compressed_punctuation = if opt.compress_cjk_punctuation {
+        scaled_tab.compress_cjk_punctuation(scaled_spec.render_size())
+    } else {
+        vec![]
+    };
+    let glyph_overrides = scaled_tab.apply_overrides(overrides, scale);
+    let scaled_spec = if opt.auto_size {
+        fontgen::fit_atlas(&scaled_tab, scaled_spec, opt.power_of_two)
+    } else {
+        scaled_spec
+    };
+    let pages = paginate_atlas(opt, &scaled_tab, scaled_spec);
+    let page_files = if pages.len() > 1 {
+        write_pages(opt.format, output_path, None, &scaled_tab, &pages, opt.color_space)?
+    } else {
+        let face = &typefaces.faces[0];
+        write_atlas(output_path, opt.format, face, &scaled_tab, scaled_spec, None, opt.color_space)?;
+
+        vec![]
+    };
+    let size_metrics = if opt.size_report {
+        match fontgen::measure_size_metrics(&typefaces.faces[0], &scaled_tab, scaled_spec, tier, scale) {
+            Ok(val) => Some(val),
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        }
+    } else {
+        None
+    };
+
+    let mut metadata = ExtendedMetadata::new(scaled_spec, renderer, opt.color_space, &scaled_tab);
+    metadata.tier = tier;
+    metadata.scale = scale;
+    metadata.compressed_punctuation = compressed_punctuation;
+    metadata.glyph_overrides = glyph_overrides;
+    metadata.optical_size = optical_size;
+    metadata.rules = Some(fontgen::sample_rule_metrics(&typefaces.faces[0], scaled_spec));
+    record_pages(&mut metadata, &pages, page_files);
+    let metadata_file = output_path.with_extension("meta.json");
+    if fontgen::write_extended_metadata(&metadata_file, &metadata).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateMetadataFile(metadata_file)));
+    }
+
+    Ok((atlas_file_name(output_path, opt.format), size_metrics))
+}
+
+/// Write the effect layers of `--layers` out next to the atlas, with the layer as a suffix, and return
+/// the names of the files of each layer. Each layer gets sampled again without the effects on top of
+/// it, at the same render size, and placed in the images of the glyphs of the atlas, so the layers
+/// have the layout of the atlas.
+fn write_layers(
+    opt: &Opt, typefaces: &Typefaces, spec: AtlasSpec, chars: &CharacterSet, renderer: &Renderer,
+    glyph_tab: &GlyphTable,
+    output_path: &Path) -> Result<BTreeMap<&'static str, Vec<String>>, Box<dyn std::error::Error>> {
+
+    let sample_layer = |with_border: bool| {
+        let mut layer_spec = spec;
+        layer_spec.glyph_size -= spec.shadow.map_or(0, |shadow| shadow.extent());
+        layer_spec.shadow = None;
+        layer_spec.glow = None;
+        layer_spec.channels = AtlasChannels::A8;
+        if !with_border {
+            layer_spec.glyph_size -= 2 * spec.outline;
+            layer_spec.outline = 0;
+        }
+        sample_typeface(opt, typefaces, layer_spec, chars, renderer)
+    };
+    let mut layer_files = BTreeMap::new();
+    for &layer in opt.layers.iter() {
+        let layer_tab = match (layer, spec.shadow) {
+            (EffectLayer::Fill, _) => glyph_tab.layer(&sample_layer(false)?),
+            (EffectLayer::Outline, _) => glyph_tab.layer(&sample_layer(true)?),
+            (EffectLayer::Shadow, Some(shadow)) => {
+                glyph_tab.shadow_layer(&sample_layer(spec.outline > 0)?, shadow)
+            }
+            (EffectLayer::Shadow, None) => continue,
+        };
+        let layer_output = output_path.with_extension(format!("{}.png", layer.name()));
+        let pages = paginate_atlas(opt, &layer_tab, spec);
+        let files = if pages.len() > 1 {
+            write_pages(opt.format, &layer_output, None, &layer_tab, &pages, opt.color_space)?
+        } else {
+            let face = &typefaces.faces[0];
+            write_atlas(&layer_output, opt.format, face, &layer_tab, spec, None, opt.color_space)?;
+
+            vec![atlas_file_name(&layer_output, opt.format)]
+        };
+        layer_files.insert(layer.name(), files);
+    }
+
+    Ok(layer_files)
+}
+
+/// Spread the glyphs of an atlas over pages of at most `--max-size` pixels on either side, trimming
+/// the height of the last page down to its glyphs with `--trim-last-page`. Atlases without
+/// `--max-size` get no pages, and atlases that fit get a single one.
+fn paginate_atlas(opt: &Opt, glyph_tab: &GlyphTable, spec: AtlasSpec) -> Vec<AtlasPage> {
+    let mut pages = match opt.max_size {
+        Some(max_size) => fontgen::paginate(glyph_tab, spec, max_size),
+        None => vec![],
+    };
+    if opt.trim_last_page && pages.len() > 1 {
+        if let Some(last_page) = pages.last_mut() {
+            let trimmed = fontgen::trim_atlas(&last_page.glyph_tab, last_page.spec, opt.power_of_two);
+            if trimmed.height <= last_page.spec.height {
+                last_page.spec = trimmed;
+            }
+        }
+    }
+
+    pages
+}
+
+/// Record the page files of an atlas, and the size of each page and the page of each glyph of a
+/// multi-page atlas, in its metadata.
+fn record_pages(metadata: &mut ExtendedMetadata, pages: &[AtlasPage], page_files: Vec<String>) {
+    metadata.page_files = page_files;
+    if pages.len() > 1 {
+        metadata.page_sizes = pages.iter().map(|page| [page.spec.width, page.spec.height]).collect();
+        for (page_index, page) in pages.iter().enumerate() {
+            for &code_point in page.glyph_tab.buffer.keys() {
+                metadata.glyph_pages.insert(code_point, page_index);
+            }
+        }
+    }
+}
+
+/// Name the page files of a multi-page atlas after the path, numbering them with as many digits
+/// as the last page needs, e.g. `font_00.png`, `font_01.png`, ..., `font_11.png`.
+fn page_file_paths(path: &Path, page_count: usize, extension: &str) -> Vec<PathBuf> {
+    let digits = format!("{}", page_count.saturating_sub(1)).len();
+
+    (0..page_count)
+        .map(|page| with_stem_suffix(path, &format!("_{:0width$}.{}", page, extension, width = digits)))
+        .collect()
+}
+
+/// Write each page of a multi-page atlas out to its own file in the output format, and return
+/// the names of the page files. The PNG images get tagged with the color space.
+fn write_pages(
+    format: OutputFormat, output_path: &Path, image_out: Option<&PathBuf>,
+    glyph_tab: &GlyphTable, pages: &[AtlasPage], color_space: ColorSpace) -> Result<Vec<String>, AppError> {
+
+    let page_paths = match format {
+        OutputFormat::Bmfa => page_file_paths(output_path, pages.len(), "bmfa"),
+        _ => page_file_paths(output_path, pages.len(), "png"),
+    };
+    let page_files: Vec<String> = page_paths.iter().map(|page_path| file_name(page_path)).collect();
+    let image_files = image_out.map(|image_out| page_file_paths(image_out, pages.len(), "png"));
+    for (page_index, page) in pages.iter().enumerate() {
+        let atlas = fontgen::create_bitmap_atlas(&page.glyph_tab, page.spec);
+        let page_path = &page_paths[page_index];
+        match format {
+            OutputFormat::Bmfa => {
+                if fontgen::write_atomically(page_path, |path| bmfa::write_to_file(path, &atlas)).is_err() {
+                    return Err(AppError::CouldNotCreateAtlasFile(page_path.clone()));
+                }
+            }
+            _ => {
+                if fontgen::write_atlas_image(page_path, &atlas, color_space).is_err() {
+                    return Err(AppError::CouldNotCreateImageFile(page_path.clone()));
+                }
+            }
+        }
+        if let Some(ref image_files) = image_files {
+            if fontgen::write_atlas_image(&image_files[page_index], &atlas, color_space).is_err() {
+                return Err(AppError::CouldNotCreateImageFile(image_files[page_index].clone()));
+            }
+        }
+    }
+    match format {
+        OutputFormat::BmfontText | OutputFormat::BmfontBinary | OutputFormat::BmfontXml => {
+            let descriptor = BmfontDescriptor::new_paged(glyph_tab, pages, &page_files);
+            write_bmfont_descriptor(output_path, format, &descriptor)?;
+        }
+        _ => {}
+    }
+
+    Ok(page_files)
+}
+
+/// The calibration strings, with the characters left out of the atlas removed. An atlas without
+/// any of the characters of the strings gets calibrated with a line of its own characters instead.
+fn calibration_strings(chars: &CharacterSet) -> Vec<String> {
+    let strings: Vec<String> = fontgen::CALIBRATION_STRINGS.iter()
+        .map(|text| text.chars().filter(|&ch| chars.contains(ch as usize)).collect::<String>())
+        .filter(|text: &String| !text.trim().is_empty())
+        .collect();
+    if !strings.is_empty() {
+        return strings;
+    }
+
+    vec![
+        chars.code_points().into_iter()
+            .filter_map(|code_point| std::char::from_u32(code_point as u32))
+            .filter(|ch| !ch.is_control())
+            .take(CALIBRATION_LINE_LENGTH)
+            .collect()
+    ]
+}
+
+/// The number of characters in the line an atlas gets calibrated with, when it has none of the
+/// characters of the calibration strings.
+const CALIBRATION_LINE_LENGTH: usize = 40;
+
+/// Run the application with a FreeType library, which can be reused across runs on the same thread.
+fn run_app(ft: &Library, face_cache: &mut FaceCache, opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
+    let renderer = Renderer::freetype(ft);
+    if let Some(ref requirement) = opt.require_renderer {
+        if !requirement.is_satisfied_by(&renderer) {
+            return Err(Box::new(AppError::RendererRequirementNotMet(requirement.clone(), renderer)));
+        }
+    }
+
+    let mut faces = vec![];
+    for input_path in opt.input_paths.iter() {
+        // The face index and the variation apply to the primary typeface.
+        let variation = if faces.is_empty() { &opt.variation[..] } else { &[] };
+        let face_index = if faces.is_empty() { opt.face_index } else { 0 };
+        // Only the primary typeface gets set to the optical size.
+        let optical_sizing = faces.is_empty() && opt.optical_sizing != OpticalSizing::None;
+        faces.push(face_cache.load(ft, input_path, face_index, variation, optical_sizing)?);
+    }
+    let mut substitutions = vec![];
+    for substitution in opt.substitutions.iter() {
+        let face = face_cache.load(ft, &substitution.font_path, 0, &[], false)?;
+        substitutions.push((substitution.chars.clone(), face));
+    }
+    let mut typefaces = Typefaces { faces: faces, substitutions: substitutions, optical_size: None };
+    let optical_size = set_optical_size(ft, opt, &mut typefaces, 1.0)?;
+    // The primary typeface provides the line metrics, outlines, and font units.
+    let face = &typefaces.faces[0];
+
+    // BMFont page images are always stored top down.
+    let origin = match opt.format {
+        OutputFormat::Bmfa => opt.origin,
+        _ => bmfa::Origin::TopLeft,
+    };
+    let slot_glyph_size = opt.slot_glyph_size();
+    let selected_chars = select_chars(&opt.chars, &opt.preset, &opt.charset_file)?;
+    let strings = match opt.measure_strings {
+        Some(ref strings_file) => read_strings_file(strings_file)?,
+        None => vec![],
+    };
+    let overrides = match opt.advance_overrides {
+        Some(ref overrides_file) => read_overrides_file(overrides_file)?,
+        None => BTreeMap::new(),
+    };
+    // The base atlas of a locale bundle holds the Latin characters, unless others were selected.
+    let selected_chars = match selected_chars {
+        None if opt.all_glyphs => Some(fontgen::all_glyph_indices(face)),
+        None if !opt.bundle.is_empty() => {
+            Some(CharacterSet::bundle_base().filter(|code_point| opt.last_resort || typefaces.covers(code_point)))
+        }
+        selected_chars => selected_chars,
+    };
+    // Concurrent runs writing the same output files take turns. The outputs get locked in order,
+    // so two runs never each hold a lock the other one waits for.
+    let outputs = vec![Some(&opt.output_path), opt.image_out.as_ref(), opt.coverage_report.as_ref()];
+    let mut locked_paths: Vec<&PathBuf> = outputs.into_iter().flatten().collect();
+    // Outputs sharing a file stem in the same directory share a lock.
+    locked_paths.sort_by_key(|path| path.with_extension(""));
+    locked_paths.dedup_by_key(|path| path.with_extension(""));
+    let lock_timeout = Duration::from_secs(opt.wait_for_lock.unwrap_or(0));
+    let mut output_locks = vec![];
+    for locked_path in locked_paths.into_iter() {
+        match OutputLock::acquire(locked_path, lock_timeout) {
+            Ok(val) => output_locks.push(val),
+            Err(e) => return Err(Box::new(AppError::CouldNotLockOutputFiles(locked_path.clone(), e))),
+        }
+    }
+    // Characters no typeface covers would come out as the missing glyph of the primary typeface,
+    // unless a fallback glyph stands in for them. Zero width and control characters never get a
+    // glyph of their own.
+    let requested_chars = selected_chars.clone().unwrap_or_default();
+    let glyph_chars = requested_chars.filter(|code_point| {
+        let control = std::char::from_u32(code_point as u32).map_or(false, |ch| ch.is_control());
+        !control && !opt.zero_width.contains(code_point)
+    });
+    let missing_chars = if opt.all_glyphs {
+        CharacterSet::default()
+    } else {
+        glyph_chars.filter(|code_point| !typefaces.covers(code_point))
+    };
+    // Every file of the run gets staged, and moved into place together once all of them are written.
+    // With `--backup`, the files they replace get kept as `.bak` files.
+    let mut transaction = OutputTransaction::new();
+    transaction.keep_backups(opt.backup);
+    if let Some(ref coverage_report_file) = opt.coverage_report {
+        let staged_report_file = match transaction.stage(coverage_report_file) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotStageOutputFiles(e))),
+        };
+        let requested = glyph_chars.len();
+        let report = CoverageReport {
+            family: face.family_name().unwrap_or_default(),
+            requested: requested,
+            covered: requested - missing_chars.len(),
+            missing: missing_chars.code_points(),
+            on_missing: match opt.on_missing {
+                MissingGlyphs::Draw => "drawn",
+                MissingGlyphs::Skip => "skipped",
+                MissingGlyphs::Fail => "failed",
+            },
+        };
+        if fontgen::write_coverage_report(&staged_report_file, &report).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateCoverageReportFile(coverage_report_file.clone())));
+        }
+    }
+    let boxed_chars = missing_chars.filter(|code_point| {
+        !opt.last_resort && !(opt.builtin_fallback && fontgen::builtin_fallback_covers(code_point))
+    });
+    let selected_chars = match opt.on_missing {
+        _ if boxed_chars.is_empty() => selected_chars,
+        MissingGlyphs::Draw => {
+            eprintln!(
+                "The typefaces have no glyphs for {} of the requested characters, which get drawn with \
+                the missing glyph: {}",
+                boxed_chars.len(), format_ranges(&boxed_chars).join(", ")
+            );
+            selected_chars
+        }
+        MissingGlyphs::Skip => {
+            eprintln!(
+                "Left out {} characters the typefaces have no glyphs for: {}",
+                boxed_chars.len(), format_ranges(&boxed_chars).join(", ")
+            );
+            Some(requested_chars.difference(&boxed_chars))
+        }
+        MissingGlyphs::Fail => {
+            // The coverage report says why the run failed, so it gets written all the same.
+            if let Err(e) = transaction.commit() {
+                return Err(Box::new(AppError::CouldNotCommitOutputFiles(e)));
+            }
+            return Err(Box::new(AppError::MissingGlyphs(boxed_chars)));
+        }
+    };
+    let (atlas_rows, atlas_columns) = match selected_chars {
+        Some(ref chars) => chars.grid_size(),
+        None => (16, 16),
+    };
+    let chars = selected_chars.unwrap_or_default();
+    // Packed atlases get sized by their glyphs.
+    let (atlas_width_px, atlas_height_px) = match opt.packing {
+        Packing::Grid => (slot_glyph_size * atlas_columns, slot_glyph_size * atlas_rows),
+        _ => (0, 0),
+    };
+    let padding_px = opt.padding;
+    let atlas_glyph_px = slot_glyph_size - padding_px;
+    let stretch = opt.stretch;
+    let output_path = match transaction.stage(&opt.output_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotStageOutputFiles(e))),
+    };
+    let image_out = match opt.image_out.as_ref().map(|image_out| transaction.stage(image_out)) {
+        Some(Ok(val)) => Some(val),
+        Some(Err(e)) => return Err(Box::new(AppError::CouldNotStageOutputFiles(e))),
+        None => None,
+    };
+    let mut metadata_file = output_path.clone();
+    metadata_file.set_extension("meta.json");
+    let mut parameters_file = output_path.clone();
+    parameters_file.set_extension("fontgen.toml");
+    let mut outlines_file = output_path.clone();
+    outlines_file.set_extension("outlines.json");
+    let mut curves_index_file = output_path.clone();
+    curves_index_file.set_extension("curves.json");
+    let mut curves_texture_file = output_path.clone();
+    curves_texture_file.set_extension("curves.bin");
+    let mut lookup_file = output_path.clone();
+    lookup_file.set_extension("lookup.bin");
+    let mut hinting_file = output_path.clone();
+    hinting_file.set_extension("hinting.png");
+    let mut size_report_file = output_path.clone();
+    size_report_file.set_extension("sizes.json");
+    let mut preview_file = output_path.clone();
+    preview_file.set_extension("preview.html");
+    let mut calibration_file = output_path.clone();
+    calibration_file.set_extension("calibration.json");
+    let mut bundle_file = output_path.clone();
+    bundle_file.set_extension("bundle.json");
+    let mut string_metrics_file = output_path.clone();
+    string_metrics_file.set_extension("strings.json");
+    let mut texture_array_file = output_path.clone();
+    texture_array_file.set_extension("ktx2");
+
+    let distance_field = if opt.msdf {
+        Some(DistanceField::Msdf { spread: opt.sdf_spread })
+    } else if opt.sdf {
+        Some(DistanceField::Sdf { spread: opt.sdf_spread })
+    } else {
+        None
+    };
+    let mut atlas_spec = AtlasSpec::new(
+        origin, atlas_width_px, atlas_height_px,
+        atlas_rows, atlas_columns, padding_px, slot_glyph_size, atlas_glyph_px,
+        stretch, opt.synthetic_small_caps, opt.last_resort, opt.zero_width,
+        distance_field, opt.packing
+    );
+    atlas_spec.point_size = opt.point_size();
+    atlas_spec.builtin_fallback = opt.builtin_fallback;
+    atlas_spec.mono = opt.mono();
+    atlas_spec.channels = opt.channels;
+    atlas_spec.gamma = opt.gamma;
+    atlas_spec.glyph_indices = opt.all_glyphs;
+    atlas_spec.resample_filter = opt.resample_filter;
+    atlas_spec.oblique = opt.oblique.unwrap_or(0.0);
+    atlas_spec.embolden = opt.embolden.unwrap_or(0.0);
+    atlas_spec.outline = opt.outline.unwrap_or(0);
+    atlas_spec.shadow = opt.shadow;
+    atlas_spec.glow = opt.glow;
+    if let Some(ruby_scale) = opt.ruby_scale {
+        if atlas_spec.scaled(ruby_scale).is_none() {
+            return Err(Box::new(OptError::InvalidRubyScale(ruby_scale)));
+        }
+    }
+    if let Some(&scale) = opt.scales.iter().find(|&&scale| atlas_spec.scaled(scale).is_none()) {
+        return Err(Box::new(OptError::InvalidScale(format!("{}", scale))));
+    }
+    if let Some(hinting) = opt.calibrate {
+        let strings = calibration_strings(&chars);
+        let report = match fontgen::calibrate_coverage(face, atlas_spec, hinting, &strings) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        };
+        eprintln!("Calibrated the atlas against {} hinting with a gamma of {}", hinting.name(), report.best_gamma);
+        atlas_spec.gamma = report.best_gamma;
+        if fontgen::write_calibration_report(&calibration_file, &report).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateCalibrationFile(calibration_file)));
+        }
+    }
+    let glyph_tab = sample_typeface(opt, &typefaces, atlas_spec, &chars, &renderer)?;
+    report_telemetry(&glyph_tab, opt.verbose);
+    #[cfg(feature = "scripting")]
+    let glyph_tab = {
+        let mut glyph_tab = glyph_tab;
+        if let Some(ref script_path) = opt.glyph_script {
+            let script = GlyphScript::load(script_path)?;
+            script.apply(&mut glyph_tab)?;
+        }
+
+        glyph_tab
+    };
+    let mut glyph_tab = glyph_tab;
+    let compressed_punctuation = if opt.compress_cjk_punctuation {
+        glyph_tab.compress_cjk_punctuation(atlas_spec.render_size())
+    } else {
+        vec![]
+    };
+    let glyph_overrides = glyph_tab.apply_overrides(&overrides, 1.0);
+    if !glyph_tab.empty.is_empty() {
+        let code_points: Vec<String> = glyph_tab.empty.iter()
+            .map(|&code_point| {
+                if opt.all_glyphs {
+                    format!("glyph {}", code_point)
+                } else {
+                    format!("U+{:04X}", code_point)
+                }
+            })
+            .collect();
+        eprintln!(
+            "Excluded {} glyphs with empty bitmaps from the atlas image: {}",
+            code_points.len(), code_points.join(", ")
+        );
+    }
+    let atlas_spec = if opt.auto_size {
+        let fitted = fontgen::fit_atlas(&glyph_tab, atlas_spec, opt.power_of_two);
+        eprintln!("Atlas size: {}x{} pixels", fitted.width, fitted.height);
+
+        fitted
+    } else {
+        atlas_spec
+    };
+    let pages = paginate_atlas(opt, &glyph_tab, atlas_spec);
+    let page_files = if pages.len() > 1 {
+        eprintln!("Spread the atlas over {} pages", pages.len());
+        write_pages(opt.format, &output_path, image_out.as_ref(), &glyph_tab, &pages, opt.color_space)?
+    } else {
+        write_atlas(
+            &output_path, opt.format, face, &glyph_tab, atlas_spec, image_out.as_ref(), opt.color_space
+        )?;
+
+        vec![]
+    };
+    if opt.texture_array {
+        let images: Vec<_> = if pages.len() > 1 {
+            pages.iter().map(|page| fontgen::create_bitmap_image(&page.glyph_tab, page.spec)).collect()
+        } else {
+            vec![fontgen::create_bitmap_image(&glyph_tab, atlas_spec)]
+        };
+        if fontgen::write_texture_array(&texture_array_file, &images, opt.color_space).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateTextureArrayFile(texture_array_file)));
+        }
+    }
+    let layer_files = write_layers(opt, &typefaces, atlas_spec, &chars, &renderer, &glyph_tab, &output_path)?;
+    if opt.measure_strings.is_some() {
+        let report = fontgen::measure_strings(&glyph_tab, &strings);
+        if fontgen::write_string_metrics(&string_metrics_file, &report).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateStringMetricsFile(string_metrics_file)));
+        }
+    }
+    // The main atlas gets measured before the ruby atlas changes the optical size.
+    let mut size_metrics = vec![];
+    if opt.size_report {
+        match fontgen::measure_size_metrics(face, &glyph_tab, atlas_spec, "base", 1.0) {
+            Ok(val) => size_metrics.push(val),
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        }
+    }
+
+    // The ruby annotation glyphs get written next to the atlas with a `.ruby` suffix.
+    // Ruby annotations get set physically smaller than the body text, so they get the optical
+    // size of their own glyph size.
+    let ruby_atlas = match opt.ruby_scale {
+        Some(ruby_scale) => {
+            let ruby_output = output_path.with_extension("ruby.png");
+            let ruby_optical_size = set_optical_size(ft, opt, &mut typefaces, ruby_scale)?;
+            let (ruby_atlas, ruby_metrics) = write_scaled_atlas(
+                opt, &typefaces, atlas_spec, &chars, &renderer,
+                ruby_scale, ruby_optical_size, &overrides, &ruby_output, "ruby"
+            )?;
+            set_optical_size(ft, opt, &mut typefaces, 1.0)?;
+            size_metrics.extend(ruby_metrics);
+            Some(ruby_atlas)
+        }
+        None => None,
+    };
+    // The atlases for high resolution displays get the usual `@2x` suffixes. Their glyphs show
+    // up at the same physical size as the ones of the main atlas, so they keep its optical size.
+    let mut scaled_atlases = vec![];
+    for &scale in opt.scales.iter().filter(|&&scale| scale != 1.0) {
+        let scaled_output = with_stem_suffix(&output_path, &format!("@{}x.png", scale));
+        let (scaled_atlas, scaled_metrics) = write_scaled_atlas(
+            opt, &typefaces, atlas_spec, &chars, &renderer,
+            scale, optical_size, &overrides, &scaled_output, "base"
+        )?;
+        scaled_atlases.push(scaled_atlas);
+        size_metrics.extend(scaled_metrics);
+    }
+    // The supplement atlas of each locale of a bundle gets written next to the base atlas with
+    // the locale as a suffix. They leave out the characters of the base atlas, along with the
+    // characters no typeface covers, which would only draw the same missing glyph box.
+    if !opt.bundle.is_empty() {
+        let base = BundleAtlas {
+            locale: None,
+            atlas: atlas_file_name(&output_path, opt.format),
+            metadata: file_name(&metadata_file),
+            base: None,
+            chars: format_ranges(&chars),
+        };
+        let mut locales = vec![];
+        for &locale in opt.bundle.iter() {
+            let supplement_chars = CharacterSet::locale_supplement(locale, &chars)
+                .unwrap_or_default()
+                .filter(|code_point| opt.last_resort || typefaces.covers(code_point));
+            if supplement_chars.is_empty() {
+                eprintln!(
+                    "The typefaces do not cover any characters of the {} locale beyond the base atlas.",
+                    locale
+                );
+                continue;
+            }
+            let supplement_output = output_path.with_extension(format!("{}.png", locale));
+            let supplement_spec = supplement_spec(atlas_spec, opt.packing, &supplement_chars);
+            let (supplement_atlas, _) = write_scaled_atlas(
+                opt, &typefaces, supplement_spec, &supplement_chars, &renderer,
+                1.0, optical_size, &overrides, &supplement_output, "base"
+            )?;
+            locales.push(BundleAtlas {
+                locale: Some(String::from(locale)),
+                atlas: supplement_atlas,
+                metadata: file_name(&supplement_output.with_extension("meta.json")),
+                base: Some(base.atlas.clone()),
+                chars: format_ranges(&supplement_chars),
+            });
+        }
+        let manifest = BundleManifest { base: base, locales: locales };
+        if fontgen::write_bundle_manifest(&bundle_file, &manifest).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateBundleFile(bundle_file)));
+        }
+    }
+    let face = &typefaces.faces[0];
+    if opt.size_report {
+        let size_report = SizeReport {
+            family: face.family_name().unwrap_or_default(),
+            units_per_em: face.raw().units_per_EM,
+            sizes: size_metrics,
+        };
+        if fontgen::write_size_report(&size_report_file, &size_report).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateSizeReportFile(size_report_file)));
+        }
+    }
+
+    let mut extended_metadata = ExtendedMetadata::new(atlas_spec, &renderer, opt.color_space, &glyph_tab);
+    extended_metadata.ruby_atlas = ruby_atlas;
+    extended_metadata.optical_size = optical_size;
+    extended_metadata.rules = Some(fontgen::sample_rule_metrics(face, atlas_spec));
+    extended_metadata.scaled_atlases = scaled_atlases;
+    extended_metadata.layer_files = layer_files;
+    if opt.texture_array {
+        extended_metadata.texture_array = Some(file_name(&texture_array_file));
+    }
+    extended_metadata.compressed_punctuation = compressed_punctuation;
+    extended_metadata.glyph_overrides = glyph_overrides;
+    record_pages(&mut extended_metadata, &pages, page_files);
+    if opt.all_glyphs {
+        extended_metadata.glyph_code_points = fontgen::glyph_code_points(face);
+    }
+    if opt.font_units {
+        match fontgen::sample_font_unit_metrics(face, &glyph_tab) {
+            Ok(font_units) => extended_metadata.font_units = Some(font_units),
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        }
+    }
+    if fontgen::write_extended_metadata(&metadata_file, &extended_metadata).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateMetadataFile(metadata_file)));
+    }
+
+    if opt.preview_html {
+        let atlases: Vec<_> = if pages.len() > 1 {
+            pages.iter().map(|page| fontgen::create_bitmap_atlas(&page.glyph_tab, page.spec)).collect()
+        } else {
+            vec![fontgen::create_bitmap_atlas(&glyph_tab, atlas_spec)]
+        };
+        let metrics = TextMetrics {
+            advances: extended_metadata.advances.clone(),
+            kerning: extended_metadata.kerning.clone(),
+            rules: extended_metadata.rules,
+        };
+        let title = file_name(&opt.output_path);
+        if fontgen::write_preview_html(&preview_file, &title, &atlases, &metrics).is_err() {
+            return Err(Box::new(AppError::CouldNotCreatePreviewFile(preview_file)));
+        }
+    }
+
+    if opt.export_outlines.is_some() {
+        let outlines = match fontgen::extract_glyph_outlines(face, &glyph_tab) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        };
+        if fontgen::write_glyph_outlines(&outlines_file, &outlines).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateOutlinesFile(outlines_file)));
+        }
+    }
+
+    if opt.export_curves {
+        let curve_atlas = match fontgen::extract_curve_atlas(face, &glyph_tab) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        };
+        if fontgen::write_curve_atlas(&curves_index_file, &curves_texture_file, &curve_atlas).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateCurvesFile(curves_texture_file)));
+        }
+    }
+
+    if opt.export_lookup {
+        let lookup_table = if pages.len() > 1 {
+            GlyphLookupTable::new_paged(&glyph_tab, &pages)
+        } else {
+            GlyphLookupTable::new(&glyph_tab, atlas_spec)
+        };
+        if fontgen::write_glyph_lookup_table(&lookup_file, &lookup_table).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateLookupFile(lookup_file)));
+        }
+    }
+
+    if opt.compare_hinting {
+        let comparison = match fontgen::render_hinting_comparison(
+            face, atlas_spec.render_size(), fontgen::HINTING_SAMPLE_TEXT) {
+
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        };
+        if fontgen::write_hinting_comparison(&hinting_file, &comparison).is_err() {
+            return Err(Box::new(AppError::CouldNotCreateHintingComparisonFile(hinting_file)));
+        }
+    }
+
+    let parameters = GenerationParameters::new(opt, atlas_spec, &chars, &renderer);
+    if write_generation_parameters(&parameters_file, &parameters).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateParametersFile(parameters_file)));
+    }
+    if let Err(e) = transaction.commit() {
+        return Err(Box::new(AppError::CouldNotCommitOutputFiles(e)));
+    }
+
+    Ok(())
+}
+
+/// How often watch mode checks the watched files for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The files whose changes make watch mode write the atlases again.
+fn watched_files(opts: &[Opt], config_file: Option<&PathBuf>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = config_file.into_iter().cloned().collect();
+    for opt in opts.iter() {
+        files.extend(opt.input_paths.iter().cloned());
+        files.extend(opt.substitutions.iter().map(|substitution| substitution.font_path.clone()));
+        files.extend(opt.charset_file.clone());
+        files.extend(opt.measure_strings.clone());
+        files.extend(opt.advance_overrides.clone());
+        #[cfg(feature = "scripting")]
+        files.extend(opt.glyph_script.clone());
+    }
+    files.sort();
+    files.dedup();
+
+    files
+}
+
+/// The modification time of each file, or `None` for the files that cannot be read at the moment,
+/// e.g. while an editor replaces them.
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter()
+        .map(|file| fs::metadata(file).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+/// Wait for some of the files to change, and then for all of them to settle, since editors often
+/// save a file in several steps.
+fn wait_for_changes(files: &[PathBuf], last_modified: &mut Vec<Option<SystemTime>>) {
+    let mut changed = false;
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let modified = modification_times(files);
+        if modified != *last_modified {
+            *last_modified = modified;
+            changed = true;
+        } else if changed {
+            return;
+        }
+    }
+}
+
+/// Write the atlases, and then write them again every time one of the watched files changes. A
+/// changed config file gets read again first. A failed run, or a config file that no longer
+/// parses, gets reported without ending the watch, so a half saved font file or a mistake in the
+/// character set file can get fixed in place.
+fn watch(args: &[OsString], mut opts: Vec<Opt>) -> Result<(), Box<dyn std::error::Error>> {
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let config_file = opts.first().and_then(|opt| opt.config.clone());
+    loop {
+        let files = watched_files(&opts, config_file.as_ref());
+        let mut last_modified = modification_times(&files);
+        // The font files may have changed since the last pass, so each pass loads them again.
+        let mut face_cache = FaceCache::default();
+        for opt in opts.iter() {
+            let start = Instant::now();
+            match run_app(&ft, &mut face_cache, opt) {
+                Ok(()) => {
+                    let elapsed = start.elapsed().as_secs_f64();
+                    eprintln!("Wrote {} in {:.3} s.", opt.output_path.display(), elapsed);
+                }
+                Err(e) => eprintln!("Error: {}", e),
+            }
+        }
+        eprintln!("Watching {} files for changes.", files.len());
+        wait_for_changes(&files, &mut last_modified);
+        if let Some(ref config_file) = config_file {
+            // The atlases only get written again once the config file parses.
+            loop {
+                match reload_config(config_file, args) {
+                    Ok(val) => {
+                        opts = val;
+                        break;
+                    }
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+                wait_for_changes(&files, &mut last_modified);
+            }
+        }
+    }
+}
+
+/// Read the options of the atlases of a config file again while watching it. Unlike the first
+/// time, mistakes in the options get reported instead of ending the process.
+fn reload_config(config_file: &Path, args: &[OsString]) -> Result<Vec<Opt>, Box<dyn std::error::Error>> {
+    let mut opts = vec![];
+    for atlas_args in read_config(config_file, &args[1..])?.into_iter() {
+        let opt = match Opt::from_iter_safe(atlas_args) {
+            Ok(val) => val,
+            Err(e) => {
+                let message = e.message.trim_start_matches("error: ").to_string();
+                return Err(Box::new(AppError::InvalidConfigFile(config_file.to_path_buf(), message)));
+            }
+        };
+        verify_settings(&opt)?;
+        opts.push(opt);
+    }
+
+    Ok(opts)
+}
+
+/// The short names of the options that have one, so the options given on the command line
+/// replace the same options of a config file whichever name they go by.
+const SHORT_OPTION_NAMES: [(&str, &str); 4] = [
+    ("-i", "--input"), ("-o", "--output"), ("-p", "--padding"), ("-v", "--verbose")
+];
+
+/// Find the config file passed on the command line, if any. This has to happen before the rest of
+/// the arguments get parsed, since the config file can hold required options like `--input`.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if arg.starts_with("--config=") {
+            return Some(PathBuf::from(&arg["--config=".len()..]));
+        }
+    }
+
+    None
+}
+
+/// Append the arguments for an option of a config file. Strings and numbers become the value of
+/// the option, arrays a value for each element, and `true` a flag without a value.
+fn append_config_option(
+    atlas_args: &mut Vec<OsString>, key: &str, value: &toml::Value) -> Result<(), String> {
+
+    let option = format!("--{}", key.replace('_', "-"));
+    let values = match *value {
+        toml::Value::Boolean(false) => return Ok(()),
+        toml::Value::Boolean(true) => {
+            atlas_args.push(OsString::from(option));
+            return Ok(());
+        }
+        toml::Value::Array(ref elements) => elements.iter().collect(),
+        _ => vec![value],
+    };
+    // Each value goes with its own option, joined by `=` so that negative numbers can't pass for
+    // options themselves. An empty array gives no option at all.
+    for value in values.into_iter() {
+        match *value {
+            toml::Value::String(ref string) => atlas_args.push(format!("{}={}", option, string).into()),
+            toml::Value::Integer(integer) => atlas_args.push(format!("{}={}", option, integer).into()),
+            toml::Value::Float(float) => atlas_args.push(format!("{}={}", option, float).into()),
+            _ => {
+                return Err(format!(
+                    "The option {} must be a string, a number, a boolean, or an array of them.", key
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a config file, and turn each atlas it describes into the command line arguments of a run.
+/// The options of an atlas replace the shared ones at the top level of the file, and the options
+/// in `cli_args` replace both.
+fn read_config(config_file: &Path, cli_args: &[OsString]) -> Result<Vec<Vec<OsString>>, AppError> {
+    let text = match fs::read_to_string(config_file) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadConfigFile(config_file.to_path_buf(), e)),
+    };
+    let is_json = config_file.extension().map_or(false, |extension| extension == "json");
+    let parsed = if is_json {
+        serde_json::from_str::<toml::Value>(&text).map_err(|e| e.to_string())
+    } else {
+        toml::from_str::<toml::Value>(&text).map_err(|e| e.to_string())
+    };
+    let invalid = |e: String| AppError::InvalidConfigFile(config_file.to_path_buf(), e);
+    let mut shared = match parsed {
+        Ok(toml::Value::Table(val)) => val,
+        Ok(_) => return Err(invalid(String::from("It must hold a table of options."))),
+        Err(e) => return Err(invalid(e)),
+    };
+    let atlases = match shared.remove("atlas") {
+        Some(toml::Value::Array(atlases)) => atlases,
+        Some(_) => return Err(invalid(String::from("The atlas key must hold an array of tables."))),
+        None => vec![toml::Value::Table(toml::value::Table::new())],
+    };
+    // The command line only has to name an option to replace it, in either form.
+    let cli_options: Vec<String> = cli_args.iter()
+        .filter_map(|arg| arg.to_str())
+        .filter(|arg| arg.starts_with('-'))
+        .map(|arg| {
+            let name = arg.splitn(2, '=').next().unwrap_or_default();
+            SHORT_OPTION_NAMES.iter()
+                .find(|&&(short_name, _)| short_name == name)
+                .map_or(name, |&(_, long_name)| long_name)
+                .to_string()
+        })
+        .collect();
+
+    let mut arg_lists = vec![];
+    for atlas in atlases.into_iter() {
+        let mut options = shared.clone();
+        match atlas {
+            toml::Value::Table(atlas) => options.extend(atlas),
+            _ => return Err(invalid(String::from("The atlas key must hold an array of tables."))),
+        }
+        let mut atlas_args = vec![OsString::from("fontgen generate")];
+        for (key, value) in options.iter() {
+            // The provenance table of a `.fontgen.toml` file records how an atlas came about.
+            let overridden = cli_options.contains(&format!("--{}", key.replace('_', "-")));
+            if key == "config" || key == "provenance" || overridden {
+                continue;
+            }
+            append_config_option(&mut atlas_args, key, value).map_err(|e| invalid(e))?;
+        }
+        atlas_args.extend(cli_args.iter().cloned());
+        arg_lists.push(atlas_args);
+    }
+
+    Ok(arg_lists)
+}
+
+/// The options of a single atlas that name its files, or that cannot be shared by every atlas of a
+/// batch. Batches name the input and output files of each atlas themselves.
+const BATCH_EXCLUDED_OPTIONS: [&str; 7] = [
+    "--input", "-i", "--output", "-o", "--image-out", "--coverage-report", "--watch"
+];
+
+/// The extensions of the font files `fontgen batch` picks up, including the TrueType and OpenType
+/// collections, which it generates the atlas of the `--face-index` face of.
+const FONT_FILE_EXTENSIONS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+
+/// Find the `.ttf`, `.otf`, `.ttc`, and `.otc` files in a directory, sorted by file name.
+fn find_font_files(fonts_dir: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let entries = match fs::read_dir(fonts_dir) {
+        Ok(val) => val,
+        Err(e) => return Err(AppError::CouldNotReadFontsDir(fonts_dir.to_path_buf(), e)),
+    };
+    let mut font_files = vec![];
+    for entry in entries {
+        let path = match entry {
+            Ok(val) => val.path(),
+            Err(e) => return Err(AppError::CouldNotReadFontsDir(fonts_dir.to_path_buf(), e)),
+        };
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        if path.is_file() && FONT_FILE_EXTENSIONS.iter().any(|font| extension.eq_ignore_ascii_case(font)) {
+            font_files.push(path);
+        }
+    }
+    font_files.sort();
+
+    Ok(font_files)
+}
+
+/// Generate an atlas from every font in a directory with the same options. The atlases get
+/// generated on several threads at once, each with its own FreeType library, since a FreeType
+/// library cannot be shared between threads. An atlas that fails gets reported without stopping
+/// the others.
+fn run_batch(batch_opt: &BatchOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let excluded = batch_opt.options.iter()
+        .filter_map(|option| option.to_str())
+        .filter_map(|option| {
+            BATCH_EXCLUDED_OPTIONS.iter()
+                .find(|&&excluded| option == excluded || option.starts_with(&format!("{}=", excluded)))
+        })
+        .next();
+    if let Some(&option) = excluded {
+        return Err(Box::new(OptError::BatchOption(option)));
+    }
+    if !batch_opt.fonts_dir.is_dir() {
+        return Err(Box::new(OptError::FontsDirDoesNotExist(batch_opt.fonts_dir.clone())));
+    }
+    let font_files = find_font_files(&batch_opt.fonts_dir)?;
+    if font_files.is_empty() {
+        return Err(Box::new(AppError::NoFontFiles(batch_opt.fonts_dir.clone())));
+    }
+    // Font files differing only in their extension would write the same atlas.
+    let mut stems: Vec<_> = font_files.iter().map(|font_file| font_file.file_stem()).collect();
+    stems.sort();
+    if let Some(stem) = stems.windows(2).find(|pair| pair[0] == pair[1]).and_then(|pair| pair[0]) {
+        return Err(Box::new(AppError::DuplicateAtlasName(batch_opt.fonts_dir.join(stem))));
+    }
+    let mut jobs = vec![];
+    for font_file in font_files.into_iter() {
+        let mut atlas_name = font_file.file_stem().unwrap_or_default().to_os_string();
+        atlas_name.push(".bmfa");
+        let output_path = batch_opt.out_dir.join(atlas_name);
+        let args = vec![
+            OsString::from("fontgen generate"),
+            OsString::from("--input"), font_file.clone().into_os_string(),
+            OsString::from("--output"), output_path.into_os_string(),
+        ];
+        let opt = Opt::from_iter(args.into_iter().chain(batch_opt.options.iter().cloned()));
+        if skips_existing_atlas(&opt) {
+            let atlas_file = atlas_file(&opt.output_path, opt.format);
+            eprintln!("Skipping {}, which exists already.", atlas_file.display());
+            continue;
+        }
+        jobs.push((font_file, opt));
+    }
+    if let Err(e) = fs::create_dir_all(&batch_opt.out_dir) {
+        return Err(Box::new(AppError::CouldNotCreateOutDir(batch_opt.out_dir.clone(), e)));
+    }
+    let total = jobs.len();
+    let thread_count = batch_opt.jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |count| count.get()))
+        .max(1)
+        .min(total);
+    let start = Instant::now();
+    let queue = Arc::new(Mutex::new(jobs.into_iter()));
+    // Every atlas that does not get written counts as failed, so the fonts a worker thread took
+    // before it panicked, and the ones left in the queue when every worker died, fail the batch.
+    let written = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..thread_count)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let written = Arc::clone(&written);
+            thread::spawn(move || {
+                let ft = Library::init().expect("Failed to initialize FreeType library.");
+                let mut face_cache = FaceCache::default();
+                loop {
+                    let job = queue.lock().map(|mut queue| queue.next()).unwrap_or(None);
+                    let (font_file, opt) = match job {
+                        Some(val) => val,
+                        None => break,
+                    };
+                    let atlas_start = Instant::now();
+                    let result = match verify_opt(&opt) {
+                        Ok(()) => run_app(&ft, &mut face_cache, &opt),
+                        Err(e) => Err(Box::new(e) as Box<dyn std::error::Error>),
+                    };
+                    match result {
+                        Ok(()) => {
+                            written.fetch_add(1, Ordering::SeqCst);
+                            eprintln!(
+                                "Wrote {} in {:.3} s.",
+                                opt.output_path.display(), atlas_start.elapsed().as_secs_f64()
+                            );
+                        }
+                        Err(e) => eprintln!("Error: {}: {}", font_file.display(), e),
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers.into_iter() {
+        if worker.join().is_err() {
+            eprintln!("Error: A worker thread panicked, so the atlas it was generating was not written.");
+        }
+    }
+    let failed = total - written.load(Ordering::SeqCst);
+    eprintln!(
+        "Wrote {} of {} atlases in {:.3} s.", total - failed, total, start.elapsed().as_secs_f64()
+    );
+    if failed > 0 {
+        return Err(Box::new(AppError::BatchFailed(failed, total)));
+    }
+
+    Ok(())
+}
+
+/// The file recording the build key an atlas was last built with by `fontgen build`, next to it.
+fn build_key_path(output_path: &Path) -> PathBuf {
+    output_path.with_extension("build-key")
+}
+
+/// Generate the atlases of a config file that are out of date, like `make` does: the ones
+/// missing, and the ones whose font files, character set files, or other input files changed
+/// since they were last built, or whose options or renderer did. Each atlas gets its build key,
+/// a hash of all of those, recorded next to it once it is written.
+fn run_build(build_opt: &BuildOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = &build_opt.config;
+    let mut opts = vec![];
+    for atlas_args in read_config(config_file, &[])?.into_iter() {
+        let opt = match Opt::from_iter_safe(atlas_args.iter().cloned()) {
+            Ok(val) => val,
+            Err(e) => {
+                let message = e.message.trim_start_matches("error: ").to_string();
+                return Err(Box::new(AppError::InvalidConfigFile(config_file.to_path_buf(), message)));
+            }
+        };
+        verify_settings(&opt)?;
+        opts.push((atlas_args, opt));
+    }
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let renderer = Renderer::freetype(&ft);
+    let start = Instant::now();
+    let mut face_cache = FaceCache::default();
+    let total = opts.len();
+    let mut built = 0;
+    for (atlas_args, mut opt) in opts.into_iter() {
+        let input_files = watched_files(slice::from_ref(&opt), None);
+        let input_paths: Vec<&Path> = input_files.iter().map(|input_file| input_file.as_path()).collect();
+        let options: Vec<String> = atlas_args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        let key = match fontgen::build_key(&input_paths, &options, &renderer) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotReadBuildInputs(opt.output_path.clone(), e))),
+        };
+        let key_path = build_key_path(&opt.output_path);
+        let recorded_key = fs::read_to_string(&key_path).unwrap_or_default();
+        // The output path only names the files of the atlas, so the atlas itself might be elsewhere.
+        let atlas_file = atlas_file(&opt.output_path, opt.format);
+        if atlas_exists(&opt.output_path, opt.format) && recorded_key.trim() == key {
+            eprintln!("{} is up to date.", atlas_file.display());
+            continue;
+        }
+        if build_opt.dry_run {
+            let _ = writeln!(io::stdout(), "{} is out of date.", atlas_file.display());
+            continue;
+        }
+        // An out of date atlas gets replaced, or backed up and replaced, whatever the config says.
+        opt.skip_existing = false;
+        opt.force = !opt.backup;
+        let atlas_start = Instant::now();
+        run_app(&ft, &mut face_cache, &opt)?;
+        if let Err(e) = fontgen::write_atomically(&key_path, |path| fs::write(path, &key)) {
+            return Err(Box::new(AppError::CouldNotWriteBuildKey(key_path, e)));
+        }
+        eprintln!("Wrote {} in {:.3} s.", atlas_file.display(), atlas_start.elapsed().as_secs_f64());
+        built += 1;
+    }
+    if !build_opt.dry_run {
+        eprintln!(
+            "Built {} of {} atlases in {:.3} s, and {} were up to date.",
+            built, total, start.elapsed().as_secs_f64(), total - built
+        );
+    }
+
+    Ok(())
+}
+
+/// The character of a code point, or nothing for the ones that don't print as anything.
+fn printable_char(code_point: usize) -> String {
+    std::char::from_u32(code_point as u32)
+        .filter(|ch| !ch.is_control() && !ch.is_whitespace())
+        .map_or(String::new(), |ch| ch.to_string())
+}
+
+/// Describe the metadata of an atlas, followed by a table of its glyphs sorted by code point. The
+/// texture coordinates are the ones stored in the atlas, and the sizes and offsets are in pixels.
+fn describe_atlas(atlas_path: &Path, metadata: &bmfa::BitmapFontAtlasMetadata) -> String {
+    let mut lines = vec![
+        format!("Atlas:           {}", atlas_path.display()),
+        format!("Dimensions:      {} x {} pixels", metadata.width, metadata.height),
+        format!("Origin:          {}", origin_name(metadata.origin)),
+        format!("Grid:            {} columns x {} rows", metadata.columns, metadata.rows),
+        format!("Slot glyph size: {} pixels", metadata.slot_glyph_size),
+        format!("Glyph size:      {} pixels", metadata.glyph_size),
+        format!("Padding:         {} pixels", metadata.padding),
+        format!("Glyphs:          {}", metadata.glyph_metadata.len()),
+        String::new(),
+        format!(
+            "{:<10} {:<4} {:>4} {:>4} {:>8} {:>8} {:>7} {:>7} {:>8}",
+            "Code point", "Char", "Row", "Col", "U", "V", "Width", "Height", "Y offset"
+        ),
+    ];
+    let mut glyphs: Vec<&bmfa::GlyphMetadata> = metadata.glyph_metadata.values().collect();
+    glyphs.sort_by_key(|glyph| glyph.code_point);
+    // The widths, heights, and offsets of the metadata are fractions of the slot glyph size. Adding
+    // zero turns the negative zero offsets of glyphs sitting on the baseline into plain zeros.
+    let slot_glyph_size = metadata.slot_glyph_size as f32;
+    for glyph in glyphs.into_iter() {
+        lines.push(format!(
+            "{:<10} {:<4} {:>4} {:>4} {:>8.5} {:>8.5} {:>7.1} {:>7.1} {:>8.1}",
+            format!("U+{:04X}", glyph.code_point), printable_char(glyph.code_point), glyph.row, glyph.column,
+            glyph.x_min, glyph.y_min, glyph.width * slot_glyph_size, glyph.height * slot_glyph_size,
+            glyph.y_offset * slot_glyph_size + 0.0
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Report the characters each locale's translation file uses that an atlas lacks, so a new
+/// localization drop can get checked against the font assets before it ships. This fails when
+/// any locale lacks characters.
+fn run_audit(audit_opt: &AuditOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let atlas = match bmfa::load(&audit_opt.atlas_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(audit_opt.atlas_path.clone(), e))),
+    };
+    let mut lines = vec![format!("Atlas:           {}", audit_opt.atlas_path.display()), String::new()];
+    let mut incomplete = 0;
+    for strings_path in audit_opt.strings_paths.iter() {
+        let locale = strings_path.file_stem().unwrap_or_default().to_string_lossy();
+        let used = CharacterSet::from_text(&read_translation_file(strings_path)?);
+        let missing = used.filter(|code_point| !atlas.metadata.glyph_metadata.contains_key(&code_point));
+        if missing.is_empty() {
+            lines.push(format!("{:<16} all {} characters covered", locale, used.len()));
+        } else {
+            incomplete += 1;
+            lines.push(format!(
+                "{:<16} {} of {} characters missing: {}",
+                locale, missing.len(), used.len(), format_ranges(&missing).join(", ")
+            ));
+        }
+    }
+    let _ = writeln!(io::stdout(), "{}", lines.join("\n"));
+    if incomplete > 0 {
+        return Err(Box::new(AppError::MissingLocaleCharacters(incomplete, audit_opt.strings_paths.len())));
+    }
+
+    Ok(())
+}
+
+/// Print what an atlas file holds, e.g. to debug an atlas someone else generated.
+fn run_inspect(inspect_opt: &InspectOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let atlas = match bmfa::load(&inspect_opt.atlas_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(inspect_opt.atlas_path.clone(), e))),
+    };
+    // Ignore write errors so that piping into something like `head` doesn't panic on a closed pipe.
+    let _ = writeln!(io::stdout(), "{}", describe_atlas(&inspect_opt.atlas_path, &atlas.metadata));
+
+    Ok(())
+}
+
+/// The code points of a list of glyphs, as a character set.
+fn glyph_character_set(code_points: &[usize]) -> CharacterSet {
+    let ranges: Vec<(usize, usize)> = code_points.iter()
+        .map(|&code_point| (code_point, code_point))
+        .collect();
+    CharacterSet::new(&ranges)
+}
+
+/// Describe what differs between two versions of an atlas: the metadata that changed, the glyphs
+/// added, removed, or changed, and how many pixels of the glyphs and of the whole image differ.
+/// A glyph that only moved to another place in the atlas doesn't count as changed, since packing
+/// a different set of glyphs moves most of them.
+fn describe_diff(
+    old_path: &Path, old: &bmfa::BitmapFontAtlas,
+    new_path: &Path, new: &bmfa::BitmapFontAtlas, tolerance: u8) -> (String, DiffSummary) {
+
+    let mut lines = vec![
+        format!("Old atlas:       {}", old_path.display()),
+        format!("New atlas:       {}", new_path.display()),
+        String::new(),
+    ];
+    let describe_metadata = |metadata: &bmfa::BitmapFontAtlasMetadata| vec![
+        ("Dimensions:     ", format!("{} x {} pixels", metadata.width, metadata.height)),
+        ("Origin:         ", origin_name(metadata.origin).to_string()),
+        ("Grid:           ", format!("{} columns x {} rows", metadata.columns, metadata.rows)),
+        ("Slot glyph size:", format!("{} pixels", metadata.slot_glyph_size)),
+        ("Glyph size:     ", format!("{} pixels", metadata.glyph_size)),
+        ("Padding:        ", format!("{} pixels", metadata.padding)),
+    ];
+    let metadata_deltas: Vec<String> = describe_metadata(&old.metadata).into_iter()
+        .zip(describe_metadata(&new.metadata).into_iter())
+        .filter(|&((_, ref old_value), (_, ref new_value))| old_value != new_value)
+        .map(|((field, old_value), (_, new_value))| format!("{} {} -> {}", field, old_value, new_value))
+        .collect();
+    if metadata_deltas.is_empty() {
+        lines.push(String::from("Metadata:        unchanged"));
+    } else {
+        lines.extend(metadata_deltas);
+    }
+
+    let old_glyphs = &old.metadata.glyph_metadata;
+    let new_glyphs = &new.metadata.glyph_metadata;
+    let mut code_points: Vec<usize> = old_glyphs.keys().chain(new_glyphs.keys()).cloned().collect();
+    code_points.sort();
+    code_points.dedup();
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut changed = vec![];
+    let mut moved = 0;
+    let mut reshaped = 0;
+    let mut glyph_pixels = 0;
+    let mut differing_glyph_pixels = 0;
+    for &code_point in code_points.iter() {
+        let (old_glyph, new_glyph) = match (old_glyphs.get(&code_point), new_glyphs.get(&code_point)) {
+            (Some(old_glyph), Some(new_glyph)) => (old_glyph, new_glyph),
+            (Some(_), None) => {
+                removed.push(code_point);
+                continue;
+            }
+            (None, _) => {
+                added.push(code_point);
+                continue;
+            }
+        };
+        if (old_glyph.row, old_glyph.column) != (new_glyph.row, new_glyph.column)
+            || (old_glyph.x_min, old_glyph.y_min) != (new_glyph.x_min, new_glyph.y_min) {
+            moved += 1;
+        }
+        // Compare the sizes and offsets in pixels, since the slot glyph sizes of the atlases can differ.
+        let size = |atlas: &bmfa::BitmapFontAtlas, glyph: &bmfa::GlyphMetadata| {
+            let slot_glyph_size = atlas.metadata.slot_glyph_size as f32;
+            format!("{:.1} x {:.1}", glyph.width * slot_glyph_size, glyph.height * slot_glyph_size)
+        };
+        let y_offset = |atlas: &bmfa::BitmapFontAtlas, glyph: &bmfa::GlyphMetadata| {
+            format!("{:.1}", glyph.y_offset * atlas.metadata.slot_glyph_size as f32 + 0.0)
+        };
+        let mut changes = vec![];
+        let (old_size, new_size) = (size(old, old_glyph), size(new, new_glyph));
+        if old_size != new_size {
+            changes.push(format!("size {} -> {}", old_size, new_size));
+        }
+        let (old_y_offset, new_y_offset) = (y_offset(old, old_glyph), y_offset(new, new_glyph));
+        if old_y_offset != new_y_offset {
+            changes.push(format!("y offset {} -> {}", old_y_offset, new_y_offset));
+        }
+        if !changes.is_empty() {
+            reshaped += 1;
+        }
+        let old_image = fontgen::glyph_image(old, old_glyph);
+        let new_image = fontgen::glyph_image(new, new_glyph);
+        if let (Some(old_image), Some(new_image)) = (old_image, new_image) {
+            match fontgen::compare_images_with_tolerance(&old_image, &new_image, tolerance) {
+                Some(difference) => {
+                    glyph_pixels += difference.pixels;
+                    differing_glyph_pixels += difference.differing_pixels;
+                    if !difference.is_empty() {
+                        changes.push(format!(
+                            "{} of {} pixels differ (max difference {})",
+                            difference.differing_pixels, difference.pixels, difference.max_difference
+                        ));
+                    }
+                }
+                None if old_size == new_size => changes.push(String::from("channel count differs")),
+                None => {}
+            }
+        }
+        if !changes.is_empty() {
+            changed.push(format!(
+                "  {:<10} {:<4} {}",
+                format!("U+{:04X}", code_point), printable_char(code_point), changes.join(", ")
+            ));
+        }
+    }
+
+    lines.push(format!(
+        "Glyphs:          {} -> {} ({} added, {} removed, {} changed, {} moved)",
+        old_glyphs.len(), new_glyphs.len(), added.len(), removed.len(), changed.len(), moved
+    ));
+    if !added.is_empty() {
+        lines.push(format!("Added:           {}", format_ranges(&glyph_character_set(&added)).join(", ")));
+    }
+    if !removed.is_empty() {
+        lines.push(format!("Removed:         {}", format_ranges(&glyph_character_set(&removed)).join(", ")));
+    }
+    if !changed.is_empty() {
+        lines.push(String::from("Changed:"));
+        lines.extend(changed);
+    }
+    let beyond_tolerance = if tolerance > 0 { format!(" by more than {}", tolerance) } else { String::new() };
+    lines.push(format!(
+        "Glyph pixels:    {} of {} pixels of the glyphs in both atlases differ{}",
+        differing_glyph_pixels, glyph_pixels, beyond_tolerance
+    ));
+    lines.push(match fontgen::compare_images_with_tolerance(&old.image, &new.image, tolerance) {
+        Some(difference) => format!(
+            "Image:           {} of {} pixels differ{} (max difference {})",
+            difference.differing_pixels, difference.pixels, beyond_tolerance, difference.max_difference
+        ),
+        None => String::from("Image:           not comparable, the dimensions or channel counts differ"),
+    });
+    let summary = DiffSummary {
+        added: added.len(),
+        removed: removed.len(),
+        reshaped: reshaped,
+        differing_glyph_pixels: differing_glyph_pixels,
+    };
+
+    (lines.join("\n"), summary)
+}
+
+/// The differences between two versions of an atlas that `fontgen diff --max-diff-pixels` checks.
+#[derive(Copy, Clone, Debug)]
+struct DiffSummary {
+    /// The number of characters only the new atlas has.
+    added: usize,
+    /// The number of characters only the old atlas has.
+    removed: usize,
+    /// The number of glyphs whose size or vertical offset changed.
+    reshaped: usize,
+    /// The number of pixels of the glyphs in both atlases that differ by more than the tolerance.
+    differing_glyph_pixels: usize,
+}
+
+/// The exit status of `fontgen diff` when the atlases differ by more than `--tolerance` and
+/// `--max-diff-pixels` allow. Errors exit with status 1, so scripts can tell the two apart.
+const DIFF_EXCEEDS_TOLERANCE_EXIT_STATUS: i32 = 2;
+
+/// Print what differs between two versions of an atlas, e.g. to review a regenerated atlas before
+/// checking it in.
+fn run_diff(diff_opt: &DiffOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let mut atlases = vec![];
+    for atlas_path in [&diff_opt.old_path, &diff_opt.new_path].iter() {
+        match bmfa::load(atlas_path) {
+            Ok(val) => atlases.push(val),
+            Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(atlas_path.to_path_buf(), e))),
+        }
+    }
+    let tolerance = diff_opt.tolerance.unwrap_or(0);
+    let (description, summary) = describe_diff(
+        &diff_opt.old_path, &atlases[0], &diff_opt.new_path, &atlases[1], tolerance
+    );
+    let _ = writeln!(io::stdout(), "{}", description);
+    if diff_opt.tolerance.is_none() && diff_opt.max_diff_pixels.is_none() {
+        return Ok(());
+    }
+    let max_diff_pixels = diff_opt.max_diff_pixels.unwrap_or(0);
+    let mut failures = vec![];
+    if summary.added > 0 || summary.removed > 0 {
+        failures.push(format!("{} glyphs added and {} removed", summary.added, summary.removed));
+    }
+    if summary.reshaped > 0 {
+        failures.push(format!("{} glyphs resized or shifted", summary.reshaped));
+    }
+    if summary.differing_glyph_pixels > max_diff_pixels {
+        failures.push(format!(
+            "{} glyph pixels differ by more than {}, where {} may",
+            summary.differing_glyph_pixels, tolerance, max_diff_pixels
+        ));
+    }
+    if failures.is_empty() {
+        let _ = writeln!(io::stdout(), "Result:          within tolerance");
+        return Ok(());
+    }
+    let _ = writeln!(io::stdout(), "Result:          beyond tolerance, {}", failures.join(", "));
+    let _ = io::stdout().flush();
+    process::exit(DIFF_EXCEEDS_TOLERANCE_EXIT_STATUS);
+}
+
+/// Write an HTML page comparing two versions of an atlas, for reviewers approving a font update.
+fn run_report(report_opt: &ReportOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if report_opt.out_path.exists() {
+        return Err(Box::new(OptError::OutputFileExists(report_opt.out_path.clone())));
+    }
+    let mut versions = vec![];
+    for atlas_path in [&report_opt.old_path, &report_opt.new_path].iter() {
+        let atlas = match bmfa::load(atlas_path) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(atlas_path.to_path_buf(), e))),
+        };
+        let metrics = read_text_metrics(&atlas_path.with_extension("meta.json"), true)?;
+        versions.push((atlas_path.display().to_string(), atlas, metrics));
+    }
+    let (ref old_name, ref old_atlas, ref old_metrics) = versions[0];
+    let (ref new_name, ref new_atlas, ref new_metrics) = versions[1];
+    let written = fontgen::write_comparison_html(
+        &report_opt.out_path, (old_name, old_atlas, old_metrics), (new_name, new_atlas, new_metrics),
+        &report_opt.text
+    );
+    if written.is_err() {
+        return Err(Box::new(AppError::CouldNotCreateReportFile(report_opt.out_path.clone())));
+    }
+
+    Ok(())
 }
 
-impl AtlasSpec {
-    fn new(
-        origin: bmfa::Origin,
-        width: usize, height: usize, rows: usize, columns: usize,
-        padding: usize, slot_glyph_size: usize, glyph_size: usize) -> AtlasSpec {
+/// Describe the scripts, with their language systems, and the features of a `GSUB` or `GPOS` table.
+fn describe_layout_features(table: &str, layout: &Option<fontgen::LayoutFeatures>) -> Vec<String> {
+    let layout = match *layout {
+        Some(ref val) => val,
+        None => return vec![format!("{} table:      none", table)],
+    };
+    let scripts: Vec<String> = layout.scripts.iter()
+        .map(|&(ref script, ref languages)| {
+            let languages: Vec<&str> = languages.iter().map(|language| language.trim_end()).collect();
+            format!("{} ({})", script.trim_end(), languages.join(", "))
+        })
+        .collect();
+    let features: Vec<String> = layout.features.iter().map(|feature| feature.trim().to_string()).collect();
+    let join = |items: &[String]| if items.is_empty() { String::from("none") } else { items.join(", ") };
 
-        AtlasSpec {
-            origin: origin,
-            width: width,
-            height: height,
-            rows: rows,
-            columns: columns,
-            padding: padding,
-            slot_glyph_size: slot_glyph_size,
-            glyph_size: glyph_size,
-        }
-    }
+    vec![
+        format!("{} scripts:    {}", table, join(&scripts)),
+        format!("{} features:   {}", table, join(&features)),
+    ]
 }
 
-/// A `GlyphImage` is a bitmapped representation of a single font glyph.
-#[derive(Clone)]
-struct GlyphImage {
-    data: Vec<u8>,
-}
+/// List the OpenType features, scripts, and languages of a font, and the variation axes and named
+/// instances of a variable font, so users know which `--variation` values it takes.
+fn run_features(features_opt: &FeaturesOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if !features_opt.font_path.exists() {
+        return Err(Box::new(OptError::InputFileDoesNotExist(features_opt.font_path.clone())));
+    }
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let face = load_typeface(&ft, &features_opt.font_path, features_opt.face_index, &[])?;
+    let features = fontgen::font_features(&face);
 
-impl GlyphImage {
-    fn new(data: Vec<u8>) -> GlyphImage {
-        GlyphImage {
-            data: data,
+    let mut lines = vec![format!("Font:            {}", features_opt.font_path.display())];
+    lines.extend(describe_layout_features("GSUB", &features.gsub));
+    lines.extend(describe_layout_features("GPOS", &features.gpos));
+    if features.axes.is_empty() {
+        lines.push(String::from("Variation axes:  none"));
+    } else {
+        lines.push(String::from("Variation axes:"));
+        for axis in features.axes.iter() {
+            lines.push(format!(
+                "  {:<5} {:<16} {} to {}, default {}",
+                axis.tag, axis.name, axis.minimum, axis.maximum, axis.default
+            ));
+        }
+        lines.push(String::from("Named instances:"));
+        for instance in features.instances.iter() {
+            let coordinates: Vec<String> = features.axes.iter()
+                .zip(instance.coordinates.iter())
+                .map(|(axis, coordinate)| format!("{}={}", axis.tag, coordinate))
+                .collect();
+            lines.push(format!("  {:<22} --variation {}", instance.name, coordinates.join(",")));
         }
     }
-}
+    if let Some(ref preview_features) = features_opt.preview_features {
+        let substitutions = fontgen::feature_substitutions(&face, preview_features);
+        lines.push(format!("Substitutions:   {} under {}", substitutions.len(), preview_features.join(", ")));
+        for substitution in substitutions.iter() {
+            lines.push(format!(
+                "  {} glyph {} -> glyph {}",
+                fontgen::describe_code_point(substitution.code_point),
+                substitution.glyph_index, substitution.substitute
+            ));
+        }
+        if let Some(ref out_path) = features_opt.out_path {
+            let glyph_size = features_opt.glyph_size;
+            let preview = match fontgen::render_substitution_preview(&face, glyph_size, &substitutions) {
+                Ok(val) => val,
+                Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+            };
+            if fontgen::write_substitution_preview(out_path, &preview).is_err() {
+                return Err(Box::new(AppError::CouldNotCreateSubstitutionPreviewFile(out_path.clone())));
+            }
+        }
+    }
+    let _ = writeln!(io::stdout(), "{}", lines.join("\n"));
 
-/// A `GlyphTable` is an intermediate date structure storing all the typeface parameters
-/// for each glyph to be used in the construction of the final bitmap atlas.
-struct GlyphTable {
-    /// The height of a glyph in pixels.
-    rows: Vec<i32>,
-    /// The width of a row in a glyph in pixels.
-    width: Vec<i32>,
-    /// The number of bytes per row in a glyph.
-    pitch: Vec<i32>,
-    /// The offset in pixels of a character from the baseline.
-    y_min: Vec<i64>,
-    /// A table holding the individual bitmap images for each glyph.
-    buffer: HashMap<usize, GlyphImage>,
+    Ok(())
 }
 
-/// Sample a single bitmap image for a single glyph from a font. The FreeType library interns
-/// each sampled glyph image one at a time internally. Each time the library samples a new glyph,
-/// the old glyph gets overwritten, so the data must be copied out before each subsequent
-/// sampling of a new glyph.
-fn create_glyph_image(glyph: &freetype::glyph_slot::GlyphSlot) -> GlyphImage {
-    let bitmap = glyph.bitmap();
-    let rows = bitmap.rows() as usize;
-    let pitch = bitmap.pitch() as usize;
+/// The packings `fontgen plan` tries at each slot size.
+const PLAN_PACKINGS: [Packing; 3] = [Packing::Grid, Packing::Skyline, Packing::MaxRects];
 
-    let mut glyph_data = vec![0 as u8; rows * pitch];
-    glyph_data.clone_from_slice(bitmap.buffer());
+/// The channel layouts `fontgen plan` tries for each packing.
+const PLAN_CHANNELS: [AtlasChannels; 2] = [AtlasChannels::A8, AtlasChannels::Rgba];
 
-    GlyphImage::new(glyph_data)
+/// An atlas configuration `fontgen plan` considered, along with the size of its atlas image.
+struct PlanCandidate {
+    slot_glyph_size: usize,
+    packing: Packing,
+    channels: AtlasChannels,
+    width: usize,
+    height: usize,
+    bytes: usize,
 }
 
-
-#[derive(Copy, Clone, Debug)]
-enum SampleTypefaceError {
-    SetPixelSize(freetype::error::Error, usize, usize),
-    LoadCharacter(freetype::error::Error, usize),
-    RenderCharacter(freetype::error::Error, usize),
-    GetGlyphImage(freetype::error::Error, usize),
+/// Format a number of bytes in the powers of 1024 that `--budget` takes.
+fn format_memory_size(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.1} KB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
 }
 
-impl fmt::Display for SampleTypefaceError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            SampleTypefaceError::SetPixelSize(_, code_point, pixels) => {
-                write!(
-                    f, "The FreeType library failed to set the size of glyph {} to {} pixels.",
-                    code_point, pixels
-                )
-            }
-            SampleTypefaceError::LoadCharacter(_, code_point) => {
-                write!(
-                    f, "The FreeType library failed to load the character with code point {}.",
-                    code_point
-                )
-            }
-            SampleTypefaceError::RenderCharacter(_, code_point) => {
-                write!(
-                    f, "The FreeType library could not render the code point {}.",
-                    code_point
-                )
-            }
-            SampleTypefaceError::GetGlyphImage(_, code_point) => {
-                write!(
-                    f, "The FreeType library extract the glyph image for the code point {}.",
-                    code_point
-                )
-            }
+/// Size the atlas image of every combination of slot size, packing, and channel layout for the
+/// characters of a font, and list which of them fit the memory budget.
+fn run_plan(plan_opt: &PlanOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if !plan_opt.input_path.exists() {
+        return Err(Box::new(OptError::InputFileDoesNotExist(plan_opt.input_path.clone())));
+    }
+    if let Some(ref charset_file) = plan_opt.charset_file {
+        if !charset_file.exists() {
+            return Err(Box::new(OptError::CharsetFileDoesNotExist(charset_file.clone())));
         }
     }
-}
-
-impl error::Error for SampleTypefaceError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match self {
-            &SampleTypefaceError::SetPixelSize(ref e,_,_) => Some(e),
-            &SampleTypefaceError::LoadCharacter(ref e,_) => Some(e),
-            &SampleTypefaceError::RenderCharacter(ref e, _) => Some(e),
-            &SampleTypefaceError::GetGlyphImage(ref e,_) => Some(e),
+    for &slot_glyph_size in plan_opt.sizes.iter() {
+        if slot_glyph_size == 0 {
+            return Err(Box::new(OptError::SlotGlyphSizeCannotBeZero(slot_glyph_size)));
+        }
+        if plan_opt.padding > slot_glyph_size {
+            return Err(Box::new(OptError::PaddingLargerThanSlotGlyphSize(plan_opt.padding, slot_glyph_size)));
         }
     }
-}
+    let chars = select_chars(&plan_opt.chars, &plan_opt.preset, &plan_opt.charset_file)?.unwrap_or_default();
 
-/// Generate the glyph image for each individual glyph slot in the typeface to be
-/// mapped into the final atlas image.
-fn sample_typeface(
-    face: freetype::face::Face, spec: AtlasSpec) -> Result<GlyphTable, SampleTypefaceError> {
-
-    // Tell FreeType the maximum size of each glyph, in pixels.
-    // The glyph height in pixels.
-    let mut glyph_rows = vec![0 as i32; 256];
-    // The glyph width in pixels.
-    let mut glyph_width = vec![0 as i32; 256];
-    // The bytes to per row of pixels per glyph.
-    let mut glyph_pitch = vec![0 as i32; 256];
-    // The offset for letters that dip below the baseline like 'g' and 'y', for example.
-    let mut glyph_ymin = vec![0 as i64; 256];
-    // A table for storing the sampled glyph images.
-    let mut glyph_buffer = HashMap::new();
-
-    // Set the height in pixels width 0 height 48 (48x48).
-    face.set_pixel_sizes(0, spec.glyph_size as u32).map_err(|e| {
-        SampleTypefaceError::SetPixelSize(e, 0, spec.glyph_size)
-    })?;
-
-    for i in 33..256 {
-        face.load_char(i, freetype::face::LoadFlag::RENDER).map_err(|e| {
-            SampleTypefaceError::LoadCharacter(e, i)
-        })?;
-
-        // Draw a glyph image anti-aliased.
-        let glyph_handle = face.glyph();
-
-        glyph_handle.render_glyph(freetype::render_mode::RenderMode::Normal).map_err(|e| {
-            SampleTypefaceError::RenderCharacter(e, i)
-        })?;
-
-        // Get the dimensions of the bitmap.
-        glyph_rows[i] = glyph_handle.bitmap().rows();
-        glyph_width[i] = glyph_handle.bitmap().width();
-        glyph_pitch[i] = glyph_handle.bitmap().pitch();
-
-        let glyph_image_i = create_glyph_image(glyph_handle);
-        glyph_buffer.insert(i, glyph_image_i);
-
-        // Get the y-offset to place glyphs on baseline. This data lies in the bounding box.
-        let glyph = match glyph_handle.get_glyph() {
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let face = load_typeface(&ft, &plan_opt.input_path, plan_opt.face_index, &[])?;
+    let zero_width = ZeroWidthClasses { joiners: true, combining: true, format: false };
+    let mut candidates = vec![];
+    for &slot_glyph_size in plan_opt.sizes.iter() {
+        let glyph_size = slot_glyph_size - plan_opt.padding;
+        let spec = AtlasSpec::new(
+            bmfa::Origin::TopLeft, 0, 0, 0, 0, plan_opt.padding, slot_glyph_size, glyph_size,
+            1.0, None, false, zero_width, None, Packing::Grid
+        );
+        let glyph_tab = match fontgen::sample_typeface(&face, spec, &chars) {
             Ok(val) => val,
-            Err(e) => {
-                return Err(SampleTypefaceError::GetGlyphImage(e, i));
-            }
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
         };
+        for &packing in PLAN_PACKINGS.iter() {
+            let mut packed_spec = spec;
+            packed_spec.packing = packing;
+            let fitted = fontgen::fit_atlas(&glyph_tab, packed_spec, plan_opt.power_of_two);
+            for &channels in PLAN_CHANNELS.iter() {
+                candidates.push(PlanCandidate {
+                    slot_glyph_size: slot_glyph_size,
+                    packing: packing,
+                    channels: channels,
+                    width: fitted.width,
+                    height: fitted.height,
+                    bytes: fitted.width * fitted.height * channels.count(),
+                });
+            }
+        }
+    }
+    candidates.sort_by_key(|candidate| (candidate.slot_glyph_size, candidate.bytes));
 
-        // Get the bounding box. Here "truncated" mode specifies that the dimensions
-        // of the bounding box are given in pixels.
-        let bbox = glyph.get_cbox(freetype::ffi::FT_GLYPH_BBOX_TRUNCATE);
-        glyph_ymin[i] = bbox.yMin;
+    let mut stdout = io::stdout();
+    let _ = writeln!(
+        stdout, "{:>5}  {:<9} {:<9} {:>11}  {:>10}  Budget", "Slot", "Packing", "Channels", "Size", "Memory"
+    );
+    for candidate in candidates.iter() {
+        let _ = writeln!(
+            stdout, "{:>5}  {:<9} {:<9} {:>11}  {:>10}  {}",
+            candidate.slot_glyph_size, candidate.packing.name(), candidate.channels.name(),
+            format!("{}x{}", candidate.width, candidate.height), format_memory_size(candidate.bytes),
+            if candidate.bytes <= plan_opt.budget { "fits" } else { "over" }
+        );
     }
+    let fitting: Vec<&PlanCandidate> = candidates.iter()
+        .filter(|candidate| candidate.bytes <= plan_opt.budget)
+        .collect();
+    let _ = writeln!(
+        stdout, "{} of {} configurations fit the budget of {}.",
+        fitting.len(), candidates.len(), format_memory_size(plan_opt.budget)
+    );
+    // The largest slot size that fits, packed into the least memory.
+    let best = fitting.iter()
+        .max_by_key(|candidate| (candidate.slot_glyph_size, usize::MAX - candidate.bytes));
+    let best = match best {
+        Some(val) => val,
+        None => return Err(Box::new(AppError::NoPlanFitsBudget(plan_opt.budget))),
+    };
+    let _ = writeln!(
+        stdout, "The largest slot size that fits is {} pixels, with `--packing {} --channels {}` in a \
+        {}x{} atlas taking {}.",
+        best.slot_glyph_size, best.packing.name(), best.channels.name(), best.width, best.height,
+        format_memory_size(best.bytes)
+    );
 
-    Ok(GlyphTable {
-        rows: glyph_rows,
-        width: glyph_width,
-        pitch: glyph_pitch,
-        y_min: glyph_ymin,
-        buffer: glyph_buffer,
-    })
+    Ok(())
 }
 
-/// Calculate the metadata for indexing into the atlas bitmap image.
-fn create_bitmap_metadata(glyph_tab: &GlyphTable, spec: AtlasSpec) -> HashMap<usize, GlyphMetadata> {
-    let mut metadata = HashMap::new();
-    let glyph_metadata_space = GlyphMetadata::new(32, 0, 0, 0.5, 1.0, 0.0, 0.0, 0.0);
-    metadata.insert(32, glyph_metadata_space);
-    for i in glyph_tab.buffer.keys() {
-        let order = i - 32;
-        let col = order % spec.columns;
-        let row = order % spec.columns;
+/// The code point of the space, which holds the first slot of every atlas, whatever its characters.
+const SPACE: usize = 0x20;
+
+/// Determine whether a glyph comes out differently in another version of an atlas, with another
+/// size, vertical offset, or image.
+fn glyph_changed(
+    old: &bmfa::BitmapFontAtlas, old_glyph: &bmfa::GlyphMetadata,
+    new: &bmfa::BitmapFontAtlas, new_glyph: &bmfa::GlyphMetadata) -> bool {
+
+    let pixels = |atlas: &bmfa::BitmapFontAtlas, value: f32| {
+        (value * atlas.metadata.slot_glyph_size as f32).round() as i64
+    };
+    if pixels(old, old_glyph.y_offset) != pixels(new, new_glyph.y_offset) {
+        return true;
+    }
+    match (fontgen::glyph_image(old, old_glyph), fontgen::glyph_image(new, new_glyph)) {
+        (Some(old_image), Some(new_image)) => {
+            fontgen::compare_images(&old_image, &new_image).map_or(true, |difference| !difference.is_empty())
+        }
+        (None, None) => false,
+        _ => true,
+    }
+}
 
-        // Glyph metadata parameters.
-        let x_min = (col * spec.slot_glyph_size) as f32 / spec.width as f32;
-        let y_min = (row * spec.slot_glyph_size) as f32 / spec.height as f32;
-        let width = (glyph_tab.width[*i] + spec.padding as i32) as f32 / spec.slot_glyph_size as f32;
-        let height = (glyph_tab.rows[*i] + spec.padding as i32) as f32 / spec.slot_glyph_size as f32;
-        let y_offset = -(spec.padding as f32 - glyph_tab.y_min[*i] as f32) / spec.slot_glyph_size as f32;
+/// Regenerate the glyphs of an atlas the way its `.fontgen.toml` file says, into a scratch
+/// directory, leaving out the ones a new character set drops, and find the ones that come out
+/// differently, e.g. after a font update.
+fn regenerated_changes(
+    mut opt: Opt, atlas: &bmfa::BitmapFontAtlas,
+    kept: &[usize]) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
 
-        let row = order / spec.rows;
-        let column = order % spec.columns;
-        let glyph_metadata_i = GlyphMetadata::new(*i, row, column, width, height, x_min, y_min, y_offset);
-        metadata.insert(*i, glyph_metadata_i);
+    if kept.is_empty() {
+        return Ok(vec![]);
     }
+    let scratch_dir = std::env::temp_dir().join(format!("fontgen-plan-update-{}", process::id()));
+    if let Err(e) = fs::create_dir_all(&scratch_dir) {
+        return Err(Box::new(AppError::CouldNotCreateOutDir(scratch_dir, e)));
+    }
+    // Only the atlas itself gets written, on a single page.
+    opt.output_path = scratch_dir.join("kept.bmfa");
+    opt.chars = Some(glyph_character_set(kept));
+    opt.image_out = None;
+    opt.coverage_report = None;
+    opt.max_size = None;
+    opt.trim_last_page = false;
+    opt.texture_array = false;
+    opt.ruby_scale = None;
+    opt.scales.clear();
+    opt.bundle.clear();
+    opt.layers.clear();
+    opt.export_outlines = None;
+    opt.export_curves = false;
+    opt.export_lookup = false;
+    opt.compare_hinting = false;
+    opt.size_report = false;
+    opt.preview_html = false;
+    opt.measure_strings = None;
+    opt.force = true;
+    opt.skip_existing = false;
+    opt.backup = false;
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let regenerated = run_app(&ft, &mut FaceCache::default(), &opt).and_then(|_| {
+        bmfa::load(&opt.output_path)
+            .map_err(|e| Box::new(AppError::CouldNotLoadAtlasFile(opt.output_path.clone(), e)).into())
+    });
+    let _ = fs::remove_dir_all(&scratch_dir);
+    let regenerated = regenerated?;
 
-    metadata
+    let old_glyphs = &atlas.metadata.glyph_metadata;
+    let new_glyphs = &regenerated.metadata.glyph_metadata;
+
+    Ok(kept.iter()
+        .cloned()
+        .filter(|code_point| match (old_glyphs.get(code_point), new_glyphs.get(code_point)) {
+            (Some(old_glyph), Some(new_glyph)) => glyph_changed(atlas, old_glyph, &regenerated, new_glyph),
+            _ => true,
+        })
+        .collect())
 }
 
-/// Pack the glyph bitmap images sampled from the typeface into a single bitmap image.
-fn create_bitmap_image(glyph_tab: &GlyphTable, spec: AtlasSpec) -> bmfa::BitmapFontAtlasImage {
-    // Next we can open a file stream to write our atlas image to.
-    let mut atlas_buffer = vec![
-        0 as u8; spec.width * spec.height * 4 * mem::size_of::<u8>()
+/// Report the glyphs a new character set adds to and removes from an atlas, and the ones that
+/// come out differently when regenerated, along with whether the atlas can get updated in place,
+/// or needs another page or a full rebuild.
+fn run_plan_update(plan_opt: &PlanUpdateOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(ref charset_file) = plan_opt.charset_file {
+        if !charset_file.exists() {
+            return Err(Box::new(OptError::CharsetFileDoesNotExist(charset_file.clone())));
+        }
+    }
+    let atlas = match bmfa::load(&plan_opt.atlas_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(plan_opt.atlas_path.clone(), e))),
+    };
+    let parameters_file = plan_opt.atlas_path.with_extension("fontgen.toml");
+    let mut arg_lists = read_config(&parameters_file, &[])?;
+    let opt = match Opt::from_iter_safe(arg_lists.remove(0)) {
+        Ok(val) => val,
+        Err(e) => {
+            let message = e.message.trim_start_matches("error: ").to_string();
+            return Err(Box::new(AppError::InvalidConfigFile(parameters_file, message)));
+        }
+    };
+    if opt.all_glyphs {
+        return Err(Box::new(OptError::GlyphIndexOption("fontgen plan-update")));
+    }
+    verify_settings(&opt)?;
+
+    let new_chars = select_chars(&plan_opt.chars, &plan_opt.preset, &plan_opt.charset_file)?;
+    let new_chars = new_chars.unwrap_or_default();
+    let glyphs = &atlas.metadata.glyph_metadata;
+    let mut old_code_points: Vec<usize> = glyphs.keys()
+        .cloned()
+        .filter(|&code_point| code_point != SPACE)
+        .collect();
+    old_code_points.sort();
+    let added: Vec<usize> = new_chars.code_points().into_iter()
+        .filter(|code_point| *code_point != SPACE && !glyphs.contains_key(code_point))
+        .collect();
+    let (kept, removed): (Vec<usize>, Vec<usize>) = old_code_points.into_iter()
+        .partition(|&code_point| new_chars.contains(code_point));
+    let (packing, max_size) = (opt.packing, opt.max_size);
+    let changed = regenerated_changes(opt, &atlas, &kept)?;
+
+    // Dropped glyphs free their slots, so new glyphs can take them.
+    let slots = atlas.metadata.rows * atlas.metadata.columns;
+    let free_slots = slots.saturating_sub(glyphs.len() - removed.len());
+    let verdict = if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        String::from("up to date, the atlas holds these glyphs already")
+    } else if packing != Packing::Grid && !(added.is_empty() && changed.is_empty()) {
+        format!("full rebuild, a {} packed atlas has no free slots to draw glyphs into", packing.name())
+    } else if added.len() <= free_slots {
+        String::from("update in place, new glyphs fit the free slots, and changed glyphs keep their slots")
+    } else if max_size.is_some() {
+        format!("add a page, {} new glyphs overflow the {} free slots", added.len(), free_slots)
+    } else {
+        format!("full rebuild, {} new glyphs overflow the {} free slots", added.len(), free_slots)
+    };
+
+    let mut lines = vec![
+        format!("Atlas:           {}", plan_opt.atlas_path.display()),
+        format!(
+            "Glyphs:          {} kept, {} added, {} removed, {} changed",
+            kept.len(), added.len(), removed.len(), changed.len()
+        ),
     ];
-    let mut atlas_buffer_index = 0;
-    for y in 0..spec.height {
-        for x in 0..spec.width {
-            // Work out which grid slot (col, row) we are in i.e. out of 16 glyphs x 16 glyphs.
-            let col = x / spec.slot_glyph_size;
-            let row = y / spec.slot_glyph_size;
-            let order = row * spec.columns + col;
-            let glyph_index = order + 32;
-
-            if (glyph_index > 32) && (glyph_index < 256) {
-                // A glyph exists for this code point in the bitmap.
-                // Pixel indices within padded glyph slot area.
-                let x_loc = ((x % spec.slot_glyph_size) as i32) - ((spec.padding / 2) as i32);
-                let y_loc = ((y % spec.slot_glyph_size) as i32) - ((spec.padding / 2) as i32);
-                // Outside of the glyph dimensions we use as default value a
-                // transparent black pixel (0,0,0,0).
-                if x_loc < 0 || y_loc < 0 || x_loc >= glyph_tab.width[glyph_index] ||
-                    y_loc >= glyph_tab.rows[glyph_index] {
-                    atlas_buffer[atlas_buffer_index] = 0;
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = 0;
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = 0;
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = 0;
-                    atlas_buffer_index += 1;
-                } else {
-                    // this is 1, but it's safer to put it in anyway
-                    // int bytes_per_pixel = gwidth[glyph_index] / gpitch[glyph_index];
-                    // int bytes_in_glyph = grows[glyph_index] * gpitch[glyph_index];
-                    let byte_order_in_glyph = y_loc * glyph_tab.width[glyph_index] + x_loc;
-                    let mut colour = [0 as u8; 4];
-                    colour[0] = glyph_tab.buffer[&glyph_index].data[byte_order_in_glyph as usize];
-                    colour[1] = colour[0];
-                    colour[2] = colour[0];
-                    colour[3] = colour[0];
-
-                    atlas_buffer[atlas_buffer_index] = glyph_tab.buffer[&glyph_index].data[byte_order_in_glyph as usize];
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = glyph_tab.buffer[&glyph_index].data[byte_order_in_glyph as usize];
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = glyph_tab.buffer[&glyph_index].data[byte_order_in_glyph as usize];
-                    atlas_buffer_index += 1;
-                    atlas_buffer[atlas_buffer_index] = glyph_tab.buffer[&glyph_index].data[byte_order_in_glyph as usize];
-                    atlas_buffer_index += 1;
-                }
-            } else {
-                // A glyph does not exist for this code point in the bitmap. We choose to use a
-                // a transparent black pixel value (0,0,0,0).
-                atlas_buffer[atlas_buffer_index] = 0;
-                atlas_buffer_index += 1;
-                atlas_buffer[atlas_buffer_index] = 0;
-                atlas_buffer_index += 1;
-                atlas_buffer[atlas_buffer_index] = 0;
-                atlas_buffer_index += 1;
-                atlas_buffer[atlas_buffer_index] = 0;
-                atlas_buffer_index += 1;
-            }
+    for &(label, code_points) in [("Added:", &added), ("Removed:", &removed), ("Changed:", &changed)].iter() {
+        if !code_points.is_empty() {
+            let ranges = format_ranges(&glyph_character_set(code_points));
+            lines.push(format!("{:<16} {}", label, ranges.join(", ")));
         }
     }
+    if packing == Packing::Grid {
+        lines.push(format!("Free slots:      {} of {}", free_slots, slots));
+    }
+    lines.push(format!("Verdict:         {}", verdict));
+    let _ = writeln!(io::stdout(), "{}", lines.join("\n"));
 
-    if spec.origin == bmfa::Origin::BottomLeft {
-        // If the origin is the bottom left of the image, we need to flip the image back over
-        // before writing it out.
-        let height = spec.height;
-        let width_in_bytes = 4 * spec.width;
-        let half_height = height / 2;
-        for row in 0..half_height {
-            for col in 0..width_in_bytes {
-                let temp = atlas_buffer[row * width_in_bytes + col];
-                atlas_buffer[row * width_in_bytes + col] = atlas_buffer[((height - row - 1) * width_in_bytes) + col];
-                atlas_buffer[((height - row - 1) * width_in_bytes) + col] = temp;
-            }
+    Ok(())
+}
+
+/// Stack images top to bottom into a single image as wide as the widest one, filling the rest with
+/// opaque black, like the background of the previews.
+fn stack_images(images: &[bmfa::BitmapFontAtlasImage]) -> bmfa::BitmapFontAtlasImage {
+    let width = images.iter().map(|image| image.width).max().unwrap_or(0);
+    let height = images.iter().map(|image| image.height).sum();
+    let mut data = [0, 0, 0, 255].repeat(width * height);
+    let mut top = 0;
+    for image in images.iter() {
+        for row in 0..image.height {
+            let start = ((top + row) * width) * 4;
+            data[start..(start + image.width * 4)]
+                .copy_from_slice(&image.data[(row * image.width * 4)..((row + 1) * image.width * 4)]);
         }
+        top += image.height;
     }
 
-    bmfa::BitmapFontAtlasImage::new(
-        atlas_buffer, spec.width, spec.height, spec.origin
-    )
+    bmfa::BitmapFontAtlasImage::new(data, width, height, bmfa::Origin::TopLeft)
 }
 
-/// Create a bitmapped atlas from a vector based font atlas.
-fn create_bitmap_atlas(
-    face: freetype::face::Face, spec: AtlasSpec) -> Result<BitmapFontAtlas, SampleTypefaceError> {
+/// Draw a sample text in each of several fonts into a single image, one row per font, for choosing
+/// the fonts of a project. Each row gets drawn from a small atlas of the characters of the text,
+/// generated from its font the way `generate` does with the default options.
+fn run_sheet(sheet_opt: &SheetOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if sheet_opt.out_path.exists() {
+        return Err(Box::new(OptError::OutputFileExists(sheet_opt.out_path.clone())));
+    }
+    for input_path in sheet_opt.input_paths.iter() {
+        if !input_path.exists() {
+            return Err(Box::new(OptError::InputFileDoesNotExist(input_path.clone())));
+        }
+    }
+    if sheet_opt.slot_glyph_size == 0 {
+        return Err(Box::new(OptError::SlotGlyphSizeCannotBeZero(sheet_opt.slot_glyph_size)));
+    }
 
-    let glyph_tab = match sample_typeface(face, spec) {
-        Ok(val) => val,
-        Err(e) => return Err(e),
-    };
-    let glyph_metadata = create_bitmap_metadata(&glyph_tab, spec);
-    let atlas_image = create_bitmap_image(&glyph_tab, spec);
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let renderer = Renderer::freetype(&ft);
+    let chars = CharacterSet::from_text(&sheet_opt.text);
+    let slot_glyph_size = sheet_opt.slot_glyph_size;
+    let spec = AtlasSpec::new(
+        bmfa::Origin::TopLeft, 0, 0, 0, 0, 0, slot_glyph_size, slot_glyph_size, 1.0, None, false,
+        ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let mut previews = vec![];
+    for input_path in sheet_opt.input_paths.iter() {
+        let face = load_typeface(&ft, input_path, 0, &[])?;
+        let missing = chars.filter(|code_point| code_point != SPACE && face.get_char_index(code_point) == 0);
+        if !missing.is_empty() {
+            eprintln!(
+                "Warning: {} has no glyphs for {}, which get drawn with its missing glyph.",
+                input_path.display(), format_ranges(&missing).join(", ")
+            );
+        }
+        let glyph_tab = match fontgen::sample_typeface(&face, spec, &chars) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e)))),
+        };
+        let atlas_spec = fontgen::fit_atlas(&glyph_tab, spec, false);
+        let atlas = fontgen::create_bitmap_atlas(&glyph_tab, atlas_spec);
+        let metadata = ExtendedMetadata::new(atlas_spec, &renderer, ColorSpace::Linear, &glyph_tab);
+        let metrics = TextMetrics { advances: metadata.advances, kerning: metadata.kerning, rules: None };
+        previews.push(fontgen::render_preview(&atlas, &metrics, &sheet_opt.text));
+    }
+    let sheet = stack_images(&previews);
+    if fontgen::write_image(&sheet_opt.out_path, &sheet, ColorSpace::Linear).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateImageFile(sheet_opt.out_path.clone())));
+    }
 
-    let metadata = BitmapFontAtlasMetadata {
-        origin: spec.origin,
-        width: spec.width,
-        height: spec.height,
-        columns: spec.columns,
-        rows: spec.columns,
-        padding: spec.padding,
-        slot_glyph_size: spec.slot_glyph_size,
-        glyph_size: spec.glyph_size,
-        glyph_metadata: glyph_metadata,
-    };
+    let mut stdout = io::stdout();
+    let _ = writeln!(stdout, "{:>5}  {:>6}  Font", "Row", "Top");
+    let mut top = 0;
+    for (row, (input_path, preview)) in sheet_opt.input_paths.iter().zip(previews.iter()).enumerate() {
+        let _ = writeln!(stdout, "{:>5}  {:>6}  {}", row + 1, top, input_path.display());
+        top += preview.height;
+    }
 
-    Ok(BitmapFontAtlas::new(metadata, atlas_image))
+    Ok(())
 }
 
-#[derive(Clone, Debug)]
-enum OptError {
-    InputFileDoesNotExist(PathBuf),
-    InputFileIsNotAFile(PathBuf),
-    OutputFileExists(PathBuf),
-    SlotGlyphSizeCannotBeZero(usize),
-    PaddingLargerThanSlotGlyphSize(usize, usize),
-    InvalidOrigin(String),
+/// Write the advances and kerning of an atlas out to a JSON file, with the same fields as the
+/// `.meta.json` files of generated atlases.
+fn write_text_metrics(path: &Path, metrics: &TextMetrics) -> io::Result<()> {
+    fontgen::write_atomically(path, |path| {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, metrics)?;
+
+        Ok(())
+    })
 }
 
-impl fmt::Display for OptError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            OptError::InputFileDoesNotExist(ref path) => {
-                write!(f, "The font file {} could not be found.", path.display())
-            }
-            OptError::InputFileIsNotAFile(ref path) => {
-                write!(f, "The path {} is not a file.", path.display())
-            }
-            OptError::OutputFileExists(ref path) => {
-                write!(f, "A file already exists in the location {}", path.display())
-            }
-            OptError::SlotGlyphSizeCannotBeZero(_) => {
-                write!(f, "The slot glyph size cannot be zero.")
-            }
-            OptError::PaddingLargerThanSlotGlyphSize(padding, glyph_size) => {
-                write!(
-                    f,
-                    "The padding ({} pixels) for each glyph is \
-                    larger than the glyph slot size ({} pixels).",
-                    padding, glyph_size
-                )
-            }
-            OptError::InvalidOrigin(ref origin) => {
-                write!(f, "Selection for image origin invalid. Got {}", origin)
+/// Write the metadata of an atlas out to a JSON file, just as the atlas file holds it.
+fn write_atlas_metadata(path: &Path, metadata: &bmfa::BitmapFontAtlasMetadata) -> io::Result<()> {
+    fontgen::write_atomically(path, |path| {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, metadata)?;
+
+        Ok(())
+    })
+}
+
+/// Unpack an atlas file into its image and its metadata, and optionally the image of each glyph,
+/// so atlases generated before can move to pipelines that do not read the `bmfa` format.
+fn run_extract(extract_opt: &ExtractOpt) -> Result<(), Box<dyn std::error::Error>> {
+    let image_file = extract_opt.out_dir.join("atlas.png");
+    let metadata_file = extract_opt.out_dir.join("metadata.json");
+    let glyphs_dir = extract_opt.out_dir.join("glyphs");
+    let mut output_paths = vec![&image_file, &metadata_file];
+    if extract_opt.glyphs {
+        output_paths.push(&glyphs_dir);
+    }
+    if let Some(output_path) = output_paths.into_iter().find(|output_path| output_path.exists()) {
+        return Err(Box::new(OptError::OutputFileExists(output_path.clone())));
+    }
+    let atlas = match bmfa::load(&extract_opt.atlas_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(extract_opt.atlas_path.clone(), e))),
+    };
+    let out_dir = if extract_opt.glyphs { &glyphs_dir } else { &extract_opt.out_dir };
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        return Err(Box::new(AppError::CouldNotCreateOutDir(out_dir.clone(), e)));
+    }
+    let color_space = read_color_space(&extract_opt.atlas_path.with_extension("meta.json"));
+    if fontgen::write_atlas_image(&image_file, &atlas, color_space).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateImageFile(image_file)));
+    }
+    if write_atlas_metadata(&metadata_file, &atlas.metadata).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateMetadataFile(metadata_file)));
+    }
+    if extract_opt.glyphs {
+        for glyph in atlas.metadata.glyph_metadata.values() {
+            let glyph_image = match fontgen::glyph_image(&atlas, glyph) {
+                Some(val) => val,
+                None => continue,
+            };
+            let glyph_file = glyphs_dir.join(format!("U+{:04X}.png", glyph.code_point));
+            if fontgen::write_image(&glyph_file, &glyph_image, color_space).is_err() {
+                return Err(Box::new(AppError::CouldNotCreateImageFile(glyph_file)));
             }
         }
     }
-}
 
-impl error::Error for OptError {}
+    Ok(())
+}
 
-fn parse_origin(st: &str) -> Result<bmfa::Origin, OptError> {
-    match st {
-        "bottom-left" => Ok(bmfa::Origin::BottomLeft),
-        "top-left" => Ok(bmfa::Origin::TopLeft),
-        _ => Err(OptError::InvalidOrigin(format!("{}", st))),
+/// Read the color space of an atlas from its `.meta.json` file. Atlases without a metadata file,
+/// or with one from before the color space got recorded, hold linear coverage values.
+fn read_color_space(metadata_file: &Path) -> ColorSpace {
+    let metadata = fs::read_to_string(metadata_file).ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+    match metadata.as_ref().and_then(|metadata| metadata["color_space"].as_str()) {
+        Some("srgb") => ColorSpace::Srgb,
+        _ => ColorSpace::Linear,
     }
 }
 
-/// The shell input options for `fontgen`.
-#[derive(Debug, StructOpt)]
-#[structopt(
-    name = "fontgen",
-    about = "A shell utility for converting TrueType or OpenType fonts into bitmapped fonts."
-)]
-struct Opt {
-    /// The path to the input file.
-    #[structopt(parse(from_os_str))]
-    #[structopt(short = "i", long = "input")]
-    input_path: PathBuf,
-    #[structopt(parse(from_os_str))]
-    #[structopt(short = "o", long = "output")]
-    /// The path to the output file.
-    output_path: PathBuf,
-    /// The size, in pixels, of a glyph slot in the font sheet. The slot glyph
-    /// is not necessarily the same as the glyph size because a glyph slot can contain padding.
-    #[structopt(long = "slot-glyph-size", default_value = "64")]
-    slot_glyph_size: usize,
-    /// The glyph slot padding size, in pixels. This is the number of pixels away from the
-    /// boundary of a glyph slot a glyph will be placed.
-    #[structopt(short = "p", long = "padding", default_value = "0")]
-    padding: usize,
-    /// The origin of the coordinate system for the atlas image. This describes the coordinate system
-    /// used to index into the image for each glyph.
-    #[structopt(long = "origin", default_value = "bottom-left")]
-    #[structopt(parse(try_from_str = "parse_origin"))]
-    origin: bmfa::Origin,
+/// Read the advances and kerning of an atlas from its `.meta.json` file. A default metadata file
+/// that does not exist gives no advances and no kerning, since atlases get passed around without
+/// their metadata files too.
+fn read_text_metrics(metadata_file: &Path, is_default: bool) -> Result<TextMetrics, AppError> {
+    let text = match fs::read_to_string(metadata_file) {
+        Ok(val) => val,
+        Err(ref e) if is_default && e.kind() == io::ErrorKind::NotFound => {
+            eprintln!(
+                "Warning: There is no metadata file {}, so each character advances by the width of \
+                its glyph, without kerning.", metadata_file.display()
+            );
+            return Ok(TextMetrics::default());
+        }
+        Err(e) => return Err(AppError::InvalidMetadataFile(metadata_file.to_path_buf(), e.to_string())),
+    };
+
+    serde_json::from_str(&text)
+        .map_err(|e| AppError::InvalidMetadataFile(metadata_file.to_path_buf(), e.to_string()))
 }
 
-/// Verify the input options.
-fn verify_opt(opt: &Opt) -> Result<(), OptError> {
-    if !opt.input_path.exists() {
-        return Err(OptError::InputFileDoesNotExist(opt.input_path.clone()));
+/// Draw sample text from an atlas, so the metrics of an atlas can be checked without an engine.
+fn run_preview(preview_opt: &PreviewOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if preview_opt.out_path.exists() {
+        return Err(Box::new(OptError::OutputFileExists(preview_opt.out_path.clone())));
+    }
+    let atlas = match bmfa::load(&preview_opt.atlas_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(preview_opt.atlas_path.clone(), e))),
+    };
+    let metrics = match preview_opt.metadata_path {
+        Some(ref metadata_path) => read_text_metrics(metadata_path, false)?,
+        None => read_text_metrics(&preview_opt.atlas_path.with_extension("meta.json"), true)?,
+    };
+    let missing = CharacterSet::from_text(&preview_opt.text)
+        .filter(|code_point| !atlas.metadata.glyph_metadata.contains_key(&code_point));
+    if !missing.is_empty() {
+        eprintln!(
+            "Warning: The atlas has no glyphs for {}, which the preview leaves out.",
+            format_ranges(&missing).join(", ")
+        );
+    }
+    let layout = fontgen::layout_preview(&atlas, &metrics, &preview_opt.text, preview_opt.wrap_width);
+    let mut preview = fontgen::render_preview_layout(&atlas, &metrics, &layout, preview_opt.alignment);
+    if !preview_opt.rules.is_empty() {
+        let rules = match metrics.rules {
+            Some(ref val) => val,
+            None => return Err(Box::new(AppError::NoRuleMetrics)),
+        };
+        fontgen::draw_preview_rules(&mut preview, &layout, preview_opt.alignment, rules, &preview_opt.rules);
     }
-    if !opt.input_path.is_file() {
-        return Err(OptError::InputFileIsNotAFile(opt.input_path.clone()));
+    let color_space = read_color_space(&preview_opt.atlas_path.with_extension("meta.json"));
+    if fontgen::write_image(&preview_opt.out_path, &preview, color_space).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateImageFile(preview_opt.out_path.clone())));
     }
-    if opt.output_path.exists() {
-        return Err(OptError::OutputFileExists(opt.output_path.clone()));
+    // The preview gets written either way, to look at what went wrong.
+    let mut mismatches = vec![];
+    let expected_line_widths = &preview_opt.expected_line_widths;
+    if !expected_line_widths.is_empty() && expected_line_widths.len() != layout.lines.len() {
+        mismatches.push(format!(
+            "{} lines where {} were expected", layout.lines.len(), expected_line_widths.len()
+        ));
     }
-    if !(opt.slot_glyph_size > 0) {
-        return Err(OptError::SlotGlyphSizeCannotBeZero(opt.slot_glyph_size));
+    let measured_lines = layout.lines.iter().zip(expected_line_widths.iter()).enumerate();
+    for (line, (preview_line, &expected_width)) in measured_lines {
+        if preview_line.width != expected_width {
+            mismatches.push(format!(
+                "line {} ({:?}) is {} pixels wide where {} were expected",
+                line + 1, preview_line.text, preview_line.width, expected_width
+            ));
+        }
+    }
+    match preview_opt.expected_line_height {
+        Some(expected_line_height) if expected_line_height != layout.line_height => {
+            mismatches.push(format!(
+                "the lines are {} pixels apart where {} were expected",
+                layout.line_height, expected_line_height
+            ));
+        }
+        _ => {}
     }
-    if opt.padding > opt.slot_glyph_size {
-        return Err(OptError::PaddingLargerThanSlotGlyphSize(opt.padding, opt.slot_glyph_size));
+    if !mismatches.is_empty() {
+        return Err(Box::new(AppError::UnexpectedPreviewMetrics(mismatches)));
     }
 
     Ok(())
 }
 
-#[derive(Debug)]
-enum AppError {
-    CouldNotOpenFontFile(PathBuf),
-    CouldNotCreateBitmapFont(Box<dyn std::error::Error>),
-    CouldNotCreateAtlasFile(PathBuf),
-}
-
-impl fmt::Display for AppError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            AppError::CouldNotOpenFontFile(input_path) => {
-                write!(f, "Could not open font file: {}.", input_path.display())
-            }
-            AppError::CouldNotCreateBitmapFont(e) => {
-                write!(f, "Could not create bitmap font. Got error: {}", e)
-            }
-            AppError::CouldNotCreateAtlasFile(atlas_file) => {
-                write!(f, "Could not create atlas file: {}.", atlas_file.display())
+/// Pack the glyphs of several atlases into a single atlas, e.g. to combine a text font and an
+/// icon font after they were generated.
+fn run_merge(merge_opt: &MergeOpt) -> Result<(), Box<dyn std::error::Error>> {
+    if merge_opt.skip_existing && merge_opt.output_path.exists() {
+        eprintln!("Skipping {}, which exists already.", merge_opt.output_path.display());
+        return Ok(());
+    }
+    if !merge_opt.force && !merge_opt.backup {
+        let metadata_file = merge_opt.output_path.with_extension("meta.json");
+        for output_path in [&merge_opt.output_path, &metadata_file].iter() {
+            if output_path.exists() {
+                return Err(Box::new(OptError::OutputFileExists(output_path.to_path_buf())));
             }
         }
     }
-}
+    let mut atlases = vec![];
+    for atlas_path in merge_opt.atlas_paths.iter() {
+        let atlas = match bmfa::load(atlas_path) {
+            Ok(val) => val,
+            Err(e) => return Err(Box::new(AppError::CouldNotLoadAtlasFile(atlas_path.clone(), e))),
+        };
+        let metrics = read_text_metrics(&atlas_path.with_extension("meta.json"), true)?;
+        atlases.push((atlas, metrics));
+    }
+    // Every atlas has an entry for the space, so it never counts as a collision.
+    let mut seen = CharacterSet::new(&[]);
+    let mut collisions = CharacterSet::new(&[]);
+    for &(ref atlas, _) in atlases.iter() {
+        let ranges: Vec<(usize, usize)> = atlas.metadata.glyph_metadata.keys()
+            .filter(|&&code_point| code_point != 32)
+            .map(|&code_point| (code_point, code_point))
+            .collect();
+        let chars = CharacterSet::new(&ranges);
+        collisions = collisions.union(&chars.filter(|code_point| seen.contains(code_point)));
+        seen = seen.union(&chars);
+    }
+    if !collisions.is_empty() {
+        match merge_opt.on_collision {
+            Collision::Fail => return Err(Box::new(AppError::GlyphCollision(collisions))),
+            Collision::First | Collision::Last => eprintln!(
+                "Warning: More than one atlas has glyphs for {}. The merged atlas keeps the ones of the {} \
+                atlas that has them.",
+                format_ranges(&collisions).join(", "),
+                if merge_opt.on_collision == Collision::First { "first" } else { "last" }
+            ),
+        }
+    }
 
-impl error::Error for AppError {}
+    let mut priority: Vec<_> = atlases.iter().map(|&(ref atlas, ref metrics)| (atlas, metrics)).collect();
+    if merge_opt.on_collision == Collision::Last {
+        priority.reverse();
+    }
+    let glyph_tab = fontgen::merge_atlas_glyphs(&priority);
+    let first = &atlases[0].0.metadata;
+    let largest = |size: fn(&bmfa::BitmapFontAtlasMetadata) -> usize| {
+        atlases.iter().map(|&(ref atlas, _)| size(&atlas.metadata)).max().unwrap_or(0)
+    };
+    let slot_glyph_size = largest(|metadata| metadata.slot_glyph_size);
+    let glyph_size = largest(|metadata| metadata.glyph_size);
+    let spec = AtlasSpec::new(
+        first.origin, 0, 0, 0, 0, 0, slot_glyph_size, glyph_size,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let spec = fontgen::fit_atlas(&glyph_tab, spec, false);
+    let mut atlas = fontgen::create_bitmap_atlas(&glyph_tab, spec);
+    // The glyph images brought their padding along from their atlases.
+    atlas.metadata.padding = largest(|metadata| metadata.padding);
+    let metrics = TextMetrics {
+        advances: glyph_tab.advance.iter()
+            .map(|(&code_point, &pixels)| {
+                let normalized = pixels as f32 / slot_glyph_size as f32;
+                (code_point, GlyphAdvance { pixels: pixels, normalized: normalized })
+            })
+            .collect(),
+        kerning: glyph_tab.kerning.clone(),
+        rules: None,
+    };
 
-/// Run the application.
-fn run_app(opt: &Opt) -> Result<(), Box<dyn std::error::Error>> {
-    let ft = Library::init().expect("Failed to initialize FreeType library.");
-    let face = match ft.new_face(&opt.input_path, 0) {
+    // Merges and runs writing the same atlas take turns, and the atlas and its metadata get
+    // moved into place together, like the files of any other run.
+    let output_path = &merge_opt.output_path;
+    let lock_timeout = Duration::from_secs(merge_opt.wait_for_lock.unwrap_or(0));
+    let _output_lock = match OutputLock::acquire(output_path, lock_timeout) {
         Ok(val) => val,
-        Err(_) => {
-            return Err(Box::new(AppError::CouldNotOpenFontFile(opt.input_path.clone())));
-        }
+        Err(e) => return Err(Box::new(AppError::CouldNotLockOutputFiles(output_path.clone(), e))),
+    };
+    let mut transaction = OutputTransaction::new();
+    transaction.keep_backups(merge_opt.backup);
+    let staged_output_path = match transaction.stage(output_path) {
+        Ok(val) => val,
+        Err(e) => return Err(Box::new(AppError::CouldNotStageOutputFiles(e))),
     };
+    if bmfa::write_to_file(&staged_output_path, &atlas).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateAtlasFile(output_path.clone())));
+    }
+    if write_text_metrics(&staged_output_path.with_extension("meta.json"), &metrics).is_err() {
+        return Err(Box::new(AppError::CouldNotCreateMetadataFile(output_path.with_extension("meta.json"))));
+    }
+    if let Err(e) = transaction.commit() {
+        return Err(Box::new(AppError::CouldNotCommitOutputFiles(e)));
+    }
+    eprintln!(
+        "Merged {} glyphs into a {}x{} atlas.", atlas.metadata.glyph_metadata.len(), spec.width, spec.height
+    );
 
-    let origin = opt.origin;
-    let slot_glyph_size = opt.slot_glyph_size;
-    let atlas_columns = 16;
-    let atlas_rows = 16;
-    let atlas_height_px = slot_glyph_size * atlas_rows;
-    let atlas_width_px = slot_glyph_size * atlas_columns;
-    let padding_px = opt.padding;
-    let atlas_glyph_px = slot_glyph_size - padding_px;
-    let mut atlas_file = opt.output_path.clone();
-    atlas_file.set_extension("bmfa");
+    Ok(())
+}
 
-    let atlas_spec = AtlasSpec::new(
-        origin, atlas_width_px, atlas_height_px,
-        atlas_rows, atlas_columns, padding_px, slot_glyph_size, atlas_glyph_px
-    );
-    let atlas = match create_bitmap_atlas(face, atlas_spec) {
-        Ok(val) => val,
-        Err(e) => {
-            return Err(Box::new(AppError::CouldNotCreateBitmapFont(Box::new(e))));
+/// The flags of `fontgen` itself, as opposed to the options of `generate` given without a
+/// subcommand.
+const TOP_LEVEL_FLAGS: [&str; 4] = ["-h", "--help", "-V", "--version"];
+
+/// The subcommands of `fontgen`. This only shows the help of `fontgen` and reports unknown
+/// subcommands, since each subcommand parses its own arguments.
+fn subcommands() -> App<'static, 'static> {
+    App::new("fontgen")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("A shell utility for converting TrueType or OpenType fonts into bitmapped fonts.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .setting(AppSettings::DeriveDisplayOrder)
+        .subcommand(Opt::clap().name("generate"))
+        .subcommand(BatchOpt::clap().name("batch"))
+        .subcommand(BuildOpt::clap().name("build"))
+        .subcommand(InspectOpt::clap().name("inspect"))
+        .subcommand(AuditOpt::clap().name("audit"))
+        .subcommand(FeaturesOpt::clap().name("features"))
+        .subcommand(ExtractOpt::clap().name("extract"))
+        .subcommand(PreviewOpt::clap().name("preview"))
+        .subcommand(SheetOpt::clap().name("sheet"))
+        .subcommand(MergeOpt::clap().name("merge"))
+        .subcommand(DiffOpt::clap().name("diff"))
+        .subcommand(ReportOpt::clap().name("report"))
+        .subcommand(PlanOpt::clap().name("plan"))
+        .subcommand(PlanUpdateOpt::clap().name("plan-update"))
+        .after_help(
+            "Run `fontgen <SUBCOMMAND> --help` for the options of a subcommand. The options of \
+             `generate` given without a subcommand generate an atlas too, as before subcommands."
+        )
+}
+
+/// Generate the atlases of `fontgen generate`, which are either the one atlas the arguments
+/// describe, or the atlases of a config file.
+fn generate(args: &[OsString]) -> Result<(), Box<dyn std::error::Error>> {
+    let opts = match config_path(args) {
+        Some(config_file) => {
+            read_config(&config_file, &args[1..])?.into_iter().map(Opt::from_iter).collect()
         }
+        None => vec![Opt::from_iter(args.iter().cloned())],
     };
-
-    if bmfa::write_to_file(&atlas_file, &atlas).is_err() {
-        return Err(Box::new(AppError::CouldNotCreateAtlasFile(atlas_file)));
+    // None of the atlases get written unless all of them can be.
+    let mut output_paths: Vec<&PathBuf> = opts.iter().map(|opt| &opt.output_path).collect();
+    output_paths.sort();
+    if let Some(pair) = output_paths.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(Box::new(OptError::SharedOutputFile(pair[0].clone())));
+    }
+    let (skipped, opts): (Vec<Opt>, Vec<Opt>) = opts.into_iter().partition(skips_existing_atlas);
+    for opt in skipped.iter() {
+        eprintln!("Skipping {}, which exists already.", atlas_file(&opt.output_path, opt.format).display());
+    }
+    if opts.is_empty() {
+        return Ok(());
+    }
+    for opt in opts.iter() {
+        verify_opt(opt)?;
+    }
+    if opts.iter().any(|opt| opt.watch) {
+        return watch(args, opts);
+    }
+    let ft = Library::init().expect("Failed to initialize FreeType library.");
+    let mut face_cache = FaceCache::default();
+    for opt in opts.iter() {
+        run_app(&ft, &mut face_cache, opt)?;
     }
 
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let opt = Opt::from_args();
-    verify_opt(&opt)?;
-    run_app(&opt)
+fn main() {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let subcommand = args.get(1).and_then(|arg| arg.to_str()).unwrap_or_default();
+    // Each subcommand gets its name in place of the program name, so its usage names it.
+    let subcommand_args = |name: &str, skip: usize| -> Vec<OsString> {
+        iter::once(OsString::from(name)).chain(args.iter().skip(skip).cloned()).collect()
+    };
+    let result = match subcommand {
+        "generate" => generate(&subcommand_args("fontgen generate", 2)),
+        "batch" => run_batch(&BatchOpt::from_iter(subcommand_args("fontgen batch", 2))),
+        "build" => run_build(&BuildOpt::from_iter(subcommand_args("fontgen build", 2))),
+        "inspect" => run_inspect(&InspectOpt::from_iter(subcommand_args("fontgen inspect", 2))),
+        "audit" => run_audit(&AuditOpt::from_iter(subcommand_args("fontgen audit", 2))),
+        "features" => run_features(&FeaturesOpt::from_iter(subcommand_args("fontgen features", 2))),
+        "extract" => run_extract(&ExtractOpt::from_iter(subcommand_args("fontgen extract", 2))),
+        "preview" => run_preview(&PreviewOpt::from_iter(subcommand_args("fontgen preview", 2))),
+        "sheet" => run_sheet(&SheetOpt::from_iter(subcommand_args("fontgen sheet", 2))),
+        "merge" => run_merge(&MergeOpt::from_iter(subcommand_args("fontgen merge", 2))),
+        "diff" => run_diff(&DiffOpt::from_iter(subcommand_args("fontgen diff", 2))),
+        "report" => run_report(&ReportOpt::from_iter(subcommand_args("fontgen report", 2))),
+        "plan" => run_plan(&PlanOpt::from_iter(subcommand_args("fontgen plan", 2))),
+        "plan-update" => {
+            run_plan_update(&PlanUpdateOpt::from_iter(subcommand_args("fontgen plan-update", 2)))
+        }
+        _ if subcommand.starts_with('-') && !TOP_LEVEL_FLAGS.contains(&subcommand) => {
+            generate(&subcommand_args("fontgen generate", 1))
+        }
+        _ => {
+            // Without a known subcommand, this prints the help or an error and exits.
+            subcommands().get_matches_from(args);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 }
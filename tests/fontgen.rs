@@ -22,8 +22,11 @@ fn generate_a_font_sheet_from_a_ttf_file() -> Result<(), Box<std::error::Error>>
     let path = Path::new("FontMono.bmfa");
 
     assert!(path.exists());
+    assert!(Path::new("FontMono.fontgen.toml").exists());
 
     fs::remove_file(path)?;
+    fs::remove_file("FontMono.meta.json")?;
+    fs::remove_file("FontMono.fontgen.toml")?;
 
     Ok(())
 }
@@ -61,3 +64,2909 @@ fn fontgen_should_reject_padding_larger_than_slot_glyph_size() -> Result<(), Box
 
     Ok(())
 }
+
+/// A stretched font sheet should record its stretch factor in the extended metadata file.
+#[test]
+fn fontgen_should_record_the_stretch_factor_in_the_metadata() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoCondensed.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--stretch")
+        .arg("0.75");
+    cmd.assert().success();
+
+    let atlas_path = Path::new("FreeMonoCondensed.bmfa");
+    let metadata_path = Path::new("FreeMonoCondensed.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    assert!(atlas_path.exists());
+    assert!(metadata.contains("\"stretch\": 0.75"));
+
+    fs::remove_file(atlas_path)?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoCondensed.fontgen.toml")?;
+
+    Ok(())
+}
+
+/// The application should reject a stretch factor that is not positive.
+#[test]
+fn fontgen_should_reject_a_nonpositive_stretch_factor() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoZeroStretch.png")
+        .arg("--stretch")
+        .arg("0");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// The application should refuse to run when the renderer does not satisfy the requirement.
+#[test]
+fn fontgen_should_reject_an_unsatisfied_renderer_requirement() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoFutureRenderer.png")
+        .arg("--require-renderer")
+        .arg("freetype>=99");
+    cmd.assert().failure();
+
+    assert!(!Path::new("FreeMonoFutureRenderer.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should reject a truncated font file with a diagnostic instead of crashing.
+#[test]
+fn fontgen_should_reject_a_truncated_font_file() -> Result<(), Box<std::error::Error>> {
+    let font = fs::read("assets/FreeMono.ttf")?;
+    let truncated_path = Path::new("FreeMonoTruncated.ttf");
+    fs::write(truncated_path, &font[0..font.len() / 2])?;
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg(truncated_path)
+        .arg("--output")
+        .arg("FreeMonoTruncated.png");
+    let assert = cmd.assert();
+    fs::remove_file(truncated_path)?;
+    assert.failure();
+
+    assert!(!Path::new("FreeMonoTruncated.bmfa").exists());
+
+    Ok(())
+}
+
+/// The atlas should hold exactly the characters selected with `--chars`.
+#[test]
+fn fontgen_should_generate_an_atlas_for_the_selected_characters() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoCyrillic.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--chars")
+        .arg("U+0400-U+04FF,U+0020-U+007E");
+    cmd.assert().success();
+
+    let atlas_path = Path::new("FreeMonoCyrillic.bmfa");
+    let atlas = bmfa::load(atlas_path)?;
+    fs::remove_file(atlas_path)?;
+    fs::remove_file("FreeMonoCyrillic.meta.json")?;
+    fs::remove_file("FreeMonoCyrillic.fontgen.toml")?;
+
+    assert!(atlas.metadata.glyph_metadata.contains_key(&0x0416));
+    assert!(!atlas.metadata.glyph_metadata.contains_key(&0x00E9));
+    assert_eq!(atlas.metadata.rows * atlas.metadata.columns, 19 * 19);
+
+    Ok(())
+}
+
+/// The application should reject a character range that ends before it starts.
+#[test]
+fn fontgen_should_reject_a_backwards_character_range() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoBackwards.png")
+        .arg("--chars")
+        .arg("U+04FF-U+0400");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// The atlas should hold exactly the distinct characters used in the charset file.
+#[test]
+fn fontgen_should_generate_an_atlas_for_the_characters_in_a_charset_file() -> Result<(), Box<std::error::Error>> {
+    let charset_path = Path::new("FreeMonoStrings.txt");
+    fs::write(charset_path, "Привет, мир!\nHello, world!\n")?;
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoStrings.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--charset-file")
+        .arg(charset_path);
+    let assert = cmd.assert();
+    fs::remove_file(charset_path)?;
+    assert.success();
+
+    let atlas_path = Path::new("FreeMonoStrings.bmfa");
+    let atlas = bmfa::load(atlas_path)?;
+    fs::remove_file(atlas_path)?;
+    fs::remove_file("FreeMonoStrings.meta.json")?;
+    fs::remove_file("FreeMonoStrings.fontgen.toml")?;
+
+    let glyph_metadata = &atlas.metadata.glyph_metadata;
+    assert!(glyph_metadata.contains_key(&('П' as usize)));
+    assert!(glyph_metadata.contains_key(&('w' as usize)));
+    assert!(!glyph_metadata.contains_key(&('A' as usize)));
+    assert!(!glyph_metadata.contains_key(&('\n' as usize)));
+
+    Ok(())
+}
+
+/// A character set preset should expand to the code points of its script.
+#[test]
+fn fontgen_should_generate_an_atlas_for_a_character_set_preset() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoGreek.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--preset")
+        .arg("ascii,greek");
+    cmd.assert().success();
+
+    let atlas_path = Path::new("FreeMonoGreek.bmfa");
+    let atlas = bmfa::load(atlas_path)?;
+    fs::remove_file(atlas_path)?;
+    fs::remove_file("FreeMonoGreek.meta.json")?;
+    fs::remove_file("FreeMonoGreek.fontgen.toml")?;
+
+    assert!(atlas.metadata.glyph_metadata.contains_key(&('λ' as usize)));
+    assert!(atlas.metadata.glyph_metadata.contains_key(&('A' as usize)));
+    assert!(!atlas.metadata.glyph_metadata.contains_key(&('é' as usize)));
+
+    Ok(())
+}
+
+/// A signed distance field atlas should record its spread in the extended metadata file.
+#[test]
+fn fontgen_should_record_the_sdf_spread_in_the_metadata() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSdf.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--sdf")
+        .arg("--sdf-spread")
+        .arg("4");
+    cmd.assert().success();
+
+    let atlas_path = Path::new("FreeMonoSdf.bmfa");
+    let metadata_path = Path::new("FreeMonoSdf.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    assert!(atlas_path.exists());
+    assert!(metadata.contains("\"sdf_spread\": 4"));
+
+    fs::remove_file(atlas_path)?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoSdf.fontgen.toml")?;
+
+    Ok(())
+}
+
+/// A multi-channel signed distance field atlas should record its kind in the extended metadata file.
+#[test]
+fn fontgen_should_record_a_multi_channel_distance_field_in_the_metadata() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoMsdf.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--msdf")
+        .arg("--sdf-spread")
+        .arg("4");
+    cmd.assert().success();
+
+    let atlas_path = Path::new("FreeMonoMsdf.bmfa");
+    let metadata_path = Path::new("FreeMonoMsdf.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    assert!(atlas_path.exists());
+    assert!(metadata.contains("\"distance_field\": \"msdf\""));
+
+    fs::remove_file(atlas_path)?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoMsdf.fontgen.toml")?;
+
+    Ok(())
+}
+
+/// The application should write the atlas image to a standalone PNG file on request.
+#[test]
+fn fontgen_should_write_a_standalone_atlas_image() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoImage.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--image-out")
+        .arg("FreeMonoImageAtlas.png");
+    cmd.assert().success();
+
+    let image_path = Path::new("FreeMonoImageAtlas.png");
+    let image = fs::read(image_path)?;
+
+    fs::remove_file(image_path)?;
+    fs::remove_file("FreeMonoImage.bmfa")?;
+    fs::remove_file("FreeMonoImage.meta.json")?;
+    fs::remove_file("FreeMonoImage.fontgen.toml")?;
+
+    assert!(image.starts_with(b"\x89PNG"));
+
+    Ok(())
+}
+
+/// The application should write an AngelCode BMFont text descriptor and page image on request.
+#[test]
+fn fontgen_should_write_a_bmfont_text_descriptor() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoBmfont.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("bmfont-text");
+    cmd.assert().success();
+
+    let descriptor = fs::read_to_string("FreeMonoBmfont.fnt")?;
+    let page_path = Path::new("FreeMonoBmfont_0.png");
+    let page_exists = page_path.exists();
+
+    fs::remove_file("FreeMonoBmfont.fnt")?;
+    fs::remove_file(page_path)?;
+    fs::remove_file("FreeMonoBmfont.meta.json")?;
+    fs::remove_file("FreeMonoBmfont.fontgen.toml")?;
+
+    assert!(page_exists);
+    assert!(!Path::new("FreeMonoBmfont.bmfa").exists());
+    assert!(descriptor.contains("page id=0 file=\"FreeMonoBmfont_0.png\""));
+    assert!(descriptor.contains("char id=65 "));
+
+    Ok(())
+}
+
+/// The application should record the unscaled glyph metrics in font units on request.
+#[test]
+fn fontgen_should_record_font_unit_metrics() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoUnits.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--font-units");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoUnits.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    fs::remove_file("FreeMonoUnits.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoUnits.fontgen.toml")?;
+
+    assert!(metadata.contains("\"units_per_em\": 1000"));
+
+    Ok(())
+}
+
+/// The application should write an AngelCode BMFont binary descriptor on request.
+#[test]
+fn fontgen_should_write_a_bmfont_binary_descriptor() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoBmfontBinary.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("bmfont-binary");
+    cmd.assert().success();
+
+    let descriptor = fs::read("FreeMonoBmfontBinary.fnt")?;
+
+    fs::remove_file("FreeMonoBmfontBinary.fnt")?;
+    fs::remove_file("FreeMonoBmfontBinary_0.png")?;
+    fs::remove_file("FreeMonoBmfontBinary.meta.json")?;
+    fs::remove_file("FreeMonoBmfontBinary.fontgen.toml")?;
+
+    assert!(descriptor.starts_with(b"BMF\x03"));
+    // The info block comes first.
+    assert_eq!(descriptor[4], 1);
+
+    Ok(())
+}
+
+/// The application should write an AngelCode BMFont XML descriptor on request.
+#[test]
+fn fontgen_should_write_a_bmfont_xml_descriptor() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoBmfontXml.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("bmfont-xml");
+    cmd.assert().success();
+
+    let descriptor = fs::read_to_string("FreeMonoBmfontXml.fnt")?;
+
+    fs::remove_file("FreeMonoBmfontXml.fnt")?;
+    fs::remove_file("FreeMonoBmfontXml_0.png")?;
+    fs::remove_file("FreeMonoBmfontXml.meta.json")?;
+    fs::remove_file("FreeMonoBmfontXml.fontgen.toml")?;
+
+    assert!(descriptor.starts_with("<?xml"));
+    assert!(descriptor.contains("<page id=\"0\" file=\"FreeMonoBmfontXml_0.png\"/>"));
+    assert!(descriptor.contains("<char id=\"65\" "));
+
+    Ok(())
+}
+
+/// The application should export the glyph outlines as SVG path data on request.
+#[test]
+fn fontgen_should_export_glyph_outlines_as_svg_paths() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoOutlines.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--export-outlines")
+        .arg("svg");
+    cmd.assert().success();
+
+    let outlines_path = Path::new("FreeMonoOutlines.outlines.json");
+    let outlines = fs::read_to_string(outlines_path)?;
+
+    fs::remove_file(outlines_path)?;
+    fs::remove_file("FreeMonoOutlines.bmfa")?;
+    fs::remove_file("FreeMonoOutlines.meta.json")?;
+    fs::remove_file("FreeMonoOutlines.fontgen.toml")?;
+
+    assert!(outlines.contains("\"units_per_em\": 1000"));
+    assert!(outlines.contains("\"path\": \"M"));
+
+    Ok(())
+}
+
+/// The application should write the atlas description in the msdf-atlas-gen JSON layout on request.
+#[test]
+fn fontgen_should_write_an_msdf_atlas_gen_json_layout() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoJson.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("json");
+    cmd.assert().success();
+
+    let layout = fs::read_to_string("FreeMonoJson.json")?;
+    let image_exists = Path::new("FreeMonoJson.png").exists();
+
+    fs::remove_file("FreeMonoJson.json")?;
+    fs::remove_file("FreeMonoJson.png")?;
+    fs::remove_file("FreeMonoJson.meta.json")?;
+    fs::remove_file("FreeMonoJson.fontgen.toml")?;
+
+    assert!(image_exists);
+    assert!(layout.contains("\"yOrigin\": \"top\""));
+    assert!(layout.contains("\"atlasBounds\""));
+
+    Ok(())
+}
+
+/// The application should record the kerning table in the metadata, even when it is empty.
+#[test]
+fn fontgen_should_record_the_kerning_table() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoKerning.png")
+        .arg("--slot-glyph-size")
+        .arg("32");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoKerning.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    fs::remove_file("FreeMonoKerning.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoKerning.fontgen.toml")?;
+
+    // FreeMono is monospaced, so it has no kerning pairs.
+    assert!(metadata.contains("\"kerning\": []"));
+
+    Ok(())
+}
+
+/// The application should export the glyph curves as a data texture and index on request.
+#[test]
+fn fontgen_should_export_a_curve_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoCurves.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--export-curves");
+    cmd.assert().success();
+
+    let index = fs::read_to_string("FreeMonoCurves.curves.json")?;
+    let texture = fs::read("FreeMonoCurves.curves.bin")?;
+
+    fs::remove_file("FreeMonoCurves.curves.json")?;
+    fs::remove_file("FreeMonoCurves.curves.bin")?;
+    fs::remove_file("FreeMonoCurves.bmfa")?;
+    fs::remove_file("FreeMonoCurves.meta.json")?;
+    fs::remove_file("FreeMonoCurves.fontgen.toml")?;
+
+    assert!(index.contains("\"texture_width\": 4096"));
+    // Full rows of four 32 bit floats per texel.
+    assert!(!texture.is_empty());
+    assert_eq!(texture.len() % (4096 * 16), 0);
+
+    Ok(())
+}
+
+/// The application should record the advance of each character in the metadata.
+#[test]
+fn fontgen_should_record_glyph_advances() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoAdvances.png")
+        .arg("--slot-glyph-size")
+        .arg("32");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoAdvances.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    fs::remove_file("FreeMonoAdvances.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoAdvances.fontgen.toml")?;
+
+    // FreeMono advances are 0.6 em, and the glyphs are rendered at 32 pixels.
+    assert!(metadata.contains("\"65\": {\n      \"pixels\": 19,"));
+
+    Ok(())
+}
+
+/// The application should render a hinting comparison image on request.
+#[test]
+fn fontgen_should_write_a_hinting_comparison() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoHinting.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--compare-hinting");
+    cmd.assert().success();
+
+    let comparison_path = Path::new("FreeMonoHinting.hinting.png");
+    let comparison = fs::read(comparison_path)?;
+
+    fs::remove_file(comparison_path)?;
+    fs::remove_file("FreeMonoHinting.bmfa")?;
+    fs::remove_file("FreeMonoHinting.meta.json")?;
+    fs::remove_file("FreeMonoHinting.fontgen.toml")?;
+
+    assert!(comparison.starts_with(b"\x89PNG"));
+
+    Ok(())
+}
+
+/// The application should shrink the atlas to fit the glyphs, and report the chosen size.
+#[test]
+fn fontgen_should_auto_size_the_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoAutoSize.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--chars")
+        .arg("U+0041-U+0045")
+        .arg("--auto-size")
+        .arg("--power-of-two");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+
+    let parameters_path = Path::new("FreeMonoAutoSize.fontgen.toml");
+    let parameters = fs::read_to_string(parameters_path)?;
+
+    fs::remove_file("FreeMonoAutoSize.bmfa")?;
+    fs::remove_file("FreeMonoAutoSize.meta.json")?;
+    fs::remove_file(parameters_path)?;
+
+    // Five letters and the space fit in three columns and two rows of 32 pixel slots.
+    assert!(String::from_utf8(output.stderr)?.contains("Atlas size: 128x64 pixels"));
+    assert!(parameters.contains("rows = 2\n"));
+    assert!(parameters.contains("columns = 4\n"));
+
+    Ok(())
+}
+
+/// The application should spill glyphs that do not fit in the maximum size onto more pages.
+#[test]
+fn fontgen_should_write_multiple_pages() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPages.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--max-size")
+        .arg("256")
+        .arg("--format")
+        .arg("bmfont-text");
+    cmd.assert().success();
+
+    let descriptor_path = Path::new("FreeMonoPages.fnt");
+    let descriptor = fs::read_to_string(descriptor_path)?;
+    let page_images: Vec<Vec<u8>> = (0..4)
+        .map(|page| fs::read(format!("FreeMonoPages_{}.png", page)))
+        .collect::<Result<_, _>>()?;
+
+    for page in 0..4 {
+        fs::remove_file(format!("FreeMonoPages_{}.png", page))?;
+    }
+    fs::remove_file(descriptor_path)?;
+    fs::remove_file("FreeMonoPages.meta.json")?;
+    fs::remove_file("FreeMonoPages.fontgen.toml")?;
+
+    // Each 256 pixel page holds 63 of the 32 pixel glyphs after the space.
+    assert!(descriptor.contains("scaleW=256 scaleH=256 pages=4"));
+    assert!(descriptor.contains("page id=3 file=\"FreeMonoPages_3.png\""));
+    assert!(page_images.iter().all(|image| image.starts_with(b"\x89PNG")));
+
+    Ok(())
+}
+
+/// The `--scales` and `--ruby-scale` atlases should be spread over pages of the maximum size too,
+/// and a maximum size too small for the slots of the largest scale should be rejected.
+#[test]
+fn fontgen_should_write_multiple_pages_for_scaled_atlases() -> Result<(), Box<std::error::Error>> {
+    let generate = |output: &str, max_size: &str| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--slot-glyph-size")
+            .arg("32")
+            .arg("--chars")
+            .arg("U+0041-U+005A")
+            .arg("--max-size")
+            .arg(max_size)
+            .arg("--scales")
+            .arg("2")
+            .arg("--ruby-scale")
+            .arg("0.5");
+
+        Ok(cmd.output()?)
+    };
+    let paged = generate("FreeMonoScaledPages.bmfa", "128")?;
+    let scaled_metadata = fs::read_to_string("FreeMonoScaledPages@2x.meta.json");
+    let scaled_first_page = Path::new("FreeMonoScaledPages@2x_0.bmfa").exists();
+    let scaled_single_page = Path::new("FreeMonoScaledPages@2x.bmfa").exists();
+    let ruby_single_page = Path::new("FreeMonoScaledPages.ruby.bmfa").exists();
+    let too_small = generate("FreeMonoScaledTooSmall.bmfa", "100")?;
+    for entry in fs::read_dir(".")? {
+        let path = entry?.path();
+        if path.to_string_lossy().contains("FreeMonoScaledPages") {
+            fs::remove_file(path)?;
+        }
+    }
+
+    assert!(paged.status.success());
+    let scaled_metadata = scaled_metadata?;
+    assert!(scaled_metadata.contains("\"FreeMonoScaledPages@2x_1.bmfa\""));
+    assert!(scaled_metadata.contains("\"page_sizes\": [\n    [\n      128,\n      128\n    ]"));
+    assert!(scaled_first_page);
+    assert!(!scaled_single_page);
+    // Half size slots of the ruby atlas fit on a single page.
+    assert!(ruby_single_page);
+    assert!(!too_small.status.success());
+    assert!(String::from_utf8(too_small.stderr)?.contains("two glyph slots (64 pixels)"));
+    assert!(!Path::new("FreeMonoScaledTooSmall.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should generate a companion atlas for ruby annotation text on request.
+#[test]
+fn fontgen_should_write_a_ruby_companion_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoRuby.png")
+        .arg("--slot-glyph-size")
+        .arg("64")
+        .arg("--ruby-scale")
+        .arg("0.5");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoRuby.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+    let ruby_metadata_path = Path::new("FreeMonoRuby.ruby.meta.json");
+    let ruby_metadata = fs::read_to_string(ruby_metadata_path)?;
+    let ruby_atlas_path = Path::new("FreeMonoRuby.ruby.bmfa");
+    let ruby_atlas_exists = ruby_atlas_path.is_file();
+
+    fs::remove_file("FreeMonoRuby.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoRuby.fontgen.toml")?;
+    fs::remove_file(ruby_atlas_path)?;
+    fs::remove_file(ruby_metadata_path)?;
+
+    assert!(ruby_atlas_exists);
+    assert!(metadata.contains("\"ruby_atlas\": \"FreeMonoRuby.ruby.bmfa\""));
+    assert!(ruby_metadata.contains("\"tier\": \"ruby\""));
+    // The ruby glyphs are rendered at 32 pixels, half the size of the base glyphs.
+    assert!(ruby_metadata.contains("\"65\": {\n      \"pixels\": 19,"));
+
+    Ok(())
+}
+
+/// The application should render ranges of characters with their own render modes.
+#[test]
+fn fontgen_should_record_per_character_render_modes() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoRenderModes.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--render-mode")
+        .arg("U+0041-U+005A=msdf");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoRenderModes.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    fs::remove_file("FreeMonoRenderModes.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoRenderModes.fontgen.toml")?;
+
+    assert!(metadata.contains("\"64\": \"coverage\""));
+    assert!(metadata.contains("\"65\": \"msdf\""));
+
+    Ok(())
+}
+
+/// The application should record the size of each page of a multi-page atlas.
+#[test]
+fn fontgen_should_record_the_size_of_each_page() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPageSizes.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--max-size")
+        .arg("256");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoPageSizes.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    for page in 0..4 {
+        fs::remove_file(format!("FreeMonoPageSizes_{}.bmfa", page))?;
+    }
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoPageSizes.fontgen.toml")?;
+
+    assert!(metadata.contains("\"page_sizes\": ["));
+    assert!(metadata.contains("[\n      256,\n      256\n    ]\n  ]"));
+
+    Ok(())
+}
+
+/// The application should shrink the last page of a multi-page atlas on request.
+#[test]
+fn fontgen_should_trim_the_last_page() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoTrimmed.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--max-size")
+        .arg("256")
+        .arg("--trim-last-page");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoTrimmed.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    for page in 0..4 {
+        fs::remove_file(format!("FreeMonoTrimmed_{}.bmfa", page))?;
+    }
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoTrimmed.fontgen.toml")?;
+
+    // The 33 glyphs left for the last page, and the space, fit in five rows of the eight columns.
+    assert!(metadata.contains("[\n      256,\n      160\n    ]\n  ]"));
+
+    Ok(())
+}
+
+/// The application should write the pages of a multi-page atlas to the layers of a KTX2 texture
+/// array on request.
+#[test]
+fn fontgen_should_write_the_pages_to_a_texture_array() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoArray.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--max-size")
+        .arg("256")
+        .arg("--texture-array");
+    cmd.assert().success();
+
+    let texture_array = fs::read("FreeMonoArray.ktx2")?;
+    let metadata = fs::read_to_string("FreeMonoArray.meta.json")?;
+
+    for page in 0..4 {
+        fs::remove_file(format!("FreeMonoArray_{}.bmfa", page))?;
+    }
+    fs::remove_file("FreeMonoArray.ktx2")?;
+    fs::remove_file("FreeMonoArray.meta.json")?;
+    fs::remove_file("FreeMonoArray.fontgen.toml")?;
+
+    let field = |index: usize| {
+        let bytes = &texture_array[12 + 4 * index..][..4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    assert_eq!(&texture_array[..12], b"\xABKTX 20\xBB\r\n\x1A\n");
+    // R8G8B8A8_UNORM pages of 256x256 pixels in four layers, with a single mip level.
+    assert_eq!((field(0), field(2), field(3), field(5), field(7)), (37, 256, 256, 4, 1));
+    assert!(texture_array.len() > 4 * 256 * 256 * 4);
+    assert!(metadata.contains("\"texture_array\": \"FreeMonoArray.ktx2\""));
+
+    Ok(())
+}
+
+/// The application should size glyphs from a point size and resolution.
+#[test]
+fn fontgen_should_size_glyphs_in_points() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPoints.png")
+        .arg("--size-pt")
+        .arg("12")
+        .arg("--dpi")
+        .arg("144")
+        .arg("--padding")
+        .arg("2");
+    cmd.assert().success();
+
+    let parameters_path = Path::new("FreeMonoPoints.fontgen.toml");
+    let parameters = fs::read_to_string(parameters_path)?;
+
+    fs::remove_file("FreeMonoPoints.bmfa")?;
+    fs::remove_file("FreeMonoPoints.meta.json")?;
+    fs::remove_file(parameters_path)?;
+
+    // Twelve points at 144 dpi is an em of 24 pixels, plus the padding.
+    assert!(parameters.contains("slot_glyph_size = 26\n"));
+    assert!(parameters.contains("dpi = 144\n"));
+
+    Ok(())
+}
+
+/// The application should generate atlases at several scales in one run.
+#[test]
+fn fontgen_should_write_atlases_at_several_scales() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoScales.png")
+        .arg("--slot-glyph-size")
+        .arg("16")
+        .arg("--scales")
+        .arg("1,2");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoScales.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+    let scaled_metadata_path = Path::new("FreeMonoScales@2x.meta.json");
+    let scaled_metadata = fs::read_to_string(scaled_metadata_path)?;
+
+    fs::remove_file("FreeMonoScales.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoScales.fontgen.toml")?;
+    fs::remove_file("FreeMonoScales@2x.bmfa")?;
+    fs::remove_file(scaled_metadata_path)?;
+
+    assert!(metadata.contains("\"scaled_atlases\": [\n    \"FreeMonoScales@2x.bmfa\"\n  ]"));
+    assert!(scaled_metadata.contains("\"scale\": 2.0"));
+    // The 2x glyphs are rendered at 32 pixels.
+    assert!(scaled_metadata.contains("\"65\": {\n      \"pixels\": 19,"));
+
+    Ok(())
+}
+
+/// The application should accept a chain of fallback typefaces, and draw the characters no
+/// typeface covers from the primary one.
+#[test]
+fn fontgen_should_accept_fallback_typefaces() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoFallback.png")
+        .arg("--chars")
+        .arg("U+0041-U+005A,U+4E00");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoFallback.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+    let parameters_path = Path::new("FreeMonoFallback.fontgen.toml");
+    let parameters = fs::read_to_string(parameters_path)?;
+
+    fs::remove_file("FreeMonoFallback.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file(parameters_path)?;
+
+    assert!(parameters.contains("input = [\"assets/FreeMono.ttf\", \"assets/FreeMono.ttf\"]\n"));
+    assert!(!metadata.contains("glyph_typefaces"));
+
+    Ok(())
+}
+
+/// The application should write atlases to paths that are not valid UTF-8, and should not leave
+/// any temporary files behind.
+#[cfg(unix)]
+#[test]
+fn fontgen_should_write_to_paths_that_are_not_utf8() -> Result<(), Box<std::error::Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg(OsStr::from_bytes(b"FreeMono\xffPath.png"))
+        .arg("--format")
+        .arg("bmfont-text");
+    cmd.assert().success();
+
+    let descriptor_path = Path::new(OsStr::from_bytes(b"FreeMono\xffPath.fnt"));
+    let descriptor = fs::read_to_string(descriptor_path)?;
+    let page_path = Path::new(OsStr::from_bytes(b"FreeMono\xffPath_0.png"));
+    let page_exists = page_path.exists();
+    let temporary_files = fs::read_dir(".")?
+        .filter(|entry| entry.as_ref().map_or(false, |entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            file_name.starts_with(".fontgen-") && file_name.contains("FreeMono\u{FFFD}Path")
+        }))
+        .count();
+
+    fs::remove_file(descriptor_path)?;
+    fs::remove_file(page_path)?;
+    fs::remove_file(OsStr::from_bytes(b"FreeMono\xffPath.meta.json"))?;
+    fs::remove_file(OsStr::from_bytes(b"FreeMono\xffPath.fontgen.toml"))?;
+
+    assert!(page_exists);
+    assert!(descriptor.contains("page id=0 file=\"FreeMono\u{FFFD}Path_0.png\"\n"));
+    assert_eq!(temporary_files, 0);
+
+    Ok(())
+}
+
+/// The face index should pick a face out of a TrueType collection, and an index past the last
+/// face should be reported as such. The second face of the collection is named FreeMonoB.
+#[test]
+fn fontgen_should_generate_an_atlas_from_a_face_of_a_collection() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMonoCollection.ttc")
+        .arg("--face-index")
+        .arg("1")
+        .arg("--output")
+        .arg("FreeMonoCollection.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--coverage-report")
+        .arg("FreeMonoCollection.coverage.json");
+    let generated = cmd.output()?;
+    let coverage_report = fs::read_to_string("FreeMonoCollection.coverage.json");
+    let parameters = fs::read_to_string("FreeMonoCollection.fontgen.toml");
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMonoCollection.ttc")
+        .arg("--face-index")
+        .arg("2")
+        .arg("--output")
+        .arg("FreeMonoCollectionMissing.bmfa");
+    let missing = cmd.output()?;
+    for extension in ["bmfa", "meta.json", "fontgen.toml", "coverage.json"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoCollection.{}", extension));
+    }
+
+    assert!(generated.status.success());
+    assert!(coverage_report?.contains("\"family\": \"FreeMonoB\""));
+    assert!(parameters?.contains("face_index = 1"));
+    assert!(!missing.status.success());
+    assert!(String::from_utf8(missing.stderr)?.contains("has no face 2"));
+    assert!(!Path::new("FreeMonoCollectionMissing.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should not leave part of the output files behind when it cannot write all
+/// of them.
+#[test]
+fn fontgen_should_roll_back_the_output_files_on_failure() -> Result<(), Box<std::error::Error>> {
+    // A directory in place of the metadata file makes the run fail after the atlas is written.
+    fs::create_dir_all("FreeMonoRollback.meta.json/blocked")?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoRollback.png");
+    let assert = cmd.assert();
+
+    let atlas_exists = Path::new("FreeMonoRollback.bmfa").exists();
+    let parameters_exist = Path::new("FreeMonoRollback.fontgen.toml").exists();
+
+    fs::remove_dir_all("FreeMonoRollback.meta.json")?;
+
+    assert.failure();
+    assert!(!atlas_exists);
+    assert!(!parameters_exist);
+
+    Ok(())
+}
+
+/// The application should refuse to set the axes of a typeface that is not a variable font.
+#[test]
+fn fontgen_should_reject_variations_of_static_fonts() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoVariation.png")
+        .arg("--variation")
+        .arg("wght=650,wdth=85");
+    let output = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("is not a variable font"));
+    assert!(!Path::new("FreeMonoVariation.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should draw substituted characters from their own typeface, and record
+/// where each of them came from.
+#[test]
+fn fontgen_should_substitute_glyphs_from_another_typeface() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSubstitution.png")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--substitute")
+        .arg("U+0026,U+0041=assets/FreeMono.ttf");
+    cmd.assert().success();
+
+    let metadata_path = Path::new("FreeMonoSubstitution.meta.json");
+    let metadata = fs::read_to_string(metadata_path)?;
+
+    fs::remove_file("FreeMonoSubstitution.bmfa")?;
+    fs::remove_file(metadata_path)?;
+    fs::remove_file("FreeMonoSubstitution.fontgen.toml")?;
+
+    // The ampersand is not in the atlas, so only the capital A gets substituted.
+    assert!(metadata.contains("\"glyph_typefaces\": {\n    \"65\": \"FreeMono\"\n  }"));
+
+    Ok(())
+}
+
+/// Optical sizing should leave typefaces without an optical size axis alone, even for the
+/// companion atlases rendered at other sizes.
+#[test]
+fn fontgen_should_skip_optical_sizing_of_static_fonts() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoOpticalSize.png")
+        .arg("--ruby-scale")
+        .arg("0.5")
+        .arg("--optical-sizing")
+        .arg("auto");
+    cmd.assert().success();
+
+    let metadata = fs::read_to_string("FreeMonoOpticalSize.meta.json")?;
+    let ruby_metadata = fs::read_to_string("FreeMonoOpticalSize.ruby.meta.json")?;
+    let parameters = fs::read_to_string("FreeMonoOpticalSize.fontgen.toml")?;
+
+    fs::remove_file("FreeMonoOpticalSize.bmfa")?;
+    fs::remove_file("FreeMonoOpticalSize.meta.json")?;
+    fs::remove_file("FreeMonoOpticalSize.ruby.bmfa")?;
+    fs::remove_file("FreeMonoOpticalSize.ruby.meta.json")?;
+    fs::remove_file("FreeMonoOpticalSize.fontgen.toml")?;
+
+    assert!(!metadata.contains("optical_size"));
+    assert!(!ruby_metadata.contains("optical_size"));
+    assert!(parameters.contains("optical_sizing = \"auto\""));
+
+    Ok(())
+}
+
+/// The corrections of an advance override file should replace the metrics of the typeface, scaled
+/// along with the glyphs of a companion atlas, and get recorded in the metadata.
+#[test]
+fn fontgen_should_apply_advance_overrides() -> Result<(), Box<std::error::Error>> {
+    let overrides_path = Path::new("FreeMonoOverrides.toml");
+    let overrides = "\"U+0020\" = { advance = 6 }\n\"U+0041\" = { x_offset = 1, y_offset = -1 }\n";
+    fs::write(overrides_path, overrides)?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoOverrides.png")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--ruby-scale")
+        .arg("0.5")
+        .arg("--advance-overrides")
+        .arg(overrides_path);
+    let output = cmd.output()?;
+
+    let metadata = fs::read_to_string("FreeMonoOverrides.meta.json");
+    let ruby_metadata = fs::read_to_string("FreeMonoOverrides.ruby.meta.json");
+    let parameters = fs::read_to_string("FreeMonoOverrides.fontgen.toml");
+    for file in ["bmfa", "meta.json", "ruby.bmfa", "ruby.meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoOverrides.{}", file));
+    }
+    fs::remove_file(overrides_path)?;
+
+    assert!(output.status.success());
+    let metadata = metadata?;
+    let ruby_metadata = ruby_metadata?;
+    assert!(metadata.contains("\"32\": {\n      \"pixels\": 6,"));
+    assert!(ruby_metadata.contains("\"32\": {\n      \"pixels\": 3,"));
+    assert!(metadata.contains("\"glyph_overrides\": {\n    \"32\": {\n      \"advance\": 6,"));
+    assert!(metadata.contains("\"65\": {\n      \"x_offset\": 1,\n      \"y_offset\": -1\n    }"));
+    assert!(parameters?.contains("advance_overrides = \"FreeMonoOverrides.toml\""));
+
+    Ok(())
+}
+
+/// The size report should compare the metrics of every size generated in the run.
+#[test]
+fn fontgen_should_report_the_metrics_of_each_size() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSizeReport.png")
+        .arg("--scales")
+        .arg("1,2")
+        .arg("--size-report");
+    cmd.assert().success();
+
+    let report = fs::read_to_string("FreeMonoSizeReport.sizes.json")?;
+
+    fs::remove_file("FreeMonoSizeReport.bmfa")?;
+    fs::remove_file("FreeMonoSizeReport.meta.json")?;
+    fs::remove_file("FreeMonoSizeReport@2x.bmfa")?;
+    fs::remove_file("FreeMonoSizeReport@2x.meta.json")?;
+    fs::remove_file("FreeMonoSizeReport.sizes.json")?;
+    fs::remove_file("FreeMonoSizeReport.fontgen.toml")?;
+
+    assert!(report.contains("\"units_per_em\": 1000"));
+    assert_eq!(report.matches("\"tier\": \"base\"").count(), 2);
+    assert!(report.contains("\"scale\": 2.0"));
+    assert!(report.contains("\"x_height\""));
+    assert!(report.contains("\"text_width_error\""));
+
+    Ok(())
+}
+
+/// The application should refuse to generate an atlas that another run is generating, unless
+/// told to wait for the other run to finish.
+#[test]
+fn fontgen_should_wait_for_locked_outputs() -> Result<(), Box<std::error::Error>> {
+    fs::create_dir(".fontgen-FreeMonoLocked.lock")?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoLocked.png");
+    let output = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let atlas_exists = Path::new("FreeMonoLocked.bmfa").exists();
+
+    let unlock = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        fs::remove_dir_all(".fontgen-FreeMonoLocked.lock")
+    });
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoLocked.png")
+        .arg("--wait-for-lock")
+        .arg("30");
+    cmd.assert().success();
+    unlock.join().unwrap()?;
+    let lock_exists = Path::new(".fontgen-FreeMonoLocked.lock").exists();
+
+    fs::remove_file("FreeMonoLocked.bmfa")?;
+    fs::remove_file("FreeMonoLocked.meta.json")?;
+    fs::remove_file("FreeMonoLocked.fontgen.toml")?;
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("Another run is generating"));
+    assert!(!atlas_exists);
+    assert!(!lock_exists);
+
+    Ok(())
+}
+
+/// The application should take over the lock of a run that is no longer running.
+#[test]
+fn fontgen_should_reclaim_the_lock_of_a_run_that_got_killed() -> Result<(), Box<std::error::Error>> {
+    // No process gets the largest process id.
+    fs::create_dir(".fontgen-FreeMonoStaleLock.lock")?;
+    fs::write(".fontgen-FreeMonoStaleLock.lock/owner", format!("{}\n", u32::max_value()))?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoStaleLock.png");
+    let output = cmd.output()?;
+    let atlas_exists = Path::new("FreeMonoStaleLock.bmfa").exists();
+    let lock_exists = Path::new(".fontgen-FreeMonoStaleLock.lock").exists();
+    let _ = fs::remove_dir_all(".fontgen-FreeMonoStaleLock.lock");
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoStaleLock.{}", extension));
+    }
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(atlas_exists);
+    assert!(!lock_exists);
+
+    Ok(())
+}
+
+/// The application should write a single channel atlas image on request, and refuse to for the
+/// `bmfa` format, which always stores RGBA images.
+#[test]
+fn fontgen_should_write_a_single_channel_atlas_image() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoA8.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("json")
+        .arg("--channels")
+        .arg("a8");
+    cmd.assert().success();
+
+    let image = fs::read("FreeMonoA8.png")?;
+    let metadata = fs::read_to_string("FreeMonoA8.meta.json")?;
+
+    fs::remove_file("FreeMonoA8.json")?;
+    fs::remove_file("FreeMonoA8.png")?;
+    fs::remove_file("FreeMonoA8.meta.json")?;
+    fs::remove_file("FreeMonoA8.fontgen.toml")?;
+
+    // The color type in the header of a grayscale PNG file is zero.
+    assert_eq!(image[25], 0);
+    assert!(metadata.contains("\"channels\": \"a8\""));
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoA8.png")
+        .arg("--channels")
+        .arg("a8");
+    cmd.assert().failure();
+
+    assert!(!Path::new("FreeMonoA8.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should record the script and bidirectional class of each character.
+#[test]
+fn fontgen_should_record_the_script_of_each_character() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoScripts.png")
+        .arg("--chars")
+        .arg("U+0031,U+0041,U+05D0");
+    cmd.assert().success();
+
+    let metadata = fs::read_to_string("FreeMonoScripts.meta.json")?;
+
+    fs::remove_file("FreeMonoScripts.bmfa")?;
+    fs::remove_file("FreeMonoScripts.meta.json")?;
+    fs::remove_file("FreeMonoScripts.fontgen.toml")?;
+
+    let properties = |script: &str, bidi_class: &str| {
+        format!("\"script\": \"{}\",\n      \"bidi_class\": \"{}\"", script, bidi_class)
+    };
+    assert!(metadata.contains(&format!("\"49\": {{\n      {}", properties("Zyyy", "EN"))));
+    assert!(metadata.contains(&format!("\"65\": {{\n      {}", properties("Latn", "L"))));
+    assert!(metadata.contains(&format!("\"1488\": {{\n      {}", properties("Hebr", "R"))));
+
+    Ok(())
+}
+
+/// The application should split a locale bundle into a shared base atlas and a supplement atlas
+/// per locale, and skip the locales the typeface does not cover.
+#[test]
+fn fontgen_should_write_a_locale_bundle() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoBundle.png")
+        .arg("--slot-glyph-size")
+        .arg("24")
+        .arg("--bundle")
+        .arg("el,th");
+    cmd.assert().success();
+
+    let manifest = fs::read_to_string("FreeMonoBundle.bundle.json")?;
+    let greek_metadata = fs::read_to_string("FreeMonoBundle.el.meta.json")?;
+    let thai_exists = Path::new("FreeMonoBundle.th.bmfa").exists();
+
+    fs::remove_file("FreeMonoBundle.bmfa")?;
+    fs::remove_file("FreeMonoBundle.meta.json")?;
+    fs::remove_file("FreeMonoBundle.fontgen.toml")?;
+    fs::remove_file("FreeMonoBundle.bundle.json")?;
+    fs::remove_file("FreeMonoBundle.el.bmfa")?;
+    fs::remove_file("FreeMonoBundle.el.meta.json")?;
+
+    assert!(!thai_exists);
+    assert!(manifest.contains("\"atlas\": \"FreeMonoBundle.bmfa\""));
+    assert!(manifest.contains("\"locale\": \"el\""));
+    assert!(manifest.contains("\"base\": \"FreeMonoBundle.bmfa\""));
+    assert!(!manifest.contains("\"locale\": \"th\""));
+    // The supplement leaves the Latin letters to the base atlas.
+    assert!(greek_metadata.contains("\"937\""));
+    assert!(!greek_metadata.contains("\"65\""));
+
+    Ok(())
+}
+
+/// The coverage report should list the characters the typeface has no glyphs for, and skipping
+/// them should leave them out of the atlas.
+#[test]
+fn fontgen_should_report_and_skip_missing_glyphs() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoCoverage.png")
+        .arg("--chars")
+        .arg("U+0041-U+0043,U+0E01-U+0E02")
+        .arg("--on-missing")
+        .arg("skip")
+        .arg("--coverage-report")
+        .arg("FreeMonoCoverage.coverage.json");
+    cmd.assert().success();
+
+    let report = fs::read_to_string("FreeMonoCoverage.coverage.json")?;
+    let metadata = fs::read_to_string("FreeMonoCoverage.meta.json")?;
+
+    fs::remove_file("FreeMonoCoverage.bmfa")?;
+    fs::remove_file("FreeMonoCoverage.meta.json")?;
+    fs::remove_file("FreeMonoCoverage.fontgen.toml")?;
+    fs::remove_file("FreeMonoCoverage.coverage.json")?;
+
+    assert!(report.contains("\"requested\": 5"));
+    assert!(report.contains("\"covered\": 3"));
+    assert!(report.contains("3585,\n    3586"));
+    assert!(report.contains("\"on_missing\": \"skipped\""));
+    assert!(metadata.contains("\"65\""));
+    assert!(!metadata.contains("\"3585\""));
+
+    Ok(())
+}
+
+/// The application should fail on characters the typeface has no glyphs for when asked to.
+#[test]
+fn fontgen_should_fail_on_missing_glyphs() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoMissing.png")
+        .arg("--chars")
+        .arg("U+0041,U+0E01")
+        .arg("--on-missing")
+        .arg("fail");
+    cmd.assert().failure();
+
+    assert!(!Path::new("FreeMonoMissing.bmfa").exists());
+
+    Ok(())
+}
+
+/// A second run rendering the same glyphs should reuse the glyphs cached by the first one,
+/// even when it packs them differently.
+#[test]
+fn fontgen_should_reuse_cached_glyphs() -> Result<(), Box<std::error::Error>> {
+    let cache_dir = Path::new("FreeMonoCache.cache");
+    for &(output, packing) in [("FreeMonoCached.png", "grid"), ("FreeMonoRepacked.png", "skyline")].iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg("U+0041-U+005A")
+            .arg("--pack")
+            .arg(packing)
+            .arg("--cache-dir")
+            .arg(cache_dir)
+            .arg("--verbose");
+        let stderr = String::from_utf8(cmd.output()?.stderr)?;
+
+        assert_eq!(stderr.contains("Reused the glyphs cached in"), packing == "skyline");
+    }
+
+    let entries = fs::read_dir(cache_dir)?.count();
+    let cached_metadata = fs::read_to_string("FreeMonoCached.meta.json")?;
+    let repacked_metadata = fs::read_to_string("FreeMonoRepacked.meta.json")?;
+
+    for stem in ["FreeMonoCached", "FreeMonoRepacked"].iter() {
+        fs::remove_file(format!("{}.bmfa", stem))?;
+        fs::remove_file(format!("{}.meta.json", stem))?;
+        fs::remove_file(format!("{}.fontgen.toml", stem))?;
+    }
+    fs::remove_dir_all(cache_dir)?;
+
+    assert_eq!(entries, 1);
+    assert_eq!(cached_metadata, repacked_metadata);
+
+    Ok(())
+}
+
+/// Verbose runs should report the rasterization time, and at the second level of verbosity, the
+/// fallbacks used and the slowest glyphs.
+#[test]
+fn fontgen_should_report_the_slowest_glyphs() -> Result<(), Box<std::error::Error>> {
+    let generate = |output: &str, verbosity: &str| -> Result<String, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg("U+0041-U+005A,U+0061")
+            .arg("--synthetic-small-caps")
+            .arg("scale=0.75")
+            .arg(verbosity);
+        let stderr = String::from_utf8(cmd.output()?.stderr)?;
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(Path::new(output).with_extension(extension));
+        }
+
+        Ok(stderr)
+    };
+    let summary = generate("FreeMonoTelemetry.bmfa", "-v")?;
+    let details = generate("FreeMonoTelemetryDetails.bmfa", "-vv")?;
+
+    // The 27 characters besides the space.
+    assert!(summary.contains("Rasterized 27 glyphs in "));
+    assert!(!summary.contains("The slowest glyphs"));
+    assert!(details.contains("Rasterized 27 glyphs in "));
+    let fallback = "Used a synthetic small capital for the character U+0061 'a' (LATIN SMALL LETTER A).\n";
+    assert!(details.contains(fallback));
+    let slowest: Vec<&str> = details.lines()
+        .skip_while(|line| *line != "The slowest glyphs to rasterize were:")
+        .skip(1)
+        .take_while(|line| line.ends_with(')'))
+        .collect();
+    assert_eq!(slowest.len(), 10);
+    assert!(slowest.iter().all(|line| line.contains(" ms  U+00")));
+
+    Ok(())
+}
+
+/// In watch mode, changing the character set file should write the atlas again with the new
+/// characters.
+#[test]
+fn fontgen_should_rewrite_the_atlas_when_a_watched_file_changes() -> Result<(), Box<std::error::Error>> {
+    fs::write("FreeMonoWatched.txt", "AB")?;
+    let mut child = Command::cargo_bin("fontgen")?
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoWatched.png")
+        .arg("--charset-file")
+        .arg("FreeMonoWatched.txt")
+        .arg("--watch")
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    let metadata_path = Path::new("FreeMonoWatched.meta.json");
+    let wait_for = |contents: &str| {
+        for _ in 0..300 {
+            if fs::read_to_string(metadata_path).map_or(false, |metadata| metadata.contains(contents)) {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        false
+    };
+    let first_run = wait_for("\"66\"");
+    // File systems with coarse modification times need a moment before the change shows.
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write("FreeMonoWatched.txt", "ABZ")?;
+    let second_run = wait_for("\"90\"");
+    child.kill()?;
+    child.wait()?;
+
+    fs::remove_file("FreeMonoWatched.txt")?;
+    fs::remove_file("FreeMonoWatched.bmfa")?;
+    fs::remove_file("FreeMonoWatched.meta.json")?;
+    fs::remove_file("FreeMonoWatched.fontgen.toml")?;
+
+    assert!(first_run);
+    assert!(second_run);
+
+    Ok(())
+}
+
+/// A batch should write an atlas named after each font file in the directory, and skip the
+/// other files.
+#[test]
+fn fontgen_should_write_an_atlas_for_each_font_in_a_batch() -> Result<(), Box<std::error::Error>> {
+    let fonts_dir = Path::new("FreeMonoBatch.fonts");
+    let out_dir = Path::new("FreeMonoBatch.atlases");
+    fs::create_dir_all(fonts_dir)?;
+    fs::copy("assets/FreeMono.ttf", fonts_dir.join("FreeMono.ttf"))?;
+    fs::copy("assets/FreeMono.ttf", fonts_dir.join("FreeMonoCopy.otf"))?;
+    fs::write(fonts_dir.join("README.txt"), "Not a font.")?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("batch")
+        .arg(fonts_dir)
+        .arg("--out")
+        .arg(out_dir)
+        .arg("--jobs")
+        .arg("2")
+        .arg("--chars")
+        .arg("U+0041-U+005A");
+    let output = cmd.output()?;
+
+    let mut atlases: Vec<_> = fs::read_dir(out_dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().into_string().unwrap_or_default()))
+        .collect::<Result<_, _>>()?;
+    atlases.sort();
+    fs::remove_dir_all(fonts_dir)?;
+    fs::remove_dir_all(out_dir)?;
+
+    assert!(output.status.success());
+    assert_eq!(atlases, vec![
+        "FreeMono.bmfa", "FreeMono.fontgen.toml", "FreeMono.meta.json",
+        "FreeMonoCopy.bmfa", "FreeMonoCopy.fontgen.toml", "FreeMonoCopy.meta.json",
+    ]);
+
+    Ok(())
+}
+
+/// A batch names the files of each atlas itself, so it should reject the output options of a
+/// single atlas.
+#[test]
+fn fontgen_should_reject_output_options_in_a_batch() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("batch")
+        .arg("assets")
+        .arg("--out")
+        .arg("FreeMonoRejectedBatch.atlases")
+        .arg("--output")
+        .arg("FreeMono.bmfa");
+    cmd.assert().failure();
+
+    assert!(!Path::new("FreeMonoRejectedBatch.atlases").exists());
+
+    Ok(())
+}
+
+/// A config file should describe several atlases at once, with the options given on the command
+/// line replacing its values.
+#[test]
+fn fontgen_should_write_the_atlases_of_a_config_file() -> Result<(), Box<std::error::Error>> {
+    let config_path = Path::new("FreeMonoConfig.toml");
+    fs::write(config_path, concat!(
+        "input = \"assets/FreeMono.ttf\"\n",
+        "chars = \"U+0041-U+005A\"\n",
+        "padding = 2\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoConfigA.bmfa\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoConfigB.bmfa\"\n",
+        "slot_glyph_size = 32\n",
+    ))?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--config")
+        .arg(config_path)
+        .arg("--padding")
+        .arg("5");
+    let output = cmd.output()?;
+
+    let parameters = fs::read_to_string("FreeMonoConfigB.fontgen.toml");
+    let written: Vec<bool> = ["FreeMonoConfigA.bmfa", "FreeMonoConfigB.bmfa"].iter()
+        .map(|path| Path::new(path).exists())
+        .collect();
+    fs::remove_file(config_path)?;
+    for stem in ["FreeMonoConfigA", "FreeMonoConfigB"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(output.status.success());
+    assert_eq!(written, vec![true, true]);
+    assert!(parameters?.contains("padding = 5"));
+
+    Ok(())
+}
+
+/// The atlases of a config file sharing a font file at different sizes should come out the same
+/// as atlases generated on their own, even though the config file only loads the font once.
+#[test]
+fn fontgen_should_reuse_the_fonts_the_atlases_of_a_config_file_share() -> Result<(), Box<std::error::Error>> {
+    let config_path = Path::new("FreeMonoSharedFace.toml");
+    fs::write(config_path, concat!(
+        "input = \"assets/FreeMono.ttf\"\n",
+        "chars = \"U+0041-U+005A\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoSharedFaceLarge.bmfa\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoSharedFaceSmall.bmfa\"\n",
+        "slot_glyph_size = 32\n",
+    ))?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--config").arg(config_path);
+    let output = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSharedFaceAlone.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--slot-glyph-size")
+        .arg("32");
+    let alone_output = cmd.output()?;
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("diff")
+        .arg("FreeMonoSharedFaceAlone.bmfa")
+        .arg("FreeMonoSharedFaceSmall.bmfa")
+        .arg("--max-diff-pixels")
+        .arg("0");
+    let diff_output = cmd.output()?;
+    fs::remove_file(config_path)?;
+    for stem in ["FreeMonoSharedFaceLarge", "FreeMonoSharedFaceSmall", "FreeMonoSharedFaceAlone"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(output.status.success());
+    assert!(alone_output.status.success());
+    assert!(diff_output.status.success());
+    assert!(String::from_utf8(diff_output.stdout)?.contains("Metadata:        unchanged"));
+
+    Ok(())
+}
+
+/// Building a config file should only generate the atlases that are missing, or whose input files
+/// or options changed since they were last built.
+#[test]
+fn fontgen_should_only_build_the_atlases_that_are_out_of_date() -> Result<(), Box<std::error::Error>> {
+    let config_path = Path::new("FreeMonoBuild.toml");
+    fs::write("FreeMonoBuildChars.txt", "ABC")?;
+    fs::write(config_path, concat!(
+        "input = \"assets/FreeMono.ttf\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoBuildA.bmfa\"\n",
+        "charset_file = \"FreeMonoBuildChars.txt\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoBuildB.bmfa\"\n",
+        "chars = \"U+0041-U+005A\"\n",
+    ))?;
+    let build = |options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("build").arg("--config").arg(config_path).args(options);
+        Ok(cmd.output()?)
+    };
+    let first = build(&[])?;
+    let unchanged = build(&[])?;
+    fs::write("FreeMonoBuildChars.txt", "ABCD")?;
+    let dry_run = build(&["--dry-run"])?;
+    let changed = build(&[])?;
+
+    fs::remove_file(config_path)?;
+    fs::remove_file("FreeMonoBuildChars.txt")?;
+    for stem in ["FreeMonoBuildA", "FreeMonoBuildB"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml", "build-key"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    for output in [&first, &unchanged, &dry_run, &changed].iter() {
+        assert!(output.status.success());
+    }
+    assert!(String::from_utf8(first.stderr)?.contains("Built 2 of 2 atlases"));
+    assert!(String::from_utf8(unchanged.stderr)?.contains("Built 0 of 2 atlases"));
+    let dry_run_stdout = String::from_utf8(dry_run.stdout)?;
+    assert!(dry_run_stdout.contains("FreeMonoBuildA.bmfa is out of date."));
+    assert!(!dry_run_stdout.contains("FreeMonoBuildB.bmfa"));
+    let changed_stderr = String::from_utf8(changed.stderr)?;
+    assert!(changed_stderr.contains("Wrote FreeMonoBuildA.bmfa"));
+    assert!(changed_stderr.contains("FreeMonoBuildB.bmfa is up to date."));
+
+    Ok(())
+}
+
+/// Building a config file should find the atlases of outputs named with another extension than
+/// the one of their format, and leave them alone while they are up to date.
+#[test]
+fn fontgen_should_find_the_atlas_files_of_a_build() -> Result<(), Box<std::error::Error>> {
+    let config_path = Path::new("FreeMonoBuildPng.toml");
+    fs::write(config_path, concat!(
+        "input = \"assets/FreeMono.ttf\"\n",
+        "\n",
+        "[[atlas]]\n",
+        "output = \"FreeMonoBuildPng.png\"\n",
+        "chars = \"U+0041-U+005A\"\n",
+    ))?;
+    let build = || -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("build").arg("--config").arg(config_path);
+        Ok(cmd.output()?)
+    };
+    let first = build()?;
+    let unchanged = build()?;
+
+    fs::remove_file(config_path)?;
+    for extension in ["bmfa", "meta.json", "fontgen.toml", "build-key"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoBuildPng.{}", extension));
+    }
+
+    assert!(first.status.success());
+    assert!(String::from_utf8(first.stderr)?.contains("Wrote FreeMonoBuildPng.bmfa"));
+    assert!(unchanged.status.success());
+    let unchanged_stderr = String::from_utf8(unchanged.stderr)?;
+    assert!(unchanged_stderr.contains("FreeMonoBuildPng.bmfa is up to date."));
+    assert!(unchanged_stderr.contains("Built 0 of 1 atlases"));
+
+    Ok(())
+}
+
+/// The `generate` subcommand should take the same options as `fontgen` without a subcommand.
+#[test]
+fn fontgen_should_generate_an_atlas_with_the_generate_subcommand() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoGenerate.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A");
+    let output = cmd.output()?;
+
+    let exists = Path::new("FreeMonoGenerate.bmfa").exists();
+    let _ = fs::remove_file("FreeMonoGenerate.bmfa");
+    let _ = fs::remove_file("FreeMonoGenerate.meta.json");
+    let _ = fs::remove_file("FreeMonoGenerate.fontgen.toml");
+
+    assert!(output.status.success());
+    assert!(exists);
+
+    Ok(())
+}
+
+/// An unknown subcommand should fail instead of getting mistaken for an input.
+#[test]
+fn fontgen_should_reject_an_unknown_subcommand() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("genrate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// Inspecting an atlas should print its metadata and a row for each glyph.
+#[test]
+fn fontgen_should_print_the_glyphs_of_an_inspected_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoInspect.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A");
+    let generated = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("inspect")
+        .arg("FreeMonoInspect.bmfa");
+    let output = cmd.output()?;
+    let _ = fs::remove_file("FreeMonoInspect.bmfa");
+    let _ = fs::remove_file("FreeMonoInspect.meta.json");
+    let _ = fs::remove_file("FreeMonoInspect.fontgen.toml");
+
+    assert!(generated.status.success());
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Glyphs:          27"));
+    let glyph_rows = stdout.lines().filter(|line| line.starts_with("U+")).count();
+    assert_eq!(glyph_rows, 27);
+
+    Ok(())
+}
+
+/// Extracting an atlas should write its image, its metadata, and an image for each glyph.
+#[test]
+fn fontgen_should_extract_the_image_and_metadata_of_an_atlas() -> Result<(), Box<std::error::Error>> {
+    let out_dir = Path::new("FreeMonoExtract.extracted");
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoExtract.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A");
+    let generated = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("extract")
+        .arg("FreeMonoExtract.bmfa")
+        .arg("--out")
+        .arg(out_dir)
+        .arg("--glyphs");
+    let output = cmd.output()?;
+
+    let written: Vec<bool> = ["atlas.png", "metadata.json", "glyphs/U+0041.png", "glyphs/U+005A.png"].iter()
+        .map(|path| out_dir.join(path).exists())
+        .collect();
+    let _ = fs::remove_file("FreeMonoExtract.bmfa");
+    let _ = fs::remove_file("FreeMonoExtract.meta.json");
+    let _ = fs::remove_file("FreeMonoExtract.fontgen.toml");
+    let _ = fs::remove_dir_all(out_dir);
+
+    assert!(generated.status.success());
+    assert!(output.status.success());
+    assert_eq!(written, vec![true, true, true, true]);
+
+    Ok(())
+}
+
+/// Previewing an atlas should draw the sample text into a PNG file.
+#[test]
+fn fontgen_should_draw_a_preview_of_an_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPreview.bmfa")
+        .arg("--chars")
+        .arg("U+0020-U+007E");
+    let generated = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("preview")
+        .arg("FreeMonoPreview.bmfa")
+        .arg("--text")
+        .arg("Hello, World!")
+        .arg("--out")
+        .arg("FreeMonoPreview.png");
+    let output = cmd.output()?;
+
+    let preview = image::open("FreeMonoPreview.png");
+    let _ = fs::remove_file("FreeMonoPreview.bmfa");
+    let _ = fs::remove_file("FreeMonoPreview.meta.json");
+    let _ = fs::remove_file("FreeMonoPreview.fontgen.toml");
+    let _ = fs::remove_file("FreeMonoPreview.png");
+
+    assert!(generated.status.success());
+    assert!(output.status.success());
+    let preview = preview?.to_rgba();
+    assert!(preview.width() > preview.height());
+    assert!(preview.pixels().any(|pixel| pixel[0] > 0));
+
+    Ok(())
+}
+
+/// A wrapped preview should break the text at its spaces, line the lines up as asked, and fail
+/// when the widths or the spacing of the lines differ from the expected ones.
+#[test]
+fn fontgen_should_wrap_align_and_check_the_lines_of_a_preview() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoWrappedPreview.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--slot-glyph-size")
+        .arg("32");
+    let generated = cmd.output()?;
+    let preview = |out: &str, options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("preview")
+            .arg("FreeMonoWrappedPreview.bmfa")
+            .arg("--text")
+            .arg("ABC ABC ABCD")
+            .arg("--out")
+            .arg(out)
+            .args(options);
+        Ok(cmd.output()?)
+    };
+    // FreeMono advances every character by 19 pixels at this size.
+    let expected = preview("FreeMonoWrappedPreview.png", &[
+        "--wrap-width", "80", "--align", "right", "--expect-line-widths", "57,57,76",
+    ])?;
+    let unexpected = preview("FreeMonoUnexpectedPreview.png", &[
+        "--wrap-width", "80", "--expect-line-widths", "57,133", "--expect-line-height", "1",
+    ])?;
+
+    let image = image::open("FreeMonoWrappedPreview.png");
+    for path in ["FreeMonoWrappedPreview.png", "FreeMonoUnexpectedPreview.png"].iter() {
+        let _ = fs::remove_file(path);
+    }
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoWrappedPreview.{}", extension));
+    }
+
+    assert!(generated.status.success());
+    assert!(expected.status.success());
+    let image = image?.to_rgba();
+    assert_eq!(image.width(), 80 + 2 * 8);
+    // The first line ends at the right edge, 23 pixels in from the left edge of the widest line.
+    let lit_columns: Vec<u32> = image.enumerate_pixels()
+        .filter(|&(_, y, pixel)| y < image.height() / 3 && pixel[0] > 0)
+        .map(|(x, _, _)| x)
+        .collect();
+    assert!(!lit_columns.is_empty());
+    assert!(lit_columns.iter().all(|&x| x >= 8 + 23));
+    assert!(!unexpected.status.success());
+    let stderr = String::from_utf8(unexpected.stderr)?;
+    assert!(stderr.contains("3 lines where 2 were expected"));
+    assert!(stderr.contains("line 2 (\"ABC\") is 57 pixels wide where 133 were expected"));
+    assert!(stderr.contains("pixels apart where 1 were expected"));
+
+    Ok(())
+}
+
+/// An atlas should record the rule metrics of its typeface, and a preview with `--rules` should
+/// draw the lines across the whole width of the text.
+#[test]
+fn fontgen_should_draw_rules_along_the_text_of_a_preview() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoRulesPreview.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--slot-glyph-size")
+        .arg("32");
+    let generated = cmd.output()?;
+    let preview = |out: &str, options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("preview")
+            .arg("FreeMonoRulesPreview.bmfa")
+            .arg("--text")
+            .arg("ABC")
+            .arg("--out")
+            .arg(out)
+            .args(options);
+        Ok(cmd.output()?)
+    };
+    let plain = preview("FreeMonoPlainPreview.png", &[])?;
+    let ruled = preview("FreeMonoRuledPreview.png", &["--rules", "underline,strikeout"])?;
+    let invalid = preview("FreeMonoInvalidRulesPreview.png", &["--rules", "double"])?;
+
+    let metadata = fs::read_to_string("FreeMonoRulesPreview.meta.json");
+    let plain_image = image::open("FreeMonoPlainPreview.png");
+    let ruled_image = image::open("FreeMonoRuledPreview.png");
+    for path in ["FreeMonoPlainPreview.png", "FreeMonoRuledPreview.png"].iter() {
+        let _ = fs::remove_file(path);
+    }
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoRulesPreview.{}", extension));
+    }
+
+    assert!(generated.status.success());
+    assert!(plain.status.success());
+    assert!(ruled.status.success());
+    assert!(!invalid.status.success());
+    let metadata = metadata?;
+    assert!(metadata.contains("\"rules\""));
+    assert!(metadata.contains("\"strikeout_position\""));
+    // FreeMono advances every character by 19 pixels at this size, so the text spans 57 columns.
+    let full_rows = |image: &image::RgbaImage| {
+        (0..image.height())
+            .filter(|&y| (8..8 + 57).all(|x| image.get_pixel(x, y)[0] == 255))
+            .count()
+    };
+    assert_eq!(full_rows(&plain_image?.to_rgba()), 0);
+    assert!(full_rows(&ruled_image?.to_rgba()) >= 2);
+
+    Ok(())
+}
+
+/// The HTML preview should embed the atlas image, and list every glyph.
+#[test]
+fn fontgen_should_write_an_html_preview_of_the_atlas() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPreviewHtml.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+005A")
+        .arg("--preview-html");
+    let output = cmd.output()?;
+
+    let html = fs::read_to_string("FreeMonoPreviewHtml.preview.html");
+    let _ = fs::remove_file("FreeMonoPreviewHtml.bmfa");
+    let _ = fs::remove_file("FreeMonoPreviewHtml.meta.json");
+    let _ = fs::remove_file("FreeMonoPreviewHtml.fontgen.toml");
+    let _ = fs::remove_file("FreeMonoPreviewHtml.preview.html");
+
+    assert!(output.status.success());
+    let html = html?;
+    assert!(html.contains("data:image/png;base64,"));
+    assert_eq!(html.matches("<td>U+00").count(), 27);
+
+    Ok(())
+}
+
+/// Glyphs that rasterize to empty bitmaps, like the spaces of other widths, should get reported
+/// and keep their metadata and advances, without taking up room in the atlas image.
+#[test]
+fn fontgen_should_leave_empty_glyphs_out_of_the_atlas_image() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoEmpty.bmfa")
+        .arg("--chars")
+        .arg("U+0041,U+00A0,U+2002-U+2003")
+        .arg("--pack")
+        .arg("skyline");
+    let output = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoNotEmpty.bmfa")
+        .arg("--chars")
+        .arg("U+0041")
+        .arg("--pack")
+        .arg("skyline");
+    cmd.assert().success();
+
+    let atlas = bmfa::load("FreeMonoEmpty.bmfa");
+    let not_empty_atlas = bmfa::load("FreeMonoNotEmpty.bmfa");
+    let metadata = fs::read_to_string("FreeMonoEmpty.meta.json");
+    for stem in ["FreeMonoEmpty", "FreeMonoNotEmpty"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    let excluded = "Excluded 3 glyphs with empty bitmaps from the atlas image: U+00A0, U+2002, U+2003\n";
+    assert!(stderr.contains(excluded));
+    let metadata = metadata?;
+    assert!(metadata.contains("\"empty_glyphs\": [\n    160,\n    8194,\n    8195\n  ]"));
+    assert!(metadata.contains("\"160\": {\n      \"pixels\""));
+    let (atlas, not_empty_atlas) = (atlas?, not_empty_atlas?);
+    assert!(atlas.metadata.glyph_metadata.contains_key(&0x2002));
+    assert_eq!(
+        (atlas.metadata.width, atlas.metadata.height),
+        (not_empty_atlas.metadata.width, not_empty_atlas.metadata.height)
+    );
+
+    Ok(())
+}
+
+/// Merging atlases should pack the glyphs of both into one atlas, and should fail on characters
+/// both atlases have when told to.
+#[test]
+fn fontgen_should_merge_the_glyphs_of_two_atlases() -> Result<(), Box<std::error::Error>> {
+    let atlases = [("FreeMonoMergeA.bmfa", "U+0041-U+005A"), ("FreeMonoMergeB.bmfa", "U+0030-U+0039,U+0041")];
+    for &(output, chars) in atlases.iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg(chars);
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("merge")
+        .arg("FreeMonoMergeA.bmfa")
+        .arg("FreeMonoMergeB.bmfa")
+        .arg("--output")
+        .arg("FreeMonoMerged.bmfa");
+    let merged = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("merge")
+        .arg("FreeMonoMergeA.bmfa")
+        .arg("FreeMonoMergeB.bmfa")
+        .arg("--output")
+        .arg("FreeMonoMergeFailed.bmfa")
+        .arg("--on-collision")
+        .arg("fail");
+    let failed = cmd.output()?;
+
+    let atlas = bmfa::load("FreeMonoMerged.bmfa");
+    let failed_atlas_exists = Path::new("FreeMonoMergeFailed.bmfa").exists();
+    for stem in ["FreeMonoMergeA", "FreeMonoMergeB", "FreeMonoMerged"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(merged.status.success());
+    let atlas = atlas?;
+    // The space, the 26 letters, and the 10 digits.
+    assert_eq!(atlas.metadata.glyph_metadata.len(), 37);
+    assert!(!failed.status.success());
+    assert!(!failed_atlas_exists);
+
+    Ok(())
+}
+
+/// Merging atlases should refuse to replace an existing merged atlas, unless told to replace it,
+/// keep it as a backup, or leave it alone.
+#[test]
+fn fontgen_should_only_replace_a_merged_atlas_when_told_to() -> Result<(), Box<std::error::Error>> {
+    let atlases = [("FreeMonoRemergeA.bmfa", "U+0041-U+005A"), ("FreeMonoRemergeB.bmfa", "U+0030-U+0039")];
+    for &(output, chars) in atlases.iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg(chars);
+        cmd.assert().success();
+    }
+    let merge = |policy: Option<&str>| -> Result<bool, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("merge")
+            .arg("FreeMonoRemergeA.bmfa")
+            .arg("FreeMonoRemergeB.bmfa")
+            .arg("--output")
+            .arg("FreeMonoRemerged.bmfa")
+            .args(policy);
+
+        Ok(cmd.output()?.status.success())
+    };
+    let first = merge(None)?;
+    let metadata = fs::read("FreeMonoRemerged.meta.json")?;
+    let again = merge(None)?;
+    let skipped = merge(Some("--skip-existing"))?;
+    let forced = merge(Some("--force"))?;
+    let backed_up = merge(Some("--backup"))?;
+
+    let atlas_backup_exists = Path::new("FreeMonoRemerged.bmfa.bak").exists();
+    let metadata_backup = fs::read("FreeMonoRemerged.meta.json.bak");
+    for stem in ["FreeMonoRemergeA", "FreeMonoRemergeB", "FreeMonoRemerged"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml", "bmfa.bak", "meta.json.bak"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(first);
+    assert!(!again);
+    assert!(skipped);
+    assert!(forced);
+    assert!(backed_up);
+    assert!(atlas_backup_exists);
+    assert_eq!(metadata_backup?, metadata);
+
+    Ok(())
+}
+
+/// Diffing two atlases should report the glyphs only the new one has and the pixels that differ.
+#[test]
+fn fontgen_should_report_the_differences_between_two_atlases() -> Result<(), Box<std::error::Error>> {
+    let atlases = [
+        ("FreeMonoDiffOld.bmfa", "U+0041-U+005A", "1.0"),
+        ("FreeMonoDiffNew.bmfa", "U+0041-U+005A,U+0030-U+0039", "1.8"),
+    ];
+    for &(output, chars, gamma) in atlases.iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg(chars)
+            .arg("--gamma")
+            .arg(gamma);
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("diff")
+        .arg("FreeMonoDiffOld.bmfa")
+        .arg("FreeMonoDiffNew.bmfa");
+    let output = cmd.output()?;
+    for stem in ["FreeMonoDiffOld", "FreeMonoDiffNew"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Added:           U+0030-U+0039"));
+    assert!(!stdout.contains("Removed:"));
+    let changed_a = stdout.lines().find(|line| line.trim_start().starts_with("U+0041"));
+    assert!(changed_a.map_or(false, |line| line.contains("pixels differ")));
+
+    Ok(())
+}
+
+/// The comparison report of two versions of an atlas should show both sample paragraphs, and list
+/// the characters added or changed, but not the unchanged ones.
+#[test]
+fn fontgen_should_write_a_report_comparing_two_atlases() -> Result<(), Box<std::error::Error>> {
+    let atlases = [
+        ("FreeMonoReportOld.bmfa", "U+0041-U+0043", "1.0"),
+        ("FreeMonoReportNew.bmfa", "U+0041-U+0044", "1.0"),
+    ];
+    for &(output, chars, gamma) in atlases.iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg(chars)
+            .arg("--gamma")
+            .arg(gamma);
+        cmd.assert().success();
+    }
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("report")
+        .arg("FreeMonoReportOld.bmfa")
+        .arg("FreeMonoReportNew.bmfa")
+        .arg("--out")
+        .arg("FreeMonoReport.html")
+        .arg("--text")
+        .arg("ABCD");
+    let output = cmd.output()?;
+
+    let html = fs::read_to_string("FreeMonoReport.html");
+    let _ = fs::remove_file("FreeMonoReport.html");
+    for stem in ["FreeMonoReportOld", "FreeMonoReportNew"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert!(output.status.success());
+    let html = html?;
+    assert_eq!(html.matches("alt=\"ABCD\"").count(), 2);
+    assert!(html.contains("<p>1 of 5 characters differ between the versions.</p>"));
+    assert!(html.contains("<td>U+0044</td><td>D</td><td>added</td>"));
+    assert!(!html.contains("<td>U+0041</td>"));
+
+    Ok(())
+}
+
+/// Listing the features of a font should show the scripts and features of its layout tables, and
+/// no variation axes for a static font.
+#[test]
+fn fontgen_should_list_the_features_of_a_font() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("features")
+        .arg("assets/FreeMono.ttf");
+    let output = cmd.output()?;
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("GSUB scripts:    grek (dflt), hebr (dflt), latn (dflt, ISM, LSM, NSM, SKS)"));
+    assert!(stdout.contains("GSUB features:   ccmp, dlig, liga, locl"));
+    assert!(stdout.contains("GPOS features:   mark"));
+    assert!(stdout.contains("Variation axes:  none"));
+
+    Ok(())
+}
+
+/// Previewing a feature should list the characters whose glyphs it substitutes, and draw the
+/// glyphs before and after.
+#[test]
+fn fontgen_should_preview_the_substitutions_of_features() -> Result<(), Box<std::error::Error>> {
+    let preview_path = Path::new("FreeMonoLocl.png");
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("features")
+        .arg("assets/FreeMono.ttf")
+        .arg("--preview-features")
+        .arg("locl,ss01")
+        .arg("--out")
+        .arg(preview_path);
+    let output = cmd.output()?;
+    let preview = image::open(preview_path);
+    let _ = std::fs::remove_file(preview_path);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Substitutions:   1 under locl, ss01"));
+    assert!(stdout.contains("U+014A '\u{14A}' (LATIN CAPITAL LETTER ENG) glyph"));
+    assert!(preview?.to_luma().pixels().any(|pixel| pixel[0] < 128));
+
+    Ok(())
+}
+
+/// A sheet should draw the sample text in each font, one row per font.
+#[test]
+fn fontgen_should_draw_a_sheet_of_the_sample_text_in_each_font() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("sheet")
+        .arg("--inputs")
+        .arg("assets/FreeMono.ttf")
+        .arg("assets/FreeMonoCollection.ttc")
+        .arg("--text")
+        .arg("Sphinx")
+        .arg("--out")
+        .arg("FreeMonoSheet.png");
+    let output = cmd.output()?;
+
+    let sheet = image::open("FreeMonoSheet.png");
+    let _ = fs::remove_file("FreeMonoSheet.png");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("assets/FreeMono.ttf"));
+    assert!(stdout.contains("assets/FreeMonoCollection.ttc"));
+    let sheet = sheet?.to_rgba();
+    let (width, height) = sheet.dimensions();
+    // Both fonts draw the text the same, so the rows match, and neither is blank.
+    let row_height = height / 2;
+    let row = |top: u32| -> Vec<u8> {
+        (top..top + row_height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| sheet.get_pixel(x, y)[0])
+            .collect()
+    };
+    assert_eq!(height % 2, 0);
+    assert_eq!(row(0), row(row_height));
+    assert!(row(0).iter().any(|&value| value > 0));
+
+    Ok(())
+}
+
+/// Planning an atlas should size every configuration, mark the ones within the budget, and
+/// recommend the largest slot size that fits.
+#[test]
+fn fontgen_should_plan_the_atlas_configurations_that_fit_a_budget() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("plan")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--chars")
+        .arg("U+0041-U+0044")
+        .arg("--sizes")
+        .arg("32,64")
+        .arg("--budget")
+        .arg("8KB");
+    let output = cmd.output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let fits = |row: &str| row.ends_with("fits");
+    let grid = |row: &str, channels: &str| {
+        row.contains("grid") && row.contains(channels) && row.contains("96x64")
+    };
+
+    assert!(output.status.success());
+    let rows: Vec<&str> = stdout.lines().skip(1).take(12).collect();
+    assert_eq!(rows.len(), 12);
+    assert!(rows.iter().any(|row| grid(row, "a8") && fits(row)));
+    assert!(rows.iter().any(|row| grid(row, "rgba") && !fits(row)));
+    assert!(rows.iter().filter(|row| row.trim_start().starts_with("64")).all(|row| !fits(row)));
+    assert!(stdout.contains("3 of 12 configurations fit the budget of 8.0 KB."));
+    assert!(stdout.contains("The largest slot size that fits is 32 pixels"));
+
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("plan")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--sizes")
+        .arg("32")
+        .arg("--budget")
+        .arg("1KB");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+/// Planning the update of an atlas should list the glyphs a new character set adds, removes, or
+/// changes, and tell whether the new glyphs fit the free slots of the grid.
+#[test]
+fn fontgen_should_plan_the_update_of_an_atlas_to_a_new_character_set() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoPlanUpdate.bmfa")
+        .arg("--chars")
+        .arg("U+0041-U+0044");
+    cmd.assert().success();
+    let plan_update = |chars: &str| -> Result<(bool, String), Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("plan-update")
+            .arg("--atlas")
+            .arg("FreeMonoPlanUpdate.bmfa")
+            .arg("--chars")
+            .arg(chars);
+        let output = cmd.output()?;
+
+        Ok((output.status.success(), String::from_utf8(output.stdout)?))
+    };
+    let in_place = plan_update("U+0042-U+0046");
+    let overflowing = plan_update("U+0041-U+0050");
+    // Regenerating the kept glyphs with another gamma changes all of them.
+    let parameters = fs::read_to_string("FreeMonoPlanUpdate.fontgen.toml")?;
+    fs::write("FreeMonoPlanUpdate.fontgen.toml", parameters.replace("gamma = 1.0", "gamma = 2.2"))?;
+    let regamma = plan_update("U+0041-U+0044");
+
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoPlanUpdate.{}", extension));
+    }
+
+    let (success, stdout) = in_place?;
+    assert!(success);
+    assert!(stdout.contains("Glyphs:          3 kept, 2 added, 1 removed, 0 changed"));
+    assert!(stdout.contains("Added:           U+0045-U+0046"));
+    assert!(stdout.contains("Removed:         U+0041"));
+    assert!(stdout.contains("Free slots:      2 of 6"));
+    assert!(stdout.contains("Verdict:         update in place"));
+    let (success, stdout) = overflowing?;
+    assert!(success);
+    assert!(stdout.contains("Verdict:         full rebuild, 12 new glyphs overflow the 1 free slots"));
+    let (success, stdout) = regamma?;
+    assert!(success);
+    assert!(stdout.contains("Changed:         U+0041-U+0044"));
+
+    Ok(())
+}
+
+/// Auditing an atlas should list the characters each translation file uses that the atlas lacks,
+/// and fail when there are any.
+#[test]
+fn fontgen_should_audit_an_atlas_against_translation_files() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoAudit.bmfa")
+        .arg("--chars")
+        .arg("U+0020-U+007E");
+    let generated = cmd.output()?;
+    fs::write("FreeMonoAudit-en.json", r#"{"menu": {"start": "Start game", "quit": ["Quit"]}}"#)?;
+    fs::write("FreeMonoAudit-de.json", r#"{"menu": {"start": "Spiel starten", "quit": "Schließen"}}"#)?;
+    let audit = |strings: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("audit").arg("--atlas").arg("FreeMonoAudit.bmfa").arg("--strings").args(strings);
+        Ok(cmd.output()?)
+    };
+    let covered = audit(&["FreeMonoAudit-en.json"])?;
+    let lacking = audit(&["FreeMonoAudit-en.json", "FreeMonoAudit-de.json"])?;
+    for path in ["FreeMonoAudit-en.json", "FreeMonoAudit-de.json"].iter() {
+        let _ = fs::remove_file(path);
+    }
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoAudit.{}", extension));
+    }
+
+    assert!(generated.status.success());
+    assert!(covered.status.success());
+    assert!(String::from_utf8(covered.stdout)?.contains("FreeMonoAudit-en all 11 characters covered"));
+    assert!(!lacking.status.success());
+    let stdout = String::from_utf8(lacking.stdout)?;
+    assert!(stdout.contains("FreeMonoAudit-de 1 of 14 characters missing: U+00DF"));
+    assert!(String::from_utf8(lacking.stderr)?.contains("characters used by 1 of the 2 locales"));
+
+    Ok(())
+}
+
+/// With `--tolerance` and `--max-diff-pixels`, `fontgen diff` should exit with status 2 only when
+/// the atlases differ by more than those allow.
+#[test]
+fn fontgen_should_check_atlas_differences_against_a_tolerance() -> Result<(), Box<std::error::Error>> {
+    let atlases = [
+        ("FreeMonoToleranceOld.bmfa", "U+0041-U+005A", "1.0"),
+        ("FreeMonoToleranceNew.bmfa", "U+0041-U+005A", "1.05"),
+        ("FreeMonoToleranceMore.bmfa", "U+0041-U+005A,U+0030", "1.0"),
+    ];
+    for &(output, chars, gamma) in atlases.iter() {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg(chars)
+            .arg("--gamma")
+            .arg(gamma);
+        cmd.assert().success();
+    }
+    let diff = |new: &str, options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("diff").arg("FreeMonoToleranceOld.bmfa").arg(new).args(options);
+        Ok(cmd.output()?)
+    };
+    let report = diff("FreeMonoToleranceNew.bmfa", &[])?;
+    let strict = diff("FreeMonoToleranceNew.bmfa", &["--max-diff-pixels", "0"])?;
+    let tolerant = diff("FreeMonoToleranceNew.bmfa", &["--tolerance", "32", "--max-diff-pixels", "50"])?;
+    let added = diff("FreeMonoToleranceMore.bmfa", &["--tolerance", "255"])?;
+    for stem in ["FreeMonoToleranceOld", "FreeMonoToleranceNew", "FreeMonoToleranceMore"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", stem, extension));
+        }
+    }
+
+    assert_eq!(report.status.code(), Some(0));
+    assert!(!String::from_utf8(report.stdout)?.contains("Result:"));
+    assert_eq!(strict.status.code(), Some(2));
+    assert!(String::from_utf8(strict.stdout)?.contains("Result:          beyond tolerance"));
+    assert_eq!(tolerant.status.code(), Some(0));
+    assert!(String::from_utf8(tolerant.stdout)?.contains("Result:          within tolerance"));
+    assert_eq!(added.status.code(), Some(2));
+    assert!(String::from_utf8(added.stdout)?.contains("1 glyphs added and 0 removed"));
+
+    Ok(())
+}
+
+/// An existing atlas should only get replaced with `--force` or `--backup`, and left alone with
+/// `--skip-existing`.
+#[test]
+fn fontgen_should_follow_the_overwrite_policy_for_existing_atlases() -> Result<(), Box<std::error::Error>> {
+    let generate = |chars: &str, policy: Option<&str>| -> Result<bool, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg("FreeMonoOverwrite.bmfa")
+            .arg("--chars")
+            .arg(chars)
+            .args(policy);
+
+        Ok(cmd.output()?.status.success())
+    };
+    let glyph_count = || {
+        bmfa::load("FreeMonoOverwrite.bmfa").map(|atlas| atlas.metadata.glyph_metadata.len())
+    };
+    let first = generate("U+0041-U+0043", None)?;
+    let refused = generate("U+0041-U+0044", None)?;
+    let skipped = generate("U+0041-U+0044", Some("--skip-existing"))?;
+    let skipped_glyph_count = glyph_count();
+    let forced = generate("U+0041-U+0045", Some("--force"))?;
+    let forced_glyph_count = glyph_count();
+    let backed_up = generate("U+0041-U+0046", Some("--backup"))?;
+    let backed_up_glyph_count = glyph_count();
+    let backup_glyph_count = bmfa::load("FreeMonoOverwrite.bmfa.bak")
+        .map(|atlas| atlas.metadata.glyph_metadata.len());
+    for extension in ["bmfa", "bmfa.bak", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoOverwrite.{}", extension));
+    }
+
+    assert!(first);
+    assert!(!refused);
+    assert!(skipped);
+    // Each count includes the space.
+    assert_eq!(skipped_glyph_count?, 4);
+    assert!(forced);
+    assert_eq!(forced_glyph_count?, 6);
+    assert!(backed_up);
+    assert_eq!(backed_up_glyph_count?, 7);
+    assert_eq!(backup_glyph_count?, 6);
+
+    Ok(())
+}
+
+/// The overwrite policy should look at the files an atlas gets written to, not at the output path
+/// as given, which only names them when it has another extension.
+#[test]
+fn fontgen_should_protect_the_atlas_files_of_a_png_output_path() -> Result<(), Box<std::error::Error>> {
+    let generate = |policy: Option<&str>| -> Result<(bool, String), Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg("FreeMonoOverwritePng.png")
+            .arg("--chars")
+            .arg("U+0041-U+0043")
+            .args(policy);
+        let output = cmd.output()?;
+
+        Ok((output.status.success(), String::from_utf8(output.stderr)?))
+    };
+    let first = generate(None)?;
+    let refused = generate(None)?;
+    let skipped = generate(Some("--skip-existing"))?;
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoOverwritePng.{}", extension));
+    }
+
+    assert!(first.0);
+    assert!(!refused.0);
+    assert!(refused.1.contains("FreeMonoOverwritePng.bmfa"));
+    assert!(skipped.0);
+    assert!(skipped.1.contains("Skipping FreeMonoOverwritePng.bmfa, which exists already."));
+
+    Ok(())
+}
+
+/// The distance field spread should count towards the effects that have to leave room for the
+/// glyph, so emboldening a signed distance field atlas too much gets rejected up front.
+#[test]
+fn fontgen_should_count_the_sdf_spread_as_an_effect() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSdfEmbolden.bmfa")
+        .arg("--slot-glyph-size")
+        .arg("64")
+        .arg("--padding")
+        .arg("6")
+        .arg("--sdf")
+        .arg("--sdf-spread")
+        .arg("14")
+        .arg("--embolden")
+        .arg("40");
+    let output = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(stderr.contains("must leave room for the glyph in a 58 pixel glyph size"));
+    assert!(!Path::new("FreeMonoSdfEmbolden.bmfa").exists());
+
+    Ok(())
+}
+
+/// Each effect layer should get an atlas of its own next to the atlas, and a layer without its
+/// effect should be rejected up front.
+#[test]
+fn fontgen_should_write_an_atlas_for_each_effect_layer() -> Result<(), Box<std::error::Error>> {
+    let generate = |output: &str, options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg("U+0041-U+005A")
+            .args(options);
+        Ok(cmd.output()?)
+    };
+    let layered = generate("FreeMonoLayers.bmfa", &[
+        "--outline", "2px", "--shadow", "2,2,1,0.5", "--layers", "fill,outline,shadow",
+    ])?;
+    let unlayered = generate("FreeMonoShadowlessLayers.bmfa", &["--outline", "2px", "--layers", "shadow"])?;
+
+    let layers: Vec<bool> = ["fill", "outline", "shadow"].iter()
+        .map(|layer| Path::new(&format!("FreeMonoLayers.{}.bmfa", layer)).exists())
+        .collect();
+    let metadata = fs::read_to_string("FreeMonoLayers.meta.json");
+    let extensions = ["bmfa", "meta.json", "fontgen.toml", "fill.bmfa", "outline.bmfa", "shadow.bmfa"];
+    for extension in extensions.iter() {
+        let _ = fs::remove_file(format!("FreeMonoLayers.{}", extension));
+    }
+
+    assert!(layered.status.success());
+    assert_eq!(layers, vec![true, true, true]);
+    assert!(metadata?.contains("\"FreeMonoLayers.outline.bmfa\""));
+    assert!(!unlayered.status.success());
+    assert!(String::from_utf8(unlayered.stderr)?.contains("The shadow layer of --layers needs --shadow"));
+    assert!(!Path::new("FreeMonoShadowlessLayers.bmfa").exists());
+
+    Ok(())
+}
+
+/// Stroking the outlines on several threads should give the same atlas as on one, and `--threads`
+/// should only be taken along with `--outline`.
+#[test]
+fn fontgen_should_stroke_outlines_on_several_threads() -> Result<(), Box<std::error::Error>> {
+    let generate = |output: &str, options: &[&str]| -> Result<std::process::Output, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg(output)
+            .arg("--chars")
+            .arg("U+0041-U+007A")
+            .args(options);
+        Ok(cmd.output()?)
+    };
+    let serial = generate("FreeMonoSerialOutline.bmfa", &["--outline", "2px"])?;
+    let parallel = generate("FreeMonoParallelOutline.bmfa", &["--outline", "2px", "--threads", "4"])?;
+    let unoutlined = generate("FreeMonoUnoutlinedThreads.bmfa", &["--threads", "4"])?;
+    let serial_atlas = bmfa::load("FreeMonoSerialOutline.bmfa");
+    let parallel_atlas = bmfa::load("FreeMonoParallelOutline.bmfa");
+    for name in ["FreeMonoSerialOutline", "FreeMonoParallelOutline"].iter() {
+        for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", name, extension));
+        }
+    }
+
+    assert!(serial.status.success());
+    assert!(parallel.status.success());
+    let (serial_atlas, parallel_atlas) = (serial_atlas?, parallel_atlas?);
+    assert_eq!(parallel_atlas.metadata, serial_atlas.metadata);
+    assert!(parallel_atlas.image.data == serial_atlas.image.data);
+    assert!(!unoutlined.status.success());
+    assert!(!Path::new("FreeMonoUnoutlinedThreads.bmfa").exists());
+
+    Ok(())
+}
+
+/// The application should generate the same atlas again from the `.fontgen.toml` file of a run.
+#[test]
+fn fontgen_should_regenerate_an_atlas_from_its_generation_parameters() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoRerun.bmfa")
+        .arg("--image-out")
+        .arg("FreeMonoRerun.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--padding")
+        .arg("2")
+        .arg("--chars")
+        .arg("U+0041-U+005A,U+0061-U+007A")
+        .arg("--pack")
+        .arg("skyline")
+        .arg("--oblique=-0.2")
+        .arg("--embolden")
+        .arg("1")
+        .arg("--zero-width")
+        .arg("none");
+    let generated = cmd.output()?;
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("generate")
+        .arg("--config")
+        .arg("FreeMonoRerun.fontgen.toml")
+        .arg("--output")
+        .arg("FreeMonoRerunAgain.bmfa")
+        .arg("--image-out")
+        .arg("FreeMonoRerunAgain.png");
+    let regenerated = cmd.output()?;
+    let parameters = fs::read_to_string("FreeMonoRerun.fontgen.toml");
+    let images = (fs::read("FreeMonoRerun.png"), fs::read("FreeMonoRerunAgain.png"));
+    let metadata = (fs::read("FreeMonoRerun.meta.json"), fs::read("FreeMonoRerunAgain.meta.json"));
+    for name in ["FreeMonoRerun", "FreeMonoRerunAgain"].iter() {
+        for extension in ["bmfa", "png", "meta.json", "fontgen.toml"].iter() {
+            let _ = fs::remove_file(format!("{}.{}", name, extension));
+        }
+    }
+
+    assert!(generated.status.success());
+    assert!(regenerated.status.success(), "{}", String::from_utf8(regenerated.stderr)?);
+    let parameters = parameters?;
+    assert!(parameters.contains("pack = \"skyline\"\n"));
+    assert!(parameters.contains("[provenance]\n"));
+    assert_eq!(images.0?, images.1?);
+    assert_eq!(metadata.0?, metadata.1?);
+
+    Ok(())
+}
+
+/// A run with `--backup` should keep every file it replaces, and a run that fails should leave the
+/// existing atlas where it is.
+#[test]
+fn fontgen_should_only_back_up_the_files_of_a_run_that_succeeds() -> Result<(), Box<std::error::Error>> {
+    let generate = |chars: &str| -> Result<bool, Box<std::error::Error>> {
+        let mut cmd = Command::cargo_bin("fontgen")?;
+        cmd.arg("generate")
+            .arg("--input")
+            .arg("assets/FreeMono.ttf")
+            .arg("--output")
+            .arg("FreeMonoBackup.bmfa")
+            .arg("--chars")
+            .arg(chars)
+            .arg("--on-missing")
+            .arg("fail")
+            .arg("--backup");
+
+        Ok(cmd.output()?.status.success())
+    };
+    let first = generate("U+0041-U+0043")?;
+    let failed = generate("U+0041-U+0043,U+4E00")?;
+    let failed_glyph_count = bmfa::load("FreeMonoBackup.bmfa")
+        .map(|atlas| atlas.metadata.glyph_metadata.len());
+    let failed_backed_up = Path::new("FreeMonoBackup.bmfa.bak").exists();
+    let second = generate("U+0041-U+0044")?;
+    let backups: Vec<bool> = ["bmfa.bak", "meta.json.bak", "fontgen.toml.bak"].iter()
+        .map(|extension| Path::new(&format!("FreeMonoBackup.{}", extension)).exists())
+        .collect();
+    let backup_parameters = fs::read_to_string("FreeMonoBackup.fontgen.toml.bak");
+    for extension in ["bmfa", "meta.json", "fontgen.toml"].iter() {
+        let _ = fs::remove_file(format!("FreeMonoBackup.{}", extension));
+        let _ = fs::remove_file(format!("FreeMonoBackup.{}.bak", extension));
+    }
+
+    assert!(first);
+    assert!(!failed);
+    // The count includes the space.
+    assert_eq!(failed_glyph_count?, 4);
+    assert!(!failed_backed_up);
+    assert!(second);
+    assert_eq!(backups, vec![true, true, true]);
+    assert!(backup_parameters?.contains("chars = \"U+0041-U+0043\"\n"));
+
+    Ok(())
+}
+
+/// The application should tag the PNG images it writes with the color space of the atlas.
+#[test]
+fn fontgen_should_tag_png_images_with_the_color_space() -> Result<(), Box<std::error::Error>> {
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoSrgb.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--format")
+        .arg("bmfont-text")
+        .arg("--image-out")
+        .arg("FreeMonoSrgbAtlas.png")
+        .arg("--color-space")
+        .arg("srgb");
+    cmd.assert().success();
+    let mut cmd = Command::cargo_bin("fontgen")?;
+    cmd.arg("--input")
+        .arg("assets/FreeMono.ttf")
+        .arg("--output")
+        .arg("FreeMonoLinear.png")
+        .arg("--slot-glyph-size")
+        .arg("32")
+        .arg("--image-out")
+        .arg("FreeMonoLinearAtlas.png");
+    cmd.assert().success();
+
+    let srgb_page = fs::read("FreeMonoSrgb_0.png")?;
+    let srgb_image = fs::read("FreeMonoSrgbAtlas.png")?;
+    let linear_image = fs::read("FreeMonoLinearAtlas.png")?;
+
+    fs::remove_file("FreeMonoSrgb.fnt")?;
+    fs::remove_file("FreeMonoSrgb_0.png")?;
+    fs::remove_file("FreeMonoSrgbAtlas.png")?;
+    fs::remove_file("FreeMonoSrgb.meta.json")?;
+    fs::remove_file("FreeMonoSrgb.fontgen.toml")?;
+    fs::remove_file("FreeMonoLinear.bmfa")?;
+    fs::remove_file("FreeMonoLinearAtlas.png")?;
+    fs::remove_file("FreeMonoLinear.meta.json")?;
+    fs::remove_file("FreeMonoLinear.fontgen.toml")?;
+
+    // The chunks come right after the PNG signature and the 25 bytes of the header chunk.
+    let srgb_chunk: &[u8] = b"\x00\x00\x00\x01sRGB\x00";
+    let linear_gamma_chunk: &[u8] = b"\x00\x00\x00\x04gAMA\x00\x01\x86\xa0";
+    assert_eq!(&srgb_page[33..(33 + srgb_chunk.len())], srgb_chunk);
+    assert_eq!(&srgb_image[33..(33 + srgb_chunk.len())], srgb_chunk);
+    assert_eq!(&linear_image[33..(33 + linear_gamma_chunk.len())], linear_gamma_chunk);
+
+    Ok(())
+}
@@ -0,0 +1,1183 @@
+extern crate fontgen;
+
+use fontgen::{
+    AtlasChannels, AtlasSpec, BmfontDescriptor, CharacterSet, Glow, GlyphCache, GlyphLookup, GlyphLookupTable,
+    HintingMode, Monochrome, Packing, Renderer, RendererVersion, ResampleFilter, SampleTypefaceError, Shadow,
+    VariationCoordinate, VariationError, ZeroWidthClasses, BUILTIN_FALLBACK_FAMILY,
+};
+use fontgen::bmfa;
+use fontgen::bmfa::Origin;
+use fontgen::freetype::Library;
+use std::collections::BTreeMap;
+
+
+/// The library should generate an atlas without going through the shell utility.
+#[test]
+fn generate_an_atlas_through_the_library() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 512, 512, 16, 16, 4, 32, 28,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+    let atlas = fontgen::create_bitmap_atlas(&glyph_tab, spec);
+
+    assert_eq!(atlas.metadata.width, 512);
+    assert_eq!(atlas.metadata.height, 512);
+    assert!(atlas.metadata.glyph_metadata.contains_key(&('A' as usize)));
+
+    Ok(())
+}
+
+/// Tightly packed glyphs should never overlap, and should take up less room than a grid.
+#[test]
+fn packed_glyphs_should_not_overlap() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    for &packing in [Packing::Skyline, Packing::MaxRects, Packing::Shelf].iter() {
+        let spec = AtlasSpec::new(
+            Origin::TopLeft, 0, 0, 16, 16, 4, 32, 28,
+            1.0, None, false, ZeroWidthClasses::default(), None, packing
+        );
+        let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+        let layout = fontgen::layout_glyphs(&glyph_tab, spec);
+        let cells: Vec<_> = layout.cells.values().collect();
+
+        assert_eq!(cells.len(), glyph_tab.buffer.len());
+        assert!(layout.width * layout.height < 512 * 512);
+        for (k, a) in cells.iter().enumerate() {
+            assert!(a.x + a.width <= layout.width && a.y + a.height <= layout.height);
+            for b in cells[(k + 1)..].iter() {
+                let disjoint = a.x + a.width <= b.x || b.x + b.width <= a.x ||
+                    a.y + a.height <= b.y || b.y + b.height <= a.y;
+                assert!(disjoint);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shelf packing should put glyphs of mixed heights on shelves as tall as their tallest glyph, and
+/// take up less room than a grid of slots as tall as the tallest glyph of the atlas.
+#[test]
+fn shelves_should_be_as_tall_as_their_tallest_glyph() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 128, 0, 16, 16, 4, 32, 28,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Shelf
+    );
+    // Capitals, lowercase letters with and without descenders, and punctuation.
+    let chars = CharacterSet::new(&[(0x2C, 0x2E), (0x41, 0x48), (0x61, 0x71)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let layout = fontgen::layout_glyphs(&glyph_tab, spec);
+
+    let mut shelves: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for cell in layout.cells.values() {
+        shelves.entry(cell.y).or_default().push(cell.height);
+    }
+    let tops: Vec<usize> = shelves.keys().cloned().chain(std::iter::once(layout.height)).collect();
+    assert!(shelves.len() > 1);
+    assert!(shelves.values().any(|heights| heights.iter().min() != heights.iter().max()));
+    for (k, heights) in shelves.values().enumerate() {
+        assert_eq!(tops[k + 1] - tops[k], *heights.iter().max().unwrap());
+    }
+    let mut grid_spec = spec;
+    grid_spec.packing = Packing::Grid;
+    let grid = fontgen::fit_atlas(&glyph_tab, grid_spec, false);
+    assert!(layout.width * layout.height < grid.width * grid.height);
+
+    Ok(())
+}
+
+/// Full width CJK brackets should lose the empty half of their em square.
+#[test]
+fn compress_cjk_punctuation_should_halve_full_width_brackets() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 128, 128, 2, 2, 0, 64, 64,
+        1.0, None, true, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x41), (0x300C, 0x300D)]);
+    let mut glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    // FreeMono has no CJK punctuation, so give the last resort boxes full width advances.
+    glyph_tab.advance.insert(0x300C, 64);
+    glyph_tab.advance.insert(0x300D, 64);
+    glyph_tab.x_bearing.insert(0x300C, 40);
+    glyph_tab.x_bearing.insert(0x300D, 4);
+    let compressed = glyph_tab.compress_cjk_punctuation(64);
+
+    assert_eq!(compressed, vec![0x300C, 0x300D]);
+    assert_eq!(glyph_tab.advance[&0x300C], 32);
+    assert_eq!(glyph_tab.x_bearing[&0x300C], 8);
+    assert_eq!(glyph_tab.advance[&0x300D], 32);
+    assert_eq!(glyph_tab.x_bearing[&0x300D], 4);
+    assert_eq!(glyph_tab.advance[&0x41], 38);
+
+    Ok(())
+}
+
+/// Rebuild a TrueType font with some of its tables added or replaced.
+fn with_tables(font: &[u8], replacements: Vec<(&[u8], Vec<u8>)>) -> Vec<u8> {
+    let read_u16 = |offset: usize| ((font[offset] as usize) << 8) | font[offset + 1] as usize;
+    let read_u32 = |offset: usize| (read_u16(offset) << 16) | read_u16(offset + 2);
+    let mut tables: Vec<(Vec<u8>, Vec<u8>)> = (0..read_u16(4))
+        .map(|i| {
+            let record = 12 + 16 * i;
+            let (offset, length) = (read_u32(record + 8), read_u32(record + 12));
+            (font[record..(record + 4)].to_vec(), font[offset..(offset + length)].to_vec())
+        })
+        .filter(|(tag, _)| replacements.iter().all(|&(replaced, _)| replaced != &tag[..]))
+        .collect();
+    tables.extend(replacements.into_iter().map(|(tag, table)| (tag.to_vec(), table)));
+    tables.sort();
+
+    let mut directory = font[0..4].to_vec();
+    directory.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+    directory.extend_from_slice(&[0; 6]);
+    let mut data = vec![];
+    for (tag, table) in tables.iter() {
+        let offset = 12 + 16 * tables.len() + data.len();
+        directory.extend_from_slice(tag);
+        directory.extend_from_slice(&[0; 4]);
+        directory.extend_from_slice(&(offset as u32).to_be_bytes());
+        directory.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        data.extend_from_slice(table);
+        data.resize((data.len() + 3) / 4 * 4, 0);
+    }
+    directory.extend_from_slice(&data);
+
+    directory
+}
+
+/// Rebuild a TrueType font with a `COLR` table drawing one glyph as a single red layer of
+/// itself, and the `CPAL` palette holding the red.
+fn with_red_color_layer(font: &[u8], glyph_id: u16) -> Vec<u8> {
+    let id = glyph_id.to_be_bytes();
+    let colr = [&[0, 0, 0, 1, 0, 0, 0, 14, 0, 0, 0, 20, 0, 1][..], &id, &[0, 0, 0, 1], &id, &[0, 0]].concat();
+    let cpal = vec![0, 0, 0, 1, 0, 1, 0, 1, 0, 0, 0, 14, 0, 0, 0, 0, 255, 255];
+
+    with_tables(font, vec![(b"COLR", colr), (b"CPAL", cpal)])
+}
+
+/// Color glyphs should keep their colors in premultiplied RGBA images, while the other glyphs
+/// of the typeface get drawn in white.
+#[test]
+fn color_glyphs_should_keep_their_colors() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let font = std::fs::read("assets/FreeMono.ttf")?;
+    let glyph_id = library.new_face("assets/FreeMono.ttf", 0)?.get_char_index('A' as usize);
+    let face = library.new_memory_face(with_red_color_layer(&font, glyph_id as u16), 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 64, 64, 2, 2, 2, 32, 30,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x42)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    assert!(fontgen::has_color_glyphs(&face));
+    assert_eq!(glyph_tab.channels, 4);
+    assert_eq!(glyph_tab.color_glyphs, vec![0x41]);
+    let red = glyph_tab.buffer[&0x41].data.chunks(4).filter(|pixel| pixel[3] > 0);
+    assert!(red.clone().count() > 0);
+    assert!(red.clone().all(|pixel| pixel[0] == pixel[3] && pixel[1] == 0 && pixel[2] == 0));
+    let white = glyph_tab.buffer[&0x42].data.chunks(4).filter(|pixel| pixel[3] > 0);
+    assert!(white.clone().all(|pixel| pixel[0] == pixel[3] && pixel[1] == pixel[3] && pixel[2] == pixel[3]));
+
+    Ok(())
+}
+
+/// Rebuild a TrueType font with a `kern` table holding a single format 0 subtable of pairs.
+fn with_kerning(font: &[u8], pairs: &[(u16, u16, i16)]) -> Vec<u8> {
+    let mut kern = vec![0, 0, 0, 1, 0, 0];
+    kern.extend_from_slice(&(14 + 6 * pairs.len() as u16).to_be_bytes());
+    kern.extend_from_slice(&[0, 1]);
+    kern.extend_from_slice(&(pairs.len() as u16).to_be_bytes());
+    kern.extend_from_slice(&[0; 6]);
+    for &(left, right, value) in pairs.iter() {
+        kern.extend_from_slice(&left.to_be_bytes());
+        kern.extend_from_slice(&right.to_be_bytes());
+        kern.extend_from_slice(&value.to_be_bytes());
+    }
+
+    with_tables(font, vec![(b"kern", kern)])
+}
+
+/// The kerning of a typeface should hold exactly the pairs of its `kern` table between the
+/// characters of the atlas.
+#[test]
+fn kerning_should_hold_the_pairs_of_the_kern_table() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let font = std::fs::read("assets/FreeMono.ttf")?;
+    let plain_face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let glyph_id = |ch: char| plain_face.get_char_index(ch as usize) as u16;
+    let pairs = [(glyph_id('A'), glyph_id('V'), -150), (glyph_id('T'), glyph_id('o'), -100)];
+    let face = library.new_memory_face(with_kerning(&font, &pairs), 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::new(&[(0x20, 0x7E)]))?;
+    let without_o = fontgen::sample_typeface(&face, spec, &CharacterSet::new(&[(0x41, 0x5A)]))?;
+
+    let kerned: Vec<(usize, usize)> = glyph_tab.kerning.iter()
+        .map(|pair| (pair.first, pair.second))
+        .collect();
+    assert_eq!(kerned, vec![(0x41, 0x56), (0x54, 0x6F)]);
+    assert!(glyph_tab.kerning.iter().all(|pair| pair.amount < 0));
+    assert_eq!(without_o.kerning.len(), 1);
+
+    Ok(())
+}
+
+/// Rebuild a TrueType font with a `GSUB` table of two features: `smcp`, a single substitution of
+/// each pair of glyphs, and `ss01`, an alternate substitution of one glyph wrapped in an extension.
+fn with_substitutions(font: &[u8], pairs: &[(u16, u16)], alternates: (u16, &[u16])) -> Vec<u8> {
+    let push = |table: &mut Vec<u8>, values: &[u16]| {
+        for value in values.iter() {
+            table.extend_from_slice(&value.to_be_bytes());
+        }
+    };
+    let count = pairs.len() as u16;
+    let mut single = vec![];
+    push(&mut single, &[1, 0, 1, 8, 2, 6 + 2 * count, count]);
+    push(&mut single, &pairs.iter().map(|&(_, substitute)| substitute).collect::<Vec<u16>>());
+    push(&mut single, &[1, count]);
+    push(&mut single, &pairs.iter().map(|&(glyph, _)| glyph).collect::<Vec<u16>>());
+    let (glyph, alternate_glyphs) = alternates;
+    let count = alternate_glyphs.len() as u16;
+    let mut alternate = vec![];
+    push(&mut alternate, &[7, 0, 1, 8, 1, 3, 0, 8, 1, 10 + 2 * count, 1, 8, count]);
+    push(&mut alternate, alternate_glyphs);
+    push(&mut alternate, &[1, 1, glyph]);
+    let mut gsub = vec![];
+    push(&mut gsub, &[1, 0, 10, 12, 38, 0, 2]);
+    gsub.extend_from_slice(b"smcp\0\x0ess01\0\x14");
+    push(&mut gsub, &[0, 1, 0, 0, 1, 1, 2, 6, 6 + single.len() as u16]);
+    gsub.extend(single);
+    gsub.extend(alternate);
+
+    with_tables(font, vec![(b"GSUB", gsub)])
+}
+
+/// Previewing features should list the characters whose glyphs their single and alternate
+/// substitutions change, and draw the glyphs before and after.
+#[test]
+fn feature_substitutions_should_follow_the_gsub_lookups() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let font = std::fs::read("assets/FreeMono.ttf")?;
+    let plain_face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let glyph_id = |ch: char| plain_face.get_char_index(ch as usize) as usize;
+    let pairs = [(glyph_id('a') as u16, glyph_id('A') as u16), (glyph_id('b') as u16, glyph_id('B') as u16)];
+    let alternates = [glyph_id('x') as u16, glyph_id('y') as u16];
+    let font = with_substitutions(&font, &pairs, (glyph_id('c') as u16, &alternates));
+    let face = library.new_memory_face(font, 0)?;
+    let features = |tags: &[&str]| -> Vec<(usize, usize, usize)> {
+        let tags: Vec<String> = tags.iter().map(|tag| String::from(*tag)).collect();
+        fontgen::feature_substitutions(&face, &tags).iter()
+            .map(|substitution| (substitution.code_point, substitution.glyph_index, substitution.substitute))
+            .collect()
+    };
+    let small_caps = vec![(0x61, glyph_id('a'), glyph_id('A')), (0x62, glyph_id('b'), glyph_id('B'))];
+    let stylistic_set = vec![(0x63, glyph_id('c'), glyph_id('x'))];
+    let both = features(&["smcp", "ss01"]);
+    let substitutions = fontgen::feature_substitutions(&face, &[String::from("smcp"), String::from("ss01")]);
+    let preview = fontgen::render_substitution_preview(&face, 32, &substitutions)?;
+    let empty_preview = fontgen::render_substitution_preview(&face, 32, &[])?;
+
+    assert_eq!(features(&["smcp"]), small_caps);
+    assert_eq!(features(&["ss01"]), stylistic_set);
+    assert_eq!(both.len(), 3);
+    assert!(features(&["liga"]).is_empty());
+    assert!(preview.width > preview.height);
+    assert!(preview.data.iter().any(|&pixel| pixel < 128));
+    assert!(empty_preview.data.iter().all(|&pixel| pixel == 255));
+
+    Ok(())
+}
+
+/// Synthetic small capitals should draw lowercase letters as capitals scaled down by the small caps
+/// scale, sitting on the baseline, with the advance of the scaled capital.
+#[test]
+fn synthetic_small_caps_should_scale_down_the_capitals() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 128, 128, 2, 2, 4, 64, 60,
+        1.0, Some(0.75), false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x41), (0x61, 0x61)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    let scaled = |value: i32| (value as f32 * 0.75).round() as i32;
+    assert!((glyph_tab.rows[&0x61] - scaled(glyph_tab.rows[&0x41])).abs() <= 1);
+    assert!((glyph_tab.width[&0x61] - scaled(glyph_tab.width[&0x41])).abs() <= 1);
+    assert!((glyph_tab.advance[&0x61] - scaled(glyph_tab.advance[&0x41])).abs() <= 1);
+    assert_eq!(glyph_tab.y_min[&0x61], glyph_tab.y_min[&0x41]);
+    let fallbacks: Vec<(usize, Option<&str>)> = glyph_tab.telemetry.iter()
+        .map(|telemetry| (telemetry.code_point, telemetry.fallback))
+        .collect();
+    assert_eq!(fallbacks, vec![(0x41, None), (0x61, Some("synthetic small capital"))]);
+
+    Ok(())
+}
+
+/// Rebuild a TrueType font without outlines, with a single grayscale bitmap strike of `ppem`
+/// pixels drawing every glyph as the same checkerboard of one pixel squares.
+fn with_checkerboard_strike(font: &[u8], num_glyphs: u16, ppem: u8) -> Vec<u8> {
+    let image: Vec<u8> = (0..(ppem as usize * ppem as usize))
+        .map(|i| if (i / ppem as usize + i % ppem as usize) % 2 == 0 { 255 } else { 0 })
+        .collect();
+    let ascender = (ppem - ppem / 8) as i8 as u8;
+    let descender = (-((ppem / 8) as i8)) as u8;
+    let line_metrics = [ascender, descender, ppem, 1, 0, 0, 0, 0, ascender, descender, 0, 0];
+    let mut eblc = vec![0, 2, 0, 0, 0, 0, 0, 1, 0, 0, 0, 56, 0, 0, 0, 28, 0, 0, 0, 1, 0, 0, 0, 0];
+    eblc.extend_from_slice(&line_metrics);
+    eblc.extend_from_slice(&line_metrics);
+    eblc.extend_from_slice(&[0, 0]);
+    eblc.extend_from_slice(&(num_glyphs - 1).to_be_bytes());
+    eblc.extend_from_slice(&[ppem, ppem, 8, 1]);
+    // A single index subtable of format 2, for glyphs of the same size, with images of format 5.
+    eblc.extend_from_slice(&[0, 0]);
+    eblc.extend_from_slice(&(num_glyphs - 1).to_be_bytes());
+    eblc.extend_from_slice(&[0, 0, 0, 8, 0, 2, 0, 5, 0, 0, 0, 4]);
+    eblc.extend_from_slice(&(image.len() as u32).to_be_bytes());
+    eblc.extend_from_slice(&[ppem, ppem, 0, ascender, ppem, 0, 0, ppem]);
+    let mut ebdt = vec![0, 2, 0, 0];
+    for _ in 0..num_glyphs {
+        ebdt.extend_from_slice(&image);
+    }
+
+    with_tables(font, vec![(b"glyf", vec![]), (b"EBLC", eblc), (b"EBDT", ebdt)])
+}
+
+/// Glyphs drawn from a bitmap strike should get scaled to the glyph size with the resampling
+/// filter of the atlas.
+#[test]
+fn bitmap_strikes_should_get_resampled_with_the_filter() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let font = std::fs::read("assets/FreeMono.ttf")?;
+    let num_glyphs = library.new_face("assets/FreeMono.ttf", 0)?.raw().num_glyphs as u16;
+    let face = library.new_memory_face(with_checkerboard_strike(&font, num_glyphs, 64), 0)?;
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 64, 64, 2, 2, 2, 32, 30,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x41)]);
+    let box_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    spec.resample_filter = ResampleFilter::Nearest;
+    let nearest_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    spec.resample_filter = ResampleFilter::Lanczos3;
+    let lanczos_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    // The strike of 64 pixels gets scaled down to the render size of 30 pixels.
+    for glyph_tab in [&box_tab, &nearest_tab, &lanczos_tab].iter() {
+        assert_eq!((glyph_tab.width[&0x41], glyph_tab.rows[&0x41]), (30, 30));
+    }
+    // Averaging the squares leaves gray, while the nearest pixels keep them black and white.
+    let gray = |value: &u8| *value > 64 && *value < 192;
+    assert!(box_tab.buffer[&0x41].data.iter().all(gray));
+    assert!(nearest_tab.buffer[&0x41].data.iter().all(|&value| value == 0 || value == 255));
+    assert_ne!(lanczos_tab.buffer[&0x41].data, box_tab.buffer[&0x41].data);
+
+    Ok(())
+}
+
+/// Printable ASCII characters missing from the typeface should get drawn from the built-in
+/// fallback font, and the rest should still get last resort glyphs.
+#[test]
+fn builtin_fallback_should_cover_missing_ascii() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let font = std::fs::read("assets/FreeMono.ttf")?;
+    // A character map with nothing but the final segment every format 4 subtable ends with.
+    let cmap = vec![
+        0, 0, 0, 1, 0, 3, 0, 1, 0, 0, 0, 12,
+        0, 4, 0, 24, 0, 0, 0, 2, 0, 2, 0, 0, 0, 0, 255, 255, 0, 0, 255, 255, 0, 1, 0, 0,
+    ];
+    let face = library.new_memory_face(with_tables(&font, vec![(b"cmap", cmap)]), 0)?;
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 128, 128, 2, 2, 4, 64, 60,
+        1.0, None, true, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    spec.builtin_fallback = true;
+    let chars = CharacterSet::new(&[(0x41, 0x41), (0x67, 0x67), (0xE9, 0xE9)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    assert_eq!(face.get_char_index('A' as usize), 0);
+    assert_eq!(glyph_tab.glyph_typefaces[&0x41], BUILTIN_FALLBACK_FAMILY);
+    assert_eq!(glyph_tab.glyph_typefaces[&0x67], BUILTIN_FALLBACK_FAMILY);
+    assert!(!glyph_tab.glyph_typefaces.contains_key(&0xE9));
+    // The pixels of the font get scaled up six times at this size, and the `g` descends below the baseline.
+    assert_eq!((glyph_tab.width[&0x41], glyph_tab.rows[&0x41], glyph_tab.advance[&0x41]), (30, 54, 36));
+    assert_eq!(glyph_tab.y_min[&0x67], -12);
+    assert!(glyph_tab.buffer[&0x41].data.iter().any(|&value| value == 255));
+
+    Ok(())
+}
+
+/// Characters no typeface covers should get last resort glyphs showing their code point, so each
+/// one looks different, instead of the missing glyph of the typeface.
+#[test]
+fn last_resort_glyphs_should_show_the_code_point() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 128, 128, 2, 2, 4, 64, 60,
+        1.0, None, true, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x41), (0xE000, 0xE001)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    spec.last_resort = false;
+    let missing_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    assert_eq!(face.get_char_index(0xE000), 0);
+    assert_eq!(face.get_char_index(0xE001), 0);
+    let fallbacks: Vec<(usize, Option<&str>)> = glyph_tab.telemetry.iter()
+        .map(|telemetry| (telemetry.code_point, telemetry.fallback))
+        .collect();
+    let last_resort = Some("last resort glyph");
+    assert_eq!(fallbacks, vec![(0x41, None), (0xE000, last_resort), (0xE001, last_resort)]);
+    assert_ne!(glyph_tab.buffer[&0xE000].data, glyph_tab.buffer[&0xE001].data);
+    assert!(missing_tab.telemetry.iter().all(|telemetry| telemetry.fallback.is_none()));
+    assert_eq!(missing_tab.buffer[&0xE000].data, missing_tab.buffer[&0xE001].data);
+
+    Ok(())
+}
+
+/// Characters in the selected zero width classes should get advance-only entries without an
+/// image, while the characters of the other classes get drawn as usual.
+#[test]
+fn zero_width_characters_should_get_advance_only_entries() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let zero_width = ZeroWidthClasses { joiners: true, combining: true, format: false };
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 4, 4, 2, 33, 31,
+        1.0, None, false, zero_width, None, Packing::Skyline
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x41), (0xAD, 0xAD), (0x0301, 0x0301), (0x200D, 0x200D)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let drawn_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 4, 4, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let drawn_tab = fontgen::sample_typeface(&face, drawn_spec, &chars)?;
+
+    assert_eq!(glyph_tab.zero_width, vec![0x0301, 0x200D]);
+    for code_point in [0x0301, 0x200D].iter() {
+        assert_eq!(glyph_tab.advance[code_point], 0);
+        assert!(!glyph_tab.buffer.contains_key(code_point));
+        assert!(!glyph_tab.telemetry.iter().any(|telemetry| telemetry.code_point == *code_point));
+    }
+    assert!(glyph_tab.advance[&0xAD] > 0);
+    assert!(drawn_tab.zero_width.is_empty());
+    assert!(drawn_tab.buffer.contains_key(&0x0301));
+
+    Ok(())
+}
+
+/// Monochrome glyphs should only hold fully covered and empty pixels, whether they come from
+/// the monochrome rasterizer or from cutting off antialiased glyphs.
+#[test]
+fn monochrome_glyphs_should_be_hard_edged() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x61, 0x7A)]);
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let antialiased = fontgen::sample_typeface(&face, spec, &chars)?;
+    let has_gray = |glyph_tab: &fontgen::GlyphTable| {
+        glyph_tab.buffer.values().any(|image| image.data.iter().any(|&value| value != 0 && value != 255))
+    };
+
+    assert!(has_gray(&antialiased));
+    for &mono in [Monochrome::Rasterizer, Monochrome::Threshold(64)].iter() {
+        spec.mono = Some(mono);
+        let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+
+        assert!(!has_gray(&glyph_tab));
+        assert_eq!(glyph_tab.buffer.len(), 26);
+        for (code_point, image) in glyph_tab.buffer.iter() {
+            assert_eq!(image.data.len() as i32, glyph_tab.width[code_point] * glyph_tab.rows[code_point]);
+        }
+    }
+
+    Ok(())
+}
+
+/// The binary glyph lookup table should find the record of every character in the atlas, and
+/// nothing else.
+#[test]
+fn glyph_lookup_tables_should_find_every_character() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::BottomLeft, 0, 0, 4, 4, 2, 32, 30,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x43), (0x0410, 0x0412)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let layout = fontgen::layout_glyphs(&glyph_tab, spec);
+    let table = GlyphLookupTable::new(&glyph_tab, spec);
+    let data = table.to_bytes().unwrap();
+    let lookup = GlyphLookup::parse(&data).unwrap();
+
+    assert_eq!(lookup.origin(), Origin::BottomLeft);
+    for record in table.records.iter() {
+        assert_eq!(lookup.find(record.id), Some(*record));
+    }
+    // With a bottom left origin, the glyph images are measured from the bottom of the page.
+    let record = lookup.find(0x41).unwrap();
+    let cell = layout.cells[&0x41];
+    assert_eq!(record.y, layout.height - (cell.y + 1) - record.height);
+    assert_eq!(lookup.find(0x44), None);
+    assert_eq!(lookup.find(0x0413), None);
+    assert_eq!(lookup.find(0x1F600), None);
+    assert!(GlyphLookup::parse(&data[..(data.len() - 1)]).is_none());
+
+    Ok(())
+}
+
+/// A gamma above one should raise the partial coverage of antialiased edges, and leave empty
+/// and fully covered pixels as they are.
+#[test]
+fn gamma_should_only_change_partial_coverage() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let linear = fontgen::sample_typeface(&face, spec, &chars)?;
+    spec.gamma = 1.43;
+    let corrected = fontgen::sample_typeface(&face, spec, &chars)?;
+
+    let mut raised = 0;
+    for (code_point, image) in linear.buffer.iter() {
+        for (&before, &after) in image.data.iter().zip(corrected.buffer[code_point].data.iter()) {
+            match before {
+                0 | 255 => assert_eq!(after, before),
+                _ => assert!(after >= before),
+            }
+            raised += (after > before) as usize;
+        }
+    }
+    assert!(raised > 0);
+
+    Ok(())
+}
+
+/// Calibrating an atlas against the renderer that draws its own glyphs should keep the coverage as it is.
+#[test]
+fn calibrating_against_the_same_hinting_should_pick_a_gamma_of_one() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let strings: Vec<String> = fontgen::CALIBRATION_STRINGS.iter().map(|s| s.to_string()).collect();
+    let report = fontgen::calibrate_coverage(&face, spec, HintingMode::Native, &strings)?;
+
+    assert_eq!(report.best_gamma, 1.0);
+    let best = report.candidates.iter().find(|candidate| candidate.gamma == report.best_gamma).unwrap();
+    assert_eq!(best.mean_delta, 0.0);
+    assert!(report.candidates.iter().all(|candidate| candidate.mean_delta >= best.mean_delta));
+
+    Ok(())
+}
+
+/// An outline should grow each glyph by its border, and draw the glyph over the border.
+#[test]
+fn outlines_should_grow_the_glyphs_by_their_border() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let plain_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 27,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let mut outlined_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    outlined_spec.outline = 2;
+    let plain = fontgen::sample_typeface(&face, plain_spec, &chars)?;
+    let outlined = fontgen::sample_typeface(&face, outlined_spec, &chars)?;
+
+    assert_eq!(outlined.channels, 4);
+    for code_point in chars.code_points() {
+        assert!(outlined.width[&code_point] >= plain.width[&code_point] + 3);
+        assert!(outlined.rows[&code_point] >= plain.rows[&code_point] + 3);
+        assert!(outlined.x_bearing[&code_point] < plain.x_bearing[&code_point]);
+        assert_eq!(outlined.advance[&code_point], plain.advance[&code_point] + 4);
+        for pixel in outlined.buffer[&code_point].data.chunks(4) {
+            assert!(pixel[3] >= pixel[0]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sampling the glyphs of a typeface on several threads should give the same glyphs as sampling
+/// them on one.
+#[test]
+fn parallel_sampling_should_match_serial_sampling() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x20, 0x7E)]);
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 10, 10, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    spec.outline = 2;
+    let serial = fontgen::sample_typeface(&face, spec, &chars)?;
+    let font_path = std::path::Path::new("assets/FreeMono.ttf");
+    let parallel = fontgen::sample_typeface_parallel(font_path, 0, &[], spec, &chars, 4)?;
+    let weight = [VariationCoordinate { tag: String::from("wght"), value: 700.0 }];
+    let varied = fontgen::sample_typeface_parallel(font_path, 0, &weight, spec, &chars, 4);
+
+    assert_eq!(parallel.channels, serial.channels);
+    assert_eq!(parallel.buffer.len(), serial.buffer.len());
+    assert_eq!(parallel.advance, serial.advance);
+    assert_eq!(parallel.x_bearing, serial.x_bearing);
+    assert_eq!(parallel.y_min, serial.y_min);
+    assert_eq!(parallel.width, serial.width);
+    assert_eq!(parallel.rows, serial.rows);
+    assert_eq!(parallel.kerning, serial.kerning);
+    for (code_point, image) in serial.buffer.iter() {
+        assert_eq!(parallel.buffer[code_point].data, image.data);
+    }
+    // Every worker sets the design coordinates, which a static font has none of.
+    match varied {
+        Err(SampleTypefaceError::SetVariation(VariationError::NotAVariableFont(_), _)) => {}
+        Err(e) => panic!("sampling gave the wrong error: {}", e),
+        Ok(_) => panic!("sampling should not set design coordinates on a static font"),
+    }
+
+    Ok(())
+}
+
+/// A drop shadow should grow each glyph toward the shadow, without moving the glyph itself.
+#[test]
+fn shadows_should_grow_the_glyphs_toward_the_shadow() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let plain_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 29,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let mut shadow_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    shadow_spec.shadow = Some(Shadow { dx: 2, dy: 2, blur: 0, alpha: 0.5 });
+    let plain = fontgen::sample_typeface(&face, plain_spec, &chars)?;
+    let shadowed = fontgen::sample_typeface(&face, shadow_spec, &chars)?;
+
+    assert_eq!(shadowed.channels, 4);
+    for code_point in chars.code_points() {
+        assert_eq!(shadowed.width[&code_point], plain.width[&code_point] + 2);
+        assert_eq!(shadowed.rows[&code_point], plain.rows[&code_point] + 2);
+        assert_eq!(shadowed.x_bearing[&code_point], plain.x_bearing[&code_point]);
+        assert_eq!(shadowed.y_min[&code_point], plain.y_min[&code_point] - 2);
+        assert_eq!(shadowed.advance[&code_point], plain.advance[&code_point]);
+
+        // The glyph covers the top left pixels of its shadow, but not the bottom right ones.
+        let width = shadowed.width[&code_point] as usize;
+        let plain_image = &plain.buffer[&code_point];
+        let shadow_image = &shadowed.buffer[&code_point];
+        let last = plain_image.data.len() - 1;
+        let corner = 4 * (shadow_image.data.len() / 4 - 1);
+        assert_eq!(shadow_image.data[corner], 0);
+        assert_eq!(shadow_image.data[corner + 3], (plain_image.data[last] as f32 * 0.5).round() as u8);
+        for pixel in shadow_image.data.chunks(4).take(width) {
+            assert!(pixel[3] >= pixel[0]);
+        }
+    }
+
+    Ok(())
+}
+
+/// The effect layers of a glyph table should line up with the effects composited into its images.
+#[test]
+fn effect_layers_should_line_up_with_the_composited_glyphs() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let shadow = Shadow { dx: 2, dy: 2, blur: 0, alpha: 0.5 };
+    let layer_spec = |glyph_size: usize, outline: usize| {
+        let mut spec = AtlasSpec::new(
+            Origin::TopLeft, 0, 0, 6, 6, 2, 33, glyph_size,
+            1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+        );
+        spec.outline = outline;
+        spec.channels = AtlasChannels::A8;
+        spec
+    };
+    let mut composite_spec = layer_spec(31, 2);
+    composite_spec.channels = AtlasChannels::Rgba;
+    composite_spec.shadow = Some(shadow);
+    let composite = fontgen::sample_typeface(&face, composite_spec, &chars)?;
+    let fill = composite.layer(&fontgen::sample_typeface(&face, layer_spec(25, 0), &chars)?);
+    let bordered = fontgen::sample_typeface(&face, layer_spec(29, 2), &chars)?;
+    let outline = composite.layer(&bordered);
+    let shadows = composite.shadow_layer(&bordered, shadow);
+
+    for code_point in chars.code_points() {
+        let pixels = composite.buffer[&code_point].data.chunks(4);
+        let layers = fill.buffer[&code_point].data.iter()
+            .zip(outline.buffer[&code_point].data.iter())
+            .zip(shadows.buffer[&code_point].data.iter());
+        for (pixel, ((&fill, &outline), &shadow)) in pixels.zip(layers) {
+            // The color channels hold the glyph, and the alpha channel the glyph along with its
+            // border over its shadow.
+            assert_eq!(pixel[0], fill);
+            let alpha = outline as f32 + shadow as f32 * (255.0 - outline as f32) / 255.0;
+            assert!((pixel[3] as f32 - alpha).abs() <= 1.0);
+        }
+    }
+
+    Ok(())
+}
+
+/// A glow should be rendered into the padding, growing the glyphs without shrinking them.
+#[test]
+fn glows_should_be_rendered_into_the_padding() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let plain_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 8, 40, 32,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let mut glow_spec = plain_spec;
+    glow_spec.glow = Some(Glow { radius: 3, intensity: 2.0 });
+    let plain = fontgen::sample_typeface(&face, plain_spec, &chars)?;
+    let glowing = fontgen::sample_typeface(&face, glow_spec, &chars)?;
+
+    assert_eq!(glow_spec.free_padding(), 2);
+    assert_eq!(glowing.channels, 1);
+    for code_point in chars.code_points() {
+        assert_eq!(glowing.width[&code_point], plain.width[&code_point] + 6);
+        assert_eq!(glowing.rows[&code_point], plain.rows[&code_point] + 6);
+        assert_eq!(glowing.x_bearing[&code_point], plain.x_bearing[&code_point] - 3);
+        assert_eq!(glowing.advance[&code_point], plain.advance[&code_point]);
+        // The glow only ever brightens the glyph.
+        let width = plain.width[&code_point] as usize;
+        let glowing_width = glowing.width[&code_point] as usize;
+        for (index, &value) in plain.buffer[&code_point].data.iter().enumerate() {
+            let glowing_index = (index / width + 3) * glowing_width + index % width + 3;
+            assert!(glowing.buffer[&code_point].data[glowing_index] >= value);
+        }
+    }
+
+    let plain_layout = fontgen::layout_glyphs(&plain, plain_spec);
+    let glowing_layout = fontgen::layout_glyphs(&glowing, glow_spec);
+    for (code_point, cell) in plain_layout.cells.iter() {
+        assert_eq!(glowing_layout.cells[code_point].width, cell.width);
+        assert_eq!(glowing_layout.cells[code_point].height, cell.height);
+    }
+
+    Ok(())
+}
+
+/// The supplement of a locale should leave out the characters of the shared base atlas.
+#[test]
+fn locale_supplements_should_leave_out_the_base_characters() {
+    let base = CharacterSet::new(&[(0x20, 0x7E), (0x3000, 0x303F)]);
+    let supplement = CharacterSet::locale_supplement("ko", &base).unwrap();
+
+    assert_eq!(supplement.ranges(), &[(0x1100, 0x11FF), (0x3130, 0x318F), (0xAC00, 0xD7A3)]);
+    assert_eq!(CharacterSet::locale_supplement("tlh", &base), None);
+    let letters = CharacterSet::new(&[(0x41, 0x5A)]);
+    let excluded = CharacterSet::new(&[(0x45, 0x45), (0x50, 0x60)]);
+    assert_eq!(letters.difference(&excluded).ranges(), &[(0x41, 0x44), (0x46, 0x4F)]);
+}
+
+/// Strings should measure the advances of their characters, leaving out the missing ones.
+#[test]
+fn measured_strings_should_add_up_the_advances() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x20, 0x7E)]);
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let strings = vec![String::from("Start"), String::from("OK \u{263A}"), String::from("   ")];
+    let report = fontgen::measure_strings(&glyph_tab, &strings);
+
+    let advance = glyph_tab.advance[&('M' as usize)] as i64;
+    assert_eq!(report.strings[0].width, 5 * advance);
+    assert_eq!(report.strings[0].height, glyph_tab.line_height);
+    let ink_bounds = report.strings[0].ink_bounds.unwrap();
+    assert!(ink_bounds[0] >= 0 && ink_bounds[2] <= 5 * advance);
+    assert!(ink_bounds[1] < ink_bounds[3] && ink_bounds[3] <= glyph_tab.ascender as i64);
+    assert_eq!(report.strings[1].width, 3 * advance);
+    assert_eq!(report.strings[1].missing, vec![0x263A]);
+    assert_eq!(report.strings[2].ink_bounds, None);
+
+    Ok(())
+}
+
+/// Emboldening should thicken the glyphs and widen their advances by the strength.
+#[test]
+fn emboldened_glyphs_should_be_heavier() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x41, 0x5A)]);
+    let regular_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let mut bold_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 34, 32,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    bold_spec.embolden = 1.0;
+    assert_eq!(bold_spec.render_size(), regular_spec.render_size());
+    let regular = fontgen::sample_typeface(&face, regular_spec, &chars)?;
+    let bold = fontgen::sample_typeface(&face, bold_spec, &chars)?;
+
+    let coverage = |image: &fontgen::GlyphImage| image.data.iter().map(|&value| value as u64).sum::<u64>();
+    for (code_point, image) in regular.buffer.iter() {
+        assert_eq!(bold.advance[code_point], regular.advance[code_point] + 1);
+        assert!(coverage(&bold.buffer[code_point]) > coverage(image));
+    }
+
+    Ok(())
+}
+
+/// Slanted glyphs should get wider images, but keep their advances.
+#[test]
+fn oblique_glyphs_should_widen_but_keep_their_advances() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let chars = CharacterSet::new(&[(0x49, 0x49), (0x7C, 0x7C)]);
+    let upright_spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let mut slanted_spec = upright_spec;
+    slanted_spec.oblique = 0.25;
+    let upright = fontgen::sample_typeface(&face, upright_spec, &chars)?;
+    let slanted = fontgen::sample_typeface(&face, slanted_spec, &chars)?;
+
+    for code_point in &['I' as usize, '|' as usize] {
+        assert_eq!(slanted.advance[code_point], upright.advance[code_point]);
+        assert!(slanted.width[code_point] >= upright.width[code_point] + 3);
+    }
+    assert!(!BmfontDescriptor::new(&upright, upright_spec, "upright.png").to_text().contains("italic=1"));
+    assert!(BmfontDescriptor::new(&slanted, slanted_spec, "slanted.png").to_text().contains("italic=1"));
+
+    Ok(())
+}
+
+/// Sampling by glyph index should draw the same glyphs as sampling by code point.
+#[test]
+fn glyph_indices_should_key_the_glyphs_their_code_points_map_to() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let mut spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let by_code_point = fontgen::sample_typeface(&face, spec, &CharacterSet::new(&[(0x41, 0x5A)]))?;
+    spec.glyph_indices = true;
+    let by_glyph_index = fontgen::sample_typeface(&face, spec, &CharacterSet::new(&[(0, 63)]))?;
+    let code_points = fontgen::glyph_code_points(&face);
+
+    let glyph_index = face.get_char_index('A' as usize) as usize;
+    assert!(glyph_index < 64);
+    assert_eq!(code_points[&glyph_index], vec!['A' as usize]);
+    assert_eq!(by_glyph_index.advance[&glyph_index], by_code_point.advance[&('A' as usize)]);
+    assert_eq!(by_glyph_index.buffer[&glyph_index].data, by_code_point.buffer[&('A' as usize)].data);
+    assert_eq!(by_glyph_index.advance.len(), 64);
+
+    Ok(())
+}
+
+/// A cached glyph table should come back the same as it was sampled, and changing only the
+/// packing should keep its cache key, while another version of the renderer should not.
+#[test]
+fn cached_glyphs_should_load_the_way_they_were_stored() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 6, 6, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let chars = CharacterSet::new(&[(0x20, 0x7E), (0xA0, 0xA0)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let font_files = [std::path::Path::new("assets/FreeMono.ttf")];
+    let renderer = Renderer::freetype(&library);
+    let key = GlyphCache::key(&font_files, spec, &chars, &renderer, "")?;
+    let mut upgraded_renderer = renderer.clone();
+    upgraded_renderer.version = RendererVersion::new(renderer.version.major + 1, 0, 0);
+    let mut repacked_spec = spec;
+    repacked_spec.packing = Packing::MaxRects;
+    let mut outlined_spec = spec;
+    outlined_spec.outline = 1;
+
+    let cache_dir = std::env::temp_dir().join(format!("fontgen-cache-test-{}", std::process::id()));
+    let cache = GlyphCache::open(&cache_dir)?;
+    let missing = cache.load(&key);
+    cache.store(&key, &glyph_tab)?;
+    let cached = cache.load(&key);
+    std::fs::remove_dir_all(&cache_dir)?;
+
+    assert!(missing.is_none());
+    let cached = cached.expect("the glyph table should have been cached");
+    assert_eq!(cached.family, glyph_tab.family);
+    assert_eq!(cached.line_height, glyph_tab.line_height);
+    assert_eq!(cached.advance, glyph_tab.advance);
+    assert_eq!(cached.y_min, glyph_tab.y_min);
+    assert_eq!(cached.empty, glyph_tab.empty);
+    assert_eq!(cached.kerning, glyph_tab.kerning);
+    assert_eq!(cached.buffer.len(), glyph_tab.buffer.len());
+    for (code_point, image) in glyph_tab.buffer.iter() {
+        assert_eq!(cached.buffer[code_point].data, image.data);
+    }
+    assert_eq!(GlyphCache::key(&font_files, repacked_spec, &chars, &renderer, "")?, key);
+    assert_ne!(GlyphCache::key(&font_files, outlined_spec, &chars, &renderer, "")?, key);
+    assert_ne!(GlyphCache::key(&font_files, spec, &chars, &upgraded_renderer, "")?, key);
+
+    Ok(())
+}
+
+/// A glyph cut out of an atlas should come out the same whichever origin the atlas has.
+#[test]
+fn glyph_images_should_not_depend_on_the_atlas_origin() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let mut glyph_images = vec![];
+    for &origin in [Origin::TopLeft, Origin::BottomLeft].iter() {
+        let spec = AtlasSpec::new(
+            origin, 512, 512, 16, 16, 4, 32, 28,
+            1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+        );
+        let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+        let atlas = fontgen::create_bitmap_atlas(&glyph_tab, spec);
+        let glyph = atlas.metadata.glyph_metadata[&('A' as usize)];
+        let glyph_image = fontgen::glyph_image(&atlas, &glyph);
+        glyph_images.push(glyph_image.map(|image| (image.width, image.height, image.data)));
+    }
+
+    assert!(glyph_images[0].is_some());
+    assert_eq!(glyph_images[0], glyph_images[1]);
+
+    Ok(())
+}
+
+/// Glyphs decoded a row at a time from an atlas file should match the glyphs cut out of the whole
+/// atlas image, whichever origin the atlas has.
+#[test]
+fn decoded_glyph_rects_should_match_the_glyph_images() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    for &(origin, atlas_path) in [
+        (Origin::TopLeft, "FreeMonoDecodeTop.bmfa"),
+        (Origin::BottomLeft, "FreeMonoDecodeBottom.bmfa")].iter() {
+
+        let spec = AtlasSpec::new(
+            origin, 512, 512, 16, 16, 4, 32, 28,
+            1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+        );
+        let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+        bmfa::write_to_file(atlas_path, &fontgen::create_bitmap_atlas(&glyph_tab, spec))?;
+        let atlas = bmfa::load(atlas_path);
+        let reader = fontgen::open_atlas(atlas_path);
+        std::fs::remove_file(atlas_path)?;
+        let (atlas, mut reader) = (atlas?, reader?);
+
+        assert_eq!(reader.metadata(), &atlas.metadata);
+        for &code_point in [0x20, 0x41, 0x67, 0x7E].iter() {
+            let glyph = atlas.metadata.glyph_metadata[&code_point];
+            let decoded = fontgen::decode_glyph_rect(&mut reader, &glyph)?;
+            let expected = fontgen::glyph_image(&atlas, &glyph);
+            assert_eq!(decoded.is_some(), code_point != 0x20);
+            assert_eq!(decoded, expected);
+        }
+        assert!(reader.decode_rect(500, 0, 16, 16).is_err());
+    }
+
+    Ok(())
+}
+
+/// A single page of a multi-page atlas should be loadable through the extended metadata file.
+#[test]
+fn load_page_should_open_the_page_file_of_the_metadata() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 512, 512, 16, 16, 4, 32, 28,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+    let pages = fontgen::paginate(&glyph_tab, spec, 128);
+    let page_files = ["FreeMonoLoadPage_0.bmfa", "FreeMonoLoadPage_1.bmfa"];
+    let metadata_path = "FreeMonoLoadPage.meta.json";
+    let mut atlases = vec![];
+    for (page, page_file) in pages.iter().zip(page_files.iter()) {
+        let atlas = fontgen::create_bitmap_atlas(&page.glyph_tab, page.spec);
+        bmfa::write_to_file(page_file, &atlas)?;
+        atlases.push(atlas);
+    }
+    std::fs::write(
+        metadata_path,
+        format!("{{\"page_files\": [\"{}\", \"{}\"]}}", page_files[0], page_files[1])
+    )?;
+    let second_page = fontgen::load_page(metadata_path, 1);
+    let missing_page = fontgen::load_page(metadata_path, 2);
+    std::fs::remove_file(metadata_path)?;
+    for page_file in page_files.iter() {
+        std::fs::remove_file(page_file)?;
+    }
+    let mut second_page = second_page?;
+
+    assert!(pages.len() > 2);
+    assert_eq!(second_page.metadata(), &atlases[1].metadata);
+    let glyph = *atlases[1].metadata.glyph_metadata.values().find(|glyph| glyph.code_point != 0x20).unwrap();
+    let decoded = fontgen::decode_glyph_rect(&mut second_page, &glyph)?;
+    assert_eq!(decoded, fontgen::glyph_image(&atlases[1], &glyph));
+    match missing_page {
+        Err(fontgen::AtlasReadError::PageOutOfRange(_, 2, 2)) => {}
+        Err(e) => panic!("load_page gave the wrong error: {}", e),
+        Ok(_) => panic!("load_page should not open a page the metadata does not list"),
+    }
+
+    Ok(())
+}
+
+/// A grid page too small for two slots per side should still hold two slots per side, one for the
+/// space and three for the other glyphs, instead of failing.
+#[test]
+fn paginate_should_give_small_grid_pages_two_slots_per_side() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 512, 512, 16, 16, 4, 32, 28,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::new(&[(0x41, 0x4A)]))?;
+
+    for &max_size in [16, 32, 63].iter() {
+        let pages = fontgen::paginate(&glyph_tab, spec, max_size);
+        assert_eq!(pages.len(), 4);
+        assert!(pages.iter().all(|page| page.spec.columns == 2 && page.spec.rows == 2));
+        assert!(pages.iter().all(|page| page.spec.width == 64 && page.spec.height == 64));
+    }
+
+    Ok(())
+}
+
+/// A single rasterized glyph should match the glyph of the same character in a sampled glyph table.
+#[test]
+fn rasterized_glyphs_should_match_the_sampled_glyphs() -> Result<(), Box<std::error::Error>> {
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 16, 16, 4, 32, 28,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Grid
+    );
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &CharacterSet::default())?;
+    let g = 'g' as usize;
+    let glyph = fontgen::rasterize_glyph(&face, g, spec)?;
+    let space = fontgen::rasterize_glyph(&face, ' ' as usize, spec)?;
+    let lone_glyph = fontgen::rasterize_glyph(&face, g, AtlasSpec::for_glyph(28))?;
+
+    assert_eq!((glyph.width, glyph.rows), (glyph_tab.width[&g], glyph_tab.rows[&g]));
+    assert_eq!((glyph.x_bearing, glyph.y_min), (glyph_tab.x_bearing[&g], glyph_tab.y_min[&g]));
+    assert_eq!(glyph.advance, glyph_tab.advance[&g]);
+    assert!(glyph.y_min < 0);
+    assert_eq!(glyph.image.map(|image| image.data), Some(glyph_tab.buffer[&g].data.clone()));
+    assert!(space.image.is_none());
+    assert_eq!(space.advance, glyph_tab.advance[&(' ' as usize)]);
+    assert_eq!((lone_glyph.width, lone_glyph.rows, lone_glyph.y_min), (glyph.width, glyph.rows, glyph.y_min));
+    assert_eq!(lone_glyph.image.map(|image| image.data), Some(glyph_tab.buffer[&g].data.clone()));
+
+    Ok(())
+}
+
+/// Comparing an atlas image with the same image stored the other way up should find no
+/// differences, and changing a pixel should show up as one differing pixel.
+#[test]
+fn image_comparisons_should_not_depend_on_the_image_origin() {
+    let top_down = vec![0, 10, 20, 30, 40, 50];
+    let bottom_up = vec![40, 50, 20, 30, 0, 10];
+    let top_left = bmfa::BitmapFontAtlasImage::new(top_down.clone(), 2, 3, Origin::TopLeft);
+    let bottom_left = bmfa::BitmapFontAtlasImage::new(bottom_up, 2, 3, Origin::BottomLeft);
+    let mut changed = top_down;
+    changed[3] = 130;
+    let changed = bmfa::BitmapFontAtlasImage::new(changed, 2, 3, Origin::TopLeft);
+    let smaller = bmfa::BitmapFontAtlasImage::new(vec![0, 10, 20, 30], 2, 2, Origin::TopLeft);
+
+    let same = fontgen::compare_images(&top_left, &bottom_left);
+    let different = fontgen::compare_images(&bottom_left, &changed);
+
+    assert_eq!(same.map(|difference| difference.is_empty()), Some(true));
+    let different = different.map(|difference| (difference.differing_pixels, difference.max_difference));
+    assert_eq!(different, Some((1, 100)));
+    assert!(fontgen::compare_images(&top_left, &smaller).is_none());
+}
+
+/// Errors and warnings should describe a character by its code point, the character itself when
+/// it is printable, and its Unicode name, along with the typeface and the FreeType error.
+#[test]
+fn errors_should_describe_the_character_and_typeface() {
+    let freetype_error = fontgen::freetype::Error::InvalidGlyphIndex;
+    let error = SampleTypefaceError::LoadCharacter(freetype_error, String::from("FreeMono"), 0x4E2D);
+    let message = format!("{}", error);
+
+    assert_eq!(fontgen::describe_code_point(0x41), "U+0041 'A' (LATIN CAPITAL LETTER A)");
+    assert_eq!(fontgen::describe_code_point(0x20), "U+0020 (SPACE)");
+    assert_eq!(fontgen::describe_code_point(0xD800), "U+D800");
+    assert!(message.starts_with("[FreeMono] While loading glyphs"));
+    assert!(message.contains("U+4E2D '\u{4E2D}' (CJK UNIFIED IDEOGRAPH-4E2D)"));
+    assert!(message.contains(&format!("{}", freetype_error)));
+}
+
+/// A glyph script should be able to change the pixels and metrics of every glyph before packing.
+#[cfg(feature = "scripting")]
+#[test]
+fn glyph_scripts_should_modify_the_glyphs() -> Result<(), Box<std::error::Error>> {
+    let script_path = std::path::Path::new("FreeMonoInvert.rhai");
+    std::fs::write(
+        script_path,
+        "fn process_glyph(glyph) {\n\
+            glyph.pixels = glyph.pixels.map(|pixel| 255 - pixel);\n\
+            glyph.y_min += 1;\n\
+            glyph\n\
+        }\n"
+    )?;
+    let library = Library::init()?;
+    let face = library.new_face("assets/FreeMono.ttf", 0)?;
+    let spec = AtlasSpec::new(
+        Origin::TopLeft, 0, 0, 4, 4, 2, 33, 31,
+        1.0, None, false, ZeroWidthClasses::default(), None, Packing::Skyline
+    );
+    let chars = CharacterSet::new(&[(0x41, 0x42)]);
+    let glyph_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let mut scripted_tab = fontgen::sample_typeface(&face, spec, &chars)?;
+    let script = fontgen::GlyphScript::load(script_path);
+    std::fs::remove_file(script_path)?;
+    script?.apply(&mut scripted_tab)?;
+
+    for code_point in [0x41, 0x42].iter() {
+        let inverted: Vec<u8> = glyph_tab.buffer[code_point].data.iter().map(|&value| 255 - value).collect();
+        assert_eq!(scripted_tab.buffer[code_point].data, inverted);
+        assert_eq!(scripted_tab.y_min[code_point], glyph_tab.y_min[code_point] + 1);
+        assert_eq!(scripted_tab.width[code_point], glyph_tab.width[code_point]);
+    }
+
+    Ok(())
+}